@@ -0,0 +1,28 @@
+//! Lines of text as `--format hex` and `--format base64` read them, which have to either be
+//! refused or give a frame that writes back to an equivalent line
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpi_led_cube::decoders::{
+    decode_base16_frame, decode_base64_frame, read_base16_frame, read_base64_frame,
+    write_base16_frame, write_base64_frame,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(frame) = decode_base16_frame(line) {
+        let mut written = Vec::new();
+        write_base16_frame(&mut written, &frame).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert_eq!(read_base16_frame(&written), Some(frame));
+    }
+    if let Ok(frame) = decode_base64_frame(line) {
+        let mut written = Vec::new();
+        write_base64_frame(&mut written, &frame).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert_eq!(read_base64_frame(&written), Some(frame));
+    }
+});
@@ -0,0 +1,33 @@
+//! Streams as `--format raw` and the frame socket read them, where every frame read has to
+//! write back to the bytes it came from
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpi_led_cube::decoders::{
+    read_binary_frame, read_length_prefixed_frame, read_raw_frame, write_binary_frame,
+    write_length_prefixed_frame, BINARY_FRAME_LEN,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Some(frame) = read_binary_frame(data) {
+        assert_eq!(write_binary_frame(&frame)[..], *data);
+    }
+
+    let mut reader = data;
+    while let Ok(Some(frame)) = read_raw_frame(&mut reader) {
+        let start = data.len() - reader.len() - BINARY_FRAME_LEN;
+        assert_eq!(
+            write_binary_frame(&frame)[..],
+            data[start..start + BINARY_FRAME_LEN]
+        );
+    }
+
+    let mut reader = data;
+    while let Ok(Some(frame)) = read_length_prefixed_frame(&mut reader) {
+        let mut written = Vec::new();
+        write_length_prefixed_frame(&mut written, &frame).unwrap();
+        let start = data.len() - reader.len() - written.len();
+        assert_eq!(written, data[start..start + written.len()]);
+    }
+});
@@ -0,0 +1,22 @@
+//! A stream as `--format framed` reads it, skipping bad messages the way the listener does,
+//! where every message read has to survive being written and read again
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpi_led_cube::decoders::framed::{read_message, write_message, FramedError};
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = data;
+    loop {
+        match read_message(&mut reader) {
+            Ok(Some(message)) => {
+                let mut written = Vec::new();
+                write_message(&mut written, &message).unwrap();
+                assert_eq!(read_message(&mut &written[..]).unwrap(), Some(message));
+            }
+            Ok(None) | Err(FramedError::Io(_)) => break,
+            Err(_) => {}
+        }
+    }
+});
@@ -0,0 +1,16 @@
+//! Documents as `play` reads JSON animations, which have to be refused with a reason or give
+//! at least one frame
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rpi_led_cube::decoders::decode_json_frames;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(frames) = decode_json_frames(text) {
+        assert!(!frames.is_empty());
+    }
+});
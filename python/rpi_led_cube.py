@@ -0,0 +1,122 @@
+"""Drive the cube from Python through the crate's C interface.
+
+Build the shared library first, from the crate's root:
+
+    cargo rustc --release --lib --features cdylib --crate-type cdylib
+
+and put target/release/librpi_led_cube.so where this module can find it, next to it, on the
+loader's path or named by RPI_LED_CUBE_LIB.
+
+    import time
+    from rpi_led_cube import Cube, Frame
+
+    with Cube() as cube:
+        for z in range(8):
+            frame = Frame()
+            for x in range(8):
+                for y in range(8):
+                    frame.set(x, y, z)
+            cube.show(frame)
+            time.sleep(0.1)
+"""
+
+import ctypes
+import ctypes.util
+import os
+
+SIZE = 8
+
+
+def _load():
+    names = [os.environ.get("RPI_LED_CUBE_LIB"),
+             os.path.join(os.path.dirname(os.path.abspath(__file__)), "librpi_led_cube.so"),
+             ctypes.util.find_library("rpi_led_cube")]
+    for name in filter(None, names):
+        try:
+            lib = ctypes.CDLL(name)
+            break
+        except OSError:
+            continue
+    else:
+        raise OSError("librpi_led_cube.so not found, see the module documentation")
+
+    lib.cube_driver_new.restype = ctypes.c_void_p
+    lib.cube_driver_new.argtypes = []
+    lib.cube_driver_write_frame.restype = ctypes.c_int
+    lib.cube_driver_write_frame.argtypes = [ctypes.c_void_p, ctypes.c_char_p]
+    lib.cube_driver_free.restype = None
+    lib.cube_driver_free.argtypes = [ctypes.c_void_p]
+    return lib
+
+
+class Frame:
+    """Which voxels are lit, 64 bytes of layers from the bottom then rows of X with a bit per Y"""
+
+    def __init__(self, data=None):
+        self.data = bytearray(data if data is not None else SIZE * SIZE)
+        if len(self.data) != SIZE * SIZE:
+            raise ValueError("a frame is 64 bytes")
+
+    @staticmethod
+    def _check(x, y, z):
+        if not all(0 <= c < SIZE for c in (x, y, z)):
+            raise IndexError(f"({x}, {y}, {z}) is off the cube")
+
+    def set(self, x, y, z, lit=True):
+        self._check(x, y, z)
+        if lit:
+            self.data[z * SIZE + x] |= 1 << y
+        else:
+            self.data[z * SIZE + x] &= ~(1 << y) & 0xFF
+
+    def get(self, x, y, z):
+        self._check(x, y, z)
+        return bool(self.data[z * SIZE + x] & (1 << y))
+
+    def clear(self):
+        self.data[:] = bytes(SIZE * SIZE)
+
+    def fill(self):
+        self.data[:] = b"\xff" * (SIZE * SIZE)
+
+    def __bytes__(self):
+        return bytes(self.data)
+
+
+class Cube:
+    """The cube, refreshed on a thread of the library's own until closed"""
+
+    _lib = None
+    _driver = None
+
+    def __init__(self):
+        if Cube._lib is None:
+            Cube._lib = _load()
+        self._driver = Cube._lib.cube_driver_new()
+        if not self._driver:
+            raise OSError("could not claim the cube, see stderr")
+
+    def show(self, frame):
+        """Show a Frame, or any 64 bytes laid out like one, until the next"""
+        data = bytes(frame)
+        if len(data) != SIZE * SIZE:
+            raise ValueError("a frame is 64 bytes")
+        if self._driver is None:
+            raise ValueError("the cube is closed")
+        if Cube._lib.cube_driver_write_frame(self._driver, data) != 0:
+            raise OSError("the driver failed, see stderr")
+
+    def close(self):
+        """Blank the cube and release the GPIO"""
+        if self._driver is not None:
+            Cube._lib.cube_driver_free(self._driver)
+            self._driver = None
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *_):
+        self.close()
+
+    def __del__(self):
+        self.close()
@@ -0,0 +1,59 @@
+"""Tests of the parts of rpi_led_cube that don't need the library or a cube.
+
+    python3 -m unittest discover python
+"""
+
+import unittest
+
+from rpi_led_cube import SIZE, Frame
+
+
+class FrameTest(unittest.TestCase):
+    def test_voxels_land_where_the_driver_reads_them(self):
+        frame = Frame()
+        frame.set(1, 2, 3)
+        frame.set(7, 7, 7)
+        data = bytes(frame)
+        self.assertEqual(len(data), 64)
+        # Layers from the bottom, then rows of X with a bit per Y, as read_binary_frame takes them
+        self.assertEqual(data[3 * SIZE + 1], 1 << 2)
+        self.assertEqual(data[7 * SIZE + 7], 1 << 7)
+        self.assertEqual(sum(bin(byte).count("1") for byte in data), 2)
+
+    def test_set_get_and_unset(self):
+        frame = Frame()
+        for x, y, z in [(0, 0, 0), (0, 1, 0), (5, 3, 6)]:
+            self.assertFalse(frame.get(x, y, z))
+            frame.set(x, y, z)
+            self.assertTrue(frame.get(x, y, z))
+        frame.set(0, 0, 0, lit=False)
+        self.assertFalse(frame.get(0, 0, 0))
+        self.assertTrue(frame.get(0, 1, 0))
+
+    def test_clear_and_fill(self):
+        frame = Frame()
+        frame.fill()
+        self.assertEqual(bytes(frame), b"\xff" * 64)
+        frame.set(2, 2, 2, lit=False)
+        self.assertEqual(bytes(frame).count(b"\xff"), 63)
+        frame.clear()
+        self.assertEqual(bytes(frame), bytes(64))
+
+    def test_frames_from_bytes(self):
+        data = bytes(range(64))
+        self.assertEqual(bytes(Frame(data)), data)
+        with self.assertRaises(ValueError):
+            Frame(bytes(63))
+
+    def test_voxels_off_the_cube_are_refused(self):
+        frame = Frame()
+        for voxel in [(8, 0, 0), (0, -1, 0), (0, 0, 8)]:
+            with self.assertRaises(IndexError):
+                frame.set(*voxel)
+            with self.assertRaises(IndexError):
+                frame.get(*voxel)
+        self.assertEqual(bytes(frame), bytes(64))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,387 @@
+//! The `.cubeanim` file format: a header followed by raw frames.
+//!
+//! | offset | size | field                                          |
+//! |--------|------|------------------------------------------------|
+//! | 0      | 8    | magic `CUBEANIM`                               |
+//! | 8      | 1    | version, currently 2                           |
+//! | 9      | 1    | flags, see below                               |
+//! | 10     | 4    | frame period in microseconds, little endian    |
+//! | 14     | 4    | frame count, little endian                     |
+//! | 18     | 2    | header length in bytes, little endian          |
+//! | ...    | 64n  | frames, layers from Z = 0, rows in order       |
+//!
+//! Flag bit 0 marks a timed file, as recorded from a running program. Each frame is then
+//! preceded by the microseconds since the previous frame as a little endian u32, 0 for the
+//! first, and the frame period is how long the last frame stays up. Flag bit 1 marks a
+//! compressed file, where everything after the header, frames and times alike, is one zlib
+//! stream. The other flag bits are reserved and always 0.
+//!
+//! Fields added later go at the end of the header and raise its length, which readers skip
+//! past, so older readers keep playing newer files. The version only changes when a file
+//! could no longer be read that way, and readers refuse versions newer than their own, as
+//! they do flags they don't know. Version 1 files have no header length field, the frames
+//! starting at offset 18, and are still read.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{image::inflate, invalid, Frame};
+
+const MAGIC: &[u8; 8] = b"CUBEANIM";
+const VERSION: u8 = 2;
+/// Header length up to and including the header length field
+const HEADER_LEN: usize = 20;
+/// Version 1 headers end where the header length field starts
+const V1_HEADER_LEN: usize = 18;
+/// Where the frame count sits, so it can be filled in once recording ends
+const COUNT_OFFSET: u64 = 14;
+pub const FRAME_LEN: usize = 64;
+/// Each frame carries the time since the one before it
+const TIMED: u8 = 1;
+/// Everything after the header is zlib compressed
+const COMPRESSED: u8 = 2;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Header {
+    pub version: u8,
+    pub period: Duration,
+    pub frames: u32,
+    /// Frames have times of their own, with `period` only holding the last one up
+    pub timed: bool,
+    pub compressed: bool,
+}
+
+impl std::fmt::Display for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "version: {}", self.version)?;
+        if self.compressed {
+            writeln!(f, "compressed: zlib")?;
+        }
+        if self.timed {
+            writeln!(f, "timed: the last frame lasting {:?}", self.period)?;
+            write!(f, "frames: {}", self.frames)
+        } else {
+            writeln!(f, "frame period: {:?}", self.period)?;
+            writeln!(f, "frames: {}", self.frames)?;
+            write!(f, "duration: {:?}", self.period * self.frames)
+        }
+    }
+}
+
+fn read_header(input: &mut impl Read) -> io::Result<Header> {
+    let mut header = [0u8; HEADER_LEN];
+    input.read_exact(&mut header[..V1_HEADER_LEN])?;
+
+    if &header[..8] != MAGIC {
+        return Err(invalid("cubeanim file", "no CUBEANIM magic"));
+    }
+    let version = header[8];
+    match version {
+        1 => {}
+        2..=VERSION => {
+            input.read_exact(&mut header[V1_HEADER_LEN..])?;
+            let len = u16::from_le_bytes([header[18], header[19]]);
+            let extra = usize::from(len)
+                .checked_sub(HEADER_LEN)
+                .ok_or_else(|| invalid("cubeanim file", "header too short"))?;
+            // Fields from newer writers that this reader doesn't know
+            io::copy(&mut input.take(extra as u64), &mut io::sink())?;
+        }
+        _ => return Err(invalid("cubeanim file", "unsupported version")),
+    }
+    if header[9] & !(TIMED | COMPRESSED) != 0 {
+        return Err(invalid("cubeanim file", "unsupported flags"));
+    }
+
+    let word = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().expect("4 bytes"));
+    Ok(Header {
+        version,
+        period: Duration::from_micros(word(10).into()),
+        frames: word(14),
+        timed: header[9] & TIMED != 0,
+        compressed: header[9] & COMPRESSED != 0,
+    })
+}
+
+pub fn read_info(path: &Path) -> io::Result<Header> {
+    read_header(&mut File::open(path)?)
+}
+
+/// Load a whole animation, with how long each frame stays up
+pub fn read_timed(path: &Path) -> io::Result<(Header, Vec<(Duration, Frame)>)> {
+    let mut input = BufReader::new(File::open(path)?);
+    let header = read_header(&mut input)?;
+    if header.compressed {
+        let mut compressed = Vec::new();
+        input.read_to_end(&mut compressed)?;
+        let frames = read_frames(&header, &mut &inflate::decompress(&compressed)?[..])?;
+        return Ok((header, frames));
+    }
+    let frames = read_frames(&header, &mut input)?;
+    Ok((header, frames))
+}
+
+/// The frames following `header`, with how long each stays up
+fn read_frames(header: &Header, input: &mut impl Read) -> io::Result<Vec<(Duration, Frame)>> {
+    let mut frames: Vec<(Duration, Frame)> = Vec::with_capacity(header.frames as usize);
+    let mut bytes = [0u8; FRAME_LEN];
+    let mut delay = [0u8; 4];
+    for _ in 0..header.frames {
+        if header.timed {
+            input.read_exact(&mut delay)?;
+            // The time since the last frame is how long that one was up
+            if let Some((duration, _)) = frames.last_mut() {
+                *duration = Duration::from_micros(u32::from_le_bytes(delay).into());
+            }
+        }
+        input.read_exact(&mut bytes)?;
+        frames.push((
+            header.period,
+            core::array::from_fn(|layer| core::array::from_fn(|row| bytes[layer * 8 + row])),
+        ));
+    }
+    Ok(frames)
+}
+
+/// Load a whole animation
+pub fn read(path: &Path) -> io::Result<(Header, Vec<Frame>)> {
+    let (header, frames) = read_timed(path)?;
+    Ok((header, frames.into_iter().map(|(_, frame)| frame).collect()))
+}
+
+/// The frames to show one per `tick` to play timed `frames` at `speed` times their own pace,
+/// each kept up for at least one tick
+pub fn at_tick(frames: &[(Duration, Frame)], tick: Duration, speed: f64) -> Vec<Frame> {
+    let mut ticks = Vec::new();
+    // Rounded against the running total, so errors don't pile up over a long animation
+    let mut due = 0.0;
+    for &(duration, frame) in frames {
+        due += duration.as_secs_f64() / speed / tick.as_secs_f64();
+        let count = (due.round() as usize).saturating_sub(ticks.len()).max(1);
+        ticks.extend(std::iter::repeat_n(frame, count));
+    }
+    ticks
+}
+
+/// Streams frames into a new animation file, filling in the frame count when finished
+pub struct Writer {
+    out: BufWriter<File>,
+    /// Everything after the header of a compressed file, held until it is finished
+    compressed: Option<Vec<u8>>,
+    frames: u32,
+    /// When the last frame was written, for timed files
+    last: Option<Instant>,
+    timed: bool,
+}
+
+impl Writer {
+    pub fn create(path: &Path, period: Duration) -> io::Result<Self> {
+        Self::create_with(path, period, false, false)
+    }
+
+    /// A file compressed once finished, which holds its frames in memory until then
+    pub fn create_compressed(path: &Path, period: Duration) -> io::Result<Self> {
+        Self::create_with(path, period, false, true)
+    }
+
+    /// A timed file recording each frame as it is written, `period` being how long the last
+    /// frame stays up on playback
+    pub fn create_timed(path: &Path, period: Duration) -> io::Result<Self> {
+        Self::create_with(path, period, true, false)
+    }
+
+    fn create_with(
+        path: &Path,
+        period: Duration,
+        timed: bool,
+        compressed: bool,
+    ) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let period = u32::try_from(period.as_micros())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "period too long"))?;
+
+        out.write_all(MAGIC)?;
+        let flags = if timed { TIMED } else { 0 } | if compressed { COMPRESSED } else { 0 };
+        out.write_all(&[VERSION, flags])?;
+        out.write_all(&period.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&(HEADER_LEN as u16).to_le_bytes())?;
+
+        Ok(Writer {
+            out,
+            compressed: compressed.then(Vec::new),
+            frames: 0,
+            last: None,
+            timed,
+        })
+    }
+
+    /// Add a frame, timed from the previous one in a timed file
+    pub fn write(&mut self, frame: &Frame) -> io::Result<()> {
+        let now = Instant::now();
+        let delay = self.last.map_or(Duration::ZERO, |last| now - last);
+        self.last = Some(now);
+        self.write_after(delay, frame)
+    }
+
+    fn write_after(&mut self, delay: Duration, frame: &Frame) -> io::Result<()> {
+        let out: &mut dyn Write = match &mut self.compressed {
+            Some(compressed) => compressed,
+            None => &mut self.out,
+        };
+        if self.timed {
+            // Saturating at over an hour between frames
+            let micros = u32::try_from(delay.as_micros()).unwrap_or(u32::MAX);
+            out.write_all(&micros.to_le_bytes())?;
+        }
+        out.write_all(frame.as_flattened())?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(compressed) = &self.compressed {
+            self.out.write_all(&inflate::compress(compressed))?;
+        }
+        self.out.seek(SeekFrom::Start(COUNT_OFFSET))?;
+        self.out.write_all(&self.frames.to_le_bytes())?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn frame(n: u8) -> Frame {
+        [[n; 8]; 8]
+    }
+
+    #[test]
+    fn plain_files_round_trip() {
+        let path = env::temp_dir().join(format!("plain-{}.cubeanim", std::process::id()));
+        let mut writer = Writer::create(&path, Duration::from_millis(100)).unwrap();
+        for n in 0..3 {
+            writer.write(&frame(n)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let (header, frames) = read(&path).unwrap();
+        assert!(!header.timed);
+        assert_eq!(
+            (header.frames, header.period),
+            (3, Duration::from_millis(100))
+        );
+        assert_eq!(frames, [frame(0), frame(1), frame(2)]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn baked_routines_play_back_as_they_ran() {
+        use crate::routines::{Rain, Wave, FRAME_TIME};
+
+        let ran: Vec<Frame> = Wave::new()
+            .take(50)
+            .chain(Rain::new(0.2).take(150))
+            .collect();
+        for compressed in [false, true] {
+            let path = env::temp_dir().join(format!(
+                "baked-{compressed}-{}.cubeanim",
+                std::process::id()
+            ));
+            let mut writer = if compressed {
+                Writer::create_compressed(&path, FRAME_TIME)
+            } else {
+                Writer::create(&path, FRAME_TIME)
+            }
+            .unwrap();
+            for frame in &ran {
+                writer.write(frame).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let (header, frames) = read_timed(&path).unwrap();
+            assert_eq!(header.compressed, compressed);
+            assert_eq!(at_tick(&frames, FRAME_TIME, 1.0), ran);
+            if compressed {
+                let size = std::fs::metadata(&path).unwrap().len() as usize;
+                assert!(size < ran.len() * FRAME_LEN / 2, "{size} bytes");
+            }
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn older_and_longer_headers_are_read() {
+        let path = env::temp_dir().join(format!("versions-{}.cubeanim", std::process::id()));
+        let mut v1 = b"CUBEANIM\x01\x00".to_vec();
+        v1.extend(100_000u32.to_le_bytes());
+        v1.extend(1u32.to_le_bytes());
+        v1.extend(frame(7).as_flattened());
+        std::fs::write(&path, &v1).unwrap();
+        let (header, frames) = read(&path).unwrap();
+        assert_eq!((header.version, frames), (1, vec![frame(7)]));
+
+        // A header with four bytes this reader knows nothing about
+        let mut longer = v1[..18].to_vec();
+        longer[8] = VERSION;
+        longer.extend(24u16.to_le_bytes());
+        longer.extend([0xee; 4]);
+        longer.extend(frame(7).as_flattened());
+        std::fs::write(&path, &longer).unwrap();
+        assert_eq!(read(&path).unwrap().1, [frame(7)]);
+
+        longer[8] = VERSION + 1;
+        std::fs::write(&path, &longer).unwrap();
+        assert_eq!(read(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn playback_ticks_follow_frame_times() {
+        let tick = Duration::from_millis(10);
+        let frames = [
+            (Duration::from_millis(30), frame(1)),
+            (Duration::from_millis(4), frame(2)),
+            (Duration::from_millis(16), frame(3)),
+        ];
+        let ticks = at_tick(&frames, tick, 1.0);
+        assert_eq!(ticks, [frame(1), frame(1), frame(1), frame(2), frame(3)]);
+
+        let ticks = at_tick(&frames, tick, 2.0);
+        assert_eq!(ticks, [frame(1), frame(1), frame(2), frame(3)]);
+    }
+
+    #[test]
+    fn timed_files_keep_each_frames_time() {
+        let path = env::temp_dir().join(format!("timed-{}.cubeanim", std::process::id()));
+        let mut writer = Writer::create_timed(&path, Duration::from_millis(100)).unwrap();
+        writer.write_after(Duration::ZERO, &frame(1)).unwrap();
+        writer
+            .write_after(Duration::from_millis(30), &frame(2))
+            .unwrap();
+        writer
+            .write_after(Duration::from_millis(250), &frame(3))
+            .unwrap();
+        writer.finish().unwrap();
+
+        let (header, frames) = read_timed(&path).unwrap();
+        assert!(header.timed);
+        assert_eq!(
+            frames,
+            [
+                (Duration::from_millis(30), frame(1)),
+                (Duration::from_millis(250), frame(2)),
+                (Duration::from_millis(100), frame(3)),
+            ]
+        );
+        assert_eq!(read(&path).unwrap().1, [frame(1), frame(2), frame(3)]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+//! Art-Net (4): DMX over UDP as lighting consoles send it. The cube answers ArtPoll, so it shows
+//! up in a console's list of nodes with an output port for each universe it listens to, and
+//! shows the ArtDmx sent to those universes.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+use crate::dmx::{Dmx, DmxProtocol, UNIVERSE_SIZE};
+
+pub const PORT: u16 = 6454;
+
+/// Starts every Art-Net packet
+const ID: &[u8; 8] = b"Art-Net\0";
+const OP_POLL: u16 = 0x2000;
+const OP_POLL_REPLY: u16 = 0x2100;
+const OP_DMX: u16 = 0x5000;
+const PROTOCOL_VERSION: u16 = 14;
+/// ArtPollReply is this long whatever it describes
+const POLL_REPLY_LEN: usize = 239;
+/// Ports one ArtPollReply can describe, all sharing the top 11 bits of their universe
+const PORTS_PER_REPLY: usize = 4;
+
+/// Art-Net packets for the cube's universes, which a console addresses by 15 bit port-address:
+/// a net, a sub-net and a universe within it
+pub struct ArtNet {
+    universes: Vec<u16>,
+}
+
+impl ArtNet {
+    pub fn new(universes: Vec<u16>) -> Self {
+        ArtNet { universes }
+    }
+
+    /// ArtPollReply packets describing the cube as a node with one output port per universe, as
+    /// many as it takes since a reply can only carry four ports of the same sub-net
+    fn poll_replies(&self, ip: Ipv4Addr) -> Vec<Vec<u8>> {
+        let mut groups: Vec<Vec<u16>> = Vec::new();
+        for &universe in &self.universes {
+            match groups.last_mut() {
+                Some(group) if group.len() < PORTS_PER_REPLY && group[0] >> 4 == universe >> 4 => {
+                    group.push(universe)
+                }
+                _ => groups.push(vec![universe]),
+            }
+        }
+
+        (1..)
+            .zip(&groups)
+            .map(|(bind_index, group)| {
+                let mut reply = vec![0u8; POLL_REPLY_LEN];
+                reply[..8].copy_from_slice(ID);
+                reply[8..10].copy_from_slice(&OP_POLL_REPLY.to_le_bytes());
+                reply[10..14].copy_from_slice(&ip.octets());
+                reply[14..16].copy_from_slice(&PORT.to_le_bytes());
+                reply[18] = (group[0] >> 8) as u8 & 0x7F;
+                reply[19] = (group[0] >> 4) as u8 & 0x0F;
+                put_name(&mut reply[26..44], "LED cube");
+                put_name(&mut reply[44..108], "8x8x8 LED cube");
+                reply[173] = group.len() as u8;
+                for (port, universe) in group.iter().enumerate() {
+                    // Outputs DMX512, and is doing so
+                    reply[174 + port] = 0x80;
+                    reply[182 + port] = 0x80;
+                    reply[190 + port] = (universe & 0x0F) as u8;
+                }
+                reply[207..211].copy_from_slice(&ip.octets());
+                reply[211] = bind_index;
+                // Takes 15 bit port-addresses
+                reply[212] = 0x08;
+                reply
+            })
+            .collect()
+    }
+}
+
+/// Copy a name in, cut short if need be, leaving room for the terminating NUL
+fn put_name(field: &mut [u8], name: &str) {
+    let len = name.len().min(field.len() - 1);
+    field[..len].copy_from_slice(&name.as_bytes()[..len]);
+}
+
+/// The address other machines reach us on when talking to `peer`, found by asking the OS which
+/// it would route through
+fn local_ip(peer: SocketAddr) -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect(peer).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(_) => None,
+    }
+}
+
+impl DmxProtocol for ArtNet {
+    fn decode<'a>(
+        &mut self,
+        datagram: &'a [u8],
+        peer: SocketAddr,
+        socket: &UdpSocket,
+    ) -> Option<Dmx<'a>> {
+        if datagram.get(..8)? != ID {
+            return None;
+        }
+        match u16::from_le_bytes([*datagram.get(8)?, *datagram.get(9)?]) {
+            OP_POLL => {
+                let ip = local_ip(peer).unwrap_or(Ipv4Addr::UNSPECIFIED);
+                // Replies go to the port Art-Net uses whichever one the poll came from
+                let to = SocketAddr::new(peer.ip(), PORT);
+                for reply in self.poll_replies(ip) {
+                    if let Err(e) = socket.send_to(&reply, to) {
+                        eprintln!("Could not answer ArtPoll from {peer}: {e}");
+                    }
+                }
+                None
+            }
+            OP_DMX => {
+                let header = datagram.get(..18)?;
+                let version = u16::from_be_bytes([header[10], header[11]]);
+                if version < PROTOCOL_VERSION {
+                    return None;
+                }
+                let length = usize::from(u16::from_be_bytes([header[16], header[17]]));
+                let channels = &datagram[18..(18 + length).min(datagram.len())];
+                Some(Dmx {
+                    universe: u16::from_le_bytes([header[14], header[15]]) & 0x7FFF,
+                    // 0 means the sender doesn't number its packets
+                    sequence: (header[12] != 0).then_some(header[12]),
+                    channels: &channels[..channels.len().min(UNIVERSE_SIZE)],
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn art_dmx(universe: u16, sequence: u8, channels: &[u8]) -> Vec<u8> {
+        let mut packet = ID.to_vec();
+        packet.extend(OP_DMX.to_le_bytes());
+        packet.extend(PROTOCOL_VERSION.to_be_bytes());
+        packet.extend([sequence, 0]);
+        packet.extend(universe.to_le_bytes());
+        packet.extend((channels.len() as u16).to_be_bytes());
+        packet.extend(channels);
+        packet
+    }
+
+    #[test]
+    fn dmx_decodes() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = socket.local_addr().unwrap();
+        let mut artnet = ArtNet::new(vec![0]);
+
+        let packet = art_dmx(0x1234, 0, &[1, 2]);
+        assert_eq!(
+            artnet.decode(&packet, peer, &socket),
+            Some(Dmx {
+                universe: 0x1234,
+                sequence: None,
+                channels: &[1, 2]
+            })
+        );
+        assert_eq!(
+            artnet
+                .decode(&art_dmx(1, 7, &[]), peer, &socket)
+                .unwrap()
+                .sequence,
+            Some(7)
+        );
+        assert_eq!(artnet.decode(&packet[..12], peer, &socket), None);
+    }
+
+    #[test]
+    fn replies_group_ports_by_sub_net() {
+        let artnet = ArtNet::new(vec![0, 1, 2, 3, 4, 0x13]);
+        let replies = artnet.poll_replies(Ipv4Addr::new(10, 0, 0, 5));
+        assert_eq!(replies.len(), 3);
+        assert!(replies.iter().all(|reply| reply.len() == POLL_REPLY_LEN));
+        assert_eq!(replies[0][10..14], [10, 0, 0, 5]);
+        assert_eq!(replies[0][173], 4);
+        assert_eq!(replies[0][190..194], [0, 1, 2, 3]);
+        assert_eq!(replies[1][173], 1);
+        assert_eq!(replies[2][19], 1);
+        assert_eq!(replies[2][190], 3);
+        assert_eq!(replies[2][211], 3);
+    }
+
+    #[test]
+    fn polls_are_answered() {
+        let node = UdpSocket::bind("127.0.0.1:0").unwrap();
+        // The console has to be on the Art-Net port to hear the reply
+        let Ok(console) = UdpSocket::bind(("127.0.0.1", PORT)) else {
+            return;
+        };
+        console
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut poll = ID.to_vec();
+        poll.extend(OP_POLL.to_le_bytes());
+        poll.extend(PROTOCOL_VERSION.to_be_bytes());
+        poll.extend([0, 0]);
+
+        let mut artnet = ArtNet::new(vec![1]);
+        let peer = console.local_addr().unwrap();
+        assert_eq!(artnet.decode(&poll, peer, &node), None);
+        let mut buf = [0; 512];
+        let len = console.recv(&mut buf).unwrap();
+        assert_eq!(len, POLL_REPLY_LEN);
+        assert_eq!(buf[8..10], OP_POLL_REPLY.to_le_bytes());
+        assert_eq!(buf[10..14], [127, 0, 0, 1]);
+    }
+}
@@ -0,0 +1,290 @@
+//! Audio capture and the spectrum analysis behind the visualizer. Capture goes through ALSA's
+//! `arecord`, so the only thing needed beyond the base build is that tool being installed.
+
+use std::{
+    f32::consts::PI,
+    io::{self, Read},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{sync_channel, Receiver, TryRecvError, TrySendError},
+    thread,
+};
+
+use crate::{geometry::Coord, Frame};
+
+/// Samples per second captured
+pub const SAMPLE_RATE: u32 = 22_050;
+/// Samples analysed at a time, a power of two for the FFT
+const WINDOW: usize = 1024;
+/// Fresh samples read between analyses, so windows overlap by half
+const HOP: usize = WINDOW / 2;
+/// Lowest and highest frequencies shown, split into 8 bands evenly on a log scale
+const LOW_HZ: f32 = 60.0;
+const HIGH_HZ: f32 = 11_000.0;
+/// Layers a peak marker drops per frame
+const PEAK_DECAY: f32 = 0.25;
+
+/// A complex number, just enough of one for `fft`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    pub fn norm(self) -> f32 {
+        self.re.hypot(self.im)
+    }
+}
+
+/// In-place radix-2 FFT, `data` being a power of two long
+pub fn fft(data: &mut [Complex]) {
+    let n = data.len();
+    assert!(n.is_power_of_two(), "FFT length {n} is not a power of two");
+
+    // Bit-reversed order so the butterflies can work in place
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let twiddle = Complex {
+                    re: (angle * k as f32).cos(),
+                    im: (angle * k as f32).sin(),
+                };
+                let even = data[start + k];
+                let odd = data[start + k + len / 2].mul(twiddle);
+                data[start + k] = Complex {
+                    re: even.re + odd.re,
+                    im: even.im + odd.im,
+                };
+                data[start + k + len / 2] = Complex {
+                    re: even.re - odd.re,
+                    im: even.im - odd.im,
+                };
+            }
+        }
+        len *= 2;
+    }
+}
+
+/// Loudness of 8 bands from low to high frequencies in dB relative to a full scale sine, read
+/// from the last `WINDOW` samples
+pub fn bands(samples: &[i16]) -> [f32; 8] {
+    let samples = &samples[samples.len().saturating_sub(WINDOW)..];
+    let mut data = [Complex::default(); WINDOW];
+    for (i, (slot, &sample)) in data.iter_mut().zip(samples).enumerate() {
+        // Hann window against leakage between bins
+        let hann = 0.5 - 0.5 * (2.0 * PI * i as f32 / (WINDOW - 1) as f32).cos();
+        slot.re = f32::from(sample) / f32::from(i16::MAX) * hann;
+    }
+    fft(&mut data);
+
+    let bin_hz = SAMPLE_RATE as f32 / WINDOW as f32;
+    let edge = |band: usize| LOW_HZ * (HIGH_HZ / LOW_HZ).powf(band as f32 / 8.0);
+    // A full scale sine peaks at a quarter of the window once halved by the Hann window
+    let full_scale = WINDOW as f32 / 4.0;
+
+    core::array::from_fn(|band| {
+        let low = (edge(band) / bin_hz).round() as usize;
+        let high = ((edge(band + 1) / bin_hz).round() as usize).max(low + 1);
+        let peak = data[low..high.min(WINDOW / 2)]
+            .iter()
+            .map(|c| c.norm())
+            .fold(0.0, f32::max);
+        20.0 * (peak / full_scale).max(1e-9).log10()
+    })
+}
+
+/// Mono 16 bit audio from an ALSA device, read on a thread of its own by `arecord`
+pub struct Capture {
+    child: Child,
+    rx: Receiver<Vec<i16>>,
+}
+
+impl Capture {
+    /// Start recording from `device`, such as `default` or `plughw:1,0` for a USB microphone
+    pub fn open(device: &str) -> io::Result<Self> {
+        let mut child = Command::new("arecord")
+            .args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-D", device])
+            .args(["-r", &SAMPLE_RATE.to_string()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("could not run arecord: {e}")))?;
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+
+        let (tx, rx) = sync_channel(8);
+        thread::spawn(move || {
+            let mut bytes = [0u8; HOP * 2];
+            while stdout.read_exact(&mut bytes).is_ok() {
+                let hop = bytes
+                    .chunks_exact(2)
+                    .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                // Dropped while the visualizer is behind, rather than stalling the recording
+                if let Err(TrySendError::Disconnected(_)) = tx.try_send(hop) {
+                    break;
+                }
+            }
+        });
+
+        Ok(Capture { child, rx })
+    }
+
+    /// Every hop of samples read since the last call, `None` once recording has stopped
+    fn read(&self) -> Option<Vec<i16>> {
+        let mut samples = Vec::new();
+        loop {
+            match self.rx.try_recv() {
+                Ok(hop) => samples.extend(hop),
+                Err(TryRecvError::Empty) => return Some(samples),
+                Err(TryRecvError::Disconnected) if samples.is_empty() => return None,
+                Err(TryRecvError::Disconnected) => return Some(samples),
+            }
+        }
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Bar heights, out of 8 layers, for band levels in dB between `floor` and 0
+pub fn heights(levels: &[f32; 8], floor: f32) -> [f32; 8] {
+    levels.map(|db| ((db - floor) / -floor * 8.0).clamp(0.0, 8.0))
+}
+
+/// Draw each band as a wall across Y at its X, as tall as its level, with its recent peak
+/// marked by the top layer it reached
+pub fn render(bars: &[f32; 8], peaks: &[f32; 8]) -> Frame {
+    let mut frame = [[0u8; 8]; 8];
+    for (x, (&bar, &peak)) in (0..8).zip(bars.iter().zip(peaks)) {
+        let top = bar.round() as u8;
+        let peak = (peak.round() as u8).checked_sub(1);
+        for y in 0..8 {
+            for z in (0..top).chain(peak) {
+                Coord::new(x, y, z).set(&mut frame);
+            }
+        }
+    }
+    frame
+}
+
+/// The spectrum of live audio as 8 bars from bass at X = 0 to treble at X = 7, with peaks that
+/// hold and slowly fall
+pub struct Visualizer {
+    capture: Capture,
+    /// The last `WINDOW` samples
+    samples: Vec<i16>,
+    floor: f32,
+    peaks: [f32; 8],
+}
+
+impl Visualizer {
+    /// Levels at `floor` dB or below show nothing, full scale fills the cube. The floor is kept
+    /// at least 1 dB down.
+    pub fn new(capture: Capture, floor: f32) -> Self {
+        Visualizer {
+            capture,
+            samples: vec![0; WINDOW],
+            floor: floor.min(-1.0),
+            peaks: [0.0; 8],
+        }
+    }
+}
+
+impl Iterator for Visualizer {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.samples.extend(self.capture.read()?);
+        self.samples.drain(..self.samples.len() - WINDOW);
+
+        let bars = heights(&bands(&self.samples), self.floor);
+        for (peak, &bar) in self.peaks.iter_mut().zip(&bars) {
+            *peak = bar.max(*peak - PEAK_DECAY);
+        }
+        Some(render(&bars, &self.peaks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(hz: f32, amplitude: f32) -> Vec<i16> {
+        (0..WINDOW)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                (amplitude * f32::from(i16::MAX) * (2.0 * PI * hz * t).sin()) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fft_finds_a_single_tone() {
+        let mut data: Vec<Complex> = (0..64)
+            .map(|i| Complex {
+                re: (2.0 * PI * 5.0 * i as f32 / 64.0).cos(),
+                im: 0.0,
+            })
+            .collect();
+        fft(&mut data);
+        let loudest = (0..32)
+            .max_by(|&a, &b| data[a].norm().total_cmp(&data[b].norm()))
+            .unwrap();
+        assert_eq!(loudest, 5);
+        assert!((data[5].norm() - 32.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tones_land_in_their_band() {
+        // Right on an FFT bin, roughly 90 Hz, 1 kHz and 8 kHz
+        let bin_hz = SAMPLE_RATE as f32 / WINDOW as f32;
+        for (hz, band) in [(4.0 * bin_hz, 0), (46.0 * bin_hz, 4), (372.0 * bin_hz, 7)] {
+            let levels = bands(&sine(hz, 0.5));
+            let loudest = (0..8)
+                .max_by(|&a, &b| levels[a].total_cmp(&levels[b]))
+                .unwrap();
+            assert_eq!(loudest, band, "{hz} Hz: {levels:?}");
+            // Half of full scale is 6 dB down
+            assert!((levels[band] + 6.0).abs() < 0.5, "{hz} Hz: {levels:?}");
+        }
+        assert!(bands(&[0; WINDOW]).iter().all(|&db| db < -100.0));
+    }
+
+    #[test]
+    fn bars_scale_between_the_floor_and_full_scale() {
+        let bars = heights(&[-80.0, -60.0, -30.0, 0.0, 6.0, -45.0, -15.0, -7.5], -60.0);
+        assert_eq!(bars, [0.0, 0.0, 4.0, 8.0, 8.0, 2.0, 6.0, 7.0]);
+
+        let frame = render(&bars, &[0.0, 3.0, 4.0, 8.0, 8.0, 2.0, 6.0, 7.0]);
+        let column = |x: u8| -> Vec<u8> {
+            (0..8)
+                .filter(|&z| Coord::new(x, 4, z).get(&frame))
+                .collect()
+        };
+        assert!(column(0).is_empty());
+        assert_eq!(column(1), [2]);
+        assert_eq!(column(2), [0, 1, 2, 3]);
+        assert_eq!(column(3), (0..8).collect::<Vec<_>>());
+    }
+}
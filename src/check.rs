@@ -0,0 +1,188 @@
+use std::{
+    mem,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Intervals this much over the frame period are still counted as on time, to allow for the
+/// scheduler waking us slightly late
+const LATE_SLACK: f64 = 1.05;
+
+/// Rejections listed by `--check`, the rest are only counted
+const MAX_LISTED: usize = 20;
+
+/// Where a frame source notes what it couldn't decode and why, such as a line and its offset,
+/// for `--check` to report once the source ends
+#[derive(Clone, Default)]
+pub struct Rejections(Arc<Mutex<RejectionLog>>);
+
+#[derive(Default)]
+struct RejectionLog {
+    count: u64,
+    listed: Vec<String>,
+}
+
+impl Rejections {
+    pub fn record(&self, rejection: String) {
+        let mut log = self.0.lock().expect("rejections poisoned");
+        log.count += 1;
+        if log.listed.len() < MAX_LISTED {
+            log.listed.push(rejection);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.lock().expect("rejections poisoned").count
+    }
+}
+
+/// Where a listener notes when each frame was decoded under `--check`. The frames are drained
+/// flat out, so it also carries the stop token that ends a listener waiting on a quiet input.
+#[derive(Clone)]
+pub struct Arrivals {
+    decoded: Arc<Mutex<Vec<Instant>>>,
+    pub stop: Arc<AtomicBool>,
+}
+
+impl Arrivals {
+    pub fn new(stop: Arc<AtomicBool>) -> Self {
+        Arrivals {
+            decoded: Arc::default(),
+            stop,
+        }
+    }
+
+    pub fn record(&self, decoded: Instant) {
+        self.decoded
+            .lock()
+            .expect("arrivals poisoned")
+            .push(decoded);
+    }
+
+    fn take(&self) -> Vec<Instant> {
+        mem::take(&mut *self.decoded.lock().expect("arrivals poisoned"))
+    }
+}
+
+/// Stands in for the display under `--check`, recording when frames arrive instead of showing
+/// them, so a frame source can be validated without any GPIO. Frames from a listener count from
+/// when they were decoded, those from anything else from when they reach the report.
+pub struct CheckReport {
+    period: Duration,
+    frames: u64,
+    last: Option<Instant>,
+    min: Duration,
+    max: Duration,
+    total: Duration,
+    late: u64,
+    rejected: Rejections,
+    arrivals: Arrivals,
+}
+
+impl CheckReport {
+    /// Reports with the frames whatever the source records in `rejected` and `arrivals`
+    pub fn new(period: Duration, rejected: Rejections, arrivals: Arrivals) -> Self {
+        CheckReport {
+            period,
+            frames: 0,
+            last: None,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            total: Duration::ZERO,
+            late: 0,
+            rejected,
+            arrivals,
+        }
+    }
+
+    /// A frame has reached the output, after its source noted when it was decoded if it could
+    pub fn record(&mut self) {
+        let decoded = self.arrivals.take();
+        if decoded.is_empty() {
+            self.record_at(Instant::now());
+        }
+        for at in decoded {
+            self.record_at(at);
+        }
+    }
+
+    fn record_at(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            let interval = now - last;
+            self.min = self.min.min(interval);
+            self.max = self.max.max(interval);
+            self.total += interval;
+            if interval > self.period.mul_f64(LATE_SLACK) {
+                self.late += 1;
+            }
+        }
+        self.last = Some(now);
+        self.frames += 1;
+    }
+
+    /// Frames decoded after the last one was sent still count
+    pub fn print(mut self) {
+        for at in self.arrivals.take() {
+            self.record_at(at);
+        }
+        println!("frames: {}", self.frames);
+        if self.frames > 1 {
+            let intervals = self.frames - 1;
+            println!(
+                "interval: min {:.2?}, mean {:.2?}, max {:.2?}",
+                self.min,
+                self.total / intervals as u32,
+                self.max
+            );
+            println!(
+                "late: {} of {intervals} intervals over the {:?} frame period",
+                self.late, self.period
+            );
+        }
+
+        let log = self.rejected.0.lock().expect("rejections poisoned");
+        println!("rejected: {}", log.count);
+        for rejection in &log.listed {
+            println!("  {rejection}");
+        }
+        if log.count > log.listed.len() as u64 {
+            println!("  and {} more", log.count - log.listed.len() as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejections_are_counted_and_the_first_listed() {
+        let rejected = Rejections::default();
+        for line in 1..=25 {
+            rejected.clone().record(format!("line {line}: bad"));
+        }
+        assert_eq!(rejected.count(), 25);
+        let log = rejected.0.lock().unwrap();
+        assert_eq!(log.listed.len(), MAX_LISTED);
+        assert_eq!(log.listed[0], "line 1: bad");
+    }
+
+    #[test]
+    fn intervals_are_between_decoded_frames() {
+        let arrivals = Arrivals::new(Arc::default());
+        let mut report = CheckReport::new(
+            Duration::from_millis(100),
+            Rejections::default(),
+            arrivals.clone(),
+        );
+        let start = Instant::now();
+        for frame in 0..5 {
+            arrivals.record(start + Duration::from_millis(350) * frame);
+            report.record();
+        }
+        assert_eq!(report.frames, 5);
+        assert_eq!(report.late, 4);
+        assert_eq!(report.min, Duration::from_millis(350));
+        assert_eq!(report.max, Duration::from_millis(350));
+    }
+}
@@ -0,0 +1,195 @@
+//! Frames for RGB cubes, as a bit-plane per channel. Each voxel can be any of the eight colours
+//! mixing its three LEDs fully on or off makes.
+
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::{
+    geometry::Coord,
+    gray::{self, GrayFrame, MAX_LEVEL},
+    orientation::Orientation,
+    Frame,
+};
+
+/// Which of a colour frame's planes carry each channel
+pub const RED: usize = 0;
+pub const GREEN: usize = 1;
+pub const BLUE: usize = 2;
+
+/// An on/off frame per channel, red, green then blue
+pub type ColorFrame = [Frame; 3];
+
+/// A lit voxel's colour
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Color {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+    Cyan,
+    Magenta,
+    #[default]
+    White,
+}
+
+impl Color {
+    /// Whether each channel is lit, red, green then blue
+    pub fn channels(self) -> [bool; 3] {
+        match self {
+            Color::Red => [true, false, false],
+            Color::Green => [false, true, false],
+            Color::Blue => [false, false, true],
+            Color::Yellow => [true, true, false],
+            Color::Cyan => [false, true, true],
+            Color::Magenta => [true, false, true],
+            Color::White => [true, true, true],
+        }
+    }
+}
+
+/// Colours that on/off frames and frames with intensity are shown in on an RGB cube, from dim
+/// to bright. Gray levels are spread evenly across them, and on/off frames take the brightest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    /// The colour of each gray level, level 0 being dark whatever it says
+    by_level: [Color; MAX_LEVEL as usize + 1],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::new(&[Color::White])
+    }
+}
+
+impl Palette {
+    /// Gray levels in equal bands from dimmest to brightest, `colors` being from dim to bright.
+    /// Panics without any colours.
+    pub fn new(colors: &[Color]) -> Self {
+        assert!(!colors.is_empty(), "a palette needs a colour");
+        let bands = colors.len();
+        let by_level = core::array::from_fn(|level| {
+            colors[(level.max(1) - 1) * bands / usize::from(MAX_LEVEL)]
+        });
+        Palette { by_level }
+    }
+
+    pub fn color(&self, level: u8) -> Color {
+        self.by_level[usize::from(level.min(MAX_LEVEL))]
+    }
+}
+
+impl std::fmt::Display for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut colors: Vec<Color> = self.by_level[1..].to_vec();
+        colors.dedup();
+        let names: Vec<_> = colors
+            .iter()
+            .map(|c| c.to_possible_value().expect("all values possible"))
+            .collect();
+        let names: Vec<_> = names.iter().map(|v| v.get_name()).collect();
+        names.join(",").fmt(f)
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// Parses colours from dim to bright, e.g. `blue,cyan,white`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colors = s
+            .split(',')
+            .map(|name| Color::from_str(name.trim(), true))
+            .collect::<Result<Vec<_>, _>>()?;
+        if colors.len() > usize::from(MAX_LEVEL) {
+            return Err(format!("at most {MAX_LEVEL} colours, one per gray level"));
+        }
+        Ok(Palette::new(&colors))
+    }
+}
+
+/// Light `c` in `color`, leaving it dark in the channels the colour doesn't use
+pub fn set(frame: &mut ColorFrame, c: Coord, color: Color) {
+    for (plane, lit) in frame.iter_mut().zip(color.channels()) {
+        if lit {
+            c.set(plane);
+        } else {
+            c.clear(plane);
+        }
+    }
+}
+
+/// Every voxel lit in any channel
+pub fn on_off(frame: &ColorFrame) -> Frame {
+    core::array::from_fn(|z| {
+        core::array::from_fn(|x| frame.iter().fold(0, |row, plane| row | plane[z][x]))
+    })
+}
+
+/// Every lit voxel of an on/off frame in the palette's brightest colour
+pub fn from_frame(frame: &Frame, palette: &Palette) -> ColorFrame {
+    from_gray(&gray::from_frame(frame), palette)
+}
+
+/// Every voxel coloured for its level by the palette
+pub fn from_gray(gray: &GrayFrame, palette: &Palette) -> ColorFrame {
+    let mut frame = [[[0; 8]; 8]; 3];
+    for c in Coord::all() {
+        let level = gray::get(gray, c);
+        if level > 0 {
+            set(&mut frame, c, palette.color(level));
+        }
+    }
+    frame
+}
+
+pub fn orient(frame: &ColorFrame, orientation: Orientation) -> ColorFrame {
+    frame.map(|plane| orientation.apply(&plane))
+}
+
+/// Each channel the other way round, so every voxel takes its complementary colour
+pub fn invert(frame: &ColorFrame) -> ColorFrame {
+    frame.map(|plane| plane.map(|layer| layer.map(|row| !row)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn palettes_spread_over_the_gray_levels() {
+        let palette: Palette = "blue, cyan,white".parse().unwrap();
+        assert_eq!(palette.color(1), Color::Blue);
+        assert_eq!(palette.color(5), Color::Blue);
+        assert_eq!(palette.color(6), Color::Cyan);
+        assert_eq!(palette.color(MAX_LEVEL), Color::White);
+        assert_eq!(palette.to_string(), "blue,cyan,white");
+        assert!("blue,teal".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn promoted_frames_light_the_same_voxels() {
+        let frame: Frame = SmallRng::seed_from_u64(10).gen();
+        let palette = Palette::new(&[Color::Magenta]);
+        let color = from_frame(&frame, &palette);
+        assert_eq!(on_off(&color), frame);
+        assert_eq!(color[RED], frame);
+        assert_eq!(color[GREEN], [[0; 8]; 8]);
+        assert_eq!(color[BLUE], frame);
+    }
+
+    #[test]
+    fn inverting_takes_the_complement() {
+        let mut frame = [[[0; 8]; 8]; 3];
+        let c = Coord::new(1, 2, 3);
+        set(&mut frame, c, Color::Yellow);
+        let inverted = invert(&frame);
+        assert_eq!(
+            [RED, GREEN, BLUE].map(|channel| c.get(&inverted[channel])),
+            Color::Blue.channels()
+        );
+        assert_eq!(invert(&inverted), frame);
+    }
+}
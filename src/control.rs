@@ -0,0 +1,500 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    color::{self, ColorFrame},
+    cube::MAX_BRIGHTNESS,
+    decoders::{decode_base16_frame, read_base16_frame, write_base16_frame},
+    display::{RefreshStats, StatsHandle},
+    geometry::Coord,
+    gray::{self, GrayFrame},
+    json,
+    orientation::Orientation,
+    pipeline::{Rotation, Transform},
+    Frame,
+};
+
+pub const DEFAULT_SOCKET: &str = "/tmp/rpi-led-cube.sock";
+
+/// Alerts beyond this many waiting are dropped rather than queued
+const MAX_QUEUED_ALERTS: usize = 4;
+
+/// Frames that pre-empt the running program for a while
+pub struct Alert {
+    frames: Vec<Frame>,
+    hold: Duration,
+}
+
+/// An alert being played, tracking where it is up to
+pub struct ActiveAlert {
+    alert: Alert,
+    started: Instant,
+    shown: usize,
+}
+
+impl ActiveAlert {
+    /// The next alert frame, or `None` once the alert has been held long enough
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        if self.started.elapsed() >= self.alert.hold || self.alert.frames.is_empty() {
+            return None;
+        }
+        let frame = self.alert.frames[self.shown % self.alert.frames.len()];
+        self.shown += 1;
+        Some(frame)
+    }
+}
+
+/// Something that happens to a remote controlled program once, see `remote::Remote`, or to a
+/// playlist given the control
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Switch to another program, as its name and arguments
+    Program(Vec<String>),
+    /// Show this frame in place of the program until the next frame or program
+    Frame(Frame),
+    /// Move a playlist on to its next item now
+    Next,
+}
+
+/// Settings that can be changed while a program runs, read by whatever they affect every frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// The program last switched to, as given
+    pub program: String,
+    /// How many times faster than its own rate the program plays
+    pub speed: f64,
+    /// Software brightness, up to `MAX_BRIGHTNESS`
+    pub brightness: u8,
+    /// Turned on top of --rotate and --orient, with the steps it was given as
+    pub orientation: Orientation,
+    pub orientation_steps: String,
+    /// Whether a pushed frame is showing in place of the program
+    pub frame_pushed: bool,
+}
+
+impl Settings {
+    /// The settings as the members of a JSON object, without its braces
+    pub fn json_members(&self) -> String {
+        format!(
+            "\"program\":{},\"speed\":{},\"brightness\":{},\"rotation\":{},\"frame_pushed\":{}",
+            json::quote(&self.program),
+            self.speed,
+            self.brightness,
+            json::quote(&self.orientation_steps),
+            self.frame_pushed,
+        )
+    }
+}
+
+/// Whether the words of a program name one that can be switched to, and why not if they don't
+pub type ProgramCheck = Box<dyn Fn(&[String]) -> Result<(), String> + Send>;
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            program: String::new(),
+            speed: 1.0,
+            brightness: MAX_BRIGHTNESS,
+            orientation: Orientation::IDENTITY,
+            orientation_steps: "none".to_owned(),
+            frame_pushed: false,
+        }
+    }
+}
+
+/// State a running program shares with its control socket
+pub struct Control {
+    alerts: Mutex<VecDeque<Alert>>,
+    commands: Mutex<VecDeque<Command>>,
+    settings: Mutex<Settings>,
+    /// Frames sent to the output so far
+    frames: AtomicU64,
+    /// What the cube is showing right now, kept up to date by whoever writes the frames
+    shown: Arc<Mutex<Frame>>,
+    /// The display thread's stats, once there is one
+    refresh: Mutex<Option<StatsHandle>>,
+    started: Instant,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Control {
+            alerts: Mutex::default(),
+            commands: Mutex::default(),
+            settings: Mutex::default(),
+            frames: AtomicU64::default(),
+            shown: Arc::default(),
+            refresh: Mutex::default(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Control::default()
+    }
+
+    /// How long since the program started
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Queue frames to play in a loop for `hold` ahead of the program's own frames. Returns false
+    /// if too many alerts are already waiting and this one was dropped.
+    pub fn inject_alert(&self, frames: Vec<Frame>, hold: Duration) -> bool {
+        let mut alerts = self.alerts.lock().expect("control state poisoned");
+        if alerts.len() >= MAX_QUEUED_ALERTS {
+            return false;
+        }
+        alerts.push_back(Alert { frames, hold });
+        true
+    }
+
+    /// Start the next queued alert, if there is one
+    pub fn next_alert(&self) -> Option<ActiveAlert> {
+        let alert = self
+            .alerts
+            .lock()
+            .expect("control state poisoned")
+            .pop_front()?;
+        Some(ActiveAlert {
+            alert,
+            started: Instant::now(),
+            shown: 0,
+        })
+    }
+
+    /// Queue a command for the program to pick up on its next frame
+    pub fn send(&self, command: Command) {
+        self.commands
+            .lock()
+            .expect("control state poisoned")
+            .push_back(command);
+    }
+
+    /// Every command sent since the last call, oldest first
+    pub fn take_commands(&self) -> Vec<Command> {
+        self.commands
+            .lock()
+            .expect("control state poisoned")
+            .drain(..)
+            .collect()
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+            .lock()
+            .expect("control state poisoned")
+            .clone()
+    }
+
+    pub fn update_settings(&self, change: impl FnOnce(&mut Settings)) {
+        change(&mut self.settings.lock().expect("control state poisoned"))
+    }
+
+    /// Change a setting or send a command by name, from its value as text: `program` with its
+    /// arguments, `speed` such as `2` or `0.5x`, `brightness`, `rotation` as `i`, `j`, `k` or
+    /// orientation steps, or `frame` as hex, where an empty frame blanks the cube. Nothing
+    /// changes if the value isn't valid, and the reason is returned.
+    pub fn change(&self, name: &str, value: &str, check: &ProgramCheck) -> Result<(), String> {
+        match name {
+            "program" => {
+                let words: Vec<String> = value.split_whitespace().map(str::to_owned).collect();
+                if words.is_empty() {
+                    return Err("expected a program and its arguments".to_owned());
+                }
+                check(&words)?;
+                self.update_settings(|settings| settings.program = words.join(" "));
+                self.send(Command::Program(words));
+            }
+            "speed" => match value.trim_end_matches(['x', 'X']).parse::<f64>() {
+                Ok(speed) if speed > 0.0 && speed.is_finite() => {
+                    self.update_settings(|settings| settings.speed = speed)
+                }
+                _ => return Err("expected a positive number such as 2 or 0.5x".to_owned()),
+            },
+            "brightness" => match value.parse::<u8>() {
+                Ok(level) if level <= MAX_BRIGHTNESS => {
+                    self.update_settings(|settings| settings.brightness = level)
+                }
+                _ => return Err(format!("expected a brightness from 0 to {MAX_BRIGHTNESS}")),
+            },
+            "rotation" => {
+                let orientation = match Rotation::from_str(value, true) {
+                    Ok(rotation) => Orientation::from(rotation),
+                    Err(_) => value.parse::<Orientation>().map_err(|e| e.to_string())?,
+                };
+                self.update_settings(|settings| {
+                    settings.orientation = orientation;
+                    settings.orientation_steps = value.to_owned();
+                });
+            }
+            "frame" if value.is_empty() => self.send(Command::Frame([[0; 8]; 8])),
+            "frame" => {
+                let frame = decode_base16_frame(value).map_err(|e| e.to_string())?;
+                self.send(Command::Frame(frame));
+            }
+            _ => return Err(format!("there is no {name} to change")),
+        }
+        Ok(())
+    }
+
+    /// Note that another frame went out, for working out the frame rate
+    pub fn count_frame(&self) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Frames sent since the program started
+    pub fn frames_counted(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// Report the stats of the display thread from now on
+    pub fn watch_refresh(&self, stats: StatsHandle) {
+        *self.refresh.lock().expect("control state poisoned") = Some(stats);
+    }
+
+    /// How the display thread is keeping up, when frames go to one
+    pub fn refresh_stats(&self) -> Option<RefreshStats> {
+        let refresh = self.refresh.lock().expect("control state poisoned");
+        refresh
+            .as_ref()
+            .map(|stats| *stats.lock().expect("stats poisoned"))
+    }
+
+    /// The shared snapshot, for a display thread to publish every frame it writes into
+    pub fn shown_handle(&self) -> Arc<Mutex<Frame>> {
+        self.shown.clone()
+    }
+
+    pub fn set_shown(&self, frame: Frame) {
+        *self.shown.lock().expect("control state poisoned") = frame;
+    }
+
+    pub fn shown(&self) -> Frame {
+        *self.shown.lock().expect("control state poisoned")
+    }
+
+    /// Run one line of the control protocol, returning the reply line
+    fn handle(&self, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("alert") => {
+                let Some(hold) = words.next().and_then(|ms| ms.parse().ok()) else {
+                    return "err expected hold in milliseconds".into();
+                };
+                let frames: Option<Vec<Frame>> = words.map(read_base16_frame).collect();
+                match frames {
+                    Some(frames) if !frames.is_empty() => {
+                        if self.inject_alert(frames, Duration::from_millis(hold)) {
+                            "ok".into()
+                        } else {
+                            "err alert queue full".into()
+                        }
+                    }
+                    _ => "err expected one or more hex frames".into(),
+                }
+            }
+            Some("status") => match words.next() {
+                None => format!(
+                    "ok alerts {}",
+                    self.alerts.lock().expect("control state poisoned").len()
+                ),
+                Some("--frame") => {
+                    let mut reply = b"ok ".to_vec();
+                    write_base16_frame(&mut reply, &self.shown())
+                        .expect("writing to a Vec cannot fail");
+                    reply.pop(); // Newline
+                    String::from_utf8(reply).expect("hex is ASCII")
+                }
+                Some(option) => format!("err unknown status option {option}"),
+            },
+            Some(command) => format!("err unknown command {command}"),
+            None => "err empty command".into(),
+        }
+    }
+}
+
+/// Turns every frame by the orientation in the settings, so it can change while running
+pub struct LiveOrientation(pub Arc<Control>);
+
+impl Transform for LiveOrientation {
+    fn apply(&mut self, frame: Frame) -> Frame {
+        self.0.settings().orientation.apply(&frame)
+    }
+
+    fn apply_gray(&mut self, gray: GrayFrame) -> GrayFrame {
+        gray::orient(&gray, self.0.settings().orientation)
+    }
+
+    fn apply_color(&mut self, color: ColorFrame) -> ColorFrame {
+        color::orient(&color, self.0.settings().orientation)
+    }
+}
+
+/// Listen for control commands on a Unix socket until the stop token is set
+pub fn serve(
+    path: PathBuf,
+    control: Arc<Control>,
+    stop_token: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    // A previous instance that was killed outright leaves its socket file behind. Only a socket
+    // nothing answers on is taken for one, anything else is left for bind to complain about.
+    if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+        if metadata.file_type().is_socket() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("another instance is serving {}", path.display()),
+                ));
+            }
+            std::fs::remove_file(&path)?;
+        }
+    }
+    let listener = UnixListener::bind(&path)?;
+    // Poll so the stop token is noticed without a connection arriving
+    listener.set_nonblocking(true)?;
+
+    Ok(thread::spawn(move || {
+        while !stop_token.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = handle_client(stream, &control) {
+                        eprintln!("Control connection failed: {e}");
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => {
+                    eprintln!("Control socket failed: {e}");
+                    break;
+                }
+            }
+        }
+        let _ = std::fs::remove_file(&path);
+    }))
+}
+
+fn handle_client(stream: UnixStream, control: &Control) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        writeln!(writer, "{}", control.handle(&line?))?;
+    }
+    Ok(())
+}
+
+/// Send one command to a running instance and return its reply
+pub fn request(path: &Path, command: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{command}")?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    Ok(reply.trim_end().to_owned())
+}
+
+/// Ready-made alerts for the `alert` command
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum AlertPattern {
+    /// A standing exclamation mark
+    #[default]
+    Exclamation,
+    /// The whole cube blinking
+    Flash,
+    /// Every edge of the cube
+    Border,
+}
+
+impl std::fmt::Display for AlertPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl AlertPattern {
+    pub fn frames(&self) -> Vec<Frame> {
+        let mut frame = [[0u8; 8]; 8];
+        match self {
+            AlertPattern::Exclamation => {
+                Coord::all()
+                    .filter(|c| (3..5).contains(&c.x) && (3..5).contains(&c.y) && c.z != 2)
+                    .for_each(|c| c.set(&mut frame));
+                vec![frame]
+            }
+            AlertPattern::Flash => vec![[[255; 8]; 8], frame],
+            AlertPattern::Border => {
+                let edge = |v: u8| v == 0 || v == 7;
+                Coord::all()
+                    .filter(|c| {
+                        [edge(c.x), edge(c.y), edge(c.z)]
+                            .iter()
+                            .filter(|&&e| e)
+                            .count()
+                            >= 2
+                    })
+                    .for_each(|c| c.set(&mut frame));
+                vec![frame]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_stale_sockets_are_replaced() {
+        let dir = std::env::temp_dir().join(format!("cube-control-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let serve_at = |path: &Path| {
+            let stop = Arc::new(AtomicBool::new(false));
+            serve(path.to_owned(), Arc::new(Control::new()), stop.clone())
+                .map(|handle| (stop, handle))
+        };
+
+        // Some other file is left alone
+        let file = dir.join("file");
+        std::fs::write(&file, "keep").unwrap();
+        assert!(serve_at(&file).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+
+        // A socket left by an instance that is gone is taken over
+        let socket = dir.join("socket");
+        drop(UnixListener::bind(&socket).unwrap());
+        let (stop, handle) = serve_at(&socket).unwrap();
+
+        // One still being served is not
+        let err = serve_at(&socket).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(UnixStream::connect(&socket).is_ok());
+
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
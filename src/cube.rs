@@ -1,35 +1,302 @@
-use std::{thread, time::Duration};
+use std::{io, time::Duration};
 
-use rppal::gpio::{Gpio, Level, OutputPin, Result};
+use clap::ValueEnum;
+use rppal::{
+    gpio::{Gpio, Level, OutputPin, Result},
+    pwm::{self, Pwm},
+};
+
+use crate::{
+    color::{self, ColorFrame, Palette, BLUE, GREEN, RED},
+    gamma::Correction,
+    geometry::CubeGeometry,
+    gray::{self, GrayFrame},
+    mask::DefectMask,
+    pins::PinConfig,
+    realtime::Realtime,
+    remap::Remap,
+    timing::{Delay, Timing},
+};
 
 const SLOWDOWN: u64 = 1;
 const ROW_DRIVE_CLOCK_SLEEP: Duration = Duration::from_micros(5 * SLOWDOWN);
 const ROW_WRITE_CLOCK_SLEEP: Duration = Duration::from_micros(5 * SLOWDOWN);
 const LAYER_STROBE_SLEEP: Duration = Duration::from_micros(100 * SLOWDOWN);
 
+/// Brightness levels run from off to this, full brightness being the original timing
+pub const MAX_BRIGHTNESS: u8 = 15;
+/// Refresh passes it takes to show every bit of a brightness level once
+pub const DUTY_PASSES: u8 = 4;
+/// Sweeps of all eight layers a second the display thread paces the cube to by default, well
+/// above where multiplexing flickers
+pub const REFRESH_RATE: u32 = 120;
+/// On time of the least significant brightness bit, the others doubling from there. Chosen so a
+/// full set of passes keeps layers lit about as long on average as `LAYER_STROBE_SLEEP`.
+const BCM_UNIT: Duration = Duration::from_micros(27 * SLOWDOWN);
+
+/// Several PWM periods fit in each layer's on time, so the strobe doesn't beat against it
+const PWM_FREQUENCY: f64 = 10_000.0;
+
+/// A hardware PWM channel to move out_enable onto. Whichever signal the pin config puts on the
+/// channel's pin swaps places with out_enable, see `PinConfig::with_out_enable_on`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PwmChannel {
+    /// PWM0 on GPIO 18, par_4 by default
+    #[default]
+    Pwm0,
+    /// PWM1 on GPIO 13, layer_sel_bit_1 by default
+    Pwm1,
+}
+
+impl std::fmt::Display for PwmChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl PwmChannel {
+    fn channel(self) -> pwm::Channel {
+        match self {
+            PwmChannel::Pwm0 => pwm::Channel::Pwm0,
+            PwmChannel::Pwm1 => pwm::Channel::Pwm1,
+        }
+    }
+
+    fn pin(self) -> u8 {
+        match self {
+            PwmChannel::Pwm0 => 18,
+            PwmChannel::Pwm1 => 13,
+        }
+    }
+}
+
+/// Global brightness through hardware PWM on out_enable
+#[derive(Copy, Clone, Debug)]
+pub struct PwmConfig {
+    pub channel: PwmChannel,
+    /// Fraction of the time the current layer is lit, 0 to 1
+    pub brightness: f64,
+}
+
+/// Everything needed to claim the cube's GPIO
+#[derive(Clone, Debug)]
+pub struct DriverConfig {
+    pub pins: PinConfig,
+    /// The size of the cube, which frames are resampled onto
+    pub geometry: CubeGeometry,
+    /// For an RGB cube, whose three banks of registers are chained red into green into blue
+    /// behind each par line, the colours that frames without any are shown in
+    pub rgb: Option<Palette>,
+    /// Voxels forced off or on whatever the frame says, before any remap
+    pub mask: Option<DefectMask>,
+    /// Where each voxel's LED really is, for cubes soldered in another order
+    pub remap: Option<Remap>,
+    pub pwm: Option<PwmConfig>,
+    /// Global brightness through software binary code modulation, up to `MAX_BRIGHTNESS`
+    pub brightness: u8,
+    /// What brightness levels are driven at, the global brightness's included
+    pub correction: Correction,
+    /// Sweeps of all the cube's layers a second, each a refresh, whatever the frame rate
+    pub refresh_rate: u32,
+    pub timing: Timing,
+    /// How the thread that claims the cube, and so refreshes it, is scheduled
+    pub realtime: Realtime,
+}
+
+impl Default for DriverConfig {
+    fn default() -> Self {
+        DriverConfig {
+            pins: PinConfig::default(),
+            geometry: CubeGeometry::default(),
+            rgb: None,
+            mask: None,
+            remap: None,
+            pwm: None,
+            brightness: MAX_BRIGHTNESS,
+            correction: Correction::default(),
+            refresh_rate: REFRESH_RATE,
+            timing: Timing::default(),
+            realtime: Realtime::default(),
+        }
+    }
+}
+
+impl DriverConfig {
+    /// How long each refresh is paced to
+    pub fn refresh_period(&self) -> Duration {
+        Duration::from_secs(1) / self.refresh_rate.max(1)
+    }
+
+    /// The least time one complete refresh of an on/off frame takes going by the driver's
+    /// timing and the refresh rate, every brightness pass included. Relaxed timing only ever
+    /// overruns, so real refreshes can take longer, and frames with intensity always take every
+    /// pass, as do all frames when the correction is calibrated voxel by voxel.
+    pub fn min_refresh_time(&self) -> Duration {
+        let layers = u32::from(self.geometry.size());
+        let banks = if self.rgb.is_some() { 3 } else { 1 };
+        let shift = banks * self.geometry.clocks_per_layer() as u32 * 3 * ROW_DRIVE_CLOCK_SLEEP;
+        let latch = 2 * ROW_WRITE_CLOCK_SLEEP;
+        if self.brightness < MAX_BRIGHTNESS || !self.correction.is_uniform() {
+            // Every pass shifts and latches again, each with a slot twice the last
+            (0..DUTY_PASSES)
+                .map(|pass| {
+                    (layers * (shift + latch + BCM_UNIT * (1 << pass))).max(self.refresh_period())
+                })
+                .sum()
+        } else {
+            (layers * (shift + latch + ROW_WRITE_CLOCK_SLEEP + LAYER_STROBE_SLEEP))
+                .max(self.refresh_period())
+        }
+    }
+}
+
+/// A GPIO output the driver can set, so that tests can stand in for the Pi's own
+pub trait OutputLine: Send {
+    fn write(&mut self, level: Level);
+
+    fn set_high(&mut self) {
+        self.write(Level::High)
+    }
+
+    fn set_low(&mut self) {
+        self.write(Level::Low)
+    }
+}
+
+impl OutputLine for OutputPin {
+    fn write(&mut self, level: Level) {
+        OutputPin::write(self, level)
+    }
+}
+
+/// Gets one layer's rows into the shift registers, where the driver latches them
+pub trait RowShifter: Send {
+    /// `rows` are as many as the cube is across, with Y as the bit
+    fn shift(&mut self, rows: &[u16], delay: &Delay) -> io::Result<()>;
+
+    /// Leave whatever lines it drives low, as the driver lets go of the cube
+    fn release(&mut self) {}
+}
+
+/// The cube as built: each par line feeds one chain of registers with its share of every
+/// layer, see `CubeGeometry::par_bit`, and par_srclk shifts them all along together. On an 8³
+/// cube that's one register per line with one bit of every row.
+pub struct ParallelRows<P: OutputLine = OutputPin> {
+    /// par_1 first, as many as `CubeGeometry::par_lines`
+    par: Vec<P>,
+    /// Rising edge
+    par_srclk: P,
+    geometry: CubeGeometry,
+}
+
+impl<P: OutputLine> ParallelRows<P> {
+    fn claim(
+        pins: &PinConfig,
+        geometry: CubeGeometry,
+        output: &mut dyn FnMut(u8, Level) -> Result<P>,
+    ) -> Result<Self> {
+        let par = pins.par()[..geometry.par_lines()]
+            .iter()
+            .map(|&pin| output(pin, Level::Low))
+            .collect::<Result<_>>()?;
+        Ok(ParallelRows {
+            par,
+            par_srclk: output(pins.par_srclk, Level::Low)?,
+            geometry,
+        })
+    }
+}
+
+impl<P: OutputLine> RowShifter for ParallelRows<P> {
+    fn shift(&mut self, rows: &[u16], delay: &Delay) -> io::Result<()> {
+        for clock in 0..self.geometry.clocks_per_layer() {
+            // Write a bit to each chain in parallel
+            for (line, par) in self.par.iter_mut().enumerate() {
+                par.write(self.geometry.par_bit(rows, line, clock).into());
+            }
+            // Need to sleep between setting channels and driving clock to allow inputs to settle
+            delay.wait(ROW_DRIVE_CLOCK_SLEEP);
+
+            // Trigger rising edge clock pulse
+            self.par_srclk.set_high();
+            delay.wait(ROW_DRIVE_CLOCK_SLEEP);
+
+            // Relax clock line
+            self.par_srclk.set_low();
+            delay.wait(ROW_DRIVE_CLOCK_SLEEP);
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        for par in &mut self.par {
+            par.set_low();
+        }
+        self.par_srclk.set_low();
+    }
+}
+
+/// The active low out_enable line
+enum OutputEnable<P> {
+    Pin(P),
+    /// Inverse polarity, so the duty cycle is the fraction of time output is enabled
+    Pwm {
+        pwm: Pwm,
+        brightness: f64,
+    },
+}
+
+impl<P: OutputLine> OutputEnable<P> {
+    fn disable(&mut self) -> io::Result<()> {
+        match self {
+            OutputEnable::Pin(pin) => pin.set_high(),
+            OutputEnable::Pwm { pwm, .. } => pwm.set_duty_cycle(0.0).map_err(pwm_error)?,
+        }
+        Ok(())
+    }
+
+    fn enable(&mut self) -> io::Result<()> {
+        match self {
+            OutputEnable::Pin(pin) => pin.set_low(),
+            OutputEnable::Pwm { pwm, brightness } => {
+                pwm.set_duty_cycle(*brightness).map_err(pwm_error)?
+            }
+        }
+        Ok(())
+    }
+}
+
+fn pwm_error(e: pwm::Error) -> io::Error {
+    match e {
+        pwm::Error::Io(e) => e,
+    }
+}
+
 /**
  * Handles all bit-banging and state for driving the cube
  */
-pub struct CubeDriver {
-    par_1: OutputPin,
-    par_2: OutputPin,
-    par_3: OutputPin,
-    par_4: OutputPin,
-    par_5: OutputPin,
-    par_6: OutputPin,
-    par_7: OutputPin,
-    par_8: OutputPin,
+pub struct CubeDriver<P: OutputLine = OutputPin, S: RowShifter = ParallelRows<P>> {
+    rows: S,
     /// Rising edge
-    par_rclk: OutputPin,
-    /// Rising edge
-    par_srclk: OutputPin,
-    /// Active low
-    par_srclr: OutputPin,
-    layer_sel_bit_0: OutputPin,
-    layer_sel_bit_1: OutputPin,
-    layer_sel_bit_2: OutputPin,
+    par_rclk: P,
     /// Active low
-    out_enable: OutputPin,
+    par_srclr: P,
+    /// layer_sel_bit_0 first, as many as `CubeGeometry::layer_select_bits`
+    layer_select: Vec<P>,
+    out_enable: OutputEnable<P>,
+    geometry: CubeGeometry,
+    rgb: Option<Palette>,
+    mask: Option<DefectMask>,
+    remap: Option<Remap>,
+    brightness: u8,
+    correction: Correction,
+    /// Which brightness bit the next `write_frame` or `write_gray_frame` shows
+    pass: u8,
+    refresh_period: Duration,
+    delay: Delay,
 }
 
 #[inline]
@@ -41,128 +308,596 @@ fn check_bit(value: u8, pow2: u8) -> Level {
     }
 }
 
-impl Drop for CubeDriver {
+impl<P: OutputLine, S: RowShifter> Drop for CubeDriver<P, S> {
     fn drop(&mut self) {
-        self.layer_sel_bit_0.set_low();
-        self.layer_sel_bit_1.set_low();
-        self.layer_sel_bit_2.set_low();
-        self.out_enable.set_high(); // Disable output
-
-        self.par_1.set_low();
-        self.par_2.set_low();
-        self.par_3.set_low();
-        self.par_4.set_low();
-        self.par_5.set_low();
-        self.par_6.set_low();
-        self.par_7.set_low();
-        self.par_8.set_low();
+        for select in &mut self.layer_select {
+            select.set_low();
+        }
+        let _ = self.out_enable.disable();
+
+        self.rows.release();
         self.par_rclk.set_low();
-        self.par_srclk.set_low();
         self.par_srclr.set_low();
     }
 }
 
 impl CubeDriver {
-    pub fn try_new() -> Result<Self> {
+    /// With `config.pwm`, out_enable moves onto a hardware PWM channel whose duty cycle sets the
+    /// brightness. If that channel can't be claimed the same pin is switched in software
+    /// instead, at full brightness.
+    pub fn try_new(config: &DriverConfig) -> Result<Self> {
+        CubeDriver::try_with_rows(config, ParallelRows::claim)
+    }
+}
+
+impl<S: RowShifter> CubeDriver<OutputPin, S> {
+    /// `try_new` with rows shifted in some other way, `rows` claiming whatever GPIO it needs
+    pub(crate) fn try_with_rows(
+        config: &DriverConfig,
+        rows: impl FnOnce(
+            &PinConfig,
+            CubeGeometry,
+            &mut dyn FnMut(u8, Level) -> Result<OutputPin>,
+        ) -> Result<S>,
+    ) -> Result<Self> {
+        // Whichever thread claims the cube is the one that goes on to refresh it
+        config.realtime.apply();
         let gpio = Gpio::new()?;
 
-        let layer_sel_bit_0 = gpio.get(06)?.into_output_low();
-        let layer_sel_bit_1 = gpio.get(13)?.into_output_low();
-        let layer_sel_bit_2 = gpio.get(16)?.into_output_low();
-        let out_enable = gpio.get(09)?.into_output_high(); // Start inactive
-
-        let par_1 = gpio.get(12)?.into_output_low();
-        let par_2 = gpio.get(05)?.into_output_low();
-        let par_3 = gpio.get(10)?.into_output_low();
-        let par_4 = gpio.get(18)?.into_output_low();
-        let par_5 = gpio.get(17)?.into_output_low();
-        let par_6 = gpio.get(04)?.into_output_low();
-        let par_7 = gpio.get(02)?.into_output_low();
-        let par_8 = gpio.get(03)?.into_output_low();
-        let par_rclk = gpio.get(08)?.into_output_low();
-        let par_srclk = gpio.get(11)?.into_output_low();
-        let mut par_srclr = gpio.get(07)?.into_output_low();
+        let pins = match config.pwm {
+            Some(pwm) => config.pins.with_out_enable_on(pwm.channel.pin()),
+            None => config.pins,
+        };
+
+        // Starts inactive
+        let pwm_out_enable = config.pwm.and_then(|config| {
+            match Pwm::with_frequency(
+                config.channel.channel(),
+                PWM_FREQUENCY,
+                0.0,
+                pwm::Polarity::Inverse,
+                true,
+            ) {
+                Ok(pwm) => Some(OutputEnable::Pwm {
+                    pwm,
+                    brightness: config.brightness.clamp(0.0, 1.0),
+                }),
+                Err(e) => {
+                    eprintln!("PWM unavailable ({e}), switching out_enable in software instead");
+                    None
+                }
+            }
+        });
+
+        let mut output = |pin, level| {
+            let pin = gpio.get(pin)?;
+            Ok(match level {
+                Level::Low => pin.into_output_low(),
+                Level::High => pin.into_output_high(),
+            })
+        };
+        CubeDriver::claim(config, &pins, pwm_out_enable, &mut output, rows)
+    }
+}
+
+impl<P: OutputLine, S: RowShifter> CubeDriver<P, S> {
+    /// Set up every signal on `pins` through `output`, which turns a GPIO into an output at a
+    /// level, and clear the shift registers. out_enable is only claimed as a pin when it isn't
+    /// on PWM.
+    fn claim(
+        config: &DriverConfig,
+        pins: &PinConfig,
+        out_enable: Option<OutputEnable<P>>,
+        output: &mut dyn FnMut(u8, Level) -> Result<P>,
+        rows: impl FnOnce(&PinConfig, CubeGeometry, &mut dyn FnMut(u8, Level) -> Result<P>) -> Result<S>,
+    ) -> Result<Self> {
+        let geometry = config.geometry;
+        let layer_select = pins.layer_select()[..geometry.layer_select_bits().into()]
+            .iter()
+            .map(|&pin| output(pin, Level::Low))
+            .collect::<Result<_>>()?;
+        // Starts inactive
+        let out_enable = match out_enable {
+            Some(out_enable) => out_enable,
+            None => OutputEnable::Pin(output(pins.out_enable, Level::High)?),
+        };
+
+        let rows = rows(pins, geometry, output)?;
+        let par_rclk = output(pins.par_rclk, Level::Low)?;
+        let mut par_srclr = output(pins.par_srclr, Level::Low)?;
 
         // Wait for initial levels to apply and settle
-        thread::sleep(Duration::from_micros(5));
+        let delay = Delay::new(config.timing);
+        delay.wait(Duration::from_micros(5));
 
         // Clear the buffers
         par_srclr.set_low();
-        thread::sleep(Duration::from_micros(5));
+        delay.wait(Duration::from_micros(5));
         par_srclr.set_high();
-        thread::sleep(Duration::from_micros(5));
+        delay.wait(Duration::from_micros(5));
 
         Ok(CubeDriver {
-            par_1,
-            par_2,
-            par_3,
-            par_4,
-            par_5,
-            par_6,
-            par_7,
-            par_8,
+            rows,
             par_rclk,
-            par_srclk,
             par_srclr,
-            layer_sel_bit_0,
-            layer_sel_bit_1,
-            layer_sel_bit_2,
+            layer_select,
             out_enable,
+            geometry,
+            rgb: config.rgb,
+            mask: config.mask,
+            remap: config.remap.clone(),
+            brightness: config.brightness.min(MAX_BRIGHTNESS),
+            correction: config.correction,
+            pass: 0,
+            refresh_period: config.refresh_period(),
+            delay,
         })
     }
 
     fn set_layer(&mut self, layer: u8) {
-        self.layer_sel_bit_0.write(check_bit(layer, 1));
-        self.layer_sel_bit_1.write(check_bit(layer, 2));
-        self.layer_sel_bit_2.write(check_bit(layer, 4));
-    }
-
-    fn write_row(&mut self, pattern: u8) {
-        // Need to sleep between setting channels and driving clock to allow inputs to settle
-        self.par_1.write(check_bit(pattern, 1));
-        self.par_2.write(check_bit(pattern, 2));
-        self.par_3.write(check_bit(pattern, 4));
-        self.par_4.write(check_bit(pattern, 8));
-        self.par_5.write(check_bit(pattern, 16));
-        self.par_6.write(check_bit(pattern, 32));
-        self.par_7.write(check_bit(pattern, 64));
-        self.par_8.write(check_bit(pattern, 128));
-        thread::sleep(ROW_DRIVE_CLOCK_SLEEP);
-
-        // Trigger rising edge clock pulse
-        self.par_srclk.set_high();
-        thread::sleep(ROW_DRIVE_CLOCK_SLEEP);
+        for (select, bit) in self.layer_select.iter_mut().zip(0..) {
+            select.write(check_bit(layer, 1 << bit));
+        }
+    }
 
-        // Relax clock line
-        self.par_srclk.set_low();
-        thread::sleep(ROW_DRIVE_CLOCK_SLEEP);
+    fn write_layer(&mut self, layer: u8, banks: &[Vec<u16>]) -> io::Result<()> {
+        self.latch_layer(layer, banks)?;
+        self.out_enable.enable()?;
+        self.delay.wait(ROW_WRITE_CLOCK_SLEEP);
+        Ok(())
     }
 
-    fn write_layer(&mut self, layer: u8, rows: [u8; 8]) {
-        for row in rows {
-            // Write 1 bit of each column in parallel
-            self.write_row(row);
+    /// Shift a layer in, one set of rows for each bank of registers, and latch it with output
+    /// disabled, leaving output off
+    fn latch_layer(&mut self, layer: u8, banks: &[Vec<u16>]) -> io::Result<()> {
+        for rows in banks {
+            self.rows.shift(rows, &self.delay)?;
         }
-        // Disable output to avoid ghosting, active low
-        self.out_enable.set_high();
-        thread::sleep(ROW_WRITE_CLOCK_SLEEP);
+        // Disable output to avoid ghosting, PWM included, for as long as the latch takes
+        self.out_enable.disable()?;
+        self.delay.wait(ROW_WRITE_CLOCK_SLEEP);
 
         // Move data to output register by triggering rising edge
         self.par_rclk.set_high();
         // Switch active layer too
         self.set_layer(layer);
-        thread::sleep(ROW_WRITE_CLOCK_SLEEP);
+        self.delay.wait(ROW_WRITE_CLOCK_SLEEP);
 
-        // Relax clock line and enable output
+        // Relax clock line
         self.par_rclk.set_low();
-        self.out_enable.set_low();
-        thread::sleep(ROW_WRITE_CLOCK_SLEEP);
+        Ok(())
+    }
+
+    /// Every layer as the banks of registers take it, resampled to the cube's size. On an RGB
+    /// cube the frame is shown in the palette's brightest colour.
+    fn layers(&self, data: &[[u8; 8]; 8]) -> Vec<Vec<Vec<u16>>> {
+        match self.rgb {
+            Some(palette) => self.color_layers(&color::from_frame(data, &palette)),
+            None => self.as_wired(
+                self.geometry
+                    .resample(data)
+                    .into_iter()
+                    .map(|rows| vec![rows])
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Every layer of a colour frame for the RGB banks. Blue's registers are furthest down the
+    /// chain, so its rows go in first.
+    fn color_layers(&self, color: &ColorFrame) -> Vec<Vec<Vec<u16>>> {
+        let [red, green, blue] = [RED, GREEN, BLUE].map(|c| self.geometry.resample(&color[c]));
+        self.as_wired(
+            blue.into_iter()
+                .zip(green)
+                .zip(red)
+                .map(|((blue, green), red)| vec![blue, green, red])
+                .collect(),
+        )
+    }
+
+    /// Force the masked voxels off and on in every bank, so forced on is white on an RGB cube,
+    /// then move every voxel to where its LED is wired
+    fn as_wired(&self, mut layers: Vec<Vec<Vec<u16>>>) -> Vec<Vec<Vec<u16>>> {
+        if let Some(mask) = &self.mask {
+            for (z, banks) in layers.iter_mut().enumerate() {
+                for rows in banks {
+                    mask.apply(z, rows);
+                }
+            }
+        }
+        if let Some(remap) = &self.remap {
+            for bank in 0..layers.first().map_or(0, Vec::len) {
+                let logical: Vec<Vec<u16>> =
+                    layers.iter().map(|banks| banks[bank].clone()).collect();
+                for (banks, rows) in layers.iter_mut().zip(remap.apply(&logical)) {
+                    banks[bank] = rows;
+                }
+            }
+        }
+        layers
+    }
+
+    /// Change the software brightness from the next refresh on
+    pub fn set_brightness(&mut self, level: u8) {
+        self.brightness = level.min(MAX_BRIGHTNESS);
+    }
+
+    /// How long each refresh should take, see `DriverConfig::refresh_rate`
+    pub fn refresh_period(&self) -> Duration {
+        self.refresh_period
     }
 
-    pub fn write_frame(&mut self, data: [[u8; 8]; 8]) {
-        for (rows, layer) in data.iter().zip(0u8..) {
-            self.write_layer(layer, *rows);
-            thread::sleep(LAYER_STROBE_SLEEP);
+    /// Whether the next refresh starts a frame's passes afresh rather than carrying on with them
+    pub fn at_frame_start(&self) -> bool {
+        self.pass == 0
+    }
+
+    /// Turn output off until the next refresh, so that waiting between refreshes doesn't leave
+    /// the top layer lit longer than the others
+    pub fn rest(&mut self) -> io::Result<()> {
+        self.out_enable.disable()
+    }
+
+    /// Refresh the cube once. Below full brightness each call is one pass of
+    /// `write_frame_with_duty`, cycling through the passes so that consecutive refreshes add up
+    /// to the configured brightness. Only fails when out_enable is on PWM, plain GPIO writes can't.
+    /// Frames are resampled onto cubes that aren't 8³, see `CubeGeometry`, and go through
+    /// `write_gray_frame` when voxels are calibrated to different brightnesses.
+    pub fn write_frame(&mut self, data: [[u8; 8]; 8]) -> io::Result<()> {
+        if !self.correction.is_uniform() && self.rgb.is_none() {
+            return self.write_gray_frame(&gray::from_frame(&data));
         }
+        let layers = self.layers(&data);
+        self.refresh(&layers)
+    }
+
+    /// Refresh the cube once with a colour frame, as `write_frame` does. A cube without RGB
+    /// banks shows every voxel lit in any channel.
+    pub fn write_color_frame(&mut self, color: &ColorFrame) -> io::Result<()> {
+        if self.rgb.is_none() {
+            return self.write_frame(color::on_off(color));
+        }
+        let layers = self.color_layers(color);
+        self.refresh(&layers)
+    }
+
+    fn refresh(&mut self, layers: &[Vec<Vec<u16>>]) -> io::Result<()> {
+        if self.brightness < MAX_BRIGHTNESS {
+            let pass = self.pass;
+            self.pass = (pass + 1) % DUTY_PASSES;
+            return self.duty_pass(layers, self.correction.level(self.brightness), pass);
+        }
+        // Full brightness has the one pass, whichever a dimmer refresh got to
+        self.pass = 0;
+
+        for (banks, layer) in layers.iter().zip(0u8..) {
+            self.write_layer(layer, banks)?;
+            self.delay.wait(LAYER_STROBE_SLEEP);
+        }
+        Ok(())
+    }
+
+    /// Refresh the cube once with an intensity per voxel through bit-angle modulation. Each call
+    /// is one pass, lighting the voxels whose level has bit `pass` set for `BCM_UNIT << pass`,
+    /// so that `DUTY_PASSES` consecutive refreshes light every voxel for its level in units.
+    /// Levels are scaled by the global brightness first, then corrected, see `Correction`. An
+    /// RGB cube shows each level in its palette colour instead, at the global brightness.
+    pub fn write_gray_frame(&mut self, gray: &GrayFrame) -> io::Result<()> {
+        if let Some(palette) = self.rgb {
+            return self.write_color_frame(&color::from_gray(gray, &palette));
+        }
+        let pass = self.pass;
+        self.pass = (pass + 1) % DUTY_PASSES;
+        let gray = self.correction.apply(&gray::dim(gray, self.brightness));
+        self.write_frame_with_duty(gray::bit_plane(&gray, pass), MAX_BRIGHTNESS, pass)
+    }
+
+    /// One pass of binary code modulation: each layer gets a slot of `BCM_UNIT << pass`, lit for
+    /// all of it if bit `pass` of `duty` is set and dark otherwise, so that showing passes 0
+    /// through `DUTY_PASSES - 1` lights each layer for `duty` units in total
+    pub fn write_frame_with_duty(
+        &mut self,
+        data: [[u8; 8]; 8],
+        duty: u8,
+        pass: u8,
+    ) -> io::Result<()> {
+        let layers = self.layers(&data);
+        self.duty_pass(&layers, duty, pass)
+    }
+
+    fn duty_pass(&mut self, layers: &[Vec<Vec<u16>>], duty: u8, pass: u8) -> io::Result<()> {
+        let slot = BCM_UNIT * (1 << pass);
+        let lit = duty & (1 << pass) != 0;
+        for (banks, layer) in layers.iter().zip(0u8..) {
+            self.latch_layer(layer, banks)?;
+            if lit {
+                self.out_enable.enable()?;
+                self.delay.wait(slot);
+                self.out_enable.disable()?;
+            } else {
+                self.delay.wait(slot);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every level written to a mock driver's pins, in order, with the GPIO it went to
+#[cfg(test)]
+pub(crate) type PinLog = std::sync::Arc<std::sync::Mutex<Vec<(u8, Level)>>>;
+
+/// A GPIO that only adds what is written to it to a log
+#[cfg(test)]
+pub(crate) struct MockPin {
+    gpio: u8,
+    log: PinLog,
+}
+
+#[cfg(test)]
+impl OutputLine for MockPin {
+    fn write(&mut self, level: Level) {
+        self.log.lock().unwrap().push((self.gpio, level));
+    }
+}
+
+/// The driver with every signal on a [`MockPin`], to check what it does without a Pi
+#[cfg(test)]
+pub(crate) type MockCubeDriver = CubeDriver<MockPin>;
+
+#[cfg(test)]
+impl MockCubeDriver {
+    /// A driver wired as `config` says, out_enable always as a plain pin, and the log its
+    /// pins write to, starting with the levels each is set up at
+    pub(crate) fn mock(config: &DriverConfig) -> (Self, PinLog) {
+        let log = PinLog::default();
+        let mut output = |gpio, level| {
+            log.lock().unwrap().push((gpio, level));
+            Ok(MockPin {
+                gpio,
+                log: log.clone(),
+            })
+        };
+        let driver =
+            CubeDriver::claim(config, &config.pins, None, &mut output, ParallelRows::claim)
+                .expect("mock pins can't fail");
+        (driver, log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::remap::Serpentine;
+
+    /// Levels of every pin as the log plays out, calling `edge` on each write with the GPIO
+    /// written, its new level and every pin's level before it
+    fn replay(log: &PinLog, mut edge: impl FnMut(u8, Level, &HashMap<u8, Level>)) {
+        let mut levels = HashMap::new();
+        for &(gpio, level) in log.lock().unwrap().iter() {
+            edge(gpio, level, &levels);
+            levels.insert(gpio, level);
+        }
+    }
+
+    fn high(levels: &HashMap<u8, Level>, gpio: u8) -> bool {
+        levels.get(&gpio) == Some(&Level::High)
+    }
+
+    /// Rows as the shift registers took them in, the par pins read at each rising edge of
+    /// par_srclk, and what was latched at each rising edge of par_rclk
+    fn shifted_and_latched(log: &PinLog, pins: &PinConfig) -> (Vec<u8>, Vec<usize>) {
+        let par = pins.par();
+        let (mut rows, mut latches) = (Vec::new(), Vec::new());
+        replay(log, |gpio, level, levels| {
+            let rising = level == Level::High && !high(levels, gpio);
+            if rising && gpio == pins.par_srclk {
+                rows.push(
+                    (0..8)
+                        .filter(|&bit| high(levels, par[bit]))
+                        .map(|bit| 1 << bit)
+                        .sum(),
+                );
+            }
+            if rising && gpio == pins.par_rclk {
+                latches.push(rows.len());
+            }
+        });
+        (rows, latches)
+    }
+
+    #[test]
+    fn rows_shift_in_order_with_their_bits_on_the_par_pins() {
+        let config = DriverConfig::default();
+        let (mut driver, log) = MockCubeDriver::mock(&config);
+        let frame: [[u8; 8]; 8] =
+            core::array::from_fn(|z| core::array::from_fn(|x| (z * 8 + x) as u8 * 3));
+        driver.write_frame(frame).unwrap();
+
+        let (rows, latches) = shifted_and_latched(&log, &config.pins);
+        assert_eq!(rows, frame.concat());
+        // Each layer latched after its eight rows and no sooner
+        assert_eq!(latches, (1..=8).map(|layer| layer * 8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn layers_are_selected_and_shown_only_once_latched() {
+        let pins = PinConfig::default();
+        let (mut driver, log) = MockCubeDriver::mock(&DriverConfig::default());
+        log.lock().unwrap().clear();
+        driver.write_frame([[0xFF; 8]; 8]).unwrap();
+
+        let select = [
+            pins.layer_sel_bit_0,
+            pins.layer_sel_bit_1,
+            pins.layer_sel_bit_2,
+        ];
+        let (mut shown, mut latched) = (Vec::new(), false);
+        replay(&log, |gpio, level, levels| {
+            if gpio == pins.par_rclk && level == Level::High {
+                // Output is off while the latch moves
+                assert!(high(levels, pins.out_enable), "latched with output on");
+                latched = true;
+            }
+            if gpio == pins.out_enable && level == Level::Low {
+                assert!(latched, "output on before anything was latched");
+                let layer: u8 = (0..3)
+                    .filter(|&bit| high(levels, select[bit]))
+                    .map(|bit| 1 << bit)
+                    .sum();
+                shown.push(layer);
+                latched = false;
+            }
+        });
+        assert_eq!(shown, (0..8).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn dimmed_refreshes_finish_their_passes_before_a_new_frame() {
+        let pins = PinConfig::default();
+        let (mut driver, log) = MockCubeDriver::mock(&DriverConfig {
+            brightness: 5,
+            ..DriverConfig::default()
+        });
+        assert!(driver.at_frame_start());
+        driver.write_frame([[0xFF; 8]; 8]).unwrap();
+        assert!(!driver.at_frame_start());
+
+        // Back at full brightness there's only the one pass
+        driver.set_brightness(MAX_BRIGHTNESS);
+        driver.write_frame([[0xFF; 8]; 8]).unwrap();
+        assert!(driver.at_frame_start());
+
+        driver.rest().unwrap();
+        assert_eq!(
+            log.lock().unwrap().last(),
+            Some(&(pins.out_enable, Level::High))
+        );
+    }
+
+    #[test]
+    fn dropping_turns_everything_off() {
+        let pins = PinConfig::default();
+        let (mut driver, log) = MockCubeDriver::mock(&DriverConfig::default());
+        driver.write_frame([[0xFF; 8]; 8]).unwrap();
+        drop(driver);
+
+        let mut levels = HashMap::new();
+        replay(&log, |gpio, level, _| {
+            levels.insert(gpio, level);
+        });
+        for (signal, gpio) in pins.signals() {
+            // Only 16³ cubes need it
+            if signal == "layer_sel_bit_3" {
+                assert!(!levels.contains_key(&gpio), "{signal} claimed");
+                continue;
+            }
+            let expected = if signal == "out_enable" {
+                Level::High
+            } else {
+                Level::Low
+            };
+            assert_eq!(levels[&gpio], expected, "{signal}");
+        }
+    }
+
+    #[test]
+    fn bigger_cubes_select_every_layer_and_shift_through_longer_chains() {
+        let config = DriverConfig {
+            geometry: CubeGeometry::new(16).unwrap(),
+            ..DriverConfig::default()
+        };
+        let (mut driver, log) = MockCubeDriver::mock(&config);
+        driver.write_frame([[0xFF; 8]; 8]).unwrap();
+
+        let (rows, latches) = shifted_and_latched(&log, &config.pins);
+        assert!(rows.iter().all(|&row| row == 0xFF));
+        assert_eq!(
+            latches,
+            (1..=16).map(|layer| layer * 32).collect::<Vec<_>>()
+        );
+
+        let select = config.pins.layer_select();
+        let mut shown: Vec<u8> = Vec::new();
+        replay(&log, |gpio, level, levels| {
+            if gpio == config.pins.out_enable && level == Level::Low {
+                shown.push(
+                    (0..4)
+                        .filter(|&bit| high(levels, select[bit]))
+                        .map(|bit| 1 << bit)
+                        .sum(),
+                );
+            }
+        });
+        assert_eq!(shown, (0..16).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn rgb_cubes_shift_blue_then_green_then_red() {
+        let config = DriverConfig {
+            rgb: Some(Palette::default()),
+            ..DriverConfig::default()
+        };
+        let (mut driver, log) = MockCubeDriver::mock(&config);
+        let mut frame = [[[0; 8]; 8]; 3];
+        frame[RED][0][0] = 1;
+        frame[GREEN][0][1] = 2;
+        frame[BLUE][0][2] = 4;
+        driver.write_color_frame(&frame).unwrap();
+
+        let (rows, latches) = shifted_and_latched(&log, &config.pins);
+        assert_eq!(latches[0], 24);
+        let mut first_layer = [0; 24];
+        first_layer[2] = 4;
+        first_layer[8 + 1] = 2;
+        first_layer[16] = 1;
+        assert_eq!(rows[..24], first_layer);
+
+        // Frames without colour come out in the palette's, white lighting every bank
+        log.lock().unwrap().clear();
+        driver.write_frame([[0xFF; 8]; 8]).unwrap();
+        let (rows, _) = shifted_and_latched(&log, &config.pins);
+        assert_eq!(rows, [0xFF; 8 * 24]);
+    }
+
+    #[test]
+    fn masked_voxels_are_forced_after_everything_else() {
+        let config = DriverConfig {
+            mask: Some(
+                DefectMask::parse(
+                    "0,0,0 = off
+1,*,0 = on",
+                    CubeGeometry::default(),
+                )
+                .unwrap(),
+            ),
+            ..DriverConfig::default()
+        };
+        let (mut driver, log) = MockCubeDriver::mock(&config);
+        let mut frame = [[0; 8]; 8];
+        frame[0][0] = 0b11;
+        driver.write_frame(frame).unwrap();
+
+        let (rows, _) = shifted_and_latched(&log, &config.pins);
+        assert_eq!(rows[..3], [0b10, 0xFF, 0]);
+        assert!(rows[8..].iter().all(|&row| row == 0));
+    }
+
+    #[test]
+    fn frames_are_remapped_onto_the_wiring() {
+        let geometry = CubeGeometry::default();
+        let config = DriverConfig {
+            remap: Some(Remap::serpentine(geometry, Serpentine::Rows)),
+            mask: Some(DefectMask::parse("1,0,0 = on", geometry).unwrap()),
+            ..DriverConfig::default()
+        };
+        let (mut driver, log) = MockCubeDriver::mock(&config);
+        driver.write_frame([[0; 8]; 8]).unwrap();
+
+        // Masked where it's drawn, and shown where that voxel is wired
+        let (rows, _) = shifted_and_latched(&log, &config.pins);
+        assert_eq!(rows[..2], [0, 1 << 7]);
     }
 }
@@ -0,0 +1,691 @@
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    geometry::Coord,
+    json::{self, Value},
+    Frame,
+};
+
+pub mod framed;
+
+/// How frames are written in a stream
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum FrameFormat {
+    /// A line of 128 hex digits per frame, see `read_base16_frame`
+    #[default]
+    Hex,
+    /// A line of 88 base64 characters per frame, see `read_base64_frame`
+    Base64,
+    /// 64 bytes per frame with nothing between them, see `read_binary_frame`
+    Raw,
+    /// Messages carrying frames, gray frames or settings, see `framed`
+    Framed,
+}
+
+impl std::fmt::Display for FrameFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Why a frame couldn't be decoded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// Not as many characters as a frame takes
+    Length { expected: usize, found: usize },
+    /// A character the format doesn't use, at this byte offset in the line
+    Char { offset: usize, found: char },
+    /// Base64 padding before the end of the frame, at this byte offset in the line
+    Padding { offset: usize },
+}
+
+impl FrameError {
+    /// Where in the line the problem is, its start for a wrong length
+    pub fn offset(&self) -> usize {
+        match self {
+            FrameError::Length { .. } => 0,
+            FrameError::Char { offset, .. } | FrameError::Padding { offset } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Length { expected, found } => {
+                write!(f, "expected {expected} characters, found {found}")
+            }
+            FrameError::Char { offset, found } => {
+                write!(f, "invalid character {found:?} at byte {offset}")
+            }
+            FrameError::Padding { offset } => write!(f, "padding too early at byte {offset}"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// A frame in a stream of lines that couldn't be decoded, and where it was
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineError {
+    /// Counting from 1
+    pub line: usize,
+    /// Bytes into the whole stream
+    pub offset: u64,
+    pub error: FrameError,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {} (byte {} of the input)",
+            self.line, self.error, self.offset
+        )
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// The characters of `line` inside any surrounding whitespace, and how many bytes of it came
+/// before them
+fn trimmed(line: &str) -> (&[u8], usize) {
+    let start = line.len() - line.trim_start().len();
+    (line.trim().as_bytes(), start)
+}
+
+/// The first character in `line` at or after byte `offset` where `valid` is false
+fn invalid_char(line: &[u8], start: usize, valid: impl Fn(u8) -> bool) -> Option<FrameError> {
+    let at = line.iter().position(|&b| !valid(b))?;
+    // Every byte before it is ASCII, so it starts a character
+    let found = String::from_utf8_lossy(&line[at..]).chars().next();
+    Some(FrameError::Char {
+        offset: start + at,
+        found: found.unwrap_or(char::REPLACEMENT_CHARACTER),
+    })
+}
+
+/// Parse 128 hex digits, two per row, eight rows per layer, layers in order from Z = 0.
+/// Surrounding whitespace is ignored, anything else malformed is an error saying where.
+pub fn decode_base16_frame(line: &str) -> Result<Frame, FrameError> {
+    let (digits, start) = trimmed(line);
+    // Not `from_str_radix`, which would also take a sign such as "+f"
+    if let Some(error) = invalid_char(digits, start, |b| b.is_ascii_hexdigit()) {
+        return Err(error);
+    }
+    if digits.len() != 128 {
+        return Err(FrameError::Length {
+            expected: 128,
+            found: digits.len(),
+        });
+    }
+
+    let mut frame = [[0u8; 8]; 8];
+    for (i, pair) in digits.chunks_exact(2).enumerate() {
+        let digit = |b: u8| char::from(b).to_digit(16).expect("checked above");
+        frame[i / 8][i % 8] = (digit(pair[0]) << 4 | digit(pair[1])) as u8;
+    }
+    Ok(frame)
+}
+
+/// `decode_base16_frame` for when the reason doesn't matter
+pub fn read_base16_frame(line: &str) -> Option<Frame> {
+    decode_base16_frame(line).ok()
+}
+
+/// Write a frame as a line in the format `read_base16_frame` accepts
+pub fn write_base16_frame(out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    for byte in frame.iter().flatten() {
+        write!(out, "{byte:02x}")?;
+    }
+    writeln!(out)
+}
+
+/// Write a frame as text to read by eye: a row of layers from the bottom, each with its rows of
+/// X going down and Y across, `#` for a lit voxel and `.` for an unlit one, then a blank line
+pub fn write_grid_frame(out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    let labels: Vec<String> = (0..8).map(|z| format!("z{z:<7}")).collect();
+    writeln!(out, "{}", labels.join(" ").trim_end())?;
+    for x in 0..8 {
+        let rows: Vec<String> = frame
+            .iter()
+            .map(|layer| {
+                (0..8)
+                    .map(|y| if layer[x] & (1 << y) != 0 { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        writeln!(out, "{}", rows.join(" "))?;
+    }
+    writeln!(out)
+}
+
+/// Bytes in a binary frame, one per row in the same order as `read_base16_frame`
+pub const BINARY_FRAME_LEN: usize = 64;
+
+/// Exactly `BINARY_FRAME_LEN` bytes, anything else gives `None`
+pub fn read_binary_frame(bytes: &[u8]) -> Option<Frame> {
+    if bytes.len() != BINARY_FRAME_LEN {
+        return None;
+    }
+    Some(core::array::from_fn(|z| {
+        core::array::from_fn(|x| bytes[z * 8 + x])
+    }))
+}
+
+pub fn write_binary_frame(frame: &Frame) -> [u8; BINARY_FRAME_LEN] {
+    core::array::from_fn(|i| frame[i / 8][i % 8])
+}
+
+/// Read one binary frame with nothing around it. `None` when the stream ends cleanly before a
+/// frame starts.
+pub fn read_raw_frame(reader: &mut impl Read) -> io::Result<Option<Frame>> {
+    let mut bytes = [0u8; BINARY_FRAME_LEN];
+    match reader.read_exact(&mut bytes[..1]) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    reader.read_exact(&mut bytes[1..])?;
+    Ok(read_binary_frame(&bytes))
+}
+
+/// The standard base64 alphabet, each character standing for its index
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Characters in a base64 frame, the `BINARY_FRAME_LEN` bytes with two of padding
+pub const BASE64_FRAME_LEN: usize = 88;
+
+/// Parse the bytes of a binary frame in standard base64 with its padding, two thirds the size
+/// of hex. Surrounding whitespace is ignored, anything else malformed is an error saying where.
+pub fn decode_base64_frame(line: &str) -> Result<Frame, FrameError> {
+    let (chars, start) = trimmed(line);
+    if let Some(error) = invalid_char(chars, start, |b| BASE64.contains(&b) || b == b'=') {
+        return Err(error);
+    }
+    if chars.len() != BASE64_FRAME_LEN {
+        return Err(FrameError::Length {
+            expected: BASE64_FRAME_LEN,
+            found: chars.len(),
+        });
+    }
+    // Only where the frame's last byte leaves it
+    let padding = BASE64_FRAME_LEN - 2;
+    if let Some(at) = chars[..padding].iter().position(|&c| c == b'=') {
+        return Err(FrameError::Padding { offset: start + at });
+    }
+    if chars[padding..] != *b"==" {
+        return Err(FrameError::Padding {
+            offset: start + padding,
+        });
+    }
+
+    let mut bytes = Vec::with_capacity(BASE64_FRAME_LEN / 4 * 3);
+    for quad in chars.chunks_exact(4) {
+        let value = quad.iter().fold(0u32, |value, &c| {
+            let digit = BASE64.iter().position(|&d| d == c).unwrap_or(0);
+            value << 6 | digit as u32
+        });
+        bytes.extend_from_slice(&value.to_be_bytes()[1..]);
+    }
+    Ok(read_binary_frame(&bytes[..BINARY_FRAME_LEN]).expect("the length of a frame"))
+}
+
+/// `decode_base64_frame` for when the reason doesn't matter
+pub fn read_base64_frame(line: &str) -> Option<Frame> {
+    decode_base64_frame(line).ok()
+}
+
+/// Any bytes in standard base64 with padding
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let mut padded = [0u8; 3];
+        padded[..group.len()].copy_from_slice(group);
+        let value = u32::from_be_bytes([0, padded[0], padded[1], padded[2]]);
+        for j in 0..4 {
+            text.push(if j <= group.len() {
+                char::from(BASE64[(value >> (18 - 6 * j) & 0x3F) as usize])
+            } else {
+                '='
+            });
+        }
+    }
+    text
+}
+
+/// Write a frame as a line in the format `read_base64_frame` accepts
+pub fn write_base64_frame(out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    writeln!(out, "{}", encode_base64(&write_binary_frame(frame)))
+}
+
+/// Read one binary frame preceded by its length as a big-endian u32, which has to be
+/// `BINARY_FRAME_LEN`. `None` when the stream ends cleanly before a frame starts.
+pub fn read_length_prefixed_frame(reader: &mut impl Read) -> io::Result<Option<Frame>> {
+    let mut prefix = [0u8; 4];
+    match reader.read_exact(&mut prefix[..1]) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    reader.read_exact(&mut prefix[1..])?;
+
+    let len = u32::from_be_bytes(prefix);
+    if len != BINARY_FRAME_LEN as u32 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("frames are {BINARY_FRAME_LEN} bytes, not {len}"),
+        ));
+    }
+
+    let mut bytes = [0u8; BINARY_FRAME_LEN];
+    reader.read_exact(&mut bytes)?;
+    Ok(read_binary_frame(&bytes))
+}
+
+/// Write a frame as `read_length_prefixed_frame` expects it
+pub fn write_length_prefixed_frame(out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    out.write_all(&(BINARY_FRAME_LEN as u32).to_be_bytes())?;
+    out.write_all(&write_binary_frame(frame))
+}
+
+/// How long each frame of a JSON animation is up when it doesn't say
+pub const JSON_DEFAULT_PERIOD: Duration = Duration::from_millis(100);
+
+/// Why a JSON animation couldn't be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JsonFrameError {
+    /// Not JSON at all
+    Syntax(json::ParseError),
+    /// JSON, but not of frames, with the path to the value at fault such as
+    /// `frames[2].voxels[5]`
+    Schema { at: String, reason: &'static str },
+}
+
+impl fmt::Display for JsonFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonFrameError::Syntax(e) => e.fmt(f),
+            JsonFrameError::Schema { at, reason } => write!(f, "{at}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for JsonFrameError {}
+
+fn schema<T>(at: impl Into<String>, reason: &'static str) -> Result<T, JsonFrameError> {
+    Err(JsonFrameError::Schema {
+        at: at.into(),
+        reason,
+    })
+}
+
+/// A number of milliseconds
+fn json_millis(value: &Value, at: String) -> Result<Duration, JsonFrameError> {
+    // Refusing negative, infinite and NaN durations, and any too long for a Duration
+    match value
+        .as_f64()
+        .map(|ms| Duration::try_from_secs_f64(ms / 1000.0))
+    {
+        Some(Ok(duration)) => Ok(duration),
+        _ => schema(at, "expected a duration in milliseconds"),
+    }
+}
+
+/// `count` items of an array
+fn json_items<'a>(value: &'a Value, count: usize, at: &str) -> Result<&'a [Value], JsonFrameError> {
+    match value.as_array() {
+        Some(items) if items.len() == count => Ok(items),
+        Some(_) => schema(at, "wrong number of items"),
+        None => schema(at, "expected an array"),
+    }
+}
+
+fn json_frame(value: &Value, at: &str) -> Result<Frame, JsonFrameError> {
+    let mut frame = [[0u8; 8]; 8];
+    match (value.get("layers"), value.get("voxels")) {
+        (Some(layers), None) => {
+            let at = format!("{at}.layers");
+            for (z, layer) in json_items(layers, 8, &at)?.iter().enumerate() {
+                let at = format!("{at}[{z}]");
+                for (x, row) in json_items(layer, 8, &at)?.iter().enumerate() {
+                    match row.as_u64().and_then(|row| u8::try_from(row).ok()) {
+                        Some(row) => frame[z][x] = row,
+                        None => {
+                            return schema(format!("{at}[{x}]"), "expected a row from 0 to 255")
+                        }
+                    }
+                }
+            }
+        }
+        (None, Some(voxels)) => {
+            let at = format!("{at}.voxels");
+            let Some(voxels) = voxels.as_array() else {
+                return schema(at, "expected an array");
+            };
+            for (i, voxel) in voxels.iter().enumerate() {
+                let at = format!("{at}[{i}]");
+                let axes = json_items(voxel, 3, &at)?;
+                let axis = |v: &Value| v.as_u64().filter(|&v| v < 8).map(|v| v as u8);
+                match (axis(&axes[0]), axis(&axes[1]), axis(&axes[2])) {
+                    (Some(x), Some(y), Some(z)) => Coord::new(x, y, z).set(&mut frame),
+                    _ => return schema(at, "expected [x, y, z] each from 0 to 7"),
+                }
+            }
+        }
+        _ => return schema(at, "expected an object with either layers or voxels"),
+    }
+    Ok(frame)
+}
+
+/// Frames written in JSON, for animations generated by scripts, with how long each is up.
+///
+/// A frame is an object with either `layers`, 8 layers from Z = 0 of 8 rows from X = 0 each,
+/// a row being a number from 0 to 255 with bit Y set for each lit voxel, or `voxels`, a list
+/// of `[x, y, z]` of the lit voxels. Either may come with `duration_ms`. The text is a single
+/// frame, a list of frames, or an object with the list under `frames` and the duration of
+/// frames that don't give their own under `period_ms`, by default `JSON_DEFAULT_PERIOD`.
+///
+/// ```json
+/// {"period_ms": 200, "frames": [
+///     {"voxels": [[0, 0, 0], [7, 7, 7]]},
+///     {"layers": [[255, 0, 0, 0, 0, 0, 0, 0], [0, 0, 0, 0, 0, 0, 0, 0], ...], "duration_ms": 500}
+/// ]}
+/// ```
+pub fn decode_json_frames(text: &str) -> Result<Vec<(Duration, Frame)>, JsonFrameError> {
+    let root = json::parse(text).map_err(JsonFrameError::Syntax)?;
+
+    // The path to the list of frames, none for a bare frame
+    let (frames, period, at) = match (&root, root.get("frames")) {
+        (Value::Array(frames), _) => (frames.as_slice(), JSON_DEFAULT_PERIOD, Some(String::new())),
+        (_, Some(frames)) => {
+            let Some(frames) = frames.as_array() else {
+                return schema("frames", "expected an array");
+            };
+            let period = match root.get("period_ms") {
+                Some(period) => json_millis(period, "period_ms".to_owned())?,
+                None => JSON_DEFAULT_PERIOD,
+            };
+            (frames, period, Some("frames".to_owned()))
+        }
+        (Value::Object(_), None) => (std::slice::from_ref(&root), JSON_DEFAULT_PERIOD, None),
+        _ => {
+            return schema(
+                "the top level",
+                "expected a frame, a list of frames or an object with frames",
+            )
+        }
+    };
+    if frames.is_empty() {
+        return schema(at.unwrap_or_default(), "no frames");
+    }
+
+    frames
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let at = at
+                .as_ref()
+                .map_or_else(|| "the frame".to_owned(), |at| format!("{at}[{i}]"));
+            let duration = match value.get("duration_ms") {
+                Some(ms) => json_millis(ms, format!("{at}.duration_ms"))?,
+                None => period,
+            };
+            Ok((duration, json_frame(value, &at)?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn encode(frame: &Frame) -> String {
+        let mut line = Vec::new();
+        write_base16_frame(&mut line, frame).unwrap();
+        String::from_utf8(line).unwrap()
+    }
+
+    #[test]
+    fn base16_round_trips() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let frame: Frame = rng.gen();
+            assert_eq!(read_base16_frame(&encode(&frame)), Some(frame));
+        }
+    }
+
+    #[test]
+    fn base16_accepts_upper_case() {
+        let frame = [[0xab; 8]; 8];
+        assert_eq!(
+            read_base16_frame(&encode(&frame).to_uppercase()),
+            Some(frame)
+        );
+    }
+
+    #[test]
+    fn base16_rejects_signs() {
+        assert_eq!(read_base16_frame(&"+f".repeat(64)), None);
+    }
+
+    #[test]
+    fn base16_rejects_wrong_lengths() {
+        assert_eq!(read_base16_frame(""), None);
+        assert_eq!(read_base16_frame(&"0".repeat(127)), None);
+        assert_eq!(read_base16_frame(&"0".repeat(129)), None);
+    }
+
+    #[test]
+    fn base16_never_panics_on_garbage() {
+        let mut rng = SmallRng::seed_from_u64(2);
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0..200);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            read_base16_frame(&String::from_utf8_lossy(&bytes));
+        }
+        // Multi-byte characters that bring the byte length to exactly 128
+        assert_eq!(read_base16_frame(&"é".repeat(64)), None);
+        assert_eq!(read_base16_frame(&format!("{}é", "0".repeat(126))), None);
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        let mut rng = SmallRng::seed_from_u64(4);
+        for _ in 0..1000 {
+            let frame: Frame = rng.gen();
+            let mut line = Vec::new();
+            write_base64_frame(&mut line, &frame).unwrap();
+            let line = String::from_utf8(line).unwrap();
+            assert_eq!(line.len(), BASE64_FRAME_LEN + 1);
+            assert_eq!(read_base64_frame(&line), Some(frame));
+        }
+
+        // As any other encoder would write it
+        let mut line =
+            "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4v".to_owned();
+        line += "MDEyMzQ1Njc4OTo7PD0+Pw==";
+        let counting: Frame = core::array::from_fn(|z| core::array::from_fn(|x| (z * 8 + x) as u8));
+        assert_eq!(read_base64_frame(&line), Some(counting));
+    }
+
+    #[test]
+    fn base64_rejects_misplaced_padding_and_other_characters() {
+        let mut line = Vec::new();
+        write_base64_frame(&mut line, &[[0x5a; 8]; 8]).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        assert_eq!(read_base64_frame(&line.replacen('W', "=", 1)), None);
+        assert_eq!(read_base64_frame(&line.replacen('W', "-", 1)), None);
+        assert_eq!(
+            read_base64_frame(line.trim_end().trim_end_matches('=')),
+            None
+        );
+        assert_eq!(read_base64_frame(""), None);
+    }
+
+    #[test]
+    fn errors_say_what_and_where() {
+        let mut line = format!("  {}", "0".repeat(128));
+        assert!(decode_base16_frame(&line).is_ok());
+        line.replace_range(12..13, "g");
+        assert_eq!(
+            decode_base16_frame(&line),
+            Err(FrameError::Char {
+                offset: 12,
+                found: 'g'
+            })
+        );
+        assert_eq!(
+            decode_base16_frame(&"0".repeat(126)),
+            Err(FrameError::Length {
+                expected: 128,
+                found: 126
+            })
+        );
+        let error = decode_base16_frame(&format!("{}é", "0".repeat(126))).unwrap_err();
+        assert_eq!(error.to_string(), "invalid character 'é' at byte 126");
+
+        let early = format!("{}=A==", "A".repeat(84));
+        assert_eq!(
+            decode_base64_frame(&early),
+            Err(FrameError::Padding { offset: 84 })
+        );
+        let missing = "A".repeat(BASE64_FRAME_LEN);
+        assert_eq!(
+            decode_base64_frame(&missing),
+            Err(FrameError::Padding { offset: 86 })
+        );
+    }
+
+    #[test]
+    fn raw_frames_follow_one_another() {
+        let mut stream = [[7u8; 8]; 8].as_flattened().to_vec();
+        stream.extend([9; 64]);
+        stream.extend([1; 10]);
+        let mut reader = io::Cursor::new(stream);
+        assert_eq!(read_raw_frame(&mut reader).unwrap(), Some([[7; 8]; 8]));
+        assert_eq!(read_raw_frame(&mut reader).unwrap(), Some([[9; 8]; 8]));
+        let err = read_raw_frame(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(read_raw_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn json_frames_as_layers_or_voxels() {
+        let mut layers = ["[0, 0, 0, 0, 0, 0, 0, 0]"; 8];
+        layers[2] = "[0, 0, 0, 0, 0, 0, 0, 129]";
+        let text = format!(
+            r#"{{"period_ms": 40, "frames": [
+                {{"layers": [{}]}},
+                {{"voxels": [[1, 2, 3], [7, 7, 7]], "duration_ms": 250}}
+            ]}}"#,
+            layers.join(", ")
+        );
+        let frames = decode_json_frames(&text).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].0, Duration::from_millis(40));
+        assert!(Coord::new(7, 0, 2).get(&frames[0].1) && Coord::new(7, 7, 2).get(&frames[0].1));
+        assert_eq!(frames[1].0, Duration::from_millis(250));
+        let lit: Vec<Coord> = Coord::all().filter(|c| c.get(&frames[1].1)).collect();
+        assert_eq!(lit.len(), 2);
+        assert!(lit.contains(&Coord::new(1, 2, 3)) && lit.contains(&Coord::new(7, 7, 7)));
+
+        // A bare frame, and a bare list of them
+        let single = decode_json_frames(r#"{"voxels": []}"#).unwrap();
+        assert_eq!(single, [(JSON_DEFAULT_PERIOD, [[0; 8]; 8])]);
+        assert_eq!(
+            decode_json_frames(r#"[{"voxels": []}, {"voxels": []}]"#)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn json_errors_give_the_path() {
+        let at = |text: &str| match decode_json_frames(text).unwrap_err() {
+            JsonFrameError::Schema { at, .. } => at,
+            JsonFrameError::Syntax(e) => panic!("{e}"),
+        };
+        assert_eq!(
+            at(r#"{"frames": [{"voxels": []}, {"voxels": [[8, 0, 0]]}]}"#),
+            "frames[1].voxels[0]"
+        );
+        assert_eq!(at(r#"[{"layers": [[0]]}]"#), "[0].layers");
+        assert_eq!(
+            at(r#"[{"voxels": [], "duration_ms": -1}]"#),
+            "[0].duration_ms"
+        );
+        assert_eq!(
+            at(r#"{"period_ms": 1e300, "frames": [{"voxels": []}]}"#),
+            "period_ms"
+        );
+        assert_eq!(at(r#"{"frames": []}"#), "frames");
+        assert_eq!(at("3"), "the top level");
+        assert!(matches!(
+            decode_json_frames("[{]"),
+            Err(JsonFrameError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn length_prefixed_frames_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let frames: Vec<Frame> = (0..10).map(|_| rng.gen()).collect();
+
+        let mut stream = Vec::new();
+        for frame in &frames {
+            write_length_prefixed_frame(&mut stream, frame).unwrap();
+        }
+
+        let mut reader = io::Cursor::new(stream);
+        for frame in &frames {
+            assert_eq!(
+                read_length_prefixed_frame(&mut reader).unwrap(),
+                Some(*frame)
+            );
+        }
+        assert_eq!(read_length_prefixed_frame(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn length_prefixed_frames_reject_other_lengths_and_truncation() {
+        let mut wrong = 63u32.to_be_bytes().to_vec();
+        wrong.extend([0; 63]);
+        let err = read_length_prefixed_frame(&mut io::Cursor::new(wrong)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut short = Vec::new();
+        write_length_prefixed_frame(&mut short, &[[1; 8]; 8]).unwrap();
+        short.truncate(40);
+        let err = read_length_prefixed_frame(&mut io::Cursor::new(short)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn grids_show_each_layer_side_by_side() {
+        let mut frame = [[0u8; 8]; 8];
+        frame[0][0] = 1;
+        frame[7][7] = 0x80;
+        let mut out = Vec::new();
+        write_grid_frame(&mut out, &frame).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert!(lines[0].starts_with("z0       z1"));
+        assert!(lines[1].starts_with("#......."));
+        assert!(lines[8].ends_with(".......#"));
+        assert_eq!(lines[9], "");
+    }
+}
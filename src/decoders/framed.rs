@@ -0,0 +1,271 @@
+//! A stream of self-describing messages, so one pipe can carry on/off frames, frames with
+//! intensity and control messages without either end guessing which is which. Every message is
+//!
+//! | Bytes | Field                                                             |
+//! |-------|-------------------------------------------------------------------|
+//! | 1     | [`MAGIC`], `0xCB`                                                 |
+//! | 1     | [`VERSION`], 1                                                    |
+//! | 1     | Payload type, 1 frame, 2 gray frame, 3 control                    |
+//! | 2     | Payload length, big-endian                                        |
+//! | n     | Payload                                                           |
+//! | 1     | Checksum, the sum of every byte from the version to the payload's |
+//! |       | end, modulo 256                                                   |
+//!
+//! A frame is 64 bytes as `read_binary_frame` takes them, a gray frame is 512 levels from 0 to
+//! 15 indexed `[z][x][y]`, higher ones taken as 15, and a control message is UTF-8 text naming
+//! a setting and its value as the HTTP and MQTT remotes change them, such as `brightness 3` or
+//! `speed 0.5`. Later versions keep the header, so a reader can skip what it doesn't know.
+//! A producer in Python:
+//!
+//! ```python
+//! import struct, sys, time
+//!
+//! def message(kind, payload):
+//!     body = struct.pack(">BBH", 1, kind, len(payload)) + payload
+//!     return b"\xcb" + body + bytes([sum(body) % 256])
+//!
+//! out = sys.stdout.buffer
+//! out.write(message(3, b"brightness 3"))
+//! for z in range(8):
+//!     levels = bytes(15 if layer == z else 0 for layer in range(8) for _ in range(64))
+//!     out.write(message(2, levels))
+//!     out.flush()
+//!     time.sleep(0.1)
+//! ```
+
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+};
+
+use super::{read_binary_frame, write_binary_frame, BINARY_FRAME_LEN};
+use crate::{
+    gray::{GrayFrame, MAX_LEVEL},
+    Frame,
+};
+
+/// The first byte of every message
+pub const MAGIC: u8 = 0xCB;
+/// The version of the protocol written and understood
+pub const VERSION: u8 = 1;
+/// Bytes in a gray frame's payload, a level per voxel
+pub const GRAY_FRAME_LEN: usize = 512;
+/// The longest control message taken
+pub const MAX_CONTROL_LEN: usize = 1024;
+
+const FRAME: u8 = 1;
+const GRAY: u8 = 2;
+const CONTROL: u8 = 3;
+
+/// One message of the stream
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Frame(Frame),
+    Gray(Box<GrayFrame>),
+    /// A setting and its value, as text
+    Control(String),
+}
+
+/// Why a message couldn't be read. All but `Io` and `Magic` leave the stream at the start of
+/// the next message.
+#[derive(Debug)]
+pub enum FramedError {
+    Io(io::Error),
+    /// A byte that doesn't start a message, which was skipped
+    Magic(u8),
+    /// A version this doesn't understand
+    Version(u8),
+    /// A payload type this doesn't understand
+    Type(u8),
+    /// A payload of the wrong length for its type
+    Length {
+        expected: usize,
+        found: usize,
+    },
+    Checksum {
+        expected: u8,
+        found: u8,
+    },
+    /// A control message that isn't UTF-8
+    Text,
+}
+
+impl fmt::Display for FramedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramedError::Io(e) => e.fmt(f),
+            FramedError::Magic(byte) => write!(f, "{byte:#04x} doesn't start a message"),
+            FramedError::Version(version) => {
+                write!(f, "version {version}, expected {VERSION}")
+            }
+            FramedError::Type(kind) => write!(f, "unknown payload type {kind}"),
+            FramedError::Length { expected, found } => {
+                write!(f, "expected a {expected} byte payload, found {found}")
+            }
+            FramedError::Checksum { expected, found } => {
+                write!(f, "checksum {found:#04x}, expected {expected:#04x}")
+            }
+            FramedError::Text => write!(f, "control message not UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for FramedError {}
+
+impl From<io::Error> for FramedError {
+    fn from(e: io::Error) -> Self {
+        FramedError::Io(e)
+    }
+}
+
+/// Read the next message. `None` when the stream ends cleanly before a message starts.
+pub fn read_message(reader: &mut impl Read) -> Result<Option<Message>, FramedError> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header[..1]) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    if header[0] != MAGIC {
+        return Err(FramedError::Magic(header[0]));
+    }
+    reader.read_exact(&mut header[1..])?;
+    let [_, version, kind, high, low] = header;
+
+    let mut payload = vec![0u8; usize::from(u16::from_be_bytes([high, low]))];
+    reader.read_exact(&mut payload)?;
+    let mut found = 0;
+    reader.read_exact(std::slice::from_mut(&mut found))?;
+
+    if version != VERSION {
+        return Err(FramedError::Version(version));
+    }
+    let expected = checksum(&header[1..], &payload);
+    if found != expected {
+        return Err(FramedError::Checksum { expected, found });
+    }
+    let length = |expected: usize| {
+        if payload.len() == expected {
+            Ok(())
+        } else {
+            Err(FramedError::Length {
+                expected,
+                found: payload.len(),
+            })
+        }
+    };
+    let message = match kind {
+        FRAME => {
+            length(BINARY_FRAME_LEN)?;
+            Message::Frame(read_binary_frame(&payload).expect("checked the length"))
+        }
+        GRAY => {
+            length(GRAY_FRAME_LEN)?;
+            Message::Gray(Box::new(core::array::from_fn(|z| {
+                core::array::from_fn(|x| {
+                    core::array::from_fn(|y| payload[z * 64 + x * 8 + y].min(MAX_LEVEL))
+                })
+            })))
+        }
+        CONTROL => {
+            if payload.len() > MAX_CONTROL_LEN {
+                return Err(FramedError::Length {
+                    expected: MAX_CONTROL_LEN,
+                    found: payload.len(),
+                });
+            }
+            Message::Control(String::from_utf8(payload).map_err(|_| FramedError::Text)?)
+        }
+        kind => return Err(FramedError::Type(kind)),
+    };
+    Ok(Some(message))
+}
+
+/// Write a message as `read_message` expects it
+pub fn write_message(out: &mut impl Write, message: &Message) -> io::Result<()> {
+    let (kind, payload) = match message {
+        Message::Frame(frame) => (FRAME, write_binary_frame(frame).to_vec()),
+        Message::Gray(gray) => (GRAY, gray.as_flattened().as_flattened().to_vec()),
+        Message::Control(text) => (CONTROL, text.as_bytes().to_vec()),
+    };
+    let length = u16::try_from(payload.len())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "payload too long"))?;
+    let [high, low] = length.to_be_bytes();
+    let header = [MAGIC, VERSION, kind, high, low];
+    out.write_all(&header)?;
+    out.write_all(&payload)?;
+    out.write_all(&[checksum(&header[1..], &payload)])
+}
+
+fn checksum(header: &[u8], payload: &[u8]) -> u8 {
+    header
+        .iter()
+        .chain(payload)
+        .fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn written(message: &Message) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, message).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut gray: GrayFrame = [[[0; 8]; 8]; 8];
+        gray.as_flattened_mut()
+            .as_flattened_mut()
+            .iter_mut()
+            .for_each(|level| *level = rng.gen_range(0..=MAX_LEVEL));
+        let messages = [
+            Message::Frame(rng.gen()),
+            Message::Gray(Box::new(gray)),
+            Message::Control("speed 2".to_owned()),
+        ];
+
+        let bytes: Vec<u8> = messages.iter().flat_map(written).collect();
+        assert_eq!(&bytes[..5], [MAGIC, VERSION, FRAME, 0, 64]);
+        let mut reader = &bytes[..];
+        for message in messages {
+            assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        }
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn bad_messages_are_skipped_whole() {
+        let frame = Message::Frame([[1; 8]; 8]);
+        let mut bytes = vec![b'x'];
+        let mut corrupt = written(&frame);
+        corrupt[10] ^= 1;
+        bytes.extend(corrupt);
+        let mut future = written(&frame);
+        future[1] = VERSION + 1;
+        bytes.extend(future);
+        bytes.extend([MAGIC, VERSION, 9, 0, 1, 7, 18]);
+        bytes.extend([MAGIC, VERSION, FRAME, 0, 1, 7, 10]);
+        bytes.extend(written(&frame));
+
+        let mut reader = &bytes[..];
+        let mut read = || read_message(&mut reader);
+        assert!(matches!(read(), Err(FramedError::Magic(b'x'))));
+        assert!(matches!(read(), Err(FramedError::Checksum { .. })));
+        assert!(matches!(read(), Err(FramedError::Version(2))));
+        assert!(matches!(read(), Err(FramedError::Type(9))));
+        assert!(matches!(
+            read(),
+            Err(FramedError::Length {
+                expected: 64,
+                found: 1
+            })
+        ));
+        assert_eq!(read().unwrap(), Some(frame));
+    }
+}
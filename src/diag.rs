@@ -0,0 +1,173 @@
+//! A walk through every signal to the cube, for checking the wiring. Each step shows a frame that
+//! needs exactly one signal, or one combination of them, to work, and says which GPIO that is, so
+//! whatever fails to light points at the wire to look at.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    display::{Display, PipelineError},
+    geometry::Coord,
+    input::{Key, Keyboard},
+    pins::PinConfig,
+    voxels::{Axis, Voxels},
+    Frame,
+};
+
+/// How often to look for a key or the stop token while a step is showing
+const POLL: Duration = Duration::from_millis(20);
+
+/// One frame of the walk and what it tests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub frame: Frame,
+    pub description: String,
+}
+
+/// The par pins in order, par_1 carrying Y=0
+fn par_pins(pins: &PinConfig) -> [u8; 8] {
+    [
+        pins.par_1, pins.par_2, pins.par_3, pins.par_4, pins.par_5, pins.par_6, pins.par_7,
+        pins.par_8,
+    ]
+}
+
+/// Which layer select lines are high for `z`, named with their GPIO
+fn layer_select(pins: &PinConfig, z: u8) -> String {
+    let bits = [
+        (2, pins.layer_sel_bit_2),
+        (1, pins.layer_sel_bit_1),
+        (0, pins.layer_sel_bit_0),
+    ];
+    let levels: String = bits
+        .iter()
+        .map(|&(bit, _)| if z & (1 << bit) != 0 { '1' } else { '0' })
+        .collect();
+    let gpio: Vec<String> = bits.iter().map(|(_, pin)| pin.to_string()).collect();
+    format!("layer select {levels} on GPIO {}", gpio.join("/"))
+}
+
+/// Every par pin on its own, then every layer, then every voxel one at a time
+pub fn steps(pins: &PinConfig) -> Vec<Step> {
+    let par = par_pins(pins);
+    let mut steps = Vec::new();
+
+    for (y, gpio) in (0..8).zip(par) {
+        let mut plane = Voxels::new();
+        plane.fill_plane(Axis::Y, y);
+        steps.push(Step {
+            frame: plane.into(),
+            description: format!(
+                "par_{} on GPIO {gpio}, clocked in by par_srclk on GPIO {}: the plane at Y={y} \
+                 should light",
+                y + 1,
+                pins.par_srclk
+            ),
+        });
+    }
+
+    for z in 0..8 {
+        let mut layer = Voxels::new();
+        layer.fill_plane(Axis::Z, z);
+        steps.push(Step {
+            frame: layer.into(),
+            description: format!("{}: layer Z={z} should light", layer_select(pins, z)),
+        });
+    }
+
+    for c in Coord::all() {
+        let mut voxel = Voxels::new();
+        voxel.set(c.x, c.y, c.z);
+        steps.push(Step {
+            frame: voxel.into(),
+            description: format!(
+                "voxel ({}, {}, {}): par_{} on GPIO {}, shifted {} of 8, {}",
+                c.x,
+                c.y,
+                c.z,
+                c.y + 1,
+                par[usize::from(c.y)],
+                c.x + 1,
+                layer_select(pins, c.z)
+            ),
+        });
+    }
+    steps
+}
+
+/// Show each step on `display` for `step` or, with `pause`, until a key is pressed, printing
+/// what it tests. `q` ends the walk early, as does the stop token.
+pub fn run(
+    stop_token: Arc<AtomicBool>,
+    display: Display<Frame>,
+    pins: &PinConfig,
+    pause: bool,
+    step: Duration,
+) -> Result<(), PipelineError> {
+    let keyboard = Keyboard::open().map_err(PipelineError::Io)?;
+    if pause {
+        println!("Press any key for the next step, q to quit");
+    }
+
+    let steps = steps(pins);
+    'walk: for (number, Step { frame, description }) in (1..).zip(&steps) {
+        println!("[{number}/{}] {description}", steps.len());
+        if !display.send(*frame) {
+            break;
+        }
+
+        let until = Instant::now() + step;
+        loop {
+            if stop_token.load(Ordering::Relaxed) {
+                break 'walk;
+            }
+            let mut pressed = false;
+            for key in keyboard.pressed() {
+                if key == Key::Char('q') {
+                    break 'walk;
+                }
+                pressed = true;
+            }
+            let next = if pause {
+                pressed
+            } else {
+                Instant::now() >= until
+            };
+            if next {
+                break;
+            }
+            thread::sleep(POLL);
+        }
+    }
+    display.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_signal_gets_a_step() {
+        let pins = PinConfig::default();
+        let steps = steps(&pins);
+        assert_eq!(steps.len(), 8 + 8 + 512);
+
+        // par_1 carries Y=0 into every row of every layer
+        assert_eq!(steps[0].frame, [[1; 8]; 8]);
+        assert!(steps[0].description.starts_with("par_1 on GPIO 12,"));
+        assert_eq!(Voxels(steps[8 + 5].frame).count(), 64);
+        assert!(steps[8 + 5].description.starts_with("layer select 101 "));
+
+        let walk = &steps[16..];
+        assert!(walk.iter().all(|step| Voxels(step.frame).count() == 1));
+        assert!(walk[1]
+            .description
+            .starts_with("voxel (0, 1, 0): par_2 on GPIO 5,"));
+    }
+}
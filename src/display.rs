@@ -0,0 +1,1020 @@
+use std::{
+    any::Any,
+    fmt, io,
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    color::{self, ColorFrame},
+    cube::{CubeDriver, DriverConfig, OutputLine, RowShifter, MAX_BRIGHTNESS},
+    gray::{self, GrayFrame},
+    pacer::Pacer,
+    Frame,
+};
+
+/// Something the display thread can refresh frames onto
+pub trait FrameSink: Send {
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()>;
+
+    /// Sinks without intensity show every voxel that is lit at all
+    fn write_gray_frame(&mut self, gray: &GrayFrame) -> io::Result<()> {
+        self.write_frame(gray::threshold(gray, 1))
+    }
+
+    /// Sinks without colour show every voxel that is lit in any channel
+    fn write_color_frame(&mut self, color: &ColorFrame) -> io::Result<()> {
+        self.write_frame(color::on_off(color))
+    }
+
+    /// Change the global brightness, up to `MAX_BRIGHTNESS`. Sinks that can't dim ignore it.
+    fn set_brightness(&mut self, _level: u8) {}
+
+    /// How long each write should take, for sinks that multiplex and so look steadiest
+    /// refreshed at a fixed rate. The display thread rests them for whatever is left of each
+    /// period. Sinks that pace themselves have none.
+    fn refresh_period(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether the next write starts showing a frame afresh. Sinks that spread a frame over
+    /// several writes say not until they're through them, and only then does the display thread
+    /// swap in a newer frame, so two frames never mix.
+    fn at_frame_start(&self) -> bool {
+        true
+    }
+
+    /// Turn the output off until the next write, before the display thread waits out a period
+    fn rest(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<P: OutputLine, S: RowShifter> FrameSink for CubeDriver<P, S> {
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        CubeDriver::write_frame(self, frame)
+    }
+
+    fn write_gray_frame(&mut self, gray: &GrayFrame) -> io::Result<()> {
+        CubeDriver::write_gray_frame(self, gray)
+    }
+
+    fn write_color_frame(&mut self, color: &ColorFrame) -> io::Result<()> {
+        CubeDriver::write_color_frame(self, color)
+    }
+
+    fn set_brightness(&mut self, level: u8) {
+        CubeDriver::set_brightness(self, level)
+    }
+
+    fn refresh_period(&self) -> Option<Duration> {
+        Some(CubeDriver::refresh_period(self))
+    }
+
+    fn at_frame_start(&self) -> bool {
+        CubeDriver::at_frame_start(self)
+    }
+
+    fn rest(&mut self) -> io::Result<()> {
+        CubeDriver::rest(self)
+    }
+}
+
+/// How long sinks that don't drive hardware take per write, so the display thread refreshing as
+/// fast as its sink allows doesn't spin
+pub const SOFT_REFRESH: Duration = Duration::from_millis(10);
+
+/// Shows nothing, for running routines where there's no cube
+#[derive(Default)]
+pub struct NullSink;
+
+impl FrameSink for NullSink {
+    fn write_frame(&mut self, _: Frame) -> io::Result<()> {
+        thread::sleep(SOFT_REFRESH);
+        Ok(())
+    }
+}
+
+/// Keeps every distinct frame written, in order, for tests to check what a display showed
+#[derive(Clone, Default)]
+pub struct RecordingSink {
+    frames: Arc<Mutex<Vec<Frame>>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frames written so far, repeated refreshes of the same frame counting once
+    pub fn frames(&self) -> Vec<Frame> {
+        self.frames.lock().expect("recording poisoned").clone()
+    }
+}
+
+impl FrameSink for RecordingSink {
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        let mut frames = self.frames.lock().expect("recording poisoned");
+        if frames.last() != Some(&frame) {
+            frames.push(frame);
+        }
+        drop(frames);
+        thread::sleep(Duration::from_millis(1));
+        Ok(())
+    }
+}
+
+/// What the display thread can keep refreshed, on/off frames, frames with intensity or frames
+/// in colour
+pub trait Refreshable: Copy + Send + 'static {
+    const BLANK: Self;
+
+    fn write_to(&self, sink: &mut impl FrameSink) -> io::Result<()>;
+
+    /// Every voxel that is lit at all, which is what gets published as shown
+    fn on_off(&self) -> Frame;
+
+    /// A frame of the same kind lighting the voxels `frame` does
+    fn from_on_off(frame: Frame) -> Self;
+}
+
+impl Refreshable for Frame {
+    const BLANK: Self = [[0; 8]; 8];
+
+    fn write_to(&self, sink: &mut impl FrameSink) -> io::Result<()> {
+        sink.write_frame(*self)
+    }
+
+    fn on_off(&self) -> Frame {
+        *self
+    }
+
+    fn from_on_off(frame: Frame) -> Self {
+        frame
+    }
+}
+
+impl Refreshable for GrayFrame {
+    const BLANK: Self = [[[0; 8]; 8]; 8];
+
+    fn write_to(&self, sink: &mut impl FrameSink) -> io::Result<()> {
+        sink.write_gray_frame(self)
+    }
+
+    fn on_off(&self) -> Frame {
+        gray::threshold(self, 1)
+    }
+
+    fn from_on_off(frame: Frame) -> Self {
+        gray::from_frame(&frame)
+    }
+}
+
+impl Refreshable for ColorFrame {
+    const BLANK: Self = [[[0; 8]; 8]; 3];
+
+    fn write_to(&self, sink: &mut impl FrameSink) -> io::Result<()> {
+        sink.write_color_frame(self)
+    }
+
+    fn on_off(&self) -> Frame {
+        color::on_off(self)
+    }
+
+    /// Lit voxels in white
+    fn from_on_off(frame: Frame) -> Self {
+        color::from_frame(&frame, &color::Palette::default())
+    }
+}
+
+/// Ways the frame pipeline can end other than a clean stop, each with its own exit status so
+/// that service managers can tell them apart
+#[derive(Debug)]
+pub enum PipelineError {
+    /// The GPIO peripheral could not be claimed or configured
+    GpioInit(rppal::gpio::Error),
+    /// The SPI bus the rows go out over could not be opened
+    SpiInit(rppal::spi::Error),
+    /// Writing a frame out to the display failed part way through a run
+    GpioWrite(io::Error),
+    /// The frame source ended without producing a single frame
+    SourceEnded,
+    /// The display thread panicked, carrying the panic message when there was one
+    Panicked(String),
+    /// A socket or file the program depends on failed
+    Io(io::Error),
+}
+
+impl PipelineError {
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            PipelineError::GpioInit(_) | PipelineError::SpiInit(_) => ExitCode::from(3),
+            PipelineError::Panicked(_) => ExitCode::from(4),
+            PipelineError::GpioWrite(_) => ExitCode::from(5),
+            PipelineError::SourceEnded => ExitCode::from(6),
+            PipelineError::Io(_) => ExitCode::FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::GpioInit(e) => write!(f, "GPIO initialization failed: {e}"),
+            PipelineError::SpiInit(e) => write!(f, "SPI initialization failed: {e}"),
+            PipelineError::GpioWrite(e) => write!(f, "writing to the display failed: {e}"),
+            PipelineError::SourceEnded => write!(f, "the frame source produced no frames"),
+            PipelineError::Panicked(msg) => write!(f, "display thread panicked: {msg}"),
+            PipelineError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Paces the display thread to its sink's refresh period, timing each write and resting the
+/// sink for whatever it leaves of the period, if anything
+struct RefreshClock(Option<Pacer>);
+
+impl RefreshClock {
+    fn new(period: Option<Duration>) -> Self {
+        // The stats meter warns about refreshes falling short
+        RefreshClock(period.map(Pacer::quiet))
+    }
+
+    /// Wait out the rest of the period after a write. Overrunning it starts the next one from
+    /// now rather than trying to catch up.
+    fn wait(&mut self, sink: &mut impl FrameSink) -> io::Result<()> {
+        let Some(pacer) = &mut self.0 else {
+            return Ok(());
+        };
+        let remaining = pacer.remaining(Instant::now());
+        if !remaining.is_zero() {
+            sink.rest()?;
+            thread::sleep(remaining);
+        }
+        Ok(())
+    }
+}
+
+/// Writes that can fail in a row before the display thread gives up. A failure on its own, such
+/// as PWM hiccupping, is counted and the next refresh tries again.
+const MAX_WRITE_ERRORS: u32 = 3;
+
+/// How long the display thread measures itself over before publishing its [`RefreshStats`]
+pub const STATS_WINDOW: Duration = Duration::from_secs(1);
+
+/// What the display thread has been doing, as of its last complete [`STATS_WINDOW`]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RefreshStats {
+    /// Sweeps of the whole cube a second
+    pub refresh_rate: f64,
+    /// Frames a second taken from the producer, shown or not
+    pub frame_rate: f64,
+    /// Frames replaced by a newer one before they could be shown, since the display started
+    pub dropped: u64,
+    /// Frames shown, since the display started
+    pub shown: u64,
+    /// The most frames found waiting at once
+    pub queue_depth: usize,
+    /// The longest one refresh took
+    pub worst_refresh: Duration,
+    /// Writes that failed, since the display started
+    pub errors: u64,
+}
+
+impl RefreshStats {
+    /// The stats as a JSON object
+    pub fn json(&self) -> String {
+        format!(
+            "{{\"refresh_rate\":{:.1},\"frame_rate\":{:.1},\"dropped\":{},\"shown\":{},\
+             \"queue_depth\":{},\"worst_refresh_ms\":{:.2},\"errors\":{}}}",
+            self.refresh_rate,
+            self.frame_rate,
+            self.dropped,
+            self.shown,
+            self.queue_depth,
+            self.worst_refresh.as_secs_f64() * 1000.0,
+            self.errors
+        )
+    }
+}
+
+impl fmt::Display for RefreshStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "refreshing at {:.1} Hz, taking {:.1} frames/s, {} dropped, slowest refresh {:.2} ms",
+            self.refresh_rate,
+            self.frame_rate,
+            self.dropped,
+            self.worst_refresh.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// The display thread's latest stats, shared with whoever reports them
+pub type StatsHandle = Arc<Mutex<RefreshStats>>;
+
+/// Counts what the display thread does over each [`STATS_WINDOW`] and publishes it, saying so
+/// once if refreshes fall short of the sink's period
+struct Meter {
+    stats: StatsHandle,
+    period: Option<Duration>,
+    since: Instant,
+    refreshes: u32,
+    frames: u32,
+    dropped: u64,
+    shown: u64,
+    /// Frames found waiting by the current drain, and the most by any this window
+    waiting: usize,
+    deepest: usize,
+    worst: Duration,
+    errors: u64,
+    warned: bool,
+}
+
+impl Meter {
+    fn new(stats: StatsHandle, period: Option<Duration>) -> Self {
+        Meter {
+            stats,
+            period,
+            since: Instant::now(),
+            refreshes: 0,
+            frames: 0,
+            dropped: 0,
+            shown: 0,
+            waiting: 0,
+            deepest: 0,
+            worst: Duration::ZERO,
+            errors: 0,
+            warned: false,
+        }
+    }
+
+    /// A frame arrived, replacing one that never got shown if `dropping`
+    fn received(&mut self, dropping: bool) {
+        self.frames += 1;
+        self.dropped += u64::from(dropping);
+        self.waiting += 1;
+    }
+
+    /// Every frame waiting has been taken
+    fn drained(&mut self) {
+        self.deepest = self.deepest.max(self.waiting);
+        self.waiting = 0;
+    }
+
+    fn showing(&mut self) {
+        self.shown += 1;
+    }
+
+    fn failed(&mut self) {
+        self.errors += 1;
+    }
+
+    fn refreshed(&mut self, took: Duration) {
+        self.refreshes += 1;
+        self.worst = self.worst.max(took);
+
+        let measured = self.since.elapsed();
+        if measured < STATS_WINDOW {
+            return;
+        }
+        let stats = RefreshStats {
+            refresh_rate: f64::from(self.refreshes) / measured.as_secs_f64(),
+            frame_rate: f64::from(self.frames) / measured.as_secs_f64(),
+            dropped: self.dropped,
+            shown: self.shown,
+            queue_depth: self.deepest,
+            worst_refresh: self.worst,
+            errors: self.errors,
+        };
+        *self.stats.lock().expect("stats poisoned") = stats;
+
+        if let Some(period) = self.period.filter(|_| !self.warned) {
+            let target = 1.0 / period.as_secs_f64();
+            // A little short is only sleeps overrunning
+            if stats.refresh_rate < 0.9 * target {
+                eprintln!(
+                    "Refreshing at {:.0} Hz, short of the {target:.0} Hz asked for",
+                    stats.refresh_rate
+                );
+                self.warned = true;
+            }
+        }
+        self.since = Instant::now();
+        self.refreshes = 0;
+        self.frames = 0;
+        self.deepest = 0;
+        self.worst = Duration::ZERO;
+    }
+}
+
+/// Raises the failure flag if the display thread unwinds, so the producer notices a panic
+/// without having to wait for a send to bounce
+struct RaiseOnUnwind(Arc<AtomicBool>);
+
+impl Drop for RaiseOnUnwind {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// The producer's end of a running display thread. Dropping it without `finish`, say on an
+/// early return or while a panic unwinds, still waits for the thread to blank the sink, so the
+/// cube is never left with one layer lit at full duty.
+pub struct Display<T> {
+    /// Taken to tell the display thread to stop
+    sender: Option<SyncSender<T>>,
+    failed: Arc<AtomicBool>,
+    /// A brightness change the display thread has yet to make
+    brightness: Arc<Mutex<Option<u8>>>,
+    stats: StatsHandle,
+    /// Taken once the display thread has been waited for
+    handle: Option<JoinHandle<Result<(), PipelineError>>>,
+}
+
+impl<T: Send + 'static> Display<T> {
+    fn spawn<F>(capacity: usize, body: F) -> Self
+    where
+        F: FnOnce(Receiver<T>, Arc<Mutex<Option<u8>>>, StatsHandle) -> Result<(), PipelineError>
+            + Send
+            + 'static,
+    {
+        let (sender, receiver) = sync_channel(capacity);
+        let failed = Arc::new(AtomicBool::new(false));
+        let flag = failed.clone();
+        let brightness = Arc::new(Mutex::new(None));
+        let pending = brightness.clone();
+        let stats = StatsHandle::default();
+        let measured = stats.clone();
+
+        let handle = thread::spawn(move || {
+            let _guard = RaiseOnUnwind(flag.clone());
+            let result = body(receiver, pending, measured);
+            if result.is_err() {
+                flag.store(true, Ordering::Release);
+            }
+            result
+        });
+
+        Display {
+            sender: Some(sender),
+            failed,
+            brightness,
+            stats,
+            handle: Some(handle),
+        }
+    }
+
+    /// Whether the display thread has given up, checked before every send so a dead display is
+    /// noticed within one frame period
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Acquire)
+    }
+
+    /// False once the display thread is no longer taking frames
+    pub fn send(&self, item: T) -> bool {
+        !self.failed()
+            && self
+                .sender
+                .as_ref()
+                .is_some_and(|sender| sender.send(item).is_ok())
+    }
+
+    /// Dim or brighten the cube from the next refresh on, up to `MAX_BRIGHTNESS`
+    pub fn set_brightness(&self, level: u8) {
+        *self.brightness.lock().expect("brightness poisoned") = Some(level.min(MAX_BRIGHTNESS));
+    }
+
+    /// How the display thread has been keeping up, for reporting as it goes
+    pub fn stats_handle(&self) -> StatsHandle {
+        self.stats.clone()
+    }
+
+    /// Let the display thread blank the sink and wait for it to exit
+    pub fn finish(mut self) -> Result<(), PipelineError> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), PipelineError> {
+        self.sender = None;
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|payload| Err(PipelineError::Panicked(panic_message(payload)))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T> Drop for Display<T> {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(handle) = self.handle.take() {
+            match handle.join() {
+                Ok(Err(e)) => eprintln!("{e}"),
+                Err(payload) => eprintln!("display thread panicked: {}", panic_message(payload)),
+                Ok(Ok(())) => {}
+            }
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(msg) => (*msg).to_owned(),
+            Err(_) => "no message".to_owned(),
+        },
+    }
+}
+
+/// Open the cube on its own thread and keep refreshing whichever frame was sent last, publishing
+/// each new frame into `shown` once it has been written. Takes on/off frames or frames with an
+/// intensity per voxel.
+///
+/// Refreshing goes on at the sink's own rate however fast frames arrive. The frame being shown
+/// and the newest one sent make a double buffer, swapped only when the sink is about to start a
+/// frame afresh, so a frame arriving mid-refresh never shows half of itself over the last.
+pub fn spawn_display<T: Refreshable>(
+    driver: DriverConfig,
+    shown: Option<Arc<Mutex<Frame>>>,
+) -> Display<T> {
+    spawn_refresh_on(
+        move || CubeDriver::try_new(&driver).map_err(PipelineError::GpioInit),
+        shown,
+    )
+}
+
+/// `spawn_display` onto any sink, opened on the display thread itself
+pub fn spawn_display_on<S, O>(open: O, shown: Option<Arc<Mutex<Frame>>>) -> Display<Frame>
+where
+    S: FrameSink,
+    O: FnOnce() -> Result<S, PipelineError> + Send + 'static,
+{
+    spawn_refresh_on(open, shown)
+}
+
+/// `spawn_display_on` for either kind of frame
+pub fn spawn_refresh_on<T, S, O>(open: O, shown: Option<Arc<Mutex<Frame>>>) -> Display<T>
+where
+    T: Refreshable,
+    S: FrameSink,
+    O: FnOnce() -> Result<S, PipelineError> + Send + 'static,
+{
+    Display::spawn(64, move |rx, brightness, stats| {
+        let mut sink = open()?;
+
+        let mut clock = RefreshClock::new(sink.refresh_period());
+        let mut meter = Meter::new(stats, sink.refresh_period());
+        let mut failures = 0;
+        let mut front = T::BLANK;
+        let mut back = None;
+
+        'refresh: loop {
+            if let Some(level) = brightness.lock().expect("brightness poisoned").take() {
+                sink.set_brightness(level);
+            }
+
+            // Latest wins, so a fast producer never builds up a backlog of stale frames
+            loop {
+                match rx.try_recv() {
+                    Ok(frame) => {
+                        meter.received(back.is_some());
+                        back = Some(frame);
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => break 'refresh,
+                }
+            }
+            meter.drained();
+
+            let mut fresh = false;
+            if sink.at_frame_start() {
+                if let Some(frame) = back.take() {
+                    front = frame;
+                    fresh = true;
+                    meter.showing();
+                }
+            }
+
+            let started = Instant::now();
+            match front.write_to(&mut sink) {
+                Ok(()) => failures = 0,
+                Err(e) => {
+                    meter.failed();
+                    failures += 1;
+                    if failures == MAX_WRITE_ERRORS {
+                        return Err(PipelineError::GpioWrite(e));
+                    }
+                }
+            }
+            meter.refreshed(started.elapsed());
+
+            if let Some(shown) = shown.as_ref().filter(|_| fresh) {
+                *shown.lock().expect("control state poisoned") = front.on_off();
+            }
+            clock.wait(&mut sink).map_err(PipelineError::GpioWrite)?;
+        }
+
+        // Leave the registers empty, Drop then disables output and settles the pins. Worth
+        // another try or two like any other write.
+        let mut blanked = sink.write_frame([[0; 8]; 8]);
+        for _ in 1..MAX_WRITE_ERRORS {
+            if blanked.is_ok() {
+                break;
+            }
+            blanked = sink.write_frame([[0; 8]; 8]);
+        }
+        blanked.map_err(PipelineError::GpioWrite)
+    })
+}
+
+/// A frame for the direct display and, optionally, who to tell once it has been written
+pub type DirectFrame = (Frame, Option<SyncSender<()>>);
+
+/// Like `spawn_display` but without the queue: each send blocks until the display thread takes
+/// the frame, which it writes immediately instead of waiting for a rate-limited producer
+pub fn spawn_direct_display(driver: DriverConfig) -> Display<DirectFrame> {
+    Display::spawn(0, move |rx: Receiver<DirectFrame>, _, _| {
+        let mut driver = CubeDriver::try_new(&driver).map_err(PipelineError::GpioInit)?;
+
+        let mut curr_frame = [[0; 8]; 8];
+
+        loop {
+            match rx.try_recv() {
+                Ok((frame, written)) => {
+                    curr_frame = frame;
+                    driver
+                        .write_frame(curr_frame)
+                        .map_err(PipelineError::GpioWrite)?;
+                    if let Some(written) = written {
+                        let _ = written.send(());
+                    }
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => break,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            driver
+                .write_frame(curr_frame)
+                .map_err(PipelineError::GpioWrite)?;
+        }
+
+        driver
+            .write_frame([[0; 8]; 8])
+            .map_err(PipelineError::GpioWrite)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// Fails every write after the first `writes`
+    struct FailAfter(usize);
+
+    impl FrameSink for FailAfter {
+        fn write_frame(&mut self, _: Frame) -> io::Result<()> {
+            match self.0.checked_sub(1) {
+                Some(left) => {
+                    self.0 = left;
+                    Ok(())
+                }
+                None => Err(io::Error::other("injected fault")),
+            }
+        }
+    }
+
+    struct PanicOnWrite;
+
+    impl FrameSink for PanicOnWrite {
+        fn write_frame(&mut self, _: Frame) -> io::Result<()> {
+            panic!("injected panic")
+        }
+    }
+
+    fn wait_for_failure<T: Send + 'static>(display: &Display<T>) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !display.failed() {
+            assert!(Instant::now() < deadline, "failure never flagged");
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn write_failure_is_flagged_and_reported() {
+        let display = spawn_display_on(|| Ok(FailAfter(10)), None);
+        wait_for_failure(&display);
+        assert!(!display.send([[0; 8]; 8]));
+        assert!(matches!(display.finish(), Err(PipelineError::GpioWrite(_))));
+    }
+
+    /// Fails every other write
+    struct Flaky(bool);
+
+    impl FrameSink for Flaky {
+        fn write_frame(&mut self, _: Frame) -> io::Result<()> {
+            self.0 = !self.0;
+            thread::sleep(Duration::from_millis(1));
+            match self.0 {
+                true => Err(io::Error::other("injected fault")),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn lone_write_failures_are_counted_not_fatal() {
+        let display = spawn_display_on(|| Ok(Flaky(false)), None);
+        let stats = display.stats_handle();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stats.lock().unwrap().errors == 0 {
+            assert!(Instant::now() < deadline, "errors never counted");
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!display.failed());
+        assert!(display.finish().is_ok());
+    }
+
+    #[test]
+    fn panic_is_flagged_and_reported() {
+        let display = spawn_display_on(|| Ok(PanicOnWrite), None);
+        wait_for_failure(&display);
+        match display.finish() {
+            Err(PipelineError::Panicked(msg)) => assert_eq!(msg, "injected panic"),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn open_failure_is_reported() {
+        let display = spawn_display_on(
+            || Err::<FailAfter, _>(PipelineError::Io(io::Error::other("no device"))),
+            None,
+        );
+        wait_for_failure(&display);
+        assert!(matches!(display.finish(), Err(PipelineError::Io(_))));
+    }
+
+    #[test]
+    fn written_frames_are_published() {
+        let shown = Arc::new(Mutex::new([[0; 8]; 8]));
+        let display = spawn_display_on(|| Ok(FailAfter(usize::MAX)), Some(shown.clone()));
+        assert!(display.send([[7; 8]; 8]));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *shown.lock().unwrap() != [[7; 8]; 8] {
+            assert!(Instant::now() < deadline, "frame never published");
+            thread::yield_now();
+        }
+        assert!(!display.failed());
+        assert!(display.finish().is_ok());
+    }
+
+    fn wait_for_written(recording: &RecordingSink, frame: Frame) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while recording.frames().last() != Some(&frame) {
+            assert!(Instant::now() < deadline, "frame never written");
+            thread::yield_now();
+        }
+    }
+
+    /// What was written after the blank frame the display starts with, if it got to write it
+    fn after_start(recording: &RecordingSink) -> Vec<Frame> {
+        let mut frames = recording.frames();
+        if frames.first() == Some(&[[0; 8]; 8]) {
+            frames.remove(0);
+        }
+        frames
+    }
+
+    #[test]
+    fn frames_are_recorded_in_order_then_blanked() {
+        let recording = RecordingSink::new();
+        let sink = recording.clone();
+        let display = spawn_display_on(move || Ok(sink), None);
+
+        for byte in 1..=3 {
+            assert!(display.send([[byte; 8]; 8]));
+            wait_for_written(&recording, [[byte; 8]; 8]);
+        }
+        assert!(display.finish().is_ok());
+        assert_eq!(
+            after_start(&recording),
+            [1, 2, 3, 0].map(|byte| [[byte; 8]; 8])
+        );
+    }
+
+    #[test]
+    fn dropping_blanks_even_while_unwinding() {
+        let recording = RecordingSink::new();
+        let sink = recording.clone();
+        let watched = recording.clone();
+        let producer = thread::spawn(move || {
+            let display = spawn_display_on(move || Ok(sink), None);
+            assert!(display.send([[4; 8]; 8]));
+            wait_for_written(&watched, [[4; 8]; 8]);
+            panic!("producer gave up");
+        });
+        assert!(producer.join().is_err());
+        assert_eq!(after_start(&recording), [[[4; 8]; 8], [[0; 8]; 8]]);
+    }
+
+    /// Remembers the brightness it was last set to
+    struct Dimmable(Arc<Mutex<Option<u8>>>);
+
+    impl FrameSink for Dimmable {
+        fn write_frame(&mut self, _: Frame) -> io::Result<()> {
+            thread::sleep(Duration::from_millis(1));
+            Ok(())
+        }
+
+        fn set_brightness(&mut self, level: u8) {
+            *self.0.lock().unwrap() = Some(level);
+        }
+    }
+
+    #[test]
+    fn brightness_reaches_the_sink() {
+        let level = Arc::new(Mutex::new(None));
+        let sink = Dimmable(level.clone());
+        let display = spawn_display_on(move || Ok(sink), None);
+
+        display.set_brightness(99);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *level.lock().unwrap() != Some(MAX_BRIGHTNESS) {
+            assert!(Instant::now() < deadline, "brightness never set");
+            thread::yield_now();
+        }
+        assert!(display.finish().is_ok());
+    }
+
+    /// Takes four writes to show a frame, recording which frame each write showed
+    struct Passes {
+        pass: u8,
+        writes: Arc<Mutex<Vec<(u8, u8)>>>,
+    }
+
+    impl FrameSink for Passes {
+        fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+            self.writes.lock().unwrap().push((frame[0][0], self.pass));
+            self.pass = (self.pass + 1) % 4;
+            Ok(())
+        }
+
+        fn at_frame_start(&self) -> bool {
+            self.pass == 0
+        }
+    }
+
+    #[test]
+    fn frames_only_swap_between_passes() {
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let sink = Passes {
+            pass: 0,
+            writes: writes.clone(),
+        };
+        let display = spawn_display_on(move || Ok(sink), None);
+        for byte in 1..=50 {
+            assert!(display.send([[byte; 8]; 8]));
+            thread::yield_now();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !writes.lock().unwrap().iter().any(|&(byte, _)| byte == 50) {
+            assert!(Instant::now() < deadline, "last frame never written");
+            thread::yield_now();
+        }
+        assert!(display.finish().is_ok());
+
+        let writes = writes.lock().unwrap();
+        // The blank frame at the end is written however far through the passes the sink is
+        for pair in writes[..writes.len() - 1].windows(2) {
+            let [(before, _), (after, pass)] = pair else {
+                unreachable!()
+            };
+            assert!(pass == &0 || before == after, "{before} mixed with {after}");
+        }
+    }
+
+    /// Multiplexes, so wants a write every `period`, takes `write_time` over each write, and
+    /// counts writes and rests
+    struct Paced {
+        period: Duration,
+        write_time: Duration,
+        counts: Arc<Mutex<(u32, u32)>>,
+    }
+
+    impl FrameSink for Paced {
+        fn write_frame(&mut self, _: Frame) -> io::Result<()> {
+            thread::sleep(self.write_time);
+            self.counts.lock().unwrap().0 += 1;
+            Ok(())
+        }
+
+        fn refresh_period(&self) -> Option<Duration> {
+            Some(self.period)
+        }
+
+        fn rest(&mut self) -> io::Result<()> {
+            self.counts.lock().unwrap().1 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn refreshes_keep_to_the_sinks_period() {
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let sink = Paced {
+            period: Duration::from_millis(20),
+            write_time: Duration::ZERO,
+            counts: counts.clone(),
+        };
+        let display = spawn_display_on(move || Ok(sink), None);
+        assert!(display.send([[1; 8]; 8]));
+        thread::sleep(Duration::from_millis(200));
+        assert!(display.finish().is_ok());
+
+        let (writes, rests) = *counts.lock().unwrap();
+        // Ten periods, give or take the start and the blank frame at the end
+        assert!((3..=13).contains(&writes), "{writes} writes");
+        assert!(rests >= writes - 2, "only rested {rests} times");
+    }
+
+    #[test]
+    fn slow_sinks_are_refreshed_back_to_back() {
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let sink = Paced {
+            period: Duration::from_millis(5),
+            write_time: Duration::from_millis(15),
+            counts: counts.clone(),
+        };
+        let display = spawn_display_on(move || Ok(sink), None);
+        assert!(display.send([[1; 8]; 8]));
+        thread::sleep(Duration::from_millis(300));
+        assert!(display.finish().is_ok());
+
+        let (writes, rests) = *counts.lock().unwrap();
+        // Twenty writes' worth, none of it spent resting on top of the overrun
+        assert_eq!(rests, 0);
+        assert!((14..=22).contains(&writes), "{writes} writes");
+    }
+
+    #[test]
+    fn stats_measure_refreshes_and_frames() {
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let sink = Paced {
+            period: Duration::from_millis(10),
+            write_time: Duration::ZERO,
+            counts,
+        };
+        let display = spawn_display_on(move || Ok(sink), None);
+        let stats = display.stats_handle();
+
+        // Frames in bursts faster than the refreshes can take them
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stats.lock().unwrap().refresh_rate == 0.0 {
+            assert!(Instant::now() < deadline, "stats never published");
+            for byte in 0..4 {
+                assert!(display.send([[byte; 8]; 8]));
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(display.finish().is_ok());
+
+        let stats = *stats.lock().unwrap();
+        assert!((50.0..=110.0).contains(&stats.refresh_rate), "{stats}");
+        assert!(stats.frame_rate > stats.refresh_rate, "{stats}");
+        assert!(stats.dropped > 0, "{stats}");
+        assert!(stats.json().starts_with("{\"refresh_rate\":"));
+    }
+
+    #[test]
+    fn gray_frames_fall_back_to_on_off() {
+        let recording = RecordingSink::new();
+        let sink = recording.clone();
+        let display = spawn_refresh_on(move || Ok(sink), None);
+
+        let mut gray = [[[0; 8]; 8]; 8];
+        gray[1][2][3] = 1;
+        let mut lit = [[0; 8]; 8];
+        lit[1][2] = 1 << 3;
+
+        assert!(display.send(gray));
+        wait_for_written(&recording, lit);
+        assert!(display.finish().is_ok());
+        assert_eq!(after_start(&recording), [lit, [[0; 8]; 8]]);
+    }
+}
@@ -0,0 +1,326 @@
+//! DMX512 received over the network from lighting software, one channel per voxel with its
+//! value as the voxel's brightness. Each protocol only has to find the DMX data in a datagram,
+//! see [`DmxProtocol`], and a [`DmxMap`] says which channels of which universes make up each
+//! layer.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{SocketAddr, UdpSocket},
+    path::Path,
+    sync::mpsc::{sync_channel, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::gray::{GrayFrame, MAX_LEVEL};
+
+/// Channels in a universe
+pub const UNIVERSE_SIZE: usize = 512;
+/// Channels one layer takes, one per voxel with rows of X in order and Y within each row
+pub const LAYER_CHANNELS: usize = 64;
+
+/// Where one layer's 64 channels are found
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Patch {
+    pub universe: u16,
+    /// The first of the layer's channels, counting from 1 as lighting consoles do
+    pub channel: u16,
+}
+
+/// Which channels of which universes light each layer, from the bottom
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmxMap {
+    layers: [Patch; 8],
+}
+
+impl DmxMap {
+    /// The whole cube in one universe, which 512 voxels fill exactly, layer after layer from the
+    /// bottom
+    pub fn packed(universe: u16) -> Self {
+        DmxMap {
+            layers: core::array::from_fn(|z| Patch {
+                universe,
+                channel: (z * LAYER_CHANNELS) as u16 + 1,
+            }),
+        }
+    }
+
+    /// A universe per layer, numbered upwards from `first` for the bottom one, each starting at
+    /// channel 1
+    pub fn per_layer(first: u16) -> Self {
+        DmxMap {
+            layers: core::array::from_fn(|z| Patch {
+                universe: first.saturating_add(z as u16),
+                channel: 1,
+            }),
+        }
+    }
+
+    /// Read a map file over `base`, which keeps every layer the file doesn't mention. Each line
+    /// is `layer = universe`, or `layer = universe/channel` for a layer that doesn't start at
+    /// channel 1, with layers numbered from 0 at the bottom and `#` starting a comment:
+    ///
+    /// ```text
+    /// # two layers to a universe
+    /// 0 = 1
+    /// 1 = 1/65
+    /// ```
+    pub fn parse(text: &str, base: DmxMap) -> io::Result<Self> {
+        let mut map = base;
+        for (line, content) in (1..).zip(text.lines()) {
+            let content = content.split('#').next().unwrap_or_default().trim();
+            if content.is_empty() {
+                continue;
+            }
+            let syntax = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {line}: {message}"),
+                )
+            };
+            let Some((layer, patch)) = content.split_once('=') else {
+                return Err(syntax(format!(
+                    "expected layer = universe, found {content:?}"
+                )));
+            };
+            let layer: usize = match layer.trim().parse() {
+                Ok(layer) if layer < 8 => layer,
+                _ => {
+                    return Err(syntax(format!(
+                        "{:?} isn't a layer from 0 to 7",
+                        layer.trim()
+                    )))
+                }
+            };
+            let (universe, channel) = match patch.split_once('/') {
+                Some((universe, channel)) => (universe, channel.trim().parse().ok()),
+                None => (patch, Some(1)),
+            };
+            let Ok(universe) = universe.trim().parse() else {
+                return Err(syntax(format!("{:?} isn't a universe", universe.trim())));
+            };
+            let last = (UNIVERSE_SIZE - LAYER_CHANNELS + 1) as u16;
+            let channel = match channel {
+                Some(channel) if (1..=last).contains(&channel) => channel,
+                _ => {
+                    return Err(syntax(format!(
+                        "layer {layer} needs a first channel from 1 to {last}"
+                    )))
+                }
+            };
+            map.layers[layer] = Patch { universe, channel };
+        }
+        Ok(map)
+    }
+
+    pub fn load(path: &Path, base: DmxMap) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, base)
+    }
+
+    /// Every universe the cube listens to, each once
+    pub fn universes(&self) -> Vec<u16> {
+        let mut universes: Vec<u16> = self.layers.iter().map(|p| p.universe).collect();
+        universes.sort_unstable();
+        universes.dedup();
+        universes
+    }
+
+    /// Set the layers patched to `universe` from its channel values. Channels a short packet
+    /// leaves out are off, as in DMX itself.
+    pub fn apply(&self, universe: u16, channels: &[u8], gray: &mut GrayFrame) {
+        for (layer, patch) in gray.iter_mut().zip(&self.layers) {
+            if patch.universe != universe {
+                continue;
+            }
+            let start = usize::from(patch.channel) - 1;
+            for (i, voxel) in layer.iter_mut().flatten().enumerate() {
+                *voxel = channels.get(start + i).map_or(0, |&value| level(value));
+            }
+        }
+    }
+}
+
+/// A DMX value from 0 to 255 as a voxel level, rounding to nearest
+pub(crate) fn level(value: u8) -> u8 {
+    let max = u16::from(MAX_LEVEL);
+    ((u16::from(value) * max + 127) / 255) as u8
+}
+
+/// DMX data for one universe as a datagram carried it
+#[derive(Debug, PartialEq, Eq)]
+pub struct Dmx<'a> {
+    pub universe: u16,
+    /// Counts up with each packet the sender sends to the universe, or `None` when it doesn't
+    /// number them
+    pub sequence: Option<u8>,
+    /// Values from channel 1 on
+    pub channels: &'a [u8],
+}
+
+/// A protocol carrying DMX over UDP
+pub trait DmxProtocol: Send + 'static {
+    /// The DMX data in a datagram from `peer`, if it carries any. Datagrams that ask something
+    /// of the cube, like discovery, are answered on `socket`.
+    fn decode<'a>(
+        &mut self,
+        datagram: &'a [u8],
+        peer: SocketAddr,
+        socket: &UdpSocket,
+    ) -> Option<Dmx<'a>>;
+}
+
+/// How far behind a sequence number can be and still count as out of order rather than a
+/// restarted sender, which E1.31 sets at 20
+const REORDER_WINDOW: i8 = 20;
+
+/// Gray frames built from DMX received on a thread of their own, newest first like
+/// [`Listener`](crate::listener::Listener). Every voxel starts off, and keeps its level until a
+/// packet for its universe changes it.
+pub struct DmxReceiver {
+    rx: Receiver<GrayFrame>,
+    frame: GrayFrame,
+}
+
+impl DmxReceiver {
+    pub fn spawn(socket: UdpSocket, map: DmxMap, mut protocol: impl DmxProtocol) -> Self {
+        let (tx, rx) = sync_channel(64);
+        thread::spawn(move || {
+            let mut gray = [[[0; 8]; 8]; 8];
+            let mut last = HashMap::new();
+            // Room for the largest packet any of the protocols sends
+            let mut buf = [0u8; 1500];
+            loop {
+                let (len, peer) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(e) => {
+                        eprintln!("Stopped receiving DMX: {e}");
+                        return;
+                    }
+                };
+                let Some(dmx) = protocol.decode(&buf[..len], peer, &socket) else {
+                    continue;
+                };
+                if let Some(sequence) = dmx.sequence {
+                    let stale = last.insert(dmx.universe, sequence).is_some_and(|last| {
+                        let ahead = sequence.wrapping_sub(last) as i8;
+                        ahead <= 0 && ahead > -REORDER_WINDOW
+                    });
+                    if stale {
+                        continue;
+                    }
+                }
+                map.apply(dmx.universe, dmx.channels, &mut gray);
+                if tx.send(gray).is_err() {
+                    return;
+                }
+            }
+        });
+        DmxReceiver {
+            rx,
+            frame: [[[0; 8]; 8]; 8],
+        }
+    }
+}
+
+impl Iterator for DmxReceiver {
+    type Item = GrayFrame;
+
+    fn next(&mut self) -> Option<GrayFrame> {
+        let mut fresh = false;
+        loop {
+            match self.rx.try_recv() {
+                Ok(frame) => {
+                    self.frame = frame;
+                    fresh = true;
+                }
+                Err(TryRecvError::Empty) => return Some(self.frame),
+                Err(TryRecvError::Disconnected) => return fresh.then_some(self.frame),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels_land_on_their_layers() {
+        let mut channels = [0u8; UNIVERSE_SIZE];
+        channels[0] = 255;
+        channels[LAYER_CHANNELS + 9] = 128;
+        channels[UNIVERSE_SIZE - 1] = 17;
+
+        let mut gray = [[[0; 8]; 8]; 8];
+        DmxMap::packed(1).apply(1, &channels, &mut gray);
+        assert_eq!(gray[0][0][0], MAX_LEVEL);
+        assert_eq!(gray[1][1][1], 8);
+        assert_eq!(gray[7][7][7], 1);
+        assert_eq!(
+            gray.iter().flatten().flatten().filter(|&&l| l > 0).count(),
+            3
+        );
+
+        // Each layer's universe starts again from channel 1
+        let map = DmxMap::per_layer(4);
+        assert_eq!(map.universes(), (4..12).collect::<Vec<_>>());
+        map.apply(6, &channels[..2], &mut gray);
+        assert_eq!(gray[2][0][..2], [MAX_LEVEL, 0]);
+    }
+
+    #[test]
+    fn short_packets_turn_the_rest_off() {
+        let mut gray = [[[MAX_LEVEL; 8]; 8]; 8];
+        DmxMap::packed(1).apply(1, &[255; 70], &mut gray);
+        assert_eq!(gray[0], [[MAX_LEVEL; 8]; 8]);
+        assert_eq!(
+            gray[1][0][..8],
+            [MAX_LEVEL, MAX_LEVEL, MAX_LEVEL, MAX_LEVEL, MAX_LEVEL, MAX_LEVEL, 0, 0]
+        );
+        assert_eq!(gray[2], [[0; 8]; 8]);
+
+        // Universes the cube doesn't use change nothing
+        DmxMap::packed(1).apply(2, &[], &mut gray);
+        assert_eq!(gray[0], [[MAX_LEVEL; 8]; 8]);
+    }
+
+    #[test]
+    fn map_files_move_only_what_they_name() {
+        let map = DmxMap::parse(
+            "# top half elsewhere
+4 = 9
+5 = 9/65 # shared
+
+",
+            DmxMap::packed(1),
+        )
+        .unwrap();
+        assert_eq!(
+            map.layers[3],
+            Patch {
+                universe: 1,
+                channel: 193
+            }
+        );
+        assert_eq!(
+            map.layers[4],
+            Patch {
+                universe: 9,
+                channel: 1
+            }
+        );
+        assert_eq!(
+            map.layers[5],
+            Patch {
+                universe: 9,
+                channel: 65
+            }
+        );
+        assert_eq!(map.universes(), [1, 9]);
+
+        for bad in ["8 = 1", "0 = one", "0 = 1/450", "0 = 1/0", "0 1"] {
+            let error = DmxMap::parse(bad, DmxMap::packed(1)).unwrap_err();
+            assert!(error.to_string().starts_with("line 1: "), "{bad}: {error}");
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! The cube driver for C and C++ programs, built as a shared library with
+//! `cargo rustc --release --lib --features cdylib --crate-type cdylib`. A driver refreshes the
+//! cube on a thread of its own from when it is made until it is freed, showing whichever frame
+//! was written last:
+//!
+//! ```c
+//! #include <stdint.h>
+//!
+//! typedef struct CubeDriver CubeDriver;
+//!
+//! /* Claim the GPIO with the default pins and start refreshing, NULL if that failed, with why
+//!    on stderr */
+//! CubeDriver *cube_driver_new(void);
+//! /* Show a frame of 64 bytes, layers from the bottom then rows of X with a bit per Y,
+//!    returning 0, or -1 once the driver has failed or if either pointer is NULL */
+//! int cube_driver_write_frame(CubeDriver *driver, const uint8_t frame[64]);
+//! /* Blank the cube, stop refreshing and release the GPIO. NULL is ignored. */
+//! void cube_driver_free(CubeDriver *driver);
+//! ```
+//!
+//! A driver may be used from any thread, though not from two at once. `python/rpi_led_cube.py`
+//! wraps it for Python.
+
+use std::{ffi::c_int, ptr, slice, sync::mpsc};
+
+use crate::{
+    cube::{CubeDriver, DriverConfig},
+    decoders::{read_binary_frame, BINARY_FRAME_LEN},
+    display::{spawn_refresh_on, Display, FrameSink, PipelineError},
+    Frame,
+};
+
+/// What C holds a pointer to
+pub struct Driver(Display<Frame>);
+
+/// Start refreshing whatever `open` opens, once it has
+fn start<S, O>(open: O) -> Result<Driver, String>
+where
+    S: FrameSink,
+    O: FnOnce() -> Result<S, PipelineError> + Send + 'static,
+{
+    let (opened, result) = mpsc::sync_channel(1);
+    let display = spawn_refresh_on(
+        move || {
+            let sink = open();
+            let _ = opened.send(sink.as_ref().err().map(ToString::to_string));
+            sink
+        },
+        None,
+    );
+    match result.recv() {
+        Ok(None) => Ok(Driver(display)),
+        Ok(Some(e)) => Err(e),
+        Err(_) => Err("the display thread panicked".to_owned()),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn cube_driver_new() -> *mut Driver {
+    let config = DriverConfig::default();
+    // A display that failed to open says why on stderr as it goes
+    start(move || CubeDriver::try_new(&config).map_err(PipelineError::GpioInit))
+        .map_or(ptr::null_mut(), |driver| Box::into_raw(Box::new(driver)))
+}
+
+/// # Safety
+/// `driver` must be NULL or come from `cube_driver_new` and not have been freed, and `frame`
+/// must be NULL or point at 64 readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn cube_driver_write_frame(driver: *mut Driver, frame: *const u8) -> c_int {
+    let Some(driver) = driver.as_ref() else {
+        return -1;
+    };
+    if frame.is_null() {
+        return -1;
+    }
+    let frame = read_binary_frame(slice::from_raw_parts(frame, BINARY_FRAME_LEN))
+        .expect("exactly a frame's bytes");
+    if driver.0.send(frame) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// # Safety
+/// `driver` must be NULL or come from `cube_driver_new`, and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn cube_driver_free(driver: *mut Driver) {
+    if !driver.is_null() {
+        drop(Box::from_raw(driver));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, thread, time::Duration};
+
+    use super::*;
+    use crate::display::RecordingSink;
+
+    #[test]
+    fn frames_written_are_shown_until_freed() {
+        let sink = RecordingSink::new();
+        let recorded = sink.clone();
+        let driver = Box::into_raw(Box::new(start(move || Ok(sink)).unwrap()));
+
+        let frame: Vec<u8> = (0..64).collect();
+        assert_eq!(
+            unsafe { cube_driver_write_frame(driver, frame.as_ptr()) },
+            0
+        );
+        assert_eq!(unsafe { cube_driver_write_frame(driver, ptr::null()) }, -1);
+        assert_eq!(
+            unsafe { cube_driver_write_frame(ptr::null_mut(), frame.as_ptr()) },
+            -1
+        );
+        thread::sleep(Duration::from_millis(50));
+        unsafe { cube_driver_free(driver) };
+        assert!(recorded
+            .frames()
+            .contains(&read_binary_frame(&frame).unwrap()));
+
+        let failed = start(|| Err::<RecordingSink, _>(PipelineError::Io(io::Error::other("no"))));
+        assert_eq!(failed.err().as_deref(), Some("no"));
+    }
+}
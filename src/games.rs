@@ -0,0 +1,7 @@
+//! Interactive programs driven by the keyboard rather than running on their own
+
+mod pong;
+mod snake;
+
+pub use pong::Pong;
+pub use snake::Snake;
@@ -0,0 +1,216 @@
+use std::time::{Duration, Instant};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::{
+    font,
+    geometry::Coord,
+    input::{Key, Keyboard},
+    Frame,
+};
+
+/// The game advances in fixed steps of this length however often frames are taken
+const TICK: Duration = Duration::from_millis(20);
+/// How long a score stays up after a point
+const SCORE_TICKS: u32 = 50;
+/// The computer paddle moves once every this many ticks
+const AI_TICKS: u32 = 6;
+/// Ball speed along X at the start of a rally, in voxels per tick
+const SERVE_SPEED: f32 = 0.12;
+
+/// Centre of a 3x3 paddle on one of the X faces
+#[derive(Copy, Clone)]
+struct Paddle {
+    y: i8,
+    z: i8,
+}
+
+impl Paddle {
+    fn nudge(&mut self, dy: i8, dz: i8) {
+        self.y = (self.y + dy).clamp(1, 6);
+        self.z = (self.z + dz).clamp(1, 6);
+    }
+}
+
+/// Two paddles on opposite X faces and a ball bouncing between them. Player one plays at X = 0
+/// with WASD, player two at X = 7 with IJKL or is left to the computer.
+pub struct Pong {
+    keyboard: Option<Keyboard>,
+    rng: SmallRng,
+    ai: bool,
+    score_limit: u8,
+    paddles: [Paddle; 2],
+    position: [f32; 3],
+    velocity: [f32; 3],
+    scores: [u8; 2],
+    /// Who just scored and for how many more ticks their score is shown
+    scored: Option<(usize, u32)>,
+    ticks: u32,
+    last: Instant,
+    behind: Duration,
+}
+
+impl Pong {
+    pub fn new(ai: bool, score_limit: u8) -> Self {
+        let keyboard = Keyboard::open()
+            .inspect_err(|e| eprintln!("No keyboard input: {e}"))
+            .ok();
+
+        let mut pong = Pong {
+            keyboard,
+            rng: SmallRng::from_entropy(),
+            ai,
+            score_limit,
+            paddles: [Paddle { y: 3, z: 3 }; 2],
+            position: [3.5; 3],
+            velocity: [0.0; 3],
+            scores: [0; 2],
+            scored: None,
+            ticks: 0,
+            last: Instant::now(),
+            behind: Duration::ZERO,
+        };
+        let receiver = pong.rng.gen_range(0..2);
+        pong.serve(receiver);
+        pong
+    }
+
+    /// Start a rally from the middle towards `receiver`
+    fn serve(&mut self, receiver: usize) {
+        let towards = if receiver == 0 { -1.0 } else { 1.0 };
+        self.position = [3.5; 3];
+        self.velocity = [
+            towards * SERVE_SPEED,
+            self.rng.gen_range(-0.08..0.08),
+            self.rng.gen_range(-0.08..0.08),
+        ];
+    }
+
+    fn read_keys(&mut self) {
+        let Some(keyboard) = &self.keyboard else {
+            return;
+        };
+        for key in keyboard.pressed() {
+            let (player, dy, dz) = match key {
+                Key::Char('w') => (0, 0, 1),
+                Key::Char('s') => (0, 0, -1),
+                Key::Char('a') => (0, 1, 0),
+                Key::Char('d') => (0, -1, 0),
+                Key::Char('i') => (1, 0, 1),
+                Key::Char('k') => (1, 0, -1),
+                Key::Char('j') => (1, 1, 0),
+                Key::Char('l') => (1, -1, 0),
+                _ => continue,
+            };
+            if player == 0 || !self.ai {
+                self.paddles[player].nudge(dy, dz);
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+
+        if let Some((scorer, left)) = self.scored {
+            if left > 0 {
+                self.scored = Some((scorer, left - 1));
+                return;
+            }
+            self.scored = None;
+            if self.scores[scorer] >= self.score_limit {
+                self.scores = [0; 2];
+            }
+            self.serve(1 - scorer);
+        }
+
+        if self.ai && self.ticks.is_multiple_of(AI_TICKS) {
+            let paddle = self.paddles[1];
+            let toward = |ball: f32, at: i8| (ball.round() as i8 - at).signum();
+            self.paddles[1].nudge(
+                toward(self.position[1], paddle.y),
+                toward(self.position[2], paddle.z),
+            );
+        }
+
+        for axis in 0..3 {
+            self.position[axis] += self.velocity[axis];
+        }
+
+        // Side walls reflect
+        for axis in 1..3 {
+            if self.position[axis] < 0.0 {
+                self.position[axis] = -self.position[axis];
+                self.velocity[axis] = -self.velocity[axis];
+            } else if self.position[axis] > 7.0 {
+                self.position[axis] = 14.0 - self.position[axis];
+                self.velocity[axis] = -self.velocity[axis];
+            }
+        }
+
+        let defender = if self.position[0] <= 0.0 {
+            0
+        } else if self.position[0] >= 7.0 {
+            1
+        } else {
+            return;
+        };
+
+        let paddle = self.paddles[defender];
+        let dy = self.position[1] - f32::from(paddle.y);
+        let dz = self.position[2] - f32::from(paddle.z);
+        if dy.abs() <= 1.5 && dz.abs() <= 1.5 {
+            // Returned, with an angle depending on where the paddle was hit
+            self.position[0] = self.position[0].clamp(0.0, 7.0);
+            self.velocity[0] = -self.velocity[0];
+            self.velocity[1] = (self.velocity[1] + dy * 0.04).clamp(-0.2, 0.2);
+            self.velocity[2] = (self.velocity[2] + dz * 0.04).clamp(-0.2, 0.2);
+        } else {
+            let scorer = 1 - defender;
+            self.scores[scorer] += 1;
+            self.scored = Some((scorer, SCORE_TICKS));
+        }
+    }
+
+    fn render(&self) -> Frame {
+        let mut frame = [[0u8; 8]; 8];
+
+        if let Some((scorer, _)) = self.scored {
+            if let Some(glyph) = font::digit(self.scores[scorer]) {
+                font::draw_on_side(&mut frame, &glyph);
+            }
+            return frame;
+        }
+
+        for (paddle, x) in self.paddles.iter().zip([0, 7]) {
+            Coord::all()
+                .filter(|c| c.x == x)
+                .filter(|c| (i16::from(c.y) - i16::from(paddle.y)).abs() <= 1)
+                .filter(|c| (i16::from(c.z) - i16::from(paddle.z)).abs() <= 1)
+                .for_each(|c| c.set(&mut frame));
+        }
+
+        let [x, y, z] = self.position.map(|p| p.round().clamp(0.0, 7.0) as u8);
+        Coord::new(x, y, z).set(&mut frame);
+
+        frame
+    }
+}
+
+impl Iterator for Pong {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.read_keys();
+
+        // Catch the game up with real time in whole ticks, independently of the frame rate
+        let now = Instant::now();
+        self.behind += now - self.last;
+        self.last = now;
+        while self.behind >= TICK {
+            self.tick();
+            self.behind -= TICK;
+        }
+
+        Some(self.render())
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+
+use crate::{
+    geometry::Coord,
+    input::{Key, Keyboard},
+    Frame,
+};
+
+/// The game advances in fixed steps of this length however often frames are taken
+const TICK: Duration = Duration::from_millis(20);
+/// The food is lit and dark for this many ticks in turn
+const BLINK_TICKS: u32 = 10;
+/// How long a dead snake flashes before a new game starts
+const DEATH_TICKS: u32 = 75;
+const START_LENGTH: usize = 3;
+
+/// One voxel along an axis
+type Heading = [i8; 3];
+
+/// A snake crawling through the cube towards a blinking food voxel, growing by one each time it
+/// eats. The arrow keys turn it within its layer, W and S send it up and down. Running into a
+/// wall or itself starts a new game.
+pub struct Snake {
+    keyboard: Option<Keyboard>,
+    rng: StdRng,
+    /// Head first
+    body: VecDeque<[i8; 3]>,
+    heading: Heading,
+    /// The latest turn asked for, taken at the next move
+    turn: Option<Heading>,
+    food: [i8; 3],
+    /// Ticks between moves
+    move_ticks: u32,
+    /// How many more ticks a dead snake flashes for
+    dying: Option<u32>,
+    ticks: u32,
+    last: Instant,
+    behind: Duration,
+}
+
+impl Snake {
+    /// Moving `speed` voxels per second
+    pub fn new(speed: f64, seed: Option<u64>) -> Self {
+        let keyboard = Keyboard::open()
+            .inspect_err(|e| eprintln!("No keyboard input: {e}"))
+            .ok();
+        Self::with_keyboard(keyboard, speed, seed)
+    }
+
+    fn with_keyboard(keyboard: Option<Keyboard>, speed: f64, seed: Option<u64>) -> Self {
+        let move_ticks = (1.0 / (speed * TICK.as_secs_f64())).round().max(1.0) as u32;
+
+        let mut snake = Snake {
+            keyboard,
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            body: VecDeque::new(),
+            heading: [1, 0, 0],
+            turn: None,
+            food: [0; 3],
+            move_ticks,
+            dying: None,
+            ticks: 0,
+            last: Instant::now(),
+            behind: Duration::ZERO,
+        };
+        snake.restart();
+        snake
+    }
+
+    /// A short snake on the bottom layer heading along +X, and food somewhere else
+    fn restart(&mut self) {
+        self.body = (0..START_LENGTH as i8).rev().map(|x| [x, 3, 0]).collect();
+        self.heading = [1, 0, 0];
+        self.turn = None;
+        self.dying = None;
+        self.place_food();
+    }
+
+    fn place_food(&mut self) {
+        let free = Coord::all()
+            .map(|c| [c.x, c.y, c.z].map(|v| v as i8))
+            .filter(|voxel| !self.body.contains(voxel));
+        // A snake filling the whole cube has nowhere left to go but into itself
+        self.food = free.choose(&mut self.rng).unwrap_or(self.body[0]);
+    }
+
+    /// Head off along `heading` from the next move, unless that would double straight back
+    fn steer(&mut self, heading: Heading) {
+        if heading != self.heading.map(|v| -v) {
+            self.turn = Some(heading);
+        }
+    }
+
+    fn read_keys(&mut self) {
+        let Some(keyboard) = &self.keyboard else {
+            return;
+        };
+        let turns: Vec<Heading> = keyboard
+            .pressed()
+            .filter_map(|key| match key {
+                Key::Right => Some([1, 0, 0]),
+                Key::Left => Some([-1, 0, 0]),
+                // Away from the viewer at the front face
+                Key::Up => Some([0, 1, 0]),
+                Key::Down => Some([0, -1, 0]),
+                Key::Char('w') => Some([0, 0, 1]),
+                Key::Char('s') => Some([0, 0, -1]),
+                _ => None,
+            })
+            .collect();
+        for heading in turns {
+            self.steer(heading);
+        }
+    }
+
+    /// Move one voxel, eating, growing or dying as it happens
+    fn crawl(&mut self) {
+        if let Some(turn) = self.turn.take() {
+            self.heading = turn;
+        }
+        let head = self.body[0];
+        let next: [i8; 3] = core::array::from_fn(|axis| head[axis] + self.heading[axis]);
+
+        let eating = next == self.food;
+        // The tail moves out of the way at the same time unless the snake is growing
+        let solid = self.body.len() - usize::from(!eating);
+        if next.iter().any(|v| !(0..8).contains(v)) || self.body.range(..solid).any(|&v| v == next)
+        {
+            self.dying = Some(DEATH_TICKS);
+            return;
+        }
+
+        self.body.push_front(next);
+        if eating {
+            self.place_food();
+        } else {
+            self.body.pop_back();
+        }
+    }
+
+    fn tick(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+
+        if let Some(left) = self.dying {
+            match left {
+                0 => self.restart(),
+                _ => self.dying = Some(left - 1),
+            }
+            return;
+        }
+
+        if self.ticks.is_multiple_of(self.move_ticks) {
+            self.crawl();
+        }
+    }
+
+    fn render(&self) -> Frame {
+        let mut frame = [[0u8; 8]; 8];
+        let lit = |voxel: &[i8; 3]| Coord::new(voxel[0] as u8, voxel[1] as u8, voxel[2] as u8);
+        let blink = (self.ticks / BLINK_TICKS).is_multiple_of(2);
+
+        if self.dying.is_none() || blink {
+            self.body.iter().for_each(|v| lit(v).set(&mut frame));
+        }
+        if self.dying.is_none() && blink {
+            lit(&self.food).set(&mut frame);
+        }
+        frame
+    }
+}
+
+impl Iterator for Snake {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.read_keys();
+
+        // Catch the game up with real time in whole ticks, independently of the frame rate
+        let now = Instant::now();
+        self.behind += now - self.last;
+        self.last = now;
+        while self.behind >= TICK {
+            self.tick();
+            self.behind -= TICK;
+        }
+
+        Some(self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game that isn't reading the terminal the tests run in
+    fn new_game() -> Snake {
+        Snake::with_keyboard(None, 1.0, Some(1))
+    }
+
+    #[test]
+    fn eating_grows_the_snake() {
+        let mut snake = new_game();
+        snake.food = [3, 3, 0];
+        snake.crawl();
+        assert_eq!(snake.body.len(), START_LENGTH + 1);
+        assert_eq!(snake.body[0], [3, 3, 0]);
+        assert_ne!(snake.food, [3, 3, 0]);
+        assert!(!snake.body.contains(&snake.food));
+
+        snake.food = [7, 7, 7];
+        snake.crawl();
+        assert_eq!(snake.body.len(), START_LENGTH + 1);
+        assert_eq!(snake.body[0], [4, 3, 0]);
+    }
+
+    #[test]
+    fn it_cannot_double_back_but_can_climb() {
+        let mut snake = new_game();
+        snake.food = [7, 7, 7];
+        snake.steer([-1, 0, 0]);
+        snake.crawl();
+        assert_eq!(snake.body[0], [3, 3, 0]);
+
+        snake.steer([0, 0, 1]);
+        snake.crawl();
+        assert_eq!(snake.body[0], [3, 3, 1]);
+        assert_eq!(snake.heading, [0, 0, 1]);
+    }
+
+    #[test]
+    fn walls_and_its_own_body_are_deadly() {
+        let mut snake = new_game();
+        snake.food = [7, 7, 7];
+        snake.steer([0, 0, -1]);
+        snake.crawl();
+        assert!(snake.dying.is_some());
+
+        // Long enough to bite its own tail going round a square
+        let mut snake = new_game();
+        snake.food = [7, 7, 7];
+        snake.body = (0..5).rev().map(|x| [x, 3, 0]).collect();
+        for heading in [[0, 1, 0], [-1, 0, 0], [0, -1, 0]] {
+            snake.steer(heading);
+            snake.crawl();
+        }
+        assert!(snake.dying.is_some());
+    }
+
+    #[test]
+    fn a_new_game_follows_the_flashing() {
+        let mut snake = new_game();
+        snake.dying = Some(1);
+        snake.body.push_back([0, 0, 7]);
+        for _ in 0..2 {
+            snake.tick();
+        }
+        assert_eq!(snake.dying, None);
+        assert_eq!(snake.body.len(), START_LENGTH);
+    }
+}
@@ -0,0 +1,196 @@
+//! Correcting brightness levels for how the eye sees them before the driver turns them into
+//! on times. Perceived brightness goes roughly as the on time to the power of 1/gamma, so a
+//! voxel at level 7 of 15 would look nearly as bright as one at 15 without the correction. A
+//! calibration file can also dim particular voxels, for cubes built from LED batches that
+//! don't match.
+
+use std::{fmt, fs, io, path::Path};
+
+use crate::{
+    geometry::{parse_voxels, Coord},
+    gray::{self, GrayFrame, MAX_LEVEL},
+};
+
+/// What the eye's response is usually taken to be
+pub const DEFAULT_GAMMA: f32 = 2.2;
+
+/// How bright each voxel is driven relative to the rest, 0 to 1, indexed `[z][x][y]`
+pub type Calibration = [[[f32; 8]; 8]; 8];
+
+/// The gamma curve and any calibration, as the driver applies them to every level
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Correction {
+    /// What each level is driven at for a voxel at full scale
+    table: [u8; MAX_LEVEL as usize + 1],
+    gamma: f32,
+    calibration: Option<Calibration>,
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Correction::new(DEFAULT_GAMMA)
+    }
+}
+
+impl Correction {
+    /// Only level 0 comes out dark, however far the curve pulls the dimmest levels down
+    pub fn new(gamma: f32) -> Self {
+        Correction {
+            table: core::array::from_fn(|level| corrected(level as u8, gamma, 1.0)),
+            gamma,
+            calibration: None,
+        }
+    }
+
+    /// Also scale each voxel by `calibration`
+    pub fn with_calibration(self, calibration: Calibration) -> Self {
+        Correction {
+            calibration: Some(calibration),
+            ..self
+        }
+    }
+
+    /// Whether every voxel is corrected the same, so that on/off frames need no more than the
+    /// global brightness
+    pub fn is_uniform(&self) -> bool {
+        self.calibration.is_none()
+    }
+
+    /// What a level is driven at, leaving aside any calibration
+    pub fn level(&self, level: u8) -> u8 {
+        self.table[usize::from(level.min(MAX_LEVEL))]
+    }
+
+    /// Every voxel's level as it should be driven
+    pub fn apply(&self, gray: &GrayFrame) -> GrayFrame {
+        let Some(calibration) = &self.calibration else {
+            return gray.map(|layer| layer.map(|row| row.map(|level| self.level(level))));
+        };
+        let mut corrected_gray = [[[0; 8]; 8]; 8];
+        for c in Coord::all() {
+            let scale = calibration[c.z as usize][c.x as usize][c.y as usize];
+            let level = corrected(gray::get(gray, c), self.gamma, scale);
+            gray::set(&mut corrected_gray, c, level);
+        }
+        corrected_gray
+    }
+}
+
+/// `level` through the gamma curve, scaled, and rounded back to a level. Anything lit at all
+/// stays lit unless its scale is 0.
+fn corrected(level: u8, gamma: f32, scale: f32) -> u8 {
+    let max = f32::from(MAX_LEVEL);
+    let linear = (f32::from(level.min(MAX_LEVEL)) / max).powf(gamma) * scale;
+    let rounded = (linear * max).round() as u8;
+    if level > 0 && scale > 0.0 {
+        rounded.max(1)
+    } else {
+        rounded
+    }
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(io::Error),
+    /// A line that isn't `x,y,z = scale` with coordinates from 0 to 7 or `*`, and a scale from
+    /// 0 to 1
+    Syntax {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Io(e) => write!(f, "{e}"),
+            CalibrationError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+pub fn load_calibration(path: &Path) -> Result<Calibration, CalibrationError> {
+    parse_calibration(&fs::read_to_string(path).map_err(CalibrationError::Io)?)
+}
+
+/// Read `x,y,z = scale` lines, any coordinate being `*` for all of them, so `*,*,3 = 0.8`
+/// dims the whole of layer 3. Later lines win where they overlap, and voxels that aren't
+/// mentioned stay at 1.
+pub fn parse_calibration(text: &str) -> Result<Calibration, CalibrationError> {
+    let mut calibration = [[[1.0; 8]; 8]; 8];
+
+    for (line, content) in (1..).zip(text.lines()) {
+        let content = content.split('#').next().unwrap_or_default().trim();
+        // Blank, or a TOML table header such as [calibration]
+        if content.is_empty() || content.starts_with('[') {
+            continue;
+        }
+
+        let syntax = |message: String| CalibrationError::Syntax { line, message };
+        let Some((voxels, scale)) = content.split_once('=') else {
+            return Err(syntax(format!("expected x,y,z = scale, found {content:?}")));
+        };
+        let voxels = parse_voxels(voxels, 8).map_err(syntax)?;
+        let scale: f32 = match scale.trim().parse() {
+            Ok(scale) if (0.0..=1.0).contains(&scale) => scale,
+            _ => {
+                return Err(syntax(format!(
+                    "scale must be 0 to 1, found {}",
+                    scale.trim()
+                )))
+            }
+        };
+
+        for c in voxels {
+            calibration[c.z as usize][c.x as usize][c.y as usize] = scale;
+        }
+    }
+
+    Ok(calibration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_curve_darkens_the_middle_but_keeps_the_ends() {
+        let correction = Correction::default();
+        assert_eq!(correction.level(0), 0);
+        assert_eq!(correction.level(1), 1);
+        assert_eq!(correction.level(8), 4);
+        assert_eq!(correction.level(MAX_LEVEL), MAX_LEVEL);
+
+        let linear = Correction::new(1.0);
+        assert!((0..=MAX_LEVEL).all(|level| linear.level(level) == level));
+    }
+
+    #[test]
+    fn calibration_scales_the_voxels_it_names() {
+        let calibration =
+            parse_calibration("# batch B\n[calibration]\n*,*,3 = 0.5\n2,5,3 = 1\n").unwrap();
+        assert_eq!(calibration[3][0][0], 0.5);
+        assert_eq!(calibration[3][2][5], 1.0);
+        assert_eq!(calibration[2][0][0], 1.0);
+
+        let correction = Correction::new(1.0).with_calibration(calibration);
+        let gray = [[[MAX_LEVEL; 8]; 8]; 8];
+        let corrected = correction.apply(&gray);
+        assert_eq!(corrected[3][0][0], 8);
+        assert_eq!(corrected[3][2][5], MAX_LEVEL);
+        assert_eq!(corrected[0][0][0], MAX_LEVEL);
+    }
+
+    #[test]
+    fn bad_lines_are_rejected() {
+        for text in ["1,2 = 0.5", "\n1,2,8 = 0.5", "1,2,3 = 2", "1,2,3"] {
+            assert!(
+                matches!(
+                    parse_calibration(text),
+                    Err(CalibrationError::Syntax { .. })
+                ),
+                "{text:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+
+use crate::Frame;
+
+/// A position along one axis, as named on the command line
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Index {
+    Zero,
+    One,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+}
+
+impl From<Index> for u8 {
+    fn from(item: Index) -> Self {
+        match item {
+            Index::Zero => 0,
+            Index::One => 1,
+            Index::Two => 2,
+            Index::Three => 3,
+            Index::Four => 4,
+            Index::Five => 5,
+            Index::Six => 6,
+            Index::Seven => 7,
+        }
+    }
+}
+
+/// A single voxel. With the cube viewed from the front, +X points forward, +Y to the left and +Z
+/// up, so (0, 0, 0) is the bottom, back, right corner.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Coord {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+}
+
+impl Coord {
+    pub fn new(x: u8, y: u8, z: u8) -> Self {
+        Coord { x, y, z }
+    }
+
+    /// The one place a voxel is mapped onto frame storage: the layer is Z, the row within the
+    /// layer is X and the bit within the row is Y, i.e. `frame[z][x] & (1 << y)`
+    pub fn index(&self) -> (usize, usize, u8) {
+        (self.z as usize, self.x as usize, 1 << self.y)
+    }
+
+    /// Every voxel of the cube, layer by layer from the bottom
+    pub fn all() -> impl Iterator<Item = Coord> {
+        (0..8).flat_map(|z| (0..8).flat_map(move |x| (0..8).map(move |y| Coord { x, y, z })))
+    }
+
+    pub fn get(&self, frame: &Frame) -> bool {
+        let (layer, row, bit) = self.index();
+        frame[layer][row] & bit != 0
+    }
+
+    pub fn set(&self, frame: &mut Frame) {
+        let (layer, row, bit) = self.index();
+        frame[layer][row] |= bit;
+    }
+
+    pub fn clear(&self, frame: &mut Frame) {
+        let (layer, row, bit) = self.index();
+        frame[layer][row] &= !bit;
+    }
+
+    /// Squared distance from the centre of the cube in half voxels, so it stays a whole number
+    /// even though the centre lies between voxels: 3 for the middle eight, 147 for the corners
+    pub fn centre_distance_squared(&self) -> u16 {
+        let half = |v: u8| (2 * i16::from(v) - 7).unsigned_abs();
+        half(self.x).pow(2) + half(self.y).pow(2) + half(self.z).pow(2)
+    }
+}
+
+/// A position in cube space with voxel centres at whole numbers 0 through 7
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Point {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Point { x, y, z }
+    }
+
+    pub fn distance(&self, other: &Point) -> f32 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+
+    /// Distance to the cube corner furthest from this point
+    pub fn furthest_corner(&self) -> f32 {
+        let far = |c: f32| c.max(7.0 - c);
+        Point::new(0.0, 0.0, 0.0).distance(&Point::new(far(self.x), far(self.y), far(self.z)))
+    }
+}
+
+impl From<Coord> for Point {
+    fn from(c: Coord) -> Self {
+        Point::new(c.x.into(), c.y.into(), c.z.into())
+    }
+}
+
+impl FromStr for Point {
+    type Err = String;
+
+    /// Parses `x,y,z`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords = s
+            .split(',')
+            .map(|c| c.trim().parse::<f32>().map_err(|e| format!("{c:?}: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match coords[..] {
+            [x, y, z] => Ok(Point { x, y, z }),
+            _ => Err(format!("expected x,y,z but got {} values", coords.len())),
+        }
+    }
+}
+
+/// Every voxel named by `x,y,z`, any coordinate being `*` for every position along its axis on
+/// a cube `size` across, so `2,3,*` is a whole column
+pub fn parse_voxels(spec: &str, size: u8) -> Result<Vec<Coord>, String> {
+    let axes = spec
+        .trim()
+        .trim_matches('"')
+        .split(',')
+        .map(|axis| match axis.trim() {
+            "*" => Ok(0..size),
+            axis => match axis.parse::<u8>() {
+                Ok(i) if i < size => Ok(i..i + 1),
+                _ => Err(format!("{axis:?} isn't 0 to {} or *", size - 1)),
+            },
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let [xs, ys, zs] = &axes[..] else {
+        return Err(format!("expected x,y,z, found {:?}", spec.trim()));
+    };
+    Ok(zs
+        .clone()
+        .flat_map(|z| {
+            xs.clone()
+                .flat_map(move |x| ys.clone().map(move |y| Coord { x, y, z }))
+        })
+        .collect())
+}
+
+/// The `index`th voxel along an order-3 Hilbert curve through the cube, so consecutive indices
+/// are always face neighbours. Uses Skilling's transpose form of the Hilbert index.
+pub fn hilbert_coord(index: u16) -> Coord {
+    const BITS: u32 = 3;
+    const DIMS: usize = 3;
+
+    // Deinterleave the index so each axis holds one bit from every level, most significant first
+    let mut axes = [0u8; DIMS];
+    for level in 0..BITS {
+        for (i, axis) in axes.iter_mut().enumerate() {
+            let bit = (index >> (level as usize * DIMS + DIMS - 1 - i)) & 1;
+            *axis |= (bit as u8) << level;
+        }
+    }
+
+    // Gray decode
+    let t = axes[DIMS - 1] >> 1;
+    for i in (1..DIMS).rev() {
+        axes[i] ^= axes[i - 1];
+    }
+    axes[0] ^= t;
+
+    // Undo the excess rotations and reflections
+    let mut q = 2u8;
+    while q != 1 << BITS {
+        let p = q - 1;
+        for i in (0..DIMS).rev() {
+            if axes[i] & q != 0 {
+                axes[0] ^= p;
+            } else {
+                let t = (axes[0] ^ axes[i]) & p;
+                axes[0] ^= t;
+                axes[i] ^= t;
+            }
+        }
+        q <<= 1;
+    }
+
+    Coord::new(axes[0], axes[1], axes[2])
+}
+
+/// The `index`th voxel of a boustrophedon scan, snaking along Y, then X, then up through the
+/// layers so consecutive indices are always face neighbours
+pub fn scan_coord(index: u16) -> Coord {
+    let z = (index / 64) as u8;
+    let in_layer = if z.is_multiple_of(2) {
+        index % 64
+    } else {
+        63 - index % 64
+    } as u8;
+    let x = in_layer / 8;
+    let y = if x.is_multiple_of(2) {
+        in_layer % 8
+    } else {
+        7 - in_layer % 8
+    };
+    Coord::new(x, y, z)
+}
+
+/// How the cube being driven is built. Frames are always 8x8x8, and the driver resamples them
+/// onto cubes of other sizes: a 16³ cube lights a 2x2x2 block for each voxel, and a 4³ cube
+/// lights a voxel when any of the 2x2x2 block it stands for is lit, so thin lines don't vanish.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CubeGeometry {
+    /// Voxels along each edge, 4, 8 or 16
+    size: u8,
+}
+
+impl Default for CubeGeometry {
+    fn default() -> Self {
+        CubeGeometry { size: 8 }
+    }
+}
+
+impl CubeGeometry {
+    /// `None` unless `size` is 4, 8 or 16
+    pub fn new(size: u8) -> Option<Self> {
+        matches!(size, 4 | 8 | 16).then_some(CubeGeometry { size })
+    }
+
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+
+    /// Layer select lines needed to pick out every layer through the decoder, 2 to 4
+    pub fn layer_select_bits(&self) -> u8 {
+        self.size.trailing_zeros() as u8
+    }
+
+    /// par lines that feed registers, one per column up to all eight of them
+    pub fn par_lines(&self) -> usize {
+        usize::from(self.size).min(8)
+    }
+
+    /// Clocks of par_srclk it takes to shift in a layer, every par line carrying its share of
+    /// the layer's bits
+    pub fn clocks_per_layer(&self) -> usize {
+        usize::from(self.size).pow(2) / self.par_lines()
+    }
+
+    /// 74HC595s daisy-chained behind each par line to hold its share of a layer
+    pub fn chain_length(&self) -> usize {
+        self.clocks_per_layer().div_ceil(8)
+    }
+
+    /// Bit `clock` of what par line `line` shifts in for a layer of `rows`. The layer's bits
+    /// go out row by row, from bit 0 of row 0, dealt across the par lines in turn, so an 8³
+    /// cube has par_k carrying bit k-1 of every row.
+    pub fn par_bit(&self, rows: &[u16], line: usize, clock: usize) -> bool {
+        let i = clock * self.par_lines() + line;
+        let size = usize::from(self.size);
+        rows[i / size] & (1 << (i % size)) != 0
+    }
+
+    /// `frame` at this size, each layer's rows bottom up with Y as the bit, as with [`Frame`]
+    pub fn resample(&self, frame: &Frame) -> Vec<Vec<u16>> {
+        let size = usize::from(self.size);
+        // The voxels of the frame each one along an axis stands for
+        let span = |i: usize| (i * 8 / size)..((i + 1) * 8).div_ceil(size);
+        (0..size)
+            .map(|z| {
+                (0..size)
+                    .map(|x| {
+                        (0..size)
+                            .filter(|&y| {
+                                span(z).any(|fz| {
+                                    span(x)
+                                        .any(|fx| span(y).any(|fy| frame[fz][fx] & (1 << fy) != 0))
+                                })
+                            })
+                            .map(|y| 1 << y)
+                            .sum()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for CubeGeometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{0}x{0}x{0}", self.size)
+    }
+}
+
+impl FromStr for CubeGeometry {
+    type Err = String;
+
+    /// Parses the edge length, `8`, or all three as in `8x8x8`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let edges = s
+            .split('x')
+            .map(|e| e.trim().parse::<u8>().map_err(|e| format!("{s:?}: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        let size = match edges[..] {
+            [size] => size,
+            [x, y, z] if x == y && y == z => x,
+            _ => return Err(format!("{s:?} isn't a cube")),
+        };
+        CubeGeometry::new(size).ok_or_else(|| format!("cubes are 4, 8 or 16 across, not {size}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn face_neighbours(a: Coord, b: Coord) -> bool {
+        a.x.abs_diff(b.x) + a.y.abs_diff(b.y) + a.z.abs_diff(b.z) == 1
+    }
+
+    #[test]
+    fn paths_visit_every_voxel_once_stepping_to_a_neighbour() {
+        for step in [hilbert_coord, scan_coord] {
+            let path: Vec<Coord> = (0..512).map(step).collect();
+            let mut seen = [[[false; 8]; 8]; 8];
+            for c in &path {
+                let seen = &mut seen[c.z as usize][c.x as usize][c.y as usize];
+                assert!(!*seen, "{c:?} twice");
+                *seen = true;
+            }
+            assert!(path.windows(2).all(|w| face_neighbours(w[0], w[1])));
+            assert_eq!(path[0], Coord::new(0, 0, 0));
+        }
+    }
+
+    #[test]
+    fn the_hilbert_path_keeps_to_each_octant_in_turn() {
+        // Every run of 64 fills one 4x4x4 octant, and so on down, which a scan doesn't
+        for size in [4u8, 2] {
+            let block = usize::from(size).pow(3);
+            for (i, run) in (0..512)
+                .map(hilbert_coord)
+                .collect::<Vec<_>>()
+                .chunks(block)
+                .enumerate()
+            {
+                let octant = |c: &Coord| (c.x / size, c.y / size, c.z / size);
+                assert!(
+                    run.iter().all(|c| octant(c) == octant(&run[0])),
+                    "block {i}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_size_shifts_a_layer_through_its_registers() {
+        let sizes = ["4", "8x8x8", "16"].map(|s| s.parse::<CubeGeometry>().unwrap());
+        let wiring = sizes.map(|g| {
+            (
+                g.layer_select_bits(),
+                g.par_lines(),
+                g.clocks_per_layer(),
+                g.chain_length(),
+            )
+        });
+        assert_eq!(wiring, [(2, 4, 4, 1), (3, 8, 8, 1), (4, 8, 32, 4)]);
+        assert!("12".parse::<CubeGeometry>().is_err());
+        assert!("8x8x4".parse::<CubeGeometry>().is_err());
+    }
+
+    #[test]
+    fn centre_distances_are_symmetric_and_whole() {
+        assert_eq!(Coord::new(3, 4, 3).centre_distance_squared(), 3);
+        assert_eq!(Coord::new(0, 7, 0).centre_distance_squared(), 147);
+        assert!(Coord::all().all(|c| {
+            let mirrored = Coord::new(7 - c.x, 7 - c.y, 7 - c.z);
+            c.centre_distance_squared() == mirrored.centre_distance_squared()
+        }));
+    }
+
+    #[test]
+    fn eight_cubed_frames_are_shown_as_they_are() {
+        let frame: Frame = core::array::from_fn(|z| core::array::from_fn(|x| (z * 8 + x) as u8));
+        let layers = CubeGeometry::default().resample(&frame);
+        let expected: Vec<Vec<u16>> = frame
+            .iter()
+            .map(|rows| rows.iter().map(|&row| row.into()).collect())
+            .collect();
+        assert_eq!(layers, expected);
+    }
+
+    #[test]
+    fn other_sizes_scale_voxels_by_block() {
+        let mut frame = Frame::default();
+        Coord::new(3, 5, 7).set(&mut frame);
+
+        let big = CubeGeometry::new(16).unwrap().resample(&frame);
+        let lit: Vec<_> = (0..16)
+            .flat_map(|z| (0..16).flat_map(move |x| (0..16).map(move |y| (x, y, z))))
+            .filter(|&(x, y, z)| big[z][x] & (1 << y) != 0)
+            .collect();
+        assert_eq!(lit.len(), 8);
+        assert!(lit
+            .iter()
+            .all(|&(x, y, z)| x / 2 == 3 && y / 2 == 5 && z / 2 == 7));
+
+        let small = CubeGeometry::new(4).unwrap().resample(&frame);
+        assert_eq!(small[3][1], 1 << 2);
+        assert_eq!(small.concat().iter().filter(|&&row| row != 0).count(), 1);
+    }
+}
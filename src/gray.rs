@@ -0,0 +1,126 @@
+use crate::{geometry::Coord, orientation::Orientation, Frame};
+
+/// Brightest intensity a voxel can have
+pub const MAX_LEVEL: u8 = 15;
+
+/// Intensity per voxel from 0 (off) to `MAX_LEVEL`, indexed `[z][x][y]`
+pub type GrayFrame = [[[u8; 8]; 8]; 8];
+
+pub fn get(gray: &GrayFrame, c: Coord) -> u8 {
+    gray[c.z as usize][c.x as usize][c.y as usize]
+}
+
+pub fn set(gray: &mut GrayFrame, c: Coord, level: u8) {
+    gray[c.z as usize][c.x as usize][c.y as usize] = level.min(MAX_LEVEL);
+}
+
+/// Raise a voxel to at least `level`, so overlapping light never dims what is already there
+pub fn brighten(gray: &mut GrayFrame, c: Coord, level: u8) {
+    let voxel = &mut gray[c.z as usize][c.x as usize][c.y as usize];
+    *voxel = (*voxel).max(level.min(MAX_LEVEL));
+}
+
+/// On/off frame with every voxel at or above `level` lit
+pub fn threshold(gray: &GrayFrame, level: u8) -> Frame {
+    let mut frame = [[0u8; 8]; 8];
+    Coord::all()
+        .filter(|&c| get(gray, c) >= level)
+        .for_each(|c| c.set(&mut frame));
+    frame
+}
+
+/// Every lit voxel of an on/off frame at full intensity
+pub fn from_frame(frame: &Frame) -> GrayFrame {
+    let mut gray = [[[0u8; 8]; 8]; 8];
+    Coord::all()
+        .filter(|c| c.get(frame))
+        .for_each(|c| set(&mut gray, c, MAX_LEVEL));
+    gray
+}
+
+/// The voxels whose level has bit `bit` set, which bit-angle modulation shows for a time
+/// proportional to `1 << bit`
+pub fn bit_plane(gray: &GrayFrame, bit: u8) -> Frame {
+    let mut frame = [[0u8; 8]; 8];
+    Coord::all()
+        .filter(|&c| get(gray, c) & (1 << bit) != 0)
+        .for_each(|c| c.set(&mut frame));
+    frame
+}
+
+/// Scale every level by `brightness / MAX_LEVEL`, rounding to nearest
+pub fn dim(gray: &GrayFrame, brightness: u8) -> GrayFrame {
+    let brightness = u16::from(brightness.min(MAX_LEVEL));
+    let max = u16::from(MAX_LEVEL);
+    gray.map(|layer| {
+        layer.map(|row| row.map(|level| ((u16::from(level) * brightness + max / 2) / max) as u8))
+    })
+}
+
+pub fn orient(gray: &GrayFrame, orientation: Orientation) -> GrayFrame {
+    let mut oriented = [[[0u8; 8]; 8]; 8];
+    for c in Coord::all() {
+        set(&mut oriented, orientation.apply_coord(c), get(gray, c));
+    }
+    oriented
+}
+
+/// Dark voxels at full intensity and the other way round
+pub fn invert(gray: &GrayFrame) -> GrayFrame {
+    gray.map(|layer| layer.map(|row| row.map(|level| MAX_LEVEL - level.min(MAX_LEVEL))))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random(rng: &mut SmallRng) -> GrayFrame {
+        core::array::from_fn(|_| {
+            core::array::from_fn(|_| core::array::from_fn(|_| rng.gen_range(0..=MAX_LEVEL)))
+        })
+    }
+
+    #[test]
+    fn bit_planes_add_up_to_the_levels() {
+        let mut rng = SmallRng::seed_from_u64(6);
+        for _ in 0..100 {
+            let gray = random(&mut rng);
+            for c in Coord::all() {
+                let level: u8 = (0..4)
+                    .filter(|&bit| c.get(&bit_plane(&gray, bit)))
+                    .map(|bit| 1 << bit)
+                    .sum();
+                assert_eq!(level, get(&gray, c));
+            }
+        }
+    }
+
+    #[test]
+    fn on_off_frames_survive_the_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let frame: Frame = rng.gen();
+            assert_eq!(threshold(&from_frame(&frame), 1), frame);
+        }
+    }
+
+    #[test]
+    fn full_brightness_leaves_levels_alone() {
+        let gray = random(&mut SmallRng::seed_from_u64(8));
+        assert_eq!(dim(&gray, MAX_LEVEL), gray);
+        assert_eq!(dim(&gray, 0), [[[0; 8]; 8]; 8]);
+    }
+
+    #[test]
+    fn orienting_matches_on_off_frames() {
+        let frame: Frame = SmallRng::seed_from_u64(9).gen();
+        for orientation in Orientation::all() {
+            assert_eq!(
+                threshold(&orient(&from_frame(&frame), orientation), 1),
+                orientation.apply(&frame)
+            );
+        }
+    }
+}
@@ -0,0 +1,380 @@
+//! Control over HTTP, so a phone's browser or `curl` can drive a running cube. `GET /` serves a
+//! page of controls, `GET /status` reports the settings as JSON and `GET /metrics` the cube's
+//! health for Prometheus, see [`metrics`](crate::metrics). Posts change the settings, each
+//! with a plain text body, and answer with the status as it then stands:
+//!
+//! - `POST /program` switches to another program, e.g. `rain --density 0.1`
+//! - `POST /speed` plays faster or slower than the program's own rate, e.g. `2` or `0.5x`
+//! - `POST /brightness` sets the software brightness, 0 to 15
+//! - `POST /rotation` turns the cube, `i`, `j`, `k` or steps such as `x90,flip-y`
+//! - `POST /frame` shows a frame of 128 hex digits in place of the program until the next
+//!   frame or program
+//!
+//! `/ws` takes WebSocket connections streaming frames both ways, see [`websocket`].
+//!
+//! Connections are served one at a time, one request each.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    control::{Control, ProgramCheck},
+    decoders::write_base16_frame,
+    json, metrics, websocket,
+};
+
+/// Bytes a request may take up to the end of its body, far more than any real one needs
+const MAX_REQUEST: u64 = 16 * 1024;
+
+const INDEX: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>LED cube</title>
+<style>
+body { font-family: sans-serif; max-width: 30em; margin: 1em auto; padding: 0 1em; }
+input, select, button { font-size: 1.2em; margin: 0.2em 0; }
+pre { white-space: pre-wrap; }
+</style>
+</head>
+<body>
+<h1>LED cube</h1>
+<p><input id="program" placeholder="rain --density 0.1"> <button onclick="post('program', value('program'))">Run</button></p>
+<p>Speed <input id="speed" type="number" value="1" min="0.1" step="0.1"> <button onclick="post('speed', value('speed'))">Set</button></p>
+<p>Brightness <input id="brightness" type="range" min="0" max="15" value="15" onchange="post('brightness', value('brightness'))"></p>
+<p>Rotation <select id="rotation" onchange="post('rotation', value('rotation'))">
+<option>none</option><option>i</option><option>j</option><option>k</option>
+</select></p>
+<pre id="status"></pre>
+<script>
+function value(id) { return document.getElementById(id).value; }
+function show(response) {
+  response.text().then(text => document.getElementById("status").textContent = text);
+}
+function post(path, body) { fetch("/" + path, { method: "POST", body: body }).then(show); }
+fetch("/status").then(show);
+</script>
+</body>
+</html>
+"#;
+
+/// The parts of a request the routes look at
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    /// Without any query string
+    path: String,
+    body: String,
+    /// Present when the client asks to upgrade to a WebSocket
+    websocket_key: Option<String>,
+}
+
+fn bad_request(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_owned())
+}
+
+/// Read one request, giving up on anything longer than `MAX_REQUEST`
+fn read_request(reader: impl Read) -> io::Result<Request> {
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (words.next(), words.next(), words.next())
+    else {
+        return Err(bad_request("malformed request line"));
+    };
+    let method = method.to_owned();
+    let path = target.split('?').next().unwrap_or_default().to_owned();
+
+    let mut length = 0;
+    let mut websocket_key = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(bad_request("headers never end"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| bad_request("bad Content-Length"))?;
+            } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| bad_request("body is not UTF-8"))?;
+    Ok(Request {
+        method,
+        path,
+        body,
+        websocket_key,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into(),
+        }
+    }
+
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Unknown",
+        };
+        write!(
+            out,
+            "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )?;
+        out.flush()
+    }
+}
+
+/// The settings and the frame showing, as one JSON object
+fn status(control: &Control) -> Response {
+    let mut frame = Vec::new();
+    write_base16_frame(&mut frame, &control.shown()).expect("writing to a Vec cannot fail");
+    let frame = String::from_utf8(frame).expect("hex is ASCII");
+
+    let refresh = control
+        .refresh_stats()
+        .map_or_else(|| "null".to_owned(), |stats| stats.json());
+    Response {
+        status: 200,
+        content_type: "application/json",
+        body: format!(
+            "{{{},\"frame\":{},\"refresh\":{refresh}}}\n",
+            control.settings().json_members(),
+            json::quote(frame.trim_end()),
+        ),
+    }
+}
+
+fn respond(control: &Control, check: &ProgramCheck, request: &Request) -> Response {
+    let changes = ["/program", "/speed", "/brightness", "/rotation", "/frame"];
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => Response {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: INDEX.to_owned(),
+        },
+        ("GET", "/status") => status(control),
+        ("GET", "/metrics") => Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: metrics::render(control),
+        },
+        ("POST", path) if changes.contains(&path) => {
+            match control.change(&path[1..], request.body.trim(), check) {
+                Ok(()) => status(control),
+                Err(reason) => Response::text(400, reason + "\n"),
+            }
+        }
+        ("GET", "/ws") => Response::text(400, "expected a WebSocket upgrade\n"),
+        (_, "/" | "/status" | "/metrics" | "/ws") => Response::text(405, "use GET\n"),
+        (_, path) if changes.contains(&path) => Response::text(405, "use POST\n"),
+        _ => Response::text(404, "no such page\n"),
+    }
+}
+
+fn handle_client(
+    stream: TcpStream,
+    control: &Arc<Control>,
+    check: &ProgramCheck,
+    stop_token: &Arc<AtomicBool>,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut writer = stream.try_clone()?;
+
+    let response = match read_request(&stream) {
+        Ok(Request {
+            method,
+            path,
+            websocket_key: Some(key),
+            ..
+        }) if method == "GET" && path == "/ws" => {
+            return websocket::start(stream, &key, control.clone(), stop_token.clone());
+        }
+        Ok(request) => respond(control, check, &request),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Response::text(400, format!("{e}\n")),
+        Err(e) => return Err(e),
+    };
+    response.write_to(&mut writer)
+}
+
+/// Answer requests on `listener` until the stop token is set
+pub fn serve(
+    listener: TcpListener,
+    control: Arc<Control>,
+    check: ProgramCheck,
+    stop_token: Arc<AtomicBool>,
+) -> io::Result<JoinHandle<()>> {
+    // Poll so the stop token is noticed without a connection arriving
+    listener.set_nonblocking(true)?;
+
+    Ok(thread::spawn(move || {
+        while !stop_token.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = handle_client(stream, &control, &check, &stop_token) {
+                        eprintln!("HTTP connection failed: {e}");
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => {
+                    eprintln!("HTTP server failed: {e}");
+                    break;
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::{control::Command, orientation::Orientation, pipeline::Rotation};
+
+    fn only_rain() -> ProgramCheck {
+        Box::new(|words| match words[0].as_str() {
+            "rain" => Ok(()),
+            other => Err(format!("unknown program {other}")),
+        })
+    }
+
+    fn post_to(control: &Control, path: &str, body: &str) -> Response {
+        let request = Request {
+            method: "POST".to_owned(),
+            path: path.to_owned(),
+            body: body.to_owned(),
+            websocket_key: None,
+        };
+        respond(control, &only_rain(), &request)
+    }
+
+    #[test]
+    fn requests_parse_with_their_bodies() {
+        let text = "POST /speed?now HTTP/1.1\r\nHost: cube\r\ncontent-length: 2\r\n\r\n2x";
+        assert_eq!(
+            read_request(text.as_bytes()).unwrap(),
+            Request {
+                method: "POST".to_owned(),
+                path: "/speed".to_owned(),
+                body: "2x".to_owned(),
+                websocket_key: None,
+            }
+        );
+        assert!(read_request("GET /\r\n\r\n".as_bytes()).is_err());
+        assert!(read_request("GET / HTTP/1.1\r\nHost: cube\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn posts_change_the_settings() {
+        let control = Control::new();
+        assert_eq!(post_to(&control, "/speed", "2x").status, 200);
+        assert_eq!(post_to(&control, "/brightness", "7").status, 200);
+        assert_eq!(post_to(&control, "/rotation", "K").status, 200);
+        let settings = control.settings();
+        assert_eq!(settings.speed, 2.0);
+        assert_eq!(settings.brightness, 7);
+        assert_eq!(settings.orientation, Orientation::from(Rotation::K));
+
+        let reply = post_to(&control, "/program", "rain --density 0.1\n");
+        assert!(reply.body.contains(r#""program":"rain --density 0.1""#));
+        let frame = "0".repeat(127) + "1";
+        assert_eq!(post_to(&control, "/frame", &frame).status, 200);
+        let mut pushed = [[0; 8]; 8];
+        pushed[7][7] = 1;
+        assert_eq!(
+            control.take_commands(),
+            [
+                Command::Program(["rain", "--density", "0.1"].map(str::to_owned).to_vec()),
+                Command::Frame(pushed),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_changes_are_refused() {
+        let control = Control::new();
+        for (path, body) in [
+            ("/speed", "-1"),
+            ("/brightness", "16"),
+            ("/rotation", "x45"),
+            ("/program", "snake"),
+            ("/program", ""),
+            ("/frame", "00"),
+        ] {
+            assert_eq!(post_to(&control, path, body).status, 400, "{path} {body}");
+        }
+        assert_eq!(control.settings(), Default::default());
+        assert!(control.take_commands().is_empty());
+        assert_eq!(post_to(&control, "/status", "").status, 405);
+        assert_eq!(post_to(&control, "/nowhere", "").status, 404);
+    }
+
+    #[test]
+    fn served_over_tcp() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let control = Arc::new(Control::new());
+        let stop_token = Arc::new(AtomicBool::new(false));
+        let server = serve(listener, control.clone(), only_rain(), stop_token.clone()).unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "POST /brightness HTTP/1.1\r\nContent-Length: 1\r\n\r\n3"
+        )
+        .unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"), "{reply}");
+        assert!(reply.contains(r#""brightness":3"#), "{reply}");
+        assert_eq!(control.settings().brightness, 3);
+
+        stop_token.store(true, Ordering::Relaxed);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+//! Pictures drawn elsewhere, brought onto the cube. Decoders in the submodules give gray
+//! pictures, which are shrunk to 8x8 and turned into frames here.
+
+pub mod gif;
+pub(crate) mod inflate;
+pub mod png;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use crate::{geometry::Coord, Frame};
+
+/// A picture in gray levels from 0 for black to 255 for white, row by row from the top
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GrayImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl GrayImage {
+    /// Shrunk or stretched to 8x8, rows from the top, each cell the average of the pixels it
+    /// covers
+    pub fn downscale(&self) -> [[u8; 8]; 8] {
+        // The pixels from `start` to before `end` that cell `i` of 8 covers, at least one
+        let span = |i: usize, len: usize| {
+            let start = i * len / 8;
+            start..((i + 1) * len / 8).max(start + 1)
+        };
+        core::array::from_fn(|row| {
+            core::array::from_fn(|column| {
+                let (rows, columns) = (span(row, self.height), span(column, self.width));
+                let count = rows.len() * columns.len();
+                let sum: usize = rows
+                    .flat_map(|y| columns.clone().map(move |x| (x, y)))
+                    .map(|(x, y)| usize::from(self.pixels[y * self.width + x]))
+                    .sum();
+                (sum / count) as u8
+            })
+        })
+    }
+}
+
+/// Perceived brightness of a colour
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000) as u8
+}
+
+/// On or off for each of 8x8 gray levels. Levels above `threshold`, a fraction of full white,
+/// are lit, or with `dither` the rounding error of each cell is spread to its neighbours
+/// (Floyd-Steinberg) so that mid grays come out as a pattern.
+pub fn to_bits(levels: &[[u8; 8]; 8], threshold: f64, dither: bool) -> [[bool; 8]; 8] {
+    let mut error = [[0.0f64; 8]; 8];
+    let mut bits = [[false; 8]; 8];
+    for row in 0..8 {
+        for column in 0..8 {
+            let level = f64::from(levels[row][column]) / 255.0 + error[row][column];
+            let lit = level > threshold;
+            bits[row][column] = lit;
+            if !dither {
+                continue;
+            }
+            let left = level - if lit { 1.0 } else { 0.0 };
+            let mut spread = |r: usize, c: Option<usize>, share: f64| {
+                if let Some(c) = c.filter(|&c| c < 8 && r < 8) {
+                    error[r][c] += left * share / 16.0;
+                }
+            };
+            spread(row, column.checked_add(1), 7.0);
+            spread(row + 1, column.checked_sub(1), 3.0);
+            spread(row + 1, Some(column), 5.0);
+            spread(row + 1, column.checked_add(1), 1.0);
+        }
+    }
+    bits
+}
+
+/// How the pictures of an animation are laid out in the cube
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ImageLayout {
+    /// Each picture on the front face, top row at the top, pushed back some layers deep
+    #[default]
+    Face,
+    /// Every 8 pictures stacked into one frame from the bottom layer up, each seen from above
+    /// with its top row at the back
+    Layers,
+}
+
+impl std::fmt::Display for ImageLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// How pictures become frames
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Conversion {
+    pub layout: ImageLayout,
+    /// Layers deep a picture on the front face reaches, 1 to 8
+    pub depth: u8,
+    /// See `to_bits`
+    pub threshold: f64,
+    pub dither: bool,
+}
+
+impl Default for Conversion {
+    fn default() -> Self {
+        Conversion {
+            layout: ImageLayout::Face,
+            depth: 1,
+            threshold: 0.5,
+            dither: false,
+        }
+    }
+}
+
+/// The frames of an animation of pictures, each up for as long as its pictures were
+pub fn to_frames(
+    pictures: &[(Duration, GrayImage)],
+    conversion: Conversion,
+) -> Vec<(Duration, Frame)> {
+    let bits = |picture: &GrayImage| {
+        to_bits(
+            &picture.downscale(),
+            conversion.threshold,
+            conversion.dither,
+        )
+    };
+    // Rows of the picture from the top, so the last row is the bottom or front of the cube
+    let lit = |bits: [[bool; 8]; 8]| {
+        (0..8u8).flat_map(move |row| {
+            (0..8u8)
+                .filter(move |&column| bits[usize::from(row)][usize::from(column)])
+                .map(move |column| (7 - row, column))
+        })
+    };
+
+    match conversion.layout {
+        ImageLayout::Face => pictures
+            .iter()
+            .map(|(duration, picture)| {
+                let mut frame = [[0u8; 8]; 8];
+                for (z, x) in lit(bits(picture)) {
+                    for y in 0..conversion.depth.clamp(1, 8) {
+                        Coord::new(x, y, z).set(&mut frame);
+                    }
+                }
+                (*duration, frame)
+            })
+            .collect(),
+        ImageLayout::Layers => pictures
+            .chunks(8)
+            .map(|slices| {
+                let mut frame = [[0u8; 8]; 8];
+                for (z, (_, picture)) in (0..).zip(slices) {
+                    for (y, x) in lit(bits(picture)) {
+                        Coord::new(x, y, z).set(&mut frame);
+                    }
+                }
+                (slices.iter().map(|(duration, _)| *duration).sum(), frame)
+            })
+            .collect(),
+    }
+}
+
+/// Which end of a picture of slices holds the bottom layer
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SliceOrder {
+    /// The first slice, at the top or the left, is the bottom layer
+    #[default]
+    BottomUp,
+    /// The first slice is the top layer
+    TopDown,
+}
+
+impl std::fmt::Display for SliceOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// A frame of voxel art drawn as its 8 layers, each an 8x8 slice seen from above with its top
+/// row at the back, one under the other in a picture 8 wide and 64 tall or side by side in one
+/// 64 wide and 8 tall. Pixels lighter than `threshold`, a fraction of full white, are lit.
+pub fn from_slices(picture: &GrayImage, order: SliceOrder, threshold: f64) -> io::Result<Frame> {
+    // Where slice `i` starts, in pixels across and down
+    let corner = match (picture.width, picture.height) {
+        (8, 64) => |i: usize| (0, i * 8),
+        (64, 8) => |i: usize| (i * 8, 0),
+        (width, height) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "slices should make a picture of 8x64 or 64x8 pixels, not {width}x{height}"
+                ),
+            ))
+        }
+    };
+
+    let mut frame = [[0u8; 8]; 8];
+    for slice in 0..8u8 {
+        let (left, top) = corner(usize::from(slice));
+        let z = match order {
+            SliceOrder::BottomUp => slice,
+            SliceOrder::TopDown => 7 - slice,
+        };
+        for c in Coord::all().filter(|c| c.z == z) {
+            let (x, y) = (left + usize::from(c.x), top + 7 - usize::from(c.y));
+            if f64::from(picture.pixels[y * picture.width + x]) / 255.0 > threshold {
+                c.set(&mut frame);
+            }
+        }
+    }
+    Ok(frame)
+}
+
+/// Frames of voxel art from a PNG of slices, see `from_slices`, or from every PNG in a
+/// directory in order of their names
+pub fn read_slices(path: &Path, order: SliceOrder, threshold: f64) -> io::Result<Vec<Frame>> {
+    let files: Vec<PathBuf> = if path.is_dir() {
+        let mut files = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        files.retain(|file| {
+            file.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+        });
+        files.sort();
+        files
+    } else {
+        vec![path.to_owned()]
+    };
+    if files.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no PNG files in {}", path.display()),
+        ));
+    }
+
+    files
+        .iter()
+        .map(|file| {
+            from_slices(&png::read(file)?, order, threshold)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", file.display())))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, pixel: impl Fn(usize, usize) -> u8) -> GrayImage {
+        GrayImage {
+            width,
+            height,
+            pixels: (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| pixel(x, y))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn downscaling_averages_and_stretches() {
+        // Left half white in a 16x16 picture
+        let half = image(16, 16, |x, _| if x < 8 { 255 } else { 0 });
+        assert_eq!(half.downscale(), [[255, 255, 255, 255, 0, 0, 0, 0]; 8]);
+
+        // Stripes a pixel wide average out to gray
+        let stripes = image(16, 8, |x, _| if x % 2 == 0 { 200 } else { 0 });
+        assert_eq!(stripes.downscale(), [[100; 8]; 8]);
+
+        // A 2x2 picture covers four cells per pixel
+        let small = image(2, 2, |x, y| (x + 2 * y) as u8);
+        assert_eq!(small.downscale()[0], [0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(small.downscale()[7], [2, 2, 2, 2, 3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_brightness() {
+        let gray = [[64u8; 8]; 8];
+        assert_eq!(to_bits(&gray, 0.5, false), [[false; 8]; 8]);
+        let lit = to_bits(&gray, 0.5, true)
+            .iter()
+            .flatten()
+            .filter(|&&b| b)
+            .count();
+        // A quarter of full white
+        assert!((14..=18).contains(&lit), "{lit}");
+    }
+
+    #[test]
+    fn pictures_land_on_the_front_or_stack_into_layers() {
+        // Just the top left pixel lit
+        let corner = image(8, 8, |x, y| if (x, y) == (0, 0) { 255 } else { 0 });
+        let pictures = vec![(Duration::from_millis(50), corner); 9];
+
+        let conversion = Conversion {
+            depth: 2,
+            ..Conversion::default()
+        };
+        let frames = to_frames(&pictures, conversion);
+        assert_eq!(frames.len(), 9);
+        let lit: Vec<Coord> = Coord::all().filter(|c| c.get(&frames[0].1)).collect();
+        assert_eq!(lit, [Coord::new(0, 0, 7), Coord::new(0, 1, 7)]);
+
+        let conversion = Conversion {
+            layout: ImageLayout::Layers,
+            ..Conversion::default()
+        };
+        let frames = to_frames(&pictures, conversion);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].0, Duration::from_millis(400));
+        assert!((0..8).all(|z| Coord::new(0, 7, z).get(&frames[0].1)));
+        let lit: Vec<Coord> = Coord::all().filter(|c| c.get(&frames[1].1)).collect();
+        assert_eq!(lit, [Coord::new(0, 7, 0)]);
+    }
+
+    #[test]
+    fn slices_stack_either_way_up() {
+        // The top left pixel of the third slice, and every pixel of the last
+        let tall = image(8, 64, |x, y| u8::from((x, y) == (0, 16) || y >= 56) * 255);
+        let frame = from_slices(&tall, SliceOrder::BottomUp, 0.5).unwrap();
+        assert!(Coord::new(0, 7, 2).get(&frame));
+        assert_eq!(frame[7], [0xFF; 8]);
+        assert_eq!(Coord::all().filter(|c| c.get(&frame)).count(), 65);
+
+        let wide = image(64, 8, |x, y| u8::from((x, y) == (16, 0) || x >= 56) * 255);
+        let frame = from_slices(&wide, SliceOrder::TopDown, 0.5).unwrap();
+        assert!(Coord::new(0, 7, 5).get(&frame));
+        assert_eq!(frame[0], [0xFF; 8]);
+
+        assert!(from_slices(&image(8, 8, |_, _| 0), SliceOrder::BottomUp, 0.5).is_err());
+    }
+}
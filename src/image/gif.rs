@@ -0,0 +1,437 @@
+//! Animated GIF decoding, just enough to play the frames back in gray: transparency, frame
+//! disposal and interlacing are handled, comments and other extensions skipped.
+
+use std::{fs, io, path::Path, time::Duration};
+
+use crate::invalid;
+
+use super::{luma, GrayImage};
+
+/// Shown for frames whose delay is 0 or 1 hundredths of a second, as browsers do
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+/// The most codes an LZW table holds, 12 bits' worth
+const MAX_CODES: usize = 4096;
+
+/// Reads through a GIF's bytes, failing on truncation
+struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(invalid("GIF", "file ends early"));
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// A colour table of 2 to the power of one more than `size_bits` entries, as gray levels
+    fn palette(&mut self, size_bits: u8) -> io::Result<Vec<u8>> {
+        let colours = self.take(3 << (size_bits + 1))?;
+        Ok(colours
+            .chunks_exact(3)
+            .map(|rgb| luma(rgb[0], rgb[1], rgb[2]))
+            .collect())
+    }
+
+    /// A run of data sub-blocks, each led by its length, up to the empty one or as much as there
+    /// is of a file cut short
+    fn sub_blocks(&mut self) -> Vec<u8> {
+        let mut data = Vec::new();
+        while let Some((&len, rest)) = self.data.split_first() {
+            let len = usize::from(len).min(rest.len());
+            data.extend_from_slice(&rest[..len]);
+            self.data = &rest[len..];
+            if len == 0 {
+                break;
+            }
+        }
+        data
+    }
+}
+
+/// Undo the variable width LZW compression of an image's colour indices
+fn lzw_decode(min_code_size: u8, data: &[u8], pixels: usize) -> io::Result<Vec<u8>> {
+    if !(1..=11).contains(&min_code_size) {
+        return Err(invalid("GIF", "LZW code size out of range"));
+    }
+    let clear = 1usize << min_code_size;
+    let end = clear + 1;
+    // Each code is the code of the string it extends and the index it adds
+    let mut prefix = vec![0u16; MAX_CODES];
+    let mut suffix = vec![0u8; MAX_CODES];
+    let mut first = vec![0u8; MAX_CODES];
+    for code in 0..clear {
+        suffix[code] = code as u8;
+        first[code] = code as u8;
+    }
+    let mut len = clear + 2;
+    let mut size = u32::from(min_code_size) + 1;
+    let mut previous: Option<usize> = None;
+
+    let mut out = Vec::with_capacity(pixels);
+    let mut string = Vec::new();
+    let (mut bits, mut held) = (0u32, 0u32);
+    let mut bytes = data.iter();
+
+    'codes: loop {
+        while held < size {
+            let Some(&byte) = bytes.next() else {
+                // Some encoders leave off the end code
+                break 'codes;
+            };
+            bits |= u32::from(byte) << held;
+            held += 8;
+        }
+        let code = (bits & ((1 << size) - 1)) as usize;
+        bits >>= size;
+        held -= size;
+
+        if code == clear {
+            len = clear + 2;
+            size = u32::from(min_code_size) + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+
+        match previous {
+            None if code < clear => {}
+            None => return Err(invalid("GIF", "LZW data starts with an unknown code")),
+            Some(previous) if len < MAX_CODES => {
+                // The new string is the last one plus the first index of this one, which for
+                // a code not yet in the table is the last one's own first index
+                let added = match code {
+                    code if code < len => first[code],
+                    code if code == len => first[previous],
+                    _ => return Err(invalid("GIF", "LZW code out of order")),
+                };
+                prefix[len] = previous as u16;
+                suffix[len] = added;
+                first[len] = first[previous];
+                len += 1;
+                if len == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+            Some(_) if code >= len => return Err(invalid("GIF", "LZW code out of order")),
+            Some(_) => {}
+        }
+
+        string.clear();
+        let mut walk = code;
+        while walk >= clear {
+            string.push(suffix[walk]);
+            walk = usize::from(prefix[walk]);
+        }
+        string.push(walk as u8);
+        out.extend(string.iter().rev());
+        previous = Some(code);
+    }
+
+    out.resize(pixels, 0);
+    Ok(out)
+}
+
+/// Rows of an interlaced image in the order they are stored
+fn interlaced_rows(height: usize) -> impl Iterator<Item = usize> {
+    [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .into_iter()
+        .flat_map(move |(start, step)| (start..height).step_by(step))
+}
+
+/// How the area of a frame is left for the next one
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Disposal {
+    Keep,
+    /// Cleared to transparent, which shows as black
+    Background,
+    /// Put back as it was before the frame was drawn
+    Previous,
+}
+
+/// Every frame of a GIF as it appears in turn, with how long each stays up. A still picture is
+/// a single frame.
+pub fn decode(data: &[u8]) -> io::Result<Vec<(Duration, GrayImage)>> {
+    let mut bytes = Bytes { data };
+    let signature = bytes.take(6)?;
+    if signature != b"GIF87a" && signature != b"GIF89a" {
+        return Err(invalid("GIF", "not a GIF file"));
+    }
+    let width = usize::from(bytes.u16()?);
+    let height = usize::from(bytes.u16()?);
+    let flags = bytes.u8()?;
+    let _background = bytes.u8()?;
+    let _aspect = bytes.u8()?;
+    let global = if flags & 0x80 != 0 {
+        Some(bytes.palette(flags & 0x07)?)
+    } else {
+        None
+    };
+
+    // Transparent pixels are black, as is anything before the first frame draws over it
+    let mut canvas = vec![0u8; width * height];
+    let mut frames = Vec::new();
+    let mut delay = DEFAULT_DELAY;
+    let mut transparent = None;
+    let mut disposal = Disposal::Keep;
+
+    loop {
+        // Files missing their trailer, or cut off partway through the last image, still play
+        // as far as they go, as they do in browsers
+        if bytes.data.is_empty() && !frames.is_empty() {
+            break;
+        }
+        match bytes.u8()? {
+            // Extension
+            0x21 => {
+                let label = bytes.u8()?;
+                let block = bytes.sub_blocks();
+                // Graphic control, setting up the next image
+                if label == 0xF9 && block.len() >= 4 {
+                    disposal = match (block[0] >> 2) & 0x07 {
+                        2 => Disposal::Background,
+                        3 => Disposal::Previous,
+                        _ => Disposal::Keep,
+                    };
+                    let hundredths = u16::from_le_bytes([block[1], block[2]]);
+                    delay = if hundredths > 1 {
+                        Duration::from_millis(u64::from(hundredths) * 10)
+                    } else {
+                        DEFAULT_DELAY
+                    };
+                    transparent = (block[0] & 0x01 != 0).then_some(block[3]);
+                }
+            }
+            // Image
+            0x2C => {
+                let left = usize::from(bytes.u16()?);
+                let top = usize::from(bytes.u16()?);
+                let w = usize::from(bytes.u16()?);
+                let h = usize::from(bytes.u16()?);
+                let flags = bytes.u8()?;
+                let local = if flags & 0x80 != 0 {
+                    Some(bytes.palette(flags & 0x07)?)
+                } else {
+                    None
+                };
+                let palette = local
+                    .as_ref()
+                    .or(global.as_ref())
+                    .ok_or_else(|| invalid("GIF", "image without a colour table"))?;
+                let min_code_size = bytes.u8()?;
+                let indices = lzw_decode(min_code_size, &bytes.sub_blocks(), w * h)?;
+
+                let before = (disposal == Disposal::Previous).then(|| canvas.clone());
+                let rows: Vec<usize> = if flags & 0x40 != 0 {
+                    interlaced_rows(h).collect()
+                } else {
+                    (0..h).collect()
+                };
+                // Clipped to the screen, as some encoders don't
+                let visible = |x: usize, y: usize| x < width && y < height;
+                for (row, &y) in indices.chunks_exact(w.max(1)).zip(&rows) {
+                    for (x, &index) in row.iter().enumerate() {
+                        if Some(index) == transparent || !visible(left + x, top + y) {
+                            continue;
+                        }
+                        canvas[(top + y) * width + left + x] =
+                            palette.get(usize::from(index)).copied().unwrap_or(0);
+                    }
+                }
+                frames.push((
+                    delay,
+                    GrayImage {
+                        width,
+                        height,
+                        pixels: canvas.clone(),
+                    },
+                ));
+
+                match disposal {
+                    Disposal::Keep => {}
+                    Disposal::Background => {
+                        for y in (top..top + h).filter(|&y| y < height) {
+                            for x in (left..left + w).filter(|&x| x < width) {
+                                canvas[y * width + x] = 0;
+                            }
+                        }
+                    }
+                    Disposal::Previous => canvas = before.expect("kept for this disposal"),
+                }
+                // Graphic control applies to only the one image
+                delay = DEFAULT_DELAY;
+                transparent = None;
+                disposal = Disposal::Keep;
+            }
+            // Trailer
+            0x3B => break,
+            _ => return Err(invalid("GIF", "unknown block")),
+        }
+    }
+
+    if frames.is_empty() {
+        return Err(invalid("GIF", "no images"));
+    }
+    Ok(frames)
+}
+
+/// Load every frame of a GIF file
+pub fn read(path: &Path) -> io::Result<Vec<(Duration, GrayImage)>> {
+    decode(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// LZW compression as an encoder does it, with the code size growing in step with the
+    /// decoder
+    fn lzw_encode(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+        let clear = 1u16 << min_code_size;
+        let mut size = u32::from(min_code_size) + 1;
+        let mut next = clear + 2;
+        let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+        let mut out = Vec::new();
+        let (mut bits, mut held) = (0u32, 0u32);
+        let mut write = |code: u16, size: u32| {
+            bits |= u32::from(code) << held;
+            held += size;
+            while held >= 8 {
+                out.push(bits as u8);
+                bits >>= 8;
+                held -= 8;
+            }
+        };
+
+        write(clear, size);
+        let mut string = u16::from(indices[0]);
+        for &index in &indices[1..] {
+            if let Some(&code) = table.get(&(string, index)) {
+                string = code;
+                continue;
+            }
+            write(string, size);
+            table.insert((string, index), next);
+            next += 1;
+            if next == (1 << size) + 1 {
+                size += 1;
+            }
+            if usize::from(next) == MAX_CODES {
+                write(clear, size);
+                table.clear();
+                next = clear + 2;
+                size = u32::from(min_code_size) + 1;
+            }
+            string = u16::from(index);
+        }
+        write(string, size);
+        // The decoder adds a code on reading the last string too
+        if next + 1 == (1 << size) + 1 {
+            size += 1;
+        }
+        write(clear + 1, size);
+        write(0, 7);
+        out
+    }
+
+    fn sub_blocks(data: &[u8]) -> Vec<u8> {
+        let mut blocks = Vec::new();
+        for chunk in data.chunks(255) {
+            blocks.push(chunk.len() as u8);
+            blocks.extend_from_slice(chunk);
+        }
+        blocks.push(0);
+        blocks
+    }
+
+    /// A 4x4 GIF with black, white, gray and red, and an image per entry of `images`: its
+    /// delay, transparent index, disposal and 16 colour indices
+    fn gif(images: &[(u16, Option<u8>, u8, [u8; 16])]) -> Vec<u8> {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend([4, 0, 4, 0, 0x81, 0, 0]);
+        gif.extend([0, 0, 0, 255, 255, 255, 128, 128, 128, 255, 0, 0]);
+        for &(delay, transparent, disposal, indices) in images {
+            let [low, high] = delay.to_le_bytes();
+            let flags = disposal << 2 | u8::from(transparent.is_some());
+            gif.extend([0x21, 0xF9, 4, flags, low, high, transparent.unwrap_or(0), 0]);
+            gif.extend([0x2C, 0, 0, 0, 0, 4, 0, 4, 0, 0, 2]);
+            gif.extend(sub_blocks(&lzw_encode(2, &indices)));
+        }
+        gif.push(0x3B);
+        gif
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        for (min_code_size, len) in [(2, 16), (2, 5000), (8, 20_000), (4, 1)] {
+            let indices: Vec<u8> = (0..len)
+                .map(|i| {
+                    // Runs as well as noise, to exercise long strings
+                    if i % 100 < 50 {
+                        (i / 100 % (1 << min_code_size)) as u8
+                    } else {
+                        rng.gen_range(0..1 << min_code_size) as u8
+                    }
+                })
+                .collect();
+            let packed = lzw_encode(min_code_size, &indices);
+            assert_eq!(lzw_decode(min_code_size, &packed, len).unwrap(), indices);
+        }
+    }
+
+    #[test]
+    fn frames_build_on_each_other() {
+        let mut first = [0; 16];
+        first[..4].copy_from_slice(&[1, 2, 3, 1]);
+        // Only the last pixel drawn, the rest left to show through
+        let mut second = [3; 16];
+        second[15] = 1;
+
+        let frames = decode(&gif(&[(5, None, 1, first), (0, Some(3), 2, second)])).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].0, Duration::from_millis(50));
+        assert_eq!(frames[1].0, DEFAULT_DELAY);
+        assert_eq!(frames[0].1.pixels[..4], [255, 128, 76, 255]);
+        assert_eq!(frames[1].1.pixels[..4], [255, 128, 76, 255]);
+        assert_eq!(frames[1].1.pixels[15], 255);
+
+        // Disposed of to the background, then a frame of nothing but transparency
+        let frames = decode(&gif(&[(5, None, 2, first), (5, Some(3), 0, [3; 16])])).unwrap();
+        assert_eq!(frames[1].1.pixels, [0; 16]);
+    }
+
+    #[test]
+    fn broken_files_are_refused() {
+        let whole = gif(&[(5, None, 0, [1; 16])]);
+        assert!(decode(&whole).is_ok());
+        assert!(decode(&whole[..20]).is_err());
+        assert!(decode(b"PNG not a gif").is_err());
+
+        // Cut off in the image, what there is of it is shown
+        let cut = decode(&whole[..whole.len() - 4]).unwrap();
+        assert_eq!(cut[0].1.pixels[0], 255);
+
+        let mut no_images = whole[..6 + 7 + 12].to_vec();
+        no_images.push(0x3B);
+        assert!(decode(&no_images).is_err());
+    }
+}
@@ -0,0 +1,511 @@
+//! DEFLATE decompression (RFC 1951) of zlib streams (RFC 1950), as found in PNG files, and a
+//! compressor using the fixed codes for animation files. Written for clarity over speed, the
+//! pictures and animations being small.
+
+use std::io;
+
+use crate::invalid;
+
+/// Lengths for codes 257 to 285, before their extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order code length code lengths are stored in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const MAX_BITS: usize = 15;
+
+/// Reads bits from the least significant end of each byte first
+struct Bits<'a> {
+    data: &'a [u8],
+    /// Bits read but not yet used, the next in the lowest place
+    held: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let (&byte, rest) = self
+                .data
+                .split_first()
+                .ok_or_else(|| invalid("zlib data", "ends early"))?;
+            self.data = rest;
+            self.held |= u32::from(byte) << self.count;
+            self.count += 8;
+        }
+        let value = self.held & ((1 << n) - 1);
+        self.held >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Skip to the next whole byte, for stored blocks
+    fn align(&mut self) {
+        self.held = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.data.len() < n {
+            return Err(invalid("zlib data", "ends early"));
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+/// A canonical Huffman code, given as how many codes there are of each length and the symbols
+/// in code order
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// The code with these code lengths for symbols 0 up, 0 for symbols not used
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+        counts[0] = 0;
+        // Shorter codes come first, then symbols in order within a length
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|&s| lengths[usize::from(s)] != 0)
+            .collect();
+        symbols.sort_by_key(|&s| lengths[usize::from(s)]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> io::Result<u16> {
+        // The first code of each length and its place among the symbols, found a bit at a time
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0usize);
+        for &count in &self.counts[1..] {
+            code |= bits.bits(1)?;
+            let count = u32::from(count);
+            if code < first + count {
+                return Ok(self.symbols[index + (code - first) as usize]);
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("zlib data", "unknown Huffman code"))
+    }
+}
+
+/// The codes of a fixed Huffman block
+fn fixed() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+/// The codes a dynamic Huffman block starts with
+fn dynamic(bits: &mut Bits) -> io::Result<(Huffman, Huffman)> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let lengths_code = Huffman::new(&lengths);
+
+    // Literal and distance code lengths run on from one to the other
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match lengths_code.decode(bits)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => {
+                let last = *lengths
+                    .last()
+                    .ok_or_else(|| invalid("zlib data", "repeat with nothing before"))?;
+                (last, 3 + bits.bits(2)?)
+            }
+            17 => (0, 3 + bits.bits(3)?),
+            _ => (0, 11 + bits.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err(invalid("zlib data", "code lengths overrun"));
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("zlib data", "no end of block code"));
+    }
+    let (literal, distance) = lengths.split_at(literals);
+    Ok((Huffman::new(literal), Huffman::new(distance)))
+}
+
+/// Unpack one Huffman coded block onto the end of `out`
+fn codes(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    literal: &Huffman,
+    distance: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = usize::from(literal.decode(bits)?);
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let code = symbol - 257;
+                if code >= LENGTH_BASE.len() {
+                    return Err(invalid("zlib data", "bad length code"));
+                }
+                let len = usize::from(LENGTH_BASE[code])
+                    + bits.bits(u32::from(LENGTH_EXTRA[code]))? as usize;
+                let code = usize::from(distance.decode(bits)?);
+                if code >= DISTANCE_BASE.len() {
+                    return Err(invalid("zlib data", "bad distance code"));
+                }
+                let back = usize::from(DISTANCE_BASE[code])
+                    + bits.bits(u32::from(DISTANCE_EXTRA[code]))? as usize;
+                let start = out
+                    .len()
+                    .checked_sub(back)
+                    .ok_or_else(|| invalid("zlib data", "distance too far back"))?;
+                // Byte by byte, as the copy may overlap what it is writing
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % MOD;
+        b = (b + a) % MOD;
+    }
+    b << 16 | a
+}
+
+/// Unpack a zlib stream, checking its checksum
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let [method, flags, ..] = *data else {
+        return Err(invalid("zlib data", "ends early"));
+    };
+    if method & 0x0F != 8 || (u16::from(method) << 8 | u16::from(flags)) % 31 != 0 {
+        return Err(invalid("zlib data", "not deflate"));
+    }
+    if flags & 0x20 != 0 {
+        return Err(invalid(
+            "zlib data",
+            "preset dictionaries are not supported",
+        ));
+    }
+
+    let mut bits = Bits {
+        data: &data[2..],
+        held: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let header = bits.bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(invalid("zlib data", "stored block length mismatch"));
+                }
+                out.extend_from_slice(bits.bytes(usize::from(len))?);
+            }
+            1 => {
+                let (literal, distance) = fixed();
+                codes(&mut bits, &mut out, &literal, &distance)?;
+            }
+            2 => {
+                let (literal, distance) = dynamic(&mut bits)?;
+                codes(&mut bits, &mut out, &literal, &distance)?;
+            }
+            _ => return Err(invalid("zlib data", "bad block type")),
+        }
+        if last {
+            break;
+        }
+    }
+
+    bits.align();
+    let checksum = bits.bytes(4)?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+        return Err(invalid("zlib data", "checksum mismatch"));
+    }
+    Ok(out)
+}
+
+/// Writes bits from the least significant end of each byte first, as `Bits` reads them
+struct BitWriter {
+    out: Vec<u8>,
+    held: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, n: u32) {
+        self.held |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.held as u8);
+            self.held >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// A Huffman code, which unlike everything else goes most significant bit first
+    fn code(&mut self, (code, len): (u32, u32)) {
+        self.bits(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.held as u8);
+        }
+        self.out
+    }
+}
+
+/// The fixed code for a literal or length symbol, and its length
+fn fixed_code(symbol: u16) -> (u32, u32) {
+    let symbol = u32::from(symbol);
+    match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    }
+}
+
+/// The code for `value` from a table of bases, and the extra bits after it
+fn base_code(bases: &[u16], value: u16) -> (usize, u32) {
+    let code = bases
+        .iter()
+        .rposition(|&base| base <= value)
+        .expect("at least the first base");
+    (code, u32::from(value - bases[code]))
+}
+
+/// How far back matches are looked for, all that DEFLATE allows
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Earlier positions with the same first bytes tried before settling for the best so far
+const MAX_CHAIN: usize = 64;
+
+/// Earlier positions in `data` sharing their first three bytes, most recent first
+struct Chains<'a> {
+    data: &'a [u8],
+    head: Vec<Option<usize>>,
+    previous: Vec<Option<usize>>,
+}
+
+impl<'a> Chains<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Chains {
+            data,
+            head: vec![None; 1 << 15],
+            previous: vec![None; data.len()],
+        }
+    }
+
+    fn hash(&self, at: usize) -> Option<usize> {
+        let bytes = self.data.get(at..at + MIN_MATCH)?;
+        Some(
+            (usize::from(bytes[0]) << 10 ^ usize::from(bytes[1]) << 5 ^ usize::from(bytes[2]))
+                & 0x7FFF,
+        )
+    }
+
+    fn insert(&mut self, at: usize) {
+        if let Some(hash) = self.hash(at) {
+            self.previous[at] = self.head[hash].replace(at);
+        }
+    }
+
+    /// The longest earlier copy of what starts at `at`, as its length and distance
+    fn longest(&self, at: usize) -> Option<(usize, usize)> {
+        let mut candidate = self.head[self.hash(at)?];
+        let limit = MAX_MATCH.min(self.data.len() - at);
+        let mut best: Option<(usize, usize)> = None;
+        for _ in 0..MAX_CHAIN {
+            let Some(from) = candidate.filter(|&from| at - from <= WINDOW) else {
+                break;
+            };
+            let len = self.data[from..]
+                .iter()
+                .zip(&self.data[at..at + limit])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if len >= MIN_MATCH && best.is_none_or(|(longest, _)| len > longest) {
+                best = Some((len, at - from));
+            }
+            candidate = self.previous[from];
+        }
+        best
+    }
+}
+
+/// Pack `data` as a zlib stream of one block with the fixed codes, which suits the long runs
+/// and repeats of animation frames without the bother of building codes of its own
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter {
+        out: vec![0x78, 0x01],
+        held: 0,
+        count: 0,
+    };
+    // The last block, with fixed codes
+    out.bits(1, 1);
+    out.bits(1, 2);
+
+    let mut chains = Chains::new(data);
+    let mut at = 0;
+    while at < data.len() {
+        match chains.longest(at) {
+            Some((len, distance)) => {
+                let (code, extra) = base_code(&LENGTH_BASE, len as u16);
+                out.code(fixed_code(257 + code as u16));
+                out.bits(extra, LENGTH_EXTRA[code].into());
+                let (code, extra) = base_code(&DISTANCE_BASE, distance as u16);
+                out.code((code as u32, 5));
+                out.bits(extra, DISTANCE_EXTRA[code].into());
+                (at..at + len).for_each(|i| chains.insert(i));
+                at += len;
+            }
+            None => {
+                out.code(fixed_code(data[at].into()));
+                chains.insert(at);
+                at += 1;
+            }
+        }
+    }
+    out.code(fixed_code(256));
+
+    let mut out = out.finish();
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// Wrap `data` as a zlib stream of stored blocks, for building test files
+#[cfg(test)]
+pub fn store(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = data.chunks(u16::MAX as usize).peekable();
+    if chunks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let len = chunk.len() as u16;
+        out.push(u8::from(chunks.peek().is_none()));
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn every_block_type_unpacks() {
+        let text = b"the cube, the cube, the cube! ".repeat(6);
+        // Stored as is, then with fixed codes, both from zlib
+        let stored = store(&text);
+        assert_eq!(decompress(&stored).unwrap(), text);
+        let fixed = hex("78da4b4c4a4e042300113d0373");
+        assert_eq!(decompress(&fixed).unwrap(), b"abcabcabc");
+
+        // Long enough for zlib to build its own codes
+        let skewed: Vec<u8> = (0..800usize)
+            .map(|i| b"aaaaaaaabbbbccde"[(i * i * 31 + i * 7 + i / 5) % 16])
+            .collect();
+        let dynamic = hex(
+            "78daed8ac111003008c2660de8fe2b145da3f2e00217902d5033516653c2e1dc35c0d679e7fde73d82f231d8",
+        );
+        assert_eq!(decompress(&dynamic).unwrap(), skewed);
+    }
+
+    #[test]
+    fn compressed_data_unpacks_to_itself() {
+        let mut rng = 0x2545_f491_4f6c_dd1du64;
+        let mut noise = || {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            rng as u8
+        };
+        let random: Vec<u8> = (0..5000).map(|_| noise()).collect();
+        let sparse: Vec<u8> = (0..5000).map(|_| noise() & noise() & noise()).collect();
+        // Repeats further back than the window can reach
+        let distant: Vec<u8> = random
+            .iter()
+            .chain(&[0; 40_000])
+            .chain(&random)
+            .copied()
+            .collect();
+        let runs = [0u8; 70_000];
+        for data in [
+            &b""[..],
+            b"a",
+            b"abcabcabc",
+            &random,
+            &sparse,
+            &distant,
+            &runs,
+        ] {
+            assert_eq!(
+                decompress(&compress(data)).unwrap(),
+                data,
+                "{} bytes",
+                data.len()
+            );
+        }
+        assert!(compress(&runs).len() < 1000);
+    }
+
+    #[test]
+    fn damage_is_caught() {
+        let mut stream = store(b"voxels");
+        let last = stream.len() - 1;
+        stream[last] ^= 1;
+        assert!(decompress(&stream).is_err());
+        assert!(decompress(&store(b"voxels")[..8]).is_err());
+        assert!(decompress(&[0x78]).is_err());
+    }
+}
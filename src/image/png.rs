@@ -0,0 +1,255 @@
+//! PNG decoding to gray, for every colour type and bit depth apart from interlaced pictures.
+//! Transparent pixels show as black.
+
+use std::{fs, io, path::Path};
+
+use crate::invalid;
+
+use super::{inflate, luma, GrayImage};
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Undo the per row filter of each scanline in place. `unit` is the bytes per pixel, at least
+/// one, that the filters look back by.
+fn unfilter(data: &mut [u8], stride: usize, unit: usize) -> io::Result<()> {
+    let mut previous = vec![0u8; stride];
+    for line in data.chunks_exact_mut(stride + 1) {
+        let (filter, row) = line.split_first_mut().expect("at least the filter byte");
+        for i in 0..stride {
+            let left = if i >= unit { row[i - unit] } else { 0 };
+            let up = previous[i];
+            let up_left = if i >= unit { previous[i - unit] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(invalid("PNG", "unknown filter")),
+            };
+            row[i] = row[i].wrapping_add(predicted);
+        }
+        previous.copy_from_slice(row);
+    }
+    Ok(())
+}
+
+/// Whichever of left, up and up-left is closest to left + up - up-left
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let distance = |v: u8| (estimate - i16::from(v)).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// The picture in a PNG
+pub fn decode(data: &[u8]) -> io::Result<GrayImage> {
+    let mut rest = data
+        .strip_prefix(SIGNATURE)
+        .ok_or_else(|| invalid("PNG", "not a PNG file"))?;
+
+    let mut header = None;
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut alphas: Vec<u8> = Vec::new();
+    let mut compressed = Vec::new();
+    loop {
+        if rest.len() < 12 {
+            return Err(invalid("PNG", "file ends early"));
+        }
+        let len = be32(rest) as usize;
+        if rest.len() < 12 + len {
+            return Err(invalid("PNG", "file ends early"));
+        }
+        let (kind, body) = (&rest[4..8], &rest[8..8 + len]);
+        if be32(&rest[8 + len..]) != crc32(&rest[4..8 + len]) {
+            return Err(invalid("PNG", "chunk checksum mismatch"));
+        }
+        rest = &rest[12 + len..];
+
+        match kind {
+            b"IHDR" if body.len() == 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+            b"tRNS" => alphas = body.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            // Ancillary chunks, named in lower case, can be skipped
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => return Err(invalid("PNG", "unknown critical chunk")),
+        }
+    }
+
+    let header = header.ok_or_else(|| invalid("PNG", "no header"))?;
+    let width = be32(&header) as usize;
+    let height = be32(&header[4..]) as usize;
+    let (depth, colour, interlace) = (header[8], header[9], header[12]);
+    if interlace != 0 {
+        return Err(invalid("PNG", "interlaced pictures are not supported"));
+    }
+    let channels = match (colour, depth) {
+        (0, 1 | 2 | 4 | 8 | 16) => 1,
+        (2, 8 | 16) => 3,
+        (3, 1 | 2 | 4 | 8) if !palette.is_empty() => 1,
+        (4, 8 | 16) => 2,
+        (6, 8 | 16) => 4,
+        _ => return Err(invalid("PNG", "unsupported colour type or depth")),
+    };
+    let bits = channels * usize::from(depth);
+    let stride = (width * bits).div_ceil(8);
+    let unit = bits.div_ceil(8);
+
+    let mut data = inflate::decompress(&compressed)?;
+    if data.len() < (stride + 1) * height {
+        return Err(invalid("PNG", "image data ends early"));
+    }
+    unfilter(&mut data, stride, unit)?;
+
+    // Samples scaled to 8 bits, taking the high byte of 16 bit ones
+    let sample = |row: &[u8], index: usize| -> u8 {
+        match depth {
+            16 => row[index * 2],
+            8 => row[index],
+            _ => {
+                let bit = index * usize::from(depth);
+                let max = (1u16 << depth) - 1;
+                let value = u16::from(row[bit / 8] >> (8 - usize::from(depth) - bit % 8)) & max;
+                if colour == 3 {
+                    value as u8
+                } else {
+                    (value * 255 / max) as u8
+                }
+            }
+        }
+    };
+    let mut pixels = Vec::with_capacity(width * height);
+    for line in data.chunks_exact(stride + 1).take(height) {
+        let row = &line[1..];
+        for x in 0..width {
+            let at = |channel: usize| sample(row, x * channels + channel);
+            let (level, alpha) = match colour {
+                0 => (at(0), 255),
+                2 => (luma(at(0), at(1), at(2)), 255),
+                3 => {
+                    let index = usize::from(at(0));
+                    let [r, g, b] = palette.get(index).copied().unwrap_or_default();
+                    (luma(r, g, b), alphas.get(index).copied().unwrap_or(255))
+                }
+                4 => (at(0), at(1)),
+                _ => (luma(at(0), at(1), at(2)), at(3)),
+            };
+            // Over black
+            pixels.push((u16::from(level) * u16::from(alpha) / 255) as u8);
+        }
+    }
+
+    Ok(GrayImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Load the picture in a PNG file
+pub fn read(path: &Path) -> io::Result<GrayImage> {
+    decode(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], body: &[u8]) {
+        png.extend((body.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend(kind);
+        png.extend(body);
+        let crc = crc32(&png[start..]);
+        png.extend(crc.to_be_bytes());
+    }
+
+    /// A PNG of the given colour type and depth, its rows already filtered
+    fn png(
+        width: u32,
+        height: u32,
+        colour: u8,
+        depth: u8,
+        extra: &[(&[u8; 4], &[u8])],
+        rows: &[u8],
+    ) -> Vec<u8> {
+        let mut png = SIGNATURE.to_vec();
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        header.extend([depth, colour, 0, 0, 0]);
+        chunk(&mut png, b"IHDR", &header);
+        for (kind, body) in extra {
+            chunk(&mut png, kind, body);
+        }
+        chunk(&mut png, b"IDAT", &inflate::store(rows));
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn filters_are_undone() {
+        // A 3x2 gray picture, the first row filtered by left, the second by Paeth
+        let rows = [1, 10, 5, 5, 4, 20, 0xFB, 0xFB];
+        let picture = decode(&png(3, 2, 0, 8, &[], &rows)).unwrap();
+        assert_eq!(picture.pixels, [10, 15, 20, 30, 25, 20]);
+
+        let mut up = [2, 10, 20, 30, 2, 1, 1, 1];
+        let picture = decode(&png(3, 2, 0, 8, &[], &up)).unwrap();
+        assert_eq!(picture.pixels, [10, 20, 30, 11, 21, 31]);
+
+        up[4] = 9;
+        assert!(decode(&png(3, 2, 0, 8, &[], &up)).is_err());
+    }
+
+    #[test]
+    fn colour_types_come_out_gray() {
+        // Red and white in RGB
+        let rgb = decode(&png(2, 1, 2, 8, &[], &[0, 255, 0, 0, 255, 255, 255])).unwrap();
+        assert_eq!(rgb.pixels, [76, 255]);
+
+        // A 1 bit palette of black and white, the white half transparent
+        let extra: [(&[u8; 4], &[u8]); 2] =
+            [(b"PLTE", &[0, 0, 0, 255, 255, 255]), (b"tRNS", &[255, 128])];
+        let indexed = decode(&png(4, 1, 3, 1, &extra, &[0, 0b0101_0000])).unwrap();
+        assert_eq!(indexed.pixels, [0, 128, 0, 128]);
+
+        // 2 bit gray scales up to full white, and 16 bit gray with alpha
+        let gray = decode(&png(2, 1, 0, 2, &[], &[0, 0b1101_0000])).unwrap();
+        assert_eq!(gray.pixels, [255, 85]);
+        let alpha = decode(&png(1, 1, 4, 16, &[], &[0, 200, 0, 127, 255])).unwrap();
+        assert_eq!(alpha.pixels, [99]);
+    }
+
+    #[test]
+    fn damage_is_caught() {
+        let mut file = png(1, 1, 0, 8, &[], &[0, 7]);
+        assert_eq!(decode(&file).unwrap().pixels, [7]);
+        let last = file.len() - 13;
+        file[last] ^= 1;
+        assert!(decode(&file).is_err());
+        assert!(decode(b"GIF89a").is_err());
+    }
+}
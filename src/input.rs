@@ -0,0 +1,94 @@
+use std::{
+    io::{self, Read},
+    mem::MaybeUninit,
+    sync::mpsc::{channel, Receiver},
+    thread,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Keys typed on stdin, delivered as soon as they are pressed. The terminal stays in cbreak mode,
+/// without echo or line buffering, for as long as this lives. Ctrl-C still raises SIGINT.
+pub struct Keyboard {
+    keys: Receiver<Key>,
+    saved: Option<libc::termios>,
+}
+
+impl Keyboard {
+    pub fn open() -> io::Result<Self> {
+        let saved = cbreak()?;
+        let (tx, keys) = channel();
+
+        thread::spawn(move || {
+            let mut bytes = io::stdin().lock().bytes();
+            while let Some(Ok(byte)) = bytes.next() {
+                let key = match byte {
+                    // Arrow keys arrive as ESC [ A..D
+                    0x1b => match (bytes.next(), bytes.next()) {
+                        (Some(Ok(b'[')), Some(Ok(b'A'))) => Key::Up,
+                        (Some(Ok(b'[')), Some(Ok(b'B'))) => Key::Down,
+                        (Some(Ok(b'[')), Some(Ok(b'C'))) => Key::Right,
+                        (Some(Ok(b'[')), Some(Ok(b'D'))) => Key::Left,
+                        _ => continue,
+                    },
+                    byte => Key::Char(char::from(byte).to_ascii_lowercase()),
+                };
+                if tx.send(key).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Keyboard { keys, saved })
+    }
+
+    /// Everything pressed since the last call, without blocking
+    pub fn pressed(&self) -> impl Iterator<Item = Key> + '_ {
+        self.keys.try_iter()
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved {
+            // SAFETY: restoring attributes previously read from the same descriptor
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved) };
+        }
+    }
+}
+
+/// Switch stdin to cbreak mode, returning the previous settings. Not being a terminal is fine,
+/// input is then just read as it arrives.
+fn cbreak() -> io::Result<Option<libc::termios>> {
+    // SAFETY: isatty only inspects the descriptor
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 0 {
+        return Ok(None);
+    }
+
+    let mut termios = MaybeUninit::uninit();
+    // SAFETY: tcgetattr fills in the struct when it succeeds
+    let saved = unsafe {
+        if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        termios.assume_init()
+    };
+
+    let mut raw = saved;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    // SAFETY: raw is a fully initialised copy of the current attributes
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(Some(saved))
+}
@@ -0,0 +1,372 @@
+//! Just enough JSON to read animations written by scripts: the whole of the syntax, parsed into
+//! a tree of `Value`s, with errors that say where the text went wrong. Writing is left to
+//! `format!`, with `quote` for strings.
+
+use std::fmt;
+
+/// Nesting allowed before giving up, so hostile input can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in the order written
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member called `key` of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// A whole number, rejecting fractions rather than rounding them
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_f64()
+            .filter(|n| n.fract() == 0.0 && *n >= 0.0 && *n <= u64::MAX as f64)
+            .map(|n| n as u64)
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// Bytes into the text
+    pub offset: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.reason, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &'static str) -> ParseError {
+        ParseError {
+            offset: self.pos,
+            reason,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Step over `byte`, which has to come next
+    fn expect(&mut self, byte: u8, reason: &'static str) -> Result<(), ParseError> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(reason));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.word("true", Value::Bool(true)),
+            Some(b'f') => self.word("false", Value::Bool(false)),
+            Some(b'n') => self.word("null", Value::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("ends early")),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Value, ParseError>,
+    ) -> Result<Value, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn word(&mut self, word: &'static str, value: Value) -> Result<Value, ParseError> {
+        if !self.text[self.pos..].starts_with(word) {
+            return Err(self.error("expected a value"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        let digits = |p: &mut Self| {
+            let from = p.pos;
+            while matches!(p.peek(), Some(b'0'..=b'9')) {
+                p.pos += 1;
+            }
+            p.pos > from
+        };
+
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        // No leading zeros, as in 01
+        if self.peek() == Some(b'0') {
+            self.pos += 1;
+        } else if !digits(self) {
+            return Err(self.error("expected a digit"));
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(self.error("expected a digit"));
+            }
+        }
+
+        let number = self.text[start..self.pos]
+            .parse()
+            .expect("checked to be a number");
+        Ok(Value::Number(number))
+    }
+
+    /// Four hex digits of a `\u` escape
+    fn code_unit(&mut self) -> Result<u16, ParseError> {
+        let hex = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected four hex digits"))?;
+        self.pos += 4;
+        Ok(u16::from_str_radix(hex, 16).expect("checked to be hex"))
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"', "expected a string")?;
+        let mut string = String::new();
+        loop {
+            // Everything up to the next quote, escape or control character as it is
+            let run = self.text[self.pos..]
+                .find(|c: char| c == '"' || c == '\\' || c < ' ')
+                .ok_or_else(|| self.error("string never ends"))?;
+            string.push_str(&self.text[self.pos..self.pos + run]);
+            self.pos += run;
+
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => self.pos += 1,
+                _ => return Err(self.error("control character in a string")),
+            }
+            let escape = self.peek().ok_or_else(|| self.error("string never ends"))?;
+            self.pos += 1;
+            string.push(match escape {
+                b'"' => '"',
+                b'\\' => '\\',
+                b'/' => '/',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'u' => {
+                    let unit = self.code_unit()?;
+                    // Characters beyond the basic plane come as a pair of surrogates
+                    let code = if (0xD800..0xDC00).contains(&unit) {
+                        self.expect(b'\\', "expected a low surrogate")?;
+                        self.expect(b'u', "expected a low surrogate")?;
+                        let low = self.code_unit()?;
+                        if !(0xDC00..0xE000).contains(&low) {
+                            return Err(self.error("expected a low surrogate"));
+                        }
+                        0x10000 + ((u32::from(unit) - 0xD800) << 10 | (u32::from(low) - 0xDC00))
+                    } else {
+                        u32::from(unit)
+                    };
+                    char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))?
+                }
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.error("unknown escape"));
+                }
+            });
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.expect(b'[', "expected an array")?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected , or ]")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, ParseError> {
+        self.expect(b'{', "expected an object")?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(b':', "expected :")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("expected , or }")),
+            }
+        }
+    }
+}
+
+/// `text` as a JSON string, quotes and all
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a whole JSON text
+pub fn parse(text: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != text.len() {
+        return Err(parser.error("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_of_every_kind() {
+        let value = parse(r#" {"a": [1, -2.5e2, 0], "b": {"c": null}, "d": true, "e": "x\"é😀"} "#)
+            .unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Value::Array(vec![
+                Value::Number(1.0),
+                Value::Number(-250.0),
+                Value::Number(0.0)
+            ]))
+        );
+        assert_eq!(value.get("b").and_then(|b| b.get("c")), Some(&Value::Null));
+        assert_eq!(value.get("d"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("e"), Some(&Value::String("x\"é😀".to_owned())));
+        assert_eq!(parse("[]"), Ok(Value::Array(Vec::new())));
+        assert_eq!(parse("7").unwrap().as_u64(), Some(7));
+        assert_eq!(parse("7.5").unwrap().as_u64(), None);
+    }
+
+    #[test]
+    fn quoted_strings_parse_back() {
+        for text in ["", "rain --density 0.1", "\"quoted\" \\ \n\t\u{1}é"] {
+            assert_eq!(parse(&quote(text)), Ok(Value::String(text.to_owned())));
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        fn error(text: &str) -> ParseError {
+            parse(text).unwrap_err()
+        }
+        assert_eq!(error("[1, 2,]").offset, 6);
+        assert_eq!(error("[1 2]").reason, "expected , or ]");
+        assert_eq!(error(r#"{"a" 1}"#).offset, 5);
+        assert_eq!(error("01").offset, 1);
+        assert_eq!(error("\"abc").reason, "string never ends");
+        assert_eq!(error(r#""\q""#).offset, 2);
+        assert_eq!(error("[] x").offset, 3);
+        assert_eq!(error(r#""\ud83d""#).reason, "expected a low surrogate");
+        assert_eq!(error(&"[".repeat(100)).reason, "nested too deeply");
+        assert_eq!(error("").reason, "ends early");
+    }
+}
@@ -0,0 +1,159 @@
+//! Changing a running program from the keyboard it was started on, through the same
+//! [`Control`] settings and commands the HTTP and MQTT remotes use.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    control::{Command, Control},
+    cube::MAX_BRIGHTNESS,
+    input::{Key, Keyboard},
+    orientation::Orientation,
+    pause::Pause,
+};
+
+/// What the keys do, printed when they start working
+pub const HELP: &str = "Keys: + and - speed, up and down brightness, space pauses, s steps a \
+                        frame at a time, x, y and z turn, 0 turns back, n skips to the next \
+                        playlist item";
+/// What the keys do with --step alone
+pub const STEP_HELP: &str = "Keys: space shows the next frame, s runs freely or steps again";
+
+/// How much faster or slower each press of + or - plays
+const SPEED_STEP: f64 = 1.25;
+/// Speeds + and - stop at
+const SPEED_RANGE: (f64, f64) = (1.0 / 16.0, 16.0);
+/// How often waiting keys are acted on
+const POLL: Duration = Duration::from_millis(20);
+
+/// Acts on keys pressed while a program runs, for as long as it lives, and hands the terminal
+/// back as it was once dropped. Without a control only pausing and stepping work.
+pub struct KeyControl {
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KeyControl {
+    pub fn spawn(control: Option<Arc<Control>>, pause: Arc<Pause>) -> io::Result<Self> {
+        let keyboard = Keyboard::open()?;
+        let done = Arc::new(AtomicBool::new(false));
+        let finished = done.clone();
+        let thread = thread::spawn(move || {
+            while !finished.load(Ordering::Relaxed) {
+                for key in keyboard.pressed() {
+                    press(key, control.as_deref(), &pause);
+                }
+                thread::sleep(POLL);
+            }
+        });
+        Ok(KeyControl {
+            done,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for KeyControl {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // The keyboard goes with the thread, restoring the terminal
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Do whatever `key` does, reporting new settings on stderr. Other keys are ignored, as are
+/// all but pausing and stepping without a control.
+pub fn press(key: Key, control: Option<&Control>, pause: &Pause) {
+    match key {
+        Key::Char(' ' | 'p') => return pause.toggle(),
+        Key::Char('s') => return pause.toggle_stepping(),
+        _ => {}
+    }
+    let Some(control) = control else {
+        return;
+    };
+    match key {
+        Key::Char('+' | '=') => change_speed(control, SPEED_STEP),
+        Key::Char('-' | '_') => change_speed(control, 1.0 / SPEED_STEP),
+        Key::Up | Key::Char(']') => change_brightness(control, 1),
+        Key::Down | Key::Char('[') => change_brightness(control, -1),
+        Key::Char(name @ ('x' | 'y' | 'z')) => {
+            let axis = usize::from(name as u8 - b'x');
+            control.update_settings(|settings| {
+                settings.orientation = settings.orientation.then(Orientation::quarter_turn(axis));
+                settings.orientation_steps = match settings.orientation_steps.as_str() {
+                    "none" => format!("{name}90"),
+                    steps => format!("{steps},{name}90"),
+                };
+                eprintln!("Rotation {}", settings.orientation_steps);
+            });
+        }
+        Key::Char('0') => {
+            control.update_settings(|settings| {
+                settings.orientation = Orientation::IDENTITY;
+                settings.orientation_steps = "none".to_owned();
+            });
+            eprintln!("Rotation none");
+        }
+        Key::Right | Key::Char('n') => control.send(Command::Next),
+        _ => {}
+    }
+}
+
+fn change_speed(control: &Control, factor: f64) {
+    control.update_settings(|settings| {
+        settings.speed = (settings.speed * factor).clamp(SPEED_RANGE.0, SPEED_RANGE.1);
+        eprintln!("Speed {:.2}x", settings.speed);
+    });
+}
+
+fn change_brightness(control: &Control, step: i8) {
+    control.update_settings(|settings| {
+        settings.brightness = settings
+            .brightness
+            .saturating_add_signed(step)
+            .min(MAX_BRIGHTNESS);
+        eprintln!("Brightness {}", settings.brightness);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_change_the_settings() {
+        let control = Control::new();
+        let pause = Pause::new();
+        for key in [Key::Char('+'), Key::Char('+'), Key::Down, Key::Char('z')] {
+            press(key, Some(&control), &pause);
+        }
+        press(Key::Char('x'), Some(&control), &pause);
+
+        let settings = control.settings();
+        assert_eq!(settings.speed, SPEED_STEP * SPEED_STEP);
+        assert_eq!(settings.brightness, MAX_BRIGHTNESS - 1);
+        assert_eq!(settings.orientation_steps, "z90,x90");
+        assert_eq!(
+            settings.orientation,
+            "z90,x90".parse::<Orientation>().unwrap()
+        );
+
+        // Brightness stops at the top, and n asks for the next item
+        for _ in 0..3 {
+            press(Key::Up, Some(&control), &pause);
+        }
+        press(Key::Char('n'), Some(&control), &pause);
+        assert_eq!(control.settings().brightness, MAX_BRIGHTNESS);
+        assert_eq!(control.take_commands(), [Command::Next]);
+    }
+}
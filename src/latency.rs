@@ -0,0 +1,179 @@
+use std::{
+    collections::VecDeque,
+    io,
+    mem::{size_of, zeroed},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket},
+    os::fd::AsRawFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::sync_channel,
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    cube::DriverConfig,
+    display::{spawn_direct_display, PipelineError},
+};
+
+/// How many measurements the rolling estimate averages over
+const WINDOW: usize = 16;
+
+/// Wait for datagrams, light the whole cube for a single frame per datagram and echo the payload
+/// back as soon as that frame has been written
+pub fn run(
+    stop_token: Arc<AtomicBool>,
+    port: u16,
+    driver: DriverConfig,
+) -> Result<(), PipelineError> {
+    let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(PipelineError::Io)?;
+    // Wake up regularly to notice the stop token
+    socket
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .map_err(PipelineError::Io)?;
+    enable_kernel_timestamps(&socket).map_err(PipelineError::Io)?;
+
+    let display = spawn_direct_display(driver);
+    let (written_tx, written_rx) = sync_channel(1);
+    let mut recent = VecDeque::with_capacity(WINDOW);
+    let mut buf = [0u8; 1500];
+
+    while !stop_token.load(Ordering::Relaxed) {
+        let (len, peer, arrived) = match recv_timestamped(&socket, &mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(PipelineError::Io(e)),
+        };
+
+        if !display.send(([[255; 8]; 8], Some(written_tx.clone()))) || written_rx.recv().is_err() {
+            break;
+        }
+        let shown = SystemTime::now();
+        if let Err(e) = socket.send_to(&buf[..len], peer) {
+            eprintln!("Failed to reply to {peer}: {e}");
+        }
+        if !display.send(([[0; 8]; 8], None)) {
+            break;
+        }
+
+        // Kernel receive timestamp to frame written, so it excludes the network path entirely
+        let latency = shown.duration_since(arrived).unwrap_or_default();
+        if recent.len() == WINDOW {
+            recent.pop_front();
+        }
+        recent.push_back(latency);
+        let mean = recent.iter().sum::<Duration>() / recent.len() as u32;
+        println!(
+            "{peer}: latency {latency:.2?}, mean of last {} {mean:.2?}",
+            recent.len()
+        );
+    }
+
+    display.finish()
+}
+
+fn enable_kernel_timestamps(socket: &UdpSocket) -> io::Result<()> {
+    let enable: libc::c_int = 1;
+    // SAFETY: the option value points at a live c_int of the advertised size
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPNS,
+            &enable as *const libc::c_int as *const libc::c_void,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// `recv_from` that also returns when the kernel received the datagram, falling back to now if
+/// the timestamp is missing
+fn recv_timestamped(
+    socket: &UdpSocket,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, SystemTime)> {
+    // SAFETY: all-zero is a valid bit pattern for these plain C structs
+    let mut addr: libc::sockaddr_storage = unsafe { zeroed() };
+    let mut control = [0u64; 8];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // SAFETY: as above
+    let mut msg: libc::msghdr = unsafe { zeroed() };
+    msg.msg_name = &mut addr as *mut libc::sockaddr_storage as *mut libc::c_void;
+    msg.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = size_of_val(&control) as _;
+
+    // SAFETY: every pointer in msg refers to a buffer that outlives the call
+    let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut arrived = None;
+    // SAFETY: the kernel filled msg_control with well-formed control messages
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS
+            {
+                let ts = (libc::CMSG_DATA(cmsg) as *const libc::timespec).read_unaligned();
+                arrived = Some(
+                    SystemTime::UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32),
+                );
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    Ok((
+        len as usize,
+        to_socket_addr(&addr)?,
+        arrived.unwrap_or_else(SystemTime::now),
+    ))
+}
+
+fn to_socket_addr(addr: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    match addr.ss_family as libc::c_int {
+        libc::AF_INET => {
+            // SAFETY: the family says this is a sockaddr_in, which fits inside sockaddr_storage
+            let v4 = unsafe { &*(addr as *const _ as *const libc::sockaddr_in) };
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(v4.sin_addr.s_addr)),
+                u16::from_be(v4.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: the family says this is a sockaddr_in6, which fits inside sockaddr_storage
+            let v6 = unsafe { &*(addr as *const _ as *const libc::sockaddr_in6) };
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(v6.sin6_addr.s6_addr),
+                u16::from_be(v6.sin6_port),
+                v6.sin6_flowinfo,
+                v6.sin6_scope_id,
+            )))
+        }
+        family => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected address family {family}"),
+        )),
+    }
+}
@@ -0,0 +1,92 @@
+//! Drive an 8x8x8 LED cube from a Raspberry Pi's GPIO, or a 4³ or 16³ one through [`geometry::CubeGeometry`].
+//!
+//! Everything the cube shows is a [`Frame`], which [`Voxels`] wraps for drawing by position. Routines in [`routines`] and [`games`] are iterators
+//! of frames, a [`pipeline::Pipeline`] of transforms can rotate or otherwise rework each frame,
+//! and [`display::spawn_display`] keeps the latest frame refreshed on the cube through
+//! [`cube::CubeDriver`] on a thread of its own. [`display::spawn_display_on`] does the same for
+//! any other [`display::FrameSink`], such as the terminal drawing in [`sim`].
+//!
+//! ```no_run
+//! use rpi_led_cube::{cube::DriverConfig, display::spawn_display, routines::Wave};
+//!
+//! let display = spawn_display(DriverConfig::default(), None);
+//! for frame in Wave::new().take(100) {
+//!     display.send(frame);
+//!     std::thread::sleep(std::time::Duration::from_millis(100));
+//! }
+//! display.finish().unwrap();
+//! ```
+
+pub mod anim;
+pub mod artnet;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod check;
+pub mod color;
+pub mod control;
+pub mod cube;
+pub mod decoders;
+pub mod diag;
+pub mod display;
+pub mod dmx;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod font;
+pub mod games;
+pub mod gamma;
+pub mod geometry;
+pub mod gray;
+pub mod http;
+pub mod image;
+pub mod input;
+pub mod json;
+pub mod keys;
+pub mod latency;
+pub mod listener;
+pub mod mask;
+pub mod metrics;
+pub mod mqtt;
+pub mod noise;
+pub mod opc;
+pub mod orientation;
+pub mod pacer;
+pub mod pause;
+pub mod pins;
+pub mod pipeline;
+pub mod playlist;
+pub mod plugin;
+pub mod raster;
+pub mod realtime;
+pub mod registry;
+pub mod remap;
+pub mod remote;
+pub mod routines;
+pub mod sacn;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod shm;
+pub mod sim;
+pub mod spi;
+pub mod systemd;
+pub mod timing;
+pub mod trail;
+pub mod transition;
+pub mod voxels;
+pub mod websocket;
+
+pub use geometry::Index;
+pub use orientation::Orientation;
+pub use pipeline::Rotation;
+pub use voxels::{Axis, Voxels};
+
+/// One image on the cube. The outer array is Z/layer from the bottom, the inner array is X/row
+/// and each bit is Y/column, see [`geometry::Coord::index`].
+pub type Frame = [[u8; 8]; 8];
+
+/// The error for input that isn't a valid `what`, reading as `bad PNG: no header`
+pub(crate) fn invalid(what: &str, reason: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("bad {what}: {reason}"),
+    )
+}
@@ -0,0 +1,613 @@
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    net::{TcpListener, UdpSocket},
+    sync::{
+        atomic::Ordering,
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, SendError, SyncSender, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    check::{Arrivals, Rejections},
+    control::{Control, ProgramCheck},
+    decoders::{
+        decode_base16_frame, decode_base64_frame,
+        framed::{read_message, FramedError, Message},
+        read_binary_frame, read_length_prefixed_frame, read_raw_frame, FrameError, FrameFormat,
+        LineError, BINARY_FRAME_LEN,
+    },
+    display::Refreshable,
+    gray::{self, GrayFrame},
+    Frame,
+};
+
+/// Frames received on a thread of their own. Receiving blocks, so it is kept off the caller's
+/// thread, which only ever takes what has already arrived and so keeps checking its stop
+/// token. The newest frame wins when several arrive between polls, and the last one keeps
+/// being shown until another arrives.
+pub struct Listener<F = Frame> {
+    rx: Receiver<(F, Instant)>,
+    frame: F,
+    /// Set under `--check`, see [`Listener::checked`]
+    arrivals: Option<Arrivals>,
+}
+
+/// How often a checked listener waiting for a frame looks at its stop token
+const STOP_POLL: Duration = Duration::from_millis(50);
+
+/// The receiving thread's end of the channel, which stamps each frame with when it was decoded
+struct Decoded<F>(SyncSender<(F, Instant)>);
+
+impl<F> Decoded<F> {
+    fn send(&self, frame: F) -> Result<(), SendError<(F, Instant)>> {
+        self.0.send((frame, Instant::now()))
+    }
+}
+
+impl<F: Refreshable> Listener<F> {
+    /// Run `receive` on a detached thread, so that one blocked on a quiet input doesn't hold up
+    /// the exit. The source ends once `receive` returns.
+    fn receive_with(receive: impl FnOnce(Decoded<F>) + Send + 'static) -> Self {
+        let (tx, rx) = sync_channel(64);
+        thread::spawn(move || receive(Decoded(tx)));
+        Listener {
+            rx,
+            frame: F::BLANK,
+            arrivals: None,
+        }
+    }
+
+    /// Hand over every frame once, waiting for each, and note in `arrivals` when it was decoded,
+    /// so `--check` describes the stream itself rather than what the cube would have shown. The
+    /// source also ends once the stop token in `arrivals` is set.
+    pub fn checked(mut self, arrivals: Arrivals) -> Self {
+        self.arrivals = Some(arrivals);
+        self
+    }
+
+    /// Messages of the framed protocol, see `decoders::framed`, with gray frames shown through
+    /// `show`. Control messages change `control`'s settings, and are reported and ignored
+    /// without one. Messages that can't be read are reported and noted in `rejected`, then
+    /// skipped, or with `strict` end the source, which also ends with `reader`.
+    fn spawn_framed(
+        reader: impl Read + Send + 'static,
+        strict: bool,
+        control: Option<Arc<Control>>,
+        rejected: Rejections,
+        show: fn(&GrayFrame) -> F,
+    ) -> Self {
+        Self::receive_with(move |tx| {
+            let check: ProgramCheck =
+                Box::new(|_| Err("programs can't be switched from a stream".to_owned()));
+            let mut reader = BufReader::new(reader);
+            let mut skipped = 0;
+            for number in 1u64.. {
+                let frame = match read_message(&mut reader) {
+                    Ok(Some(Message::Frame(frame))) => show(&gray::from_frame(&frame)),
+                    Ok(Some(Message::Gray(gray))) => show(&gray),
+                    Ok(Some(Message::Control(text))) => {
+                        let text = text.trim();
+                        let (name, value) =
+                            text.split_once(char::is_whitespace).unwrap_or((text, ""));
+                        let changed = match &control {
+                            Some(control) => control.change(name, value.trim(), &check),
+                            None => Err("nothing to control without --control".to_owned()),
+                        };
+                        if let Err(e) = changed {
+                            eprintln!("Ignored control message {number} ({text}): {e}");
+                        }
+                        continue;
+                    }
+                    Ok(None) => break,
+                    // Bytes between messages are counted up and reported once a message is found
+                    Err(FramedError::Magic(_)) if !strict => {
+                        skipped += 1;
+                        continue;
+                    }
+                    Err(FramedError::Io(e)) => {
+                        eprintln!("Stopped reading messages at message {number}: {e}");
+                        rejected.record(format!("message {number}: {e}"));
+                        break;
+                    }
+                    Err(e) if strict => {
+                        eprintln!("Stopped at a malformed message {number}, {e}");
+                        rejected.record(format!("message {number}: {e}"));
+                        break;
+                    }
+                    Err(e) => {
+                        eprintln!("Skipped message {number}, {e}");
+                        rejected.record(format!("message {number}: {e}"));
+                        continue;
+                    }
+                };
+                if skipped > 0 {
+                    eprintln!("Skipped {skipped} bytes before message {number}");
+                    rejected.record(format!("{skipped} bytes before message {number}"));
+                    skipped = 0;
+                }
+                if tx.send(frame).is_err() {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+impl Listener<GrayFrame> {
+    /// Messages of the framed protocol, keeping the intensity of gray frames
+    pub fn framed(
+        reader: impl Read + Send + 'static,
+        strict: bool,
+        control: Option<Arc<Control>>,
+        rejected: Rejections,
+    ) -> Self {
+        Self::spawn_framed(reader, strict, control, rejected, |gray| *gray)
+    }
+}
+
+impl Listener {
+    /// Frames written in `format`. Frames that can't be decoded are reported with where they
+    /// are and noted in `rejected`, then skipped, or with `strict` end the source. It also ends
+    /// once `reader` does.
+    pub fn spawn(
+        reader: impl Read + Send + 'static,
+        format: FrameFormat,
+        strict: bool,
+        rejected: Rejections,
+    ) -> Self {
+        let decode: fn(&str) -> Result<Frame, FrameError> = match format {
+            FrameFormat::Hex => decode_base16_frame,
+            FrameFormat::Base64 => decode_base64_frame,
+            FrameFormat::Raw => return Self::spawn_raw(reader, rejected),
+            FrameFormat::Framed => {
+                return Self::spawn_framed(reader, strict, None, rejected, GrayFrame::on_off)
+            }
+        };
+        Self::receive_with(move |tx| {
+            let mut reader = BufReader::new(reader);
+            let mut bytes = Vec::new();
+            let (mut number, mut offset) = (0, 0u64);
+            loop {
+                bytes.clear();
+                let len = match reader.read_until(b'\n', &mut bytes) {
+                    Ok(0) => break,
+                    Ok(len) => len,
+                    Err(e) => {
+                        eprintln!("Stopped reading frames at byte {offset}: {e}");
+                        rejected.record(format!("byte {offset}: {e}"));
+                        break;
+                    }
+                };
+                let start = offset;
+                number += 1;
+                offset += len as u64;
+
+                // Anything not UTF-8 can't be a frame either, and is reported as such
+                let line = String::from_utf8_lossy(&bytes);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match decode(&line) {
+                    Ok(frame) => {
+                        if tx.send(frame).is_err() {
+                            break;
+                        }
+                    }
+                    Err(error) => {
+                        let error = LineError {
+                            line: number,
+                            offset: start + error.offset() as u64,
+                            error,
+                        };
+                        rejected.record(error.to_string());
+                        if strict {
+                            eprintln!("Stopped at a malformed frame, {error}");
+                            break;
+                        }
+                        eprintln!("Skipped {error}");
+                    }
+                }
+            }
+        })
+    }
+
+    /// Frames as `read_binary_frame` bytes one after another
+    fn spawn_raw(reader: impl Read + Send + 'static, rejected: Rejections) -> Self {
+        Self::receive_with(move |tx| {
+            let mut reader = BufReader::new(reader);
+            for frames in 0u64.. {
+                match read_raw_frame(&mut reader) {
+                    Ok(Some(frame)) => {
+                        if tx.send(frame).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        let offset = frames * BINARY_FRAME_LEN as u64;
+                        eprintln!("Stopped reading frames at byte {offset}: {e}");
+                        rejected.record(format!("byte {offset}: {e}"));
+                        break;
+                    }
+                }
+            }
+        })
+    }
+
+    pub fn stdin(format: FrameFormat, strict: bool, rejected: Rejections) -> Self {
+        Self::spawn(io::stdin(), format, strict, rejected)
+    }
+
+    /// Frames from TCP clients, each as a big-endian u32 length followed by that many bytes,
+    /// see `read_length_prefixed_frame`. One client is served at a time, and the next waiting
+    /// one is accepted once it disconnects, so the source never ends by itself.
+    pub fn tcp(listener: TcpListener) -> Self {
+        Self::receive_with(move |tx| {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("Could not accept a client: {e}");
+                        continue;
+                    }
+                };
+                let peer = stream
+                    .peer_addr()
+                    .map_or_else(|_| "unknown peer".to_owned(), |addr| addr.to_string());
+                eprintln!("Streaming frames from {peer}");
+
+                loop {
+                    match read_length_prefixed_frame(&mut stream) {
+                        Ok(Some(frame)) => {
+                            if tx.send(frame).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {
+                            eprintln!("{peer} disconnected");
+                            break;
+                        }
+                        Err(e) => {
+                            eprintln!("Dropping {peer}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Frames from UDP datagrams of 64 bytes each, one byte per row. With `sequenced` every
+    /// datagram starts with a big-endian u32 sequence number instead, and any that isn't newer
+    /// than the last one received is dropped so reordering on the network can't step backwards.
+    /// Sequence numbers may wrap around, and a sender that starts counting again far behind is
+    /// taken to have restarted.
+    pub fn udp(socket: UdpSocket, sequenced: bool) -> Self {
+        Self::receive_with(move |tx| {
+            let header = if sequenced { 4 } else { 0 };
+            let mut last = None;
+            // Bigger than any valid datagram, so oversized ones are noticed rather than cut short
+            let mut buf = [0u8; 1500];
+
+            loop {
+                let (len, peer) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(e) => {
+                        eprintln!("Stopped receiving frames: {e}");
+                        return;
+                    }
+                };
+                let datagram = &buf[..len];
+
+                let Some(frame) = datagram.get(header..).and_then(read_binary_frame) else {
+                    eprintln!("Ignoring a {len} byte datagram from {peer}");
+                    continue;
+                };
+                if sequenced {
+                    let sequence = u32::from_be_bytes(datagram[..4].try_into().expect("4 bytes"));
+                    if !is_newer(sequence, last) {
+                        continue;
+                    }
+                    last = Some(sequence);
+                }
+
+                if tx.send(frame).is_err() {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+impl<F: Refreshable> Iterator for Listener<F> {
+    type Item = F;
+
+    fn next(&mut self) -> Option<F> {
+        if let Some(arrivals) = &self.arrivals {
+            return loop {
+                match self.rx.recv_timeout(STOP_POLL) {
+                    Ok((frame, decoded)) => {
+                        arrivals.record(decoded);
+                        break Some(frame);
+                    }
+                    Err(RecvTimeoutError::Timeout) if !arrivals.stop.load(Ordering::Relaxed) => {}
+                    Err(_) => break None,
+                }
+            };
+        }
+
+        let mut fresh = false;
+        loop {
+            match self.rx.try_recv() {
+                Ok((frame, _)) => {
+                    self.frame = frame;
+                    fresh = true;
+                }
+                Err(TryRecvError::Empty) => return Some(self.frame),
+                // Whatever arrived just before the end still gets shown
+                Err(TryRecvError::Disconnected) => return fresh.then_some(self.frame),
+            }
+        }
+    }
+}
+
+/// How far behind a sequence number can be and still count as reordered rather than restarted
+const REORDER_WINDOW: i32 = 1024;
+
+/// Serial number comparison, so that 0 follows `u32::MAX`
+fn is_newer(sequence: u32, last: Option<u32>) -> bool {
+    last.is_none_or(|last| {
+        let ahead = sequence.wrapping_sub(last) as i32;
+        ahead > 0 || ahead <= -REORDER_WINDOW
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::TcpStream,
+        sync::atomic::AtomicBool,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::decoders::{framed::write_message, write_base64_frame, write_length_prefixed_frame};
+
+    /// A reader that never returns, like stdin with nobody typing
+    struct Silent;
+
+    impl Read for Silent {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_secs(3600));
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn a_silent_reader_never_blocks() {
+        let mut listener = Listener::spawn(Silent, FrameFormat::Hex, false, Rejections::default());
+        let start = Instant::now();
+        assert_eq!(listener.next(), Some([[0; 8]; 8]));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// Poll until `frame` shows up
+    fn wait_for(listener: &mut Listener, frame: Frame) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while listener.next() != Some(frame) {
+            assert!(Instant::now() < deadline, "frame never arrived");
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn tcp_clients_are_served_one_after_another() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut listener = Listener::tcp(server);
+
+        for byte in [0x11, 0x22] {
+            let mut client = TcpStream::connect(addr).unwrap();
+            write_length_prefixed_frame(&mut client, &[[byte; 8]; 8]).unwrap();
+            wait_for(&mut listener, [[byte; 8]; 8]);
+        }
+    }
+
+    #[test]
+    fn tcp_client_with_a_bad_length_is_dropped() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut listener = Listener::tcp(server);
+
+        let mut bad = TcpStream::connect(addr).unwrap();
+        bad.write_all(&[0, 0, 0, 65]).unwrap();
+        let mut buf = [0];
+        // The server hangs up instead of waiting for 65 bytes
+        assert_eq!(bad.read(&mut buf).unwrap(), 0);
+
+        let mut good = TcpStream::connect(addr).unwrap();
+        write_length_prefixed_frame(&mut good, &[[0x33; 8]; 8]).unwrap();
+        wait_for(&mut listener, [[0x33; 8]; 8]);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        assert!(is_newer(0, None));
+        assert!(is_newer(5, Some(4)));
+        assert!(!is_newer(4, Some(4)));
+        assert!(!is_newer(3, Some(4)));
+        assert!(is_newer(0, Some(u32::MAX)));
+        assert!(!is_newer(u32::MAX, Some(0)));
+        // The sender restarted
+        assert!(is_newer(0, Some(100_000)));
+    }
+
+    #[test]
+    fn udp_drops_stale_and_malformed_datagrams() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut listener = Listener::udp(server, true);
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let datagram = |sequence: u32, byte: u8| {
+            let mut datagram = sequence.to_be_bytes().to_vec();
+            datagram.extend([byte; 64]);
+            datagram
+        };
+        client.send_to(&datagram(10, 0x10), addr).unwrap();
+        wait_for(&mut listener, [[0x10; 8]; 8]);
+
+        // Stale, then too short, then the next one in sequence
+        client.send_to(&datagram(9, 0x09), addr).unwrap();
+        client.send_to(&[0x12; 64], addr).unwrap();
+        client.send_to(&datagram(11, 0x11), addr).unwrap();
+        wait_for(&mut listener, [[0x11; 8]; 8]);
+    }
+
+    #[test]
+    fn newest_frame_wins_and_bad_lines_are_skipped() {
+        let input = format!("{}\nnot a frame\n\n{}\n", "00".repeat(64), "ff".repeat(64));
+        let listener = Listener::spawn(
+            io::Cursor::new(input),
+            FrameFormat::Hex,
+            false,
+            Rejections::default(),
+        );
+        assert_eq!(last_frame(listener), Some([[0xff; 8]; 8]));
+    }
+
+    #[test]
+    fn checked_listeners_hand_over_every_frame() {
+        let mut input = "00".repeat(64) + "\n";
+        input = input.repeat(300) + "not a frame\n";
+        let rejected = Rejections::default();
+        let listener = Listener::spawn(
+            io::Cursor::new(input),
+            FrameFormat::Hex,
+            false,
+            rejected.clone(),
+        )
+        .checked(Arrivals::new(Arc::default()));
+        assert_eq!(listener.count(), 300);
+        assert_eq!(rejected.count(), 1);
+    }
+
+    #[test]
+    fn checked_listeners_end_when_stopped() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut listener = Listener::spawn(Silent, FrameFormat::Hex, false, Rejections::default())
+            .checked(Arrivals::new(stop.clone()));
+        stop.store(true, Ordering::Relaxed);
+        assert_eq!(listener.next(), None);
+    }
+
+    /// The last frame shown before the listener ends
+    fn last_frame<F: Refreshable>(listener: Listener<F>) -> Option<F> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut last = None;
+        for frame in listener {
+            assert!(Instant::now() < deadline, "reader never ended");
+            last = Some(frame);
+        }
+        last
+    }
+
+    #[test]
+    fn base64_and_raw_streams() {
+        let mut input = Vec::new();
+        write_base64_frame(&mut input, &[[0x42; 8]; 8]).unwrap();
+        input.extend(b"garbage\n");
+        let listener = Listener::spawn(
+            io::Cursor::new(input),
+            FrameFormat::Base64,
+            false,
+            Rejections::default(),
+        );
+        assert_eq!(last_frame(listener), Some([[0x42; 8]; 8]));
+
+        let mut input = vec![0x01; 64];
+        input.extend([0x02; 64]);
+        let listener = Listener::spawn(
+            io::Cursor::new(input),
+            FrameFormat::Raw,
+            false,
+            Rejections::default(),
+        );
+        assert_eq!(last_frame(listener), Some([[0x02; 8]; 8]));
+    }
+
+    #[test]
+    fn strict_listeners_stop_at_the_first_bad_frame() {
+        let input = format!(
+            "{}\n{}\n{}\n",
+            "11".repeat(64),
+            "2".repeat(127),
+            "33".repeat(64)
+        );
+        let listener = Listener::spawn(
+            io::Cursor::new(input.clone()),
+            FrameFormat::Hex,
+            true,
+            Rejections::default(),
+        );
+        assert_eq!(last_frame(listener), Some([[0x11; 8]; 8]));
+        let rejected = Rejections::default();
+        let listener = Listener::spawn(
+            io::Cursor::new(input),
+            FrameFormat::Hex,
+            false,
+            rejected.clone(),
+        );
+        assert_eq!(last_frame(listener), Some([[0x33; 8]; 8]));
+        assert_eq!(rejected.count(), 1);
+    }
+
+    #[test]
+    fn framed_streams_carry_gray_frames_and_settings() {
+        let mut gray = [[[0; 8]; 8]; 8];
+        gray[1][2][3] = 7;
+        let mut input = Vec::new();
+        write_message(&mut input, &Message::Frame([[0xff; 8]; 8])).unwrap();
+        input.extend(b"noise");
+        for message in [
+            Message::Control("brightness 2".to_owned()),
+            Message::Control("program sine".to_owned()),
+            Message::Gray(Box::new(gray)),
+        ] {
+            write_message(&mut input, &message).unwrap();
+        }
+
+        let control = Arc::new(Control::new());
+        let listener = Listener::framed(
+            io::Cursor::new(input.clone()),
+            false,
+            Some(control.clone()),
+            Rejections::default(),
+        );
+        assert_eq!(last_frame(listener), Some(gray));
+        assert_eq!(control.settings().brightness, 2);
+        assert_ne!(control.settings().program, "sine");
+
+        // On/off without a control, and stopping at the noise when strict
+        let rejected = Rejections::default();
+        let listener = Listener::spawn(
+            io::Cursor::new(input.clone()),
+            FrameFormat::Framed,
+            false,
+            rejected.clone(),
+        );
+        assert_eq!(last_frame(listener), Some(gray.on_off()));
+        assert_eq!(rejected.count(), 1);
+        let listener = Listener::spawn(
+            io::Cursor::new(input),
+            FrameFormat::Framed,
+            true,
+            Rejections::default(),
+        );
+        assert_eq!(last_frame(listener), Some([[0xff; 8]; 8]));
+    }
+}
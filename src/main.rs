@@ -1,24 +1,75 @@
-mod cube;
-mod routines;
-
 use std::{
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, UdpSocket},
+    path::{Path, PathBuf},
+    process::ExitCode,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::{sync_channel, Receiver, SyncSender, TryRecvError},
-        Arc,
+        Arc, Mutex,
     },
-    thread::{self, JoinHandle},
-    time::Duration,
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::{Parser, Subcommand, ValueEnum};
-
-use cube::CubeDriver;
+use clap::{error::ErrorKind, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 
-use routines::*;
+#[cfg(feature = "audio")]
+use rpi_led_cube::audio;
+#[cfg(feature = "script")]
+use rpi_led_cube::script;
+use rpi_led_cube::{
+    anim,
+    artnet::{self, ArtNet},
+    check::{Arrivals, CheckReport, Rejections},
+    color::Palette,
+    control::{self, ActiveAlert, AlertPattern, Control, LiveOrientation},
+    cube::{CubeDriver, DriverConfig, PwmChannel, PwmConfig, MAX_BRIGHTNESS, REFRESH_RATE},
+    decoders::{
+        decode_json_frames, read_base16_frame, write_base16_frame, write_grid_frame, FrameFormat,
+    },
+    diag,
+    display::{
+        spawn_display, spawn_refresh_on, Display, NullSink, PipelineError, RefreshStats,
+        Refreshable, StatsHandle,
+    },
+    dmx::{DmxMap, DmxReceiver},
+    games::{Pong, Snake},
+    gamma::{self, Correction, DEFAULT_GAMMA},
+    geometry::CubeGeometry,
+    gray::GrayFrame,
+    http,
+    image::{self, Conversion, ImageLayout, SliceOrder},
+    keys::{self, KeyControl},
+    latency,
+    listener::Listener,
+    mask::DefectMask,
+    mqtt,
+    opc::{self, OpcServer, PixelOrder},
+    pacer::Pacer,
+    pause::{self, Pause},
+    pins::PinConfig,
+    pipeline::{Invert, Orient, Persist, Pipeline},
+    playlist::{parse_duration, parse_item, Entry, Opened, Playlist},
+    plugin,
+    realtime::Realtime,
+    registry::{parse_fraction, parse_rate, parse_speed, Chosen},
+    remap::{Remap, Serpentine},
+    remote::Remote,
+    routines::*,
+    sacn::{self, Sacn},
+    shm::ShmSource,
+    sim::TerminalSink,
+    spi::SpiCubeDriver,
+    systemd::{self, Watchdog},
+    timing::Timing,
+    transition::{Transition, TransitionStyle},
+    Frame, Orientation, Rotation,
+};
 
-/// Outer array is Z/layer, inner array is X/row, each bit is Y/column
-type Frame = [[u8; 8]; 8];
+/// Period a playlist is shown at, that of the fastest program, so that every item keeps its own
+const PLAYLIST_TICK: Duration = Duration::from_millis(20);
+/// Resolution of the frame times in recorded animation files on playback
+const PLAYBACK_TICK: Duration = Duration::from_millis(10);
 
 /// Bit-bang the PI GPIO pins to render 3D values on the LED cube
 #[derive(Parser)]
@@ -30,22 +81,213 @@ struct Cli {
     invert: bool,
     #[arg(long, default_value_t = Rotation::None)]
     rotate: Rotation,
+    /// Turn and mirror the cube by any of its symmetries, as steps applied in order after
+    /// --rotate, e.g. x90,z180,flip-y
+    #[arg(long)]
+    orient: Option<Orientation>,
+    /// Keep LEDs lit for this many frames after the program turns them off
+    #[arg(long)]
+    persist: Option<u32>,
+    /// With --persist, clear every afterglow when the program sends a blank frame
+    #[arg(long, requires = "persist")]
+    persist_hard_clear: bool,
+    /// Dim the whole cube in software, from 0 for off to 15 for full brightness
+    #[arg(long, default_value_t = MAX_BRIGHTNESS, value_parser = clap::value_parser!(u8).range(0..=MAX_BRIGHTNESS as i64))]
+    brightness: u8,
+    /// Gamma the cube's brightness levels are corrected for, so that they look evenly spaced.
+    /// 1 drives them as they are.
+    #[arg(long, default_value_t = DEFAULT_GAMMA, value_parser = parse_gamma)]
+    gamma: f32,
+    /// Scale particular voxels' brightness, as `x,y,z = scale` lines with scales from 0 to 1
+    /// and `*` for every position along an axis, for LEDs brighter than the rest
+    #[arg(long)]
+    calibration: Option<PathBuf>,
+    /// Voxels to force off or on whatever is shown, working around dead or stuck LEDs, as
+    /// `x,y,z = off` or `x,y,z = on` lines with `*` for every position along an axis
+    #[arg(long)]
+    mask: Option<PathBuf>,
+    /// Cubes soldered with alternate rows, layers or both running backwards, so frames are
+    /// still drawn the right way round
+    #[arg(long)]
+    serpentine: Option<Serpentine>,
+    /// Where each voxel's LED is wired, for any other soldering order, as `x,y,z = x,y,z`
+    /// lines from where a voxel is drawn to where it is wired
+    #[arg(long, conflicts_with = "serpentine")]
+    remap: Option<PathBuf>,
+    /// Which GPIO each signal is wired to, as `signal = pin` lines or a JSON object
+    #[arg(long)]
+    pins: Option<PathBuf>,
+    /// Voxels along each edge of the cube, 4, 8 or 16, e.g. 16 or 16x16x16. Programs still draw
+    /// 8x8x8 frames, scaled up or down to fit. A 16³ cube chains four registers behind each
+    /// par line and takes a fourth layer select line, layer_sel_bit_3.
+    #[arg(long, default_value_t = CubeGeometry::default())]
+    cube_size: CubeGeometry,
+    /// Drive an RGB cube, its three banks of registers chained red into green into blue behind
+    /// each par line
+    #[arg(long)]
+    rgb: bool,
+    /// With --rgb, the colours programs are shown in, from dim to bright, e.g. blue,cyan,white.
+    /// Gray levels spread across them and on/off programs take the last.
+    #[arg(long, default_value_t = Palette::default(), requires = "rgb")]
+    palette: Palette,
+    /// Dim the whole cube through hardware PWM on out_enable, 0 to 1. Needs out_enable wired
+    /// to the --pwm-channel pin and the PWM overlay enabled.
+    #[arg(long, value_parser = parse_fraction)]
+    pwm_brightness: Option<f64>,
+    /// PWM channel carrying out_enable for --pwm-brightness
+    #[arg(long, default_value_t = PwmChannel::Pwm0, requires = "pwm_brightness")]
+    pwm_channel: PwmChannel,
+    /// Sweeps of all the layers a second on the cube, whatever the frame rate. Faster
+    /// flickers less, as long as the cube can keep up.
+    #[arg(long, default_value_t = REFRESH_RATE, value_parser = clap::value_parser!(u32).range(1..=10_000))]
+    refresh_rate: u32,
+    /// How the cube driver waits between GPIO writes. Precise spins for the shortest waits,
+    /// keeping a core busy while the cube refreshes much faster.
+    #[arg(long, default_value_t = Timing::Precise)]
+    timing: Timing,
+    /// Refresh the cube from a SCHED_FIFO thread at this priority, 1 to 99, so other work can't
+    /// hold a layer lit. Needs root or CAP_SYS_NICE, otherwise scheduling stays normal.
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=99))]
+    rt_priority: Option<u8>,
+    /// Refresh the cube only from this CPU, best one isolated from everything else with
+    /// isolcpus=
+    #[arg(long)]
+    cpu: Option<usize>,
+    /// How to ease back into the program once an alert is over
+    #[arg(long, default_value_t = TransitionStyle::None)]
+    transition: TransitionStyle,
+    /// How long a transition lasts
+    #[arg(long, default_value_t = 500)]
+    transition_ms: u64,
+    /// What to show once the program runs out of frames
+    #[arg(long, default_value_t = OnExit::Clear)]
+    on_exit: OnExit,
+    /// What shows the frames
+    #[arg(long, default_value_t = Backend::Gpio)]
+    backend: Backend,
+    /// Validate the frame source without touching GPIO, reporting statistics on exit
+    #[arg(long)]
+    check: bool,
+    /// Print frames to stdout as hex lines instead of displaying them
+    #[arg(long, conflicts_with = "check")]
+    dump: bool,
+    /// Also save every frame shown, with its timing, to this animation file for `play`
+    #[arg(long)]
+    record: Option<PathBuf>,
+    /// Stop after this many frames
+    #[arg(long, visible_alias = "max-frames")]
+    frames: Option<usize>,
+    /// Stop after this long, e.g. 30s, 5m or 1500ms, seconds when no unit is given
+    #[arg(long, value_parser = parse_duration)]
+    duration: Option<Duration>,
+    /// Print how the display is keeping up this often, e.g. 10s: the refresh rate, frames
+    /// taken, frames dropped and the slowest refresh
+    #[arg(long, value_parser = parse_duration)]
+    stats: Option<Duration>,
+    /// Frames per second, instead of the program's own rate
+    #[arg(long, value_parser = parse_fps, conflicts_with = "frame_ms")]
+    fps: Option<f64>,
+    /// Milliseconds per frame, instead of the program's own rate
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    frame_ms: Option<u64>,
+    /// Play this many times faster than the program's own rate, e.g. 2x or 0.5
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed, conflicts_with_all = ["fps", "frame_ms"])]
+    speed: f64,
+    /// Accept commands such as alerts on this Unix socket, or connect to it for `alert`. A path
+    /// goes after an equals sign, as in --control=/tmp/cube.sock, so a bare --control can come
+    /// straight before the program.
+    #[arg(long, global = true, num_args = 0..=1, require_equals = true, default_missing_value = control::DEFAULT_SOCKET)]
+    control: Option<PathBuf>,
+    /// Change the speed, brightness and rotation, pause, and skip playlist items from the
+    /// keyboard while the program runs
+    #[arg(long)]
+    keys: bool,
+    /// Hold each frame until space is pressed or SIGUSR1 arrives, for checking a program frame
+    /// by frame. s switches between stepping and running freely.
+    #[arg(long)]
+    step: bool,
+    /// Serve a page and REST endpoints on this address, e.g. 0.0.0.0:8080, to switch programs,
+    /// set the speed, brightness and rotation, and push frames while running. Frames also
+    /// stream both ways over a WebSocket at /ws.
+    #[arg(long)]
+    http: Option<SocketAddr>,
+    /// Take the same changes from an MQTT broker, HOST or HOST:PORT, on topics under
+    /// --mqtt-prefix, and publish the cube's state there
+    #[arg(long, value_name = "HOST[:PORT]")]
+    mqtt: Option<String>,
+    /// Topic prefix for --mqtt, giving e.g. cube/program and cube/state
+    #[arg(long, default_value = "cube", requires = "mqtt")]
+    mqtt_prefix: String,
+    /// Run as a systemd notify service: report ready once the cube is up, pet the watchdog from
+    /// the display thread, and mark fatal errors as such in the journal
+    #[arg(long)]
+    daemon: bool,
+    /// Print a systemd unit running this command line with --daemon, then exit
+    #[arg(long)]
+    print_systemd_unit: bool,
+    /// Add the routines of the plugins (.so files) in this directory as programs
+    #[arg(long, value_name = "DIR")]
+    plugin_dir: Option<PathBuf>,
 }
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
-/// Assume +X is "forward", +Y is "left", and +Z is "up", then
-enum Rotation {
-    /// No-op
-    None,
-    /// Rotate about X
-    I,
-    /// Rotate about Y
-    J,
-    /// Rotate about Z
-    K,
+/// The program given to `bake` or as a playlist item, parsed separately since a subcommand
+/// can't contain itself
+#[derive(Parser)]
+struct Baked {
+    #[command(subcommand)]
+    program: Program,
+}
+
+fn parse_gamma(s: &str) -> Result<f32, String> {
+    parse_rate(s).map(|gamma| gamma as f32)
+}
+
+/// Frames per second, no more than the millisecond a frame --frame-ms allows and few enough
+/// that a frame's period fits in a `Duration`
+fn parse_fps(s: &str) -> Result<f64, String> {
+    let fps = parse_rate(s)?;
+    if fps > 1000.0 {
+        return Err("must be at most 1000".to_owned());
+    }
+    Duration::try_from_secs_f64(1.0 / fps)
+        .map(|_| fps)
+        .map_err(|_| "too few for a frame period".to_owned())
+}
+
+impl Cli {
+    /// The frame period asked for with --fps or --frame-ms
+    fn frame_time(&self) -> Option<Duration> {
+        match (self.fps, self.frame_ms) {
+            (Some(fps), _) => Some(Duration::from_secs_f64(1.0 / fps)),
+            (None, Some(ms)) => Some(Duration::from_millis(ms)),
+            (None, None) => None,
+        }
+    }
+
+    fn pwm(&self) -> Option<PwmConfig> {
+        self.pwm_brightness.map(|brightness| PwmConfig {
+            channel: self.pwm_channel,
+            brightness,
+        })
+    }
+
+    fn control_path(&self) -> PathBuf {
+        self.control
+            .clone()
+            .unwrap_or_else(|| control::DEFAULT_SOCKET.into())
+    }
 }
 
-impl std::fmt::Display for Rotation {
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum OnExit {
+    /// Blank the cube
+    #[default]
+    Clear,
+    /// Keep showing the last frame until interrupted
+    Hold,
+}
+
+impl std::fmt::Display for OnExit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.to_possible_value()
             .expect("all values possible")
@@ -54,237 +296,1479 @@ impl std::fmt::Display for Rotation {
     }
 }
 
-impl Default for Rotation {
-    fn default() -> Self {
-        Rotation::None
+/// What kind of file `play` reads
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum AnimFormat {
+    /// An animation saved by `bake` or --record
+    #[default]
+    Cubeanim,
+    /// An animated or still GIF, shrunk to 8x8
+    Gif,
+    /// Frames listed in JSON as layers of rows or lit voxels, each with an optional duration
+    Json,
+    /// Voxel art as a PNG of 8 layer slices 8x64 or 64x8 pixels, or a directory of them played
+    /// in name order
+    Slices,
+}
+
+impl std::fmt::Display for AnimFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
     }
 }
 
-impl Rotation {
-    fn apply(&self, data: &[[u8; 8]; 8]) -> Frame {
-        match self {
-            Self::None => data.clone(),
-            Self::I => core::array::from_fn(|layer| {
-                core::array::from_fn(|row| {
-                    // Build a row from each of the bits in the corresponding layer
-                    (0..8).map(|l| data[l][row]).fold(0u8, |acc, e| {
-                        (acc << 1) + if e & (1 << layer) != 0 { 1 } else { 0 }
-                    })
-                })
-            }),
-            Self::J => {
-                core::array::from_fn(|layer| core::array::from_fn(|row| data[row][7 - layer]))
-            }
-            Self::K => core::array::from_fn(|layer| {
-                core::array::from_fn(|row| {
-                    // Build row from each of the bits in the corresponding column
-                    data[layer].into_iter().fold(0u8, |acc, e| {
-                        (acc << 1) + if e & (1 << row) != 0 { 1 } else { 0 }
-                    })
-                })
-            }),
-        }
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// The LED cube on the GPIO
+    #[default]
+    Gpio,
+    /// The LED cube with its shift registers chained off the SPI bus instead of the par pins:
+    /// MOSI on par_1's register, SCLK on every SRCLK
+    Spi,
+    /// A drawing of the cube in the terminal, for working without the hardware
+    Sim,
+    /// Nowhere, for running routines without the hardware or a terminal
+    Null,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
     }
 }
 
-#[derive(Copy, Clone, ValueEnum)]
-enum Index {
-    Zero,
-    One,
-    Two,
-    Three,
-    Four,
-    Five,
-    Six,
-    Seven,
+/// How `dump` prints frames
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum DumpFormat {
+    /// A line of 128 hex digits per frame, as `--dump` prints and `stdin` reads
+    #[default]
+    Hex,
+    /// The layers side by side as grids of # and .
+    Grid,
 }
 
-impl From<Index> for u8 {
-    fn from(item: Index) -> Self {
-        match item {
-            Index::Zero => 0,
-            Index::One => 1,
-            Index::Two => 2,
-            Index::Three => 3,
-            Index::Four => 4,
-            Index::Five => 5,
-            Index::Six => 6,
-            Index::Seven => 7,
-        }
+impl std::fmt::Display for DumpFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
     }
 }
 
 #[derive(Clone, Subcommand)]
 enum Program {
-    /// Turn on all of the LEDs
-    AllOn,
-    /// Turn on a single LED
-    OneOn {
-        row: Index,
-        col: Index,
-        layer: Index,
+    #[command(flatten)]
+    Routine(Chosen),
+    /// The spectrum of a microphone as 8 bars, bass to treble along X
+    #[cfg(feature = "audio")]
+    Visualizer {
+        /// The ALSA capture device, e.g. plughw:1,0 for a USB microphone
+        #[arg(long, default_value = "default")]
+        device: String,
+        /// Level in dB below full scale that shows as nothing
+        #[arg(long, default_value_t = -60.0, allow_negative_numbers = true)]
+        floor: f32,
     },
-    /// Cycle one layer at a time
-    Cycle,
-    /// Like rainfall
-    Rain,
-    /// Plane waves moving diagonally
-    PlaneWave { reflect: Option<bool> },
-    /// Flat wave
-    Wave,
-    /// Turn on alternate LEDs like a chessboard
-    Chess,
-    /// Turn on one full layer of LEDs
-    OneLayer { which: Index },
-    /// Turn on one full row of LEDs
-    OneRow { which: Index },
-    /// Turn on one full column of LEDs
-    OneCol { which: Index },
-    /// Tiny cube in a cube
-    MiniCube,
-    /// Flip a random bit at a time
-    RandomFlip,
-    /// A fistful of lights
-    LittleBlips,
-}
-
-fn spawn_display() -> (SyncSender<Frame>, JoinHandle<rppal::gpio::Result<()>>) {
-    let (tx, rx): (SyncSender<Frame>, Receiver<Frame>) = sync_channel(64);
-
-    let handler = thread::spawn(move || {
-        let mut driver = CubeDriver::try_new()?;
-
-        let mut curr_frame = [[0; 8]; 8];
-
-        loop {
-            let maybe_frame = rx.try_recv();
-            if let Ok(frame) = maybe_frame {
-                curr_frame = frame;
-            } else if let Err(TryRecvError::Disconnected) = maybe_frame {
-                break;
+    /// Two player 3D pong, WASD against IJKL
+    Pong {
+        /// Let the computer play the IJKL paddle
+        #[arg(long)]
+        ai: bool,
+        /// Points needed to win a game, at most 9
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u8).range(1..=9))]
+        score_limit: u8,
+    },
+    /// Steer a growing snake to the food, arrow keys within a layer and WS up and down
+    Snake {
+        /// Voxels moved per second
+        #[arg(long, default_value_t = 4.0, value_parser = parse_rate)]
+        speed: f64,
+        /// Place the food in the same spots every game
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Run another program headless and save its frames to an animation file
+    Bake {
+        /// The animation file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// How many frames to record
+        #[arg(long)]
+        frames: usize,
+        /// Compress the file with zlib, holding the frames in memory until they are all in
+        #[arg(long)]
+        compress: bool,
+        /// The program to record and its arguments, e.g. `rain --density 0.1`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        program: Vec<String>,
+    },
+    /// Run another program headless and print its first frames to stdout, as fast as they come
+    Dump {
+        /// How many frames to print
+        #[arg(long, default_value_t = 10)]
+        frames: usize,
+        #[arg(long, default_value_t = DumpFormat::Hex)]
+        format: DumpFormat,
+        /// The program to run and its arguments, e.g. `rubik --seed 3`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        program: Vec<String>,
+    },
+    /// Cycle through other programs, each running for a while
+    Playlist {
+        /// A program with its arguments and how long it runs, e.g. `rain --density 0.1:20s`.
+        /// Repeat for more items.
+        #[arg(long)]
+        item: Vec<String>,
+        /// A file of items, one per line as for --item, after any given on the command line
+        #[arg(long)]
+        file: Option<PathBuf>,
+    },
+    /// Play back an animation file
+    Play {
+        file: PathBuf,
+        /// Start again from the beginning after the last frame
+        #[arg(long = "loop")]
+        repeat: bool,
+        /// Play this many times faster, e.g. 2x or 0.5
+        #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+        speed: f64,
+        /// Start this far into the animation, as timed in the file, e.g. 5s or 1500ms
+        #[arg(long, value_parser = parse_duration)]
+        seek: Option<Duration>,
+        #[arg(long, default_value_t = AnimFormat::Cubeanim)]
+        format: AnimFormat,
+        /// Where the pictures of an image go in the cube
+        #[arg(long, default_value_t = ImageLayout::Face)]
+        layout: ImageLayout,
+        /// Layers deep a picture on the front face reaches
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=8))]
+        depth: u8,
+        /// Lightness, from 0 to 1, above which an image's pixels are lit
+        #[arg(long, default_value_t = 0.5, value_parser = parse_fraction)]
+        threshold: f64,
+        /// Dither an image's grays into patterns of lit voxels rather than cutting off at the
+        /// threshold
+        #[arg(long)]
+        dither: bool,
+        /// Which end of a picture of slices is the bottom layer
+        #[arg(long, default_value_t = SliceOrder::BottomUp)]
+        slice_order: SliceOrder,
+    },
+    /// Describe an animation file
+    Info { file: PathBuf },
+    /// Interrupt the instance serving --control with a short alert
+    Alert {
+        #[arg(long, default_value_t = AlertPattern::Exclamation, conflicts_with = "hex")]
+        pattern: AlertPattern,
+        /// Frames to show instead of a pattern, as 128 hex digits each
+        #[arg(long)]
+        hex: Vec<String>,
+        /// How long the alert stays up before the program resumes
+        #[arg(long, default_value_t = 2000)]
+        hold_ms: u64,
+    },
+    /// Show frames read from stdin, one line of 128 hex digits each unless another format is
+    /// given
+    Listener {
+        #[arg(long, default_value_t = FrameFormat::Hex)]
+        format: FrameFormat,
+        /// Stop at the first frame that can't be decoded, instead of reporting and skipping it
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Show frames streamed over TCP, each a big-endian u32 length of 64 followed by one byte
+    /// per row, layers in order from the bottom
+    Serve {
+        /// TCP port to listen on
+        port: u16,
+    },
+    /// Show frames sent as UDP datagrams of 64 bytes, one byte per row, layers in order from
+    /// the bottom. Datagrams that arrive faster than the frame rate are dropped.
+    Udp {
+        /// UDP port to listen on
+        port: u16,
+        /// Every datagram starts with a big-endian u32 sequence number, and any that arrives
+        /// after a newer one is dropped
+        #[arg(long)]
+        sequenced: bool,
+    },
+    /// Show DMX that lighting software such as xLights or Jinx! sends over E1.31 (sACN), one
+    /// channel per voxel setting its brightness, rows of X in order and Y within each row
+    Sacn {
+        /// Universe carrying the whole cube, or its bottom layer with --per-layer
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..=63999))]
+        universe: u16,
+        /// Give each layer a universe of its own, numbered upwards from --universe, instead of
+        /// packing all 512 voxels into one
+        #[arg(long)]
+        per_layer: bool,
+        /// File of `layer = universe[/channel]` lines moving layers elsewhere
+        #[arg(long)]
+        map: Option<PathBuf>,
+    },
+    /// Show DMX that a lighting console sends over Art-Net, mapped onto the voxels as for sacn.
+    /// The cube answers ArtPoll, so consoles find it by themselves.
+    Artnet {
+        /// Port-address of the universe carrying the whole cube, or its bottom layer with
+        /// --per-layer
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u16).range(..=0x7FFF))]
+        universe: u16,
+        /// Give each layer a universe of its own, numbered upwards from --universe, instead of
+        /// packing all 512 voxels into one
+        #[arg(long)]
+        per_layer: bool,
+        /// File of `layer = universe[/channel]` lines moving layers elsewhere
+        #[arg(long)]
+        map: Option<PathBuf>,
+    },
+    /// Show pixels from Open Pixel Control clients such as FadeCandy tools and Processing
+    /// sketches, the first 512 pixels of channel 0 each lighting a voxel
+    Opc {
+        /// TCP port to listen on
+        #[arg(long, default_value_t = opc::PORT)]
+        port: u16,
+        /// File with the x,y,z of each pixel's voxel, one line per pixel, instead of layer by
+        /// layer from the bottom with rows of X and Y within each row
+        #[arg(long)]
+        order: Option<PathBuf>,
+    },
+    /// Show frames another local process publishes to a shared-memory file
+    Shm {
+        /// File to map, created if it doesn't exist
+        path: PathBuf,
+        /// Blank the cube once the writer has published nothing new for this long
+        #[arg(long, default_value_t = 5000)]
+        idle_timeout_ms: u64,
+    },
+    /// Save what a running instance is showing, as one line of hex, through its control socket
+    Snapshot {
+        /// Where to write the frame, stdout if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Walk through every signal to check the wiring: each par pin on its own, each layer,
+    /// then each voxel, printing which GPIO should be driving what lights
+    Diag {
+        /// Wait for a key before every step instead of moving on by itself
+        #[arg(long)]
+        pause: bool,
+        /// How long each step shows without --pause
+        #[arg(long, default_value_t = 500)]
+        step_ms: u64,
+    },
+    /// Flash the cube once per received UDP datagram and echo it back once displayed
+    LatencyTest {
+        /// UDP port to listen on
+        port: u16,
+    },
+}
+
+impl Program {
+    /// Whether the program can be a playlist item, started afresh every time its turn comes
+    /// round. Programs that take input, or that do something other than show frames, can't.
+    fn playable(&self) -> bool {
+        !matches!(
+            self,
+            Program::Pong { .. }
+                | Program::Snake { .. }
+                | Program::Bake { .. }
+                | Program::Dump { .. }
+                | Program::Playlist { .. }
+                | Program::Info { .. }
+                | Program::Alert { .. }
+                | Program::Listener { .. }
+                | Program::Serve { .. }
+                | Program::Udp { .. }
+                | Program::Sacn { .. }
+                | Program::Artnet { .. }
+                | Program::Opc { .. }
+                | Program::Shm { .. }
+                | Program::Snapshot { .. }
+                | Program::Diag { .. }
+                | Program::LatencyTest { .. }
+        )
+    }
+}
+
+/// What every program runs with besides its own frames, built once from the command line
+struct Session {
+    stop_token: Arc<AtomicBool>,
+    pipeline: Pipeline,
+    on_exit: OnExit,
+    /// Present when a control socket is being served
+    control: Option<Arc<Control>>,
+    pause: Arc<Pause>,
+    destination: Destination,
+    driver: DriverConfig,
+    max_frames: Option<usize>,
+    /// Wall-clock time after which the program stops pulling frames
+    duration: Option<Duration>,
+    /// Overrides the frame period each program picks for itself
+    frame_time: Option<Duration>,
+    /// Animation file to save a timed copy of the output to
+    record: Option<PathBuf>,
+    /// How to ease between sources, for now back into the program after an alert
+    transition: TransitionStyle,
+    transition_time: Duration,
+    /// Running under systemd with --daemon
+    daemon: bool,
+    /// How often to print the display's refresh stats
+    stats: Option<Duration>,
+}
+
+/// Where frames should end up, as chosen on the command line
+enum Destination {
+    Display(Backend),
+    /// `--check`, which reports what the source rejected along with when its frames arrived
+    Check {
+        rejected: Rejections,
+        arrivals: Arrivals,
+    },
+    /// Stdout, flat out unless `paced`
+    Dump {
+        format: DumpFormat,
+        paced: bool,
+    },
+    Bake {
+        path: PathBuf,
+        compress: bool,
+    },
+}
+
+impl Destination {
+    /// Where the source should note what it rejects, kept only for `--check`
+    fn rejections(&self) -> Rejections {
+        match self {
+            Destination::Check { rejected, .. } => rejected.clone(),
+            _ => Rejections::default(),
+        }
+    }
+
+    /// Where listeners should note when they decode each frame, only under `--check`
+    fn arrivals(&self) -> Option<Arrivals> {
+        match self {
+            Destination::Check { arrivals, .. } => Some(arrivals.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The kinds of frame `run_routine` takes, on/off or with an intensity per voxel
+trait Voxels: Refreshable {
+    fn transform(self, pipeline: &mut Pipeline) -> Self;
+
+    fn blend(fade: &mut Transition, old: &Self, new: &Self) -> Self;
+}
+
+impl Voxels for Frame {
+    fn transform(self, pipeline: &mut Pipeline) -> Self {
+        pipeline.apply(self)
+    }
+
+    fn blend(fade: &mut Transition, old: &Self, new: &Self) -> Self {
+        fade.blend(old, new)
+    }
+}
+
+impl Voxels for GrayFrame {
+    fn transform(self, pipeline: &mut Pipeline) -> Self {
+        pipeline.apply_gray(self)
+    }
+
+    fn blend(fade: &mut Transition, old: &Self, new: &Self) -> Self {
+        fade.blend_gray(old, new)
+    }
+}
+
+/// Where `run_routine` sends its frames. The display is only spawned, and GPIO only claimed,
+/// when frames are really going to the cube. Everywhere else only knows on/off, so frames with
+/// intensity arrive there as every voxel that is lit at all.
+enum Output<T> {
+    Display(Display<T>),
+    Check(CheckReport),
+    Dump {
+        out: io::StdoutLock<'static>,
+        format: DumpFormat,
+        paced: bool,
+    },
+    Bake(anim::Writer),
+}
+
+impl<T: Voxels> Output<T> {
+    /// With a `watchdog` interval the display thread pets systemd's watchdog that often
+    fn open(
+        destination: Destination,
+        driver: DriverConfig,
+        shown: Option<Arc<Mutex<Frame>>>,
+        frame_sleep: Duration,
+        watchdog: Option<Duration>,
+    ) -> Result<Self, PipelineError> {
+        Ok(match destination {
+            Destination::Display(Backend::Gpio) => Output::Display(spawn_refresh_on(
+                move || {
+                    let cube = CubeDriver::try_new(&driver).map_err(PipelineError::GpioInit)?;
+                    Ok(Watchdog::new(cube, watchdog))
+                },
+                shown,
+            )),
+            Destination::Display(Backend::Spi) => Output::Display(spawn_refresh_on(
+                move || Ok(Watchdog::new(SpiCubeDriver::open(&driver)?, watchdog)),
+                shown,
+            )),
+            Destination::Display(Backend::Null) => Output::Display(spawn_refresh_on(
+                move || Ok(Watchdog::new(NullSink, watchdog)),
+                shown,
+            )),
+            Destination::Display(Backend::Sim) => Output::Display(spawn_refresh_on(
+                move || Ok(Watchdog::new(TerminalSink::new(), watchdog)),
+                shown,
+            )),
+            Destination::Check { rejected, arrivals } => {
+                Output::Check(CheckReport::new(frame_sleep, rejected, arrivals))
             }
+            Destination::Dump { format, paced } => Output::Dump {
+                out: io::stdout().lock(),
+                format,
+                paced,
+            },
+            Destination::Bake { path, compress } => Output::Bake(
+                if compress {
+                    anim::Writer::create_compressed(&path, frame_sleep)
+                } else {
+                    anim::Writer::create(&path, frame_sleep)
+                }
+                .map_err(PipelineError::Io)?,
+            ),
+        })
+    }
+
+    /// Baking, `dump` and `--check` run flat out, everything else keeps the routine's cadence
+    fn paced(&self) -> bool {
+        !matches!(
+            self,
+            Output::Bake(_) | Output::Check(_) | Output::Dump { paced: false, .. }
+        )
+    }
+
+    /// True once the display thread has died, the other outputs fail on `send` instead
+    fn failed(&self) -> bool {
+        matches!(self, Output::Display(display) if display.failed())
+    }
 
-            driver.write_frame(curr_frame);
+    /// False once frames can no longer be delivered
+    fn send(&mut self, frame: T) -> bool {
+        match self {
+            Output::Display(display) => display.send(frame),
+            Output::Check(report) => {
+                report.record();
+                true
+            }
+            // Flushed per frame so whatever reads the pipe keeps the routine's cadence
+            Output::Dump { out, format, .. } => match format {
+                DumpFormat::Hex => write_base16_frame(out, &frame.on_off()),
+                DumpFormat::Grid => write_grid_frame(out, &frame.on_off()),
+            }
+            .and_then(|()| out.flush())
+            .is_ok(),
+            Output::Bake(writer) => writer.write(&frame.on_off()).is_ok(),
         }
-        Ok(())
-    });
+    }
 
-    (tx, handler)
+    fn finish(self) -> Result<(), PipelineError> {
+        match self {
+            Output::Display(display) => display.finish(),
+            Output::Check(report) => {
+                report.print();
+                Ok(())
+            }
+            Output::Dump { .. } => Ok(()),
+            Output::Bake(writer) => writer.finish().map_err(PipelineError::Io),
+        }
+    }
 }
 
-fn run_routine<'a, I>(
-    stop_token: Arc<AtomicBool>,
+fn run_routine<T, I>(
+    session: Session,
     frame_sleep: Duration,
     frames: I,
-    invert: bool,
-    rotate: Rotation,
-) where
-    I: IntoIterator<Item = Frame>,
+) -> Result<(), PipelineError>
+where
+    T: Voxels,
+    I: IntoIterator<Item = T>,
 {
-    let (sender, handle) = spawn_display();
+    let Session {
+        stop_token,
+        mut pipeline,
+        on_exit,
+        control,
+        pause,
+        destination,
+        driver,
+        max_frames,
+        duration,
+        frame_time,
+        record,
+        transition,
+        transition_time,
+        daemon,
+        stats,
+    } = session;
+    let frame_sleep = frame_time.unwrap_or(frame_sleep);
+    let mut recorder = record
+        .map(|path| anim::Writer::create_timed(&path, frame_sleep))
+        .transpose()
+        .map_err(PipelineError::Io)?;
+
+    let mut output = Output::<T>::open(
+        destination,
+        driver,
+        control.as_ref().map(|c| c.shown_handle()),
+        frame_sleep,
+        daemon.then(systemd::watchdog_interval).flatten(),
+    )?;
+    if daemon {
+        notify_systemd("READY=1");
+    }
+    if let Output::Display(display) = &output {
+        if let Some(control) = &control {
+            control.watch_refresh(display.stats_handle());
+        }
+        if let Some(interval) = stats {
+            report_stats(display.stats_handle(), interval, stop_token.clone());
+        }
+    }
+    // The display thread publishes what it really wrote, the other outputs take frames as sent
+    let mut publish = |output: &Output<T>, frame: T| {
+        if let Some(control) = &control {
+            control.count_frame();
+            if !matches!(output, Output::Display(_)) {
+                control.set_shown(frame.on_off());
+            }
+        }
+        // A recording that can't be written to doesn't stop the show
+        if let Some(Err(e)) = recorder.as_mut().map(|r| r.write(&frame.on_off())) {
+            eprintln!("Stopped recording: {e}");
+            recorder = None;
+        }
+    };
+
+    let deadline = duration.map(|duration| Instant::now() + duration);
+    let mut frames = frames
+        .into_iter()
+        .take(max_frames.unwrap_or(usize::MAX))
+        .take_while(|_| deadline.is_none_or(|deadline| Instant::now() < deadline));
+    let mut pacer = Pacer::new(frame_sleep);
+    let mut alert: Option<ActiveAlert> = None;
+    // Last alert frame shown, until the routine has taken over again
+    let mut alert_frame: Option<T> = None;
+    let mut resume: Option<(T, Transition)> = None;
+    // What the output is showing, to put back after alerts played over a held frame
+    let mut held = T::BLANK;
+    let transition_frames = transition_time.div_duration_f32(frame_sleep).round() as u32;
+    let mut exhausted = true;
+    let mut produced = 0;
+    let mut brightness = None;
 
-    for frame in frames {
+    loop {
+        pause.wait_while_paused(&stop_token);
         if stop_token.load(Ordering::Relaxed) {
+            exhausted = false;
             break;
         }
 
-        let rotated = rotate.apply(&frame);
-        let inverted = if invert {
-            rotated.map(|layer| layer.map(|row| row ^ 0xff))
-        } else {
-            rotated
+        if let (Some(control), Output::Display(display)) = (&control, &output) {
+            let level = control.settings().brightness;
+            if brightness != Some(level) {
+                display.set_brightness(level);
+                brightness = Some(level);
+            }
+        }
+
+        // Alerts pre-empt the routine without pulling frames from it
+        if alert.is_none() {
+            alert = control.as_ref().and_then(|c| c.next_alert());
+        }
+        let frame = match alert.as_mut().and_then(ActiveAlert::next_frame) {
+            Some(frame) => {
+                let frame = T::from_on_off(frame);
+                alert_frame = Some(frame);
+                frame
+            }
+            None => {
+                alert = None;
+                let frame = match frames.next() {
+                    Some(frame) => {
+                        produced += 1;
+                        frame
+                    }
+                    None => break,
+                };
+                if let Some(old) = alert_frame.take() {
+                    resume = Some((old, Transition::new(transition, transition_frames)));
+                }
+                match &mut resume {
+                    Some((old, fade)) if !fade.finished() => T::blend(fade, old, &frame),
+                    _ => {
+                        resume = None;
+                        frame
+                    }
+                }
+            }
         };
 
-        if sender.send(inverted).is_err() {
-            eprintln!("Failed to write layer");
+        // A dead display reports why from `finish`
+        let frame = frame.transform(&mut pipeline);
+        if !output.send(frame) {
+            if !output.failed() {
+                eprintln!("Failed to write layer");
+            }
+            exhausted = false;
             break;
         }
+        publish(&output, frame);
+        held = frame;
 
-        thread::sleep(frame_sleep);
+        if output.paced() {
+            pacer.wait();
+        }
+    }
+
+    if exhausted && on_exit == OnExit::Hold {
+        // The display thread keeps refreshing the last frame for as long as the sender lives.
+        // Alerts still play over it, after which the exact frame that was showing is put back.
+        while !stop_token.load(Ordering::Relaxed) && !output.failed() {
+            let Some(mut alert) = control.as_ref().and_then(|c| c.next_alert()) else {
+                thread::sleep(frame_sleep);
+                continue;
+            };
+
+            pacer = Pacer::new(frame_sleep);
+            while let Some(frame) = alert.next_frame() {
+                let frame = T::from_on_off(frame).transform(&mut pipeline);
+                if stop_token.load(Ordering::Relaxed) || !output.send(frame) {
+                    break;
+                }
+                publish(&output, frame);
+                pacer.wait();
+            }
+            if output.send(held) {
+                publish(&output, held);
+            }
+        }
     }
 
-    drop(sender);
+    if daemon {
+        notify_systemd("STOPPING=1");
+    }
+    output.finish()?;
+    if let Some(recorder) = recorder {
+        recorder.finish().map_err(PipelineError::Io)?;
+    }
 
-    let _ = handle.join().expect("Could not join sender thread");
+    // Asking for no frames at all isn't the source's fault
+    let limited = max_frames == Some(0) || duration == Some(Duration::ZERO);
+    if exhausted && produced == 0 && !limited {
+        return Err(PipelineError::SourceEnded);
+    }
+    Ok(())
 }
 
-fn main() {
-    let args = Cli::parse();
+/// A program's frames and the period it shows them at
+enum Source {
+    Frames(Duration, Box<dyn Iterator<Item = Frame>>),
+    Gray(Duration, Box<dyn Iterator<Item = GrayFrame>>),
+}
+
+impl Source {
+    /// On/off frames either way, lighting every voxel that is lit at all
+    fn into_frames(self) -> Opened {
+        match self {
+            Source::Frames(period, frames) => (period, frames),
+            Source::Gray(period, frames) => (period, Box::new(frames.map(|gray| gray.on_off()))),
+        }
+    }
+}
+
+/// The universes for `sacn` and `artnet`, from their options
+fn dmx_map(universe: u16, per_layer: bool, file: Option<&Path>) -> io::Result<DmxMap> {
+    let map = if per_layer {
+        DmxMap::per_layer(universe)
+    } else {
+        DmxMap::packed(universe)
+    };
+    match file {
+        Some(path) => DmxMap::load(path, map)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display()))),
+        None => Ok(map),
+    }
+}
+
+/// Start the frames of any program that produces them, with the control any of them that take
+/// settings from their input change. Those decoding their input note what they reject in
+/// `rejected`.
+/// Under `--check` listeners hand over every frame they decode, see [`Listener::checked`]
+fn checked<F: Refreshable>(listener: Listener<F>, arrivals: Option<&Arrivals>) -> Listener<F> {
+    match arrivals {
+        Some(arrivals) => listener.checked(arrivals.clone()),
+        None => listener,
+    }
+}
+
+fn open_source(
+    program: Program,
+    control: Option<&Arc<Control>>,
+    rejected: &Rejections,
+    arrivals: Option<&Arrivals>,
+) -> io::Result<Source> {
+    Ok(match program {
+        Program::Routine(Chosen { routine, .. }) => {
+            let period = routine.preferred_frame_time();
+            match routine.gray_frames() {
+                Some(frames) => Source::Gray(period, frames),
+                None => Source::Frames(period, routine.frames()),
+            }
+        }
+        #[cfg(feature = "audio")]
+        Program::Visualizer { device, floor } => {
+            let capture = audio::Capture::open(&device)?;
+            Source::Frames(
+                Duration::from_millis(50),
+                Box::new(audio::Visualizer::new(capture, floor)),
+            )
+        }
+        Program::Pong { ai, score_limit } => Source::Frames(
+            Duration::from_millis(20),
+            Box::new(Pong::new(ai, score_limit)),
+        ),
+        Program::Snake { speed, seed } => {
+            Source::Frames(Duration::from_millis(20), Box::new(Snake::new(speed, seed)))
+        }
+        Program::Listener {
+            format: FrameFormat::Framed,
+            strict,
+        } => Source::Gray(
+            FRAME_TIME,
+            Box::new(checked(
+                Listener::framed(io::stdin(), strict, control.cloned(), rejected.clone()),
+                arrivals,
+            )),
+        ),
+        Program::Listener { format, strict } => {
+            let listener = Listener::stdin(format, strict, rejected.clone());
+            Source::Frames(FRAME_TIME, Box::new(checked(listener, arrivals)))
+        }
+        Program::Serve { port } => {
+            let server = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?;
+            Source::Frames(
+                FRAME_TIME,
+                Box::new(checked(Listener::tcp(server), arrivals)),
+            )
+        }
+        Program::Udp { port, sequenced } => {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+            let listener = Listener::udp(socket, sequenced);
+            Source::Frames(FRAME_TIME, Box::new(checked(listener, arrivals)))
+        }
+        Program::Sacn {
+            universe,
+            per_layer,
+            map,
+        } => {
+            let map = dmx_map(universe, per_layer, map.as_deref())?;
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, sacn::PORT))?;
+            sacn::join(&socket, &map.universes());
+            Source::Gray(FRAME_TIME, Box::new(DmxReceiver::spawn(socket, map, Sacn)))
+        }
+        Program::Artnet {
+            universe,
+            per_layer,
+            map,
+        } => {
+            let map = dmx_map(universe, per_layer, map.as_deref())?;
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, artnet::PORT))?;
+            let artnet = ArtNet::new(map.universes());
+            Source::Gray(
+                FRAME_TIME,
+                Box::new(DmxReceiver::spawn(socket, map, artnet)),
+            )
+        }
+        Program::Opc { port, order } => {
+            let order = match order {
+                Some(path) => PixelOrder::load(&path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?,
+                None => PixelOrder::default(),
+            };
+            let server = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?;
+            Source::Gray(FRAME_TIME, Box::new(OpcServer::spawn(server, order)))
+        }
+        Program::Shm {
+            path,
+            idle_timeout_ms,
+        } => {
+            let source = ShmSource::open(&path, Duration::from_millis(idle_timeout_ms))?;
+            Source::Frames(FRAME_TIME, Box::new(source))
+        }
+        Program::Play {
+            file,
+            repeat,
+            speed,
+            seek,
+            format,
+            layout,
+            depth,
+            threshold,
+            dither,
+            slice_order,
+        } => {
+            let (timed, period, frames) = match format {
+                AnimFormat::Cubeanim => {
+                    let (header, frames) = anim::read_timed(&file)?;
+                    (header.timed, header.period, frames)
+                }
+                AnimFormat::Gif => {
+                    let conversion = Conversion {
+                        layout,
+                        depth,
+                        threshold,
+                        dither,
+                    };
+                    let frames = image::to_frames(&image::gif::read(&file)?, conversion);
+                    (true, PLAYBACK_TICK, frames)
+                }
+                AnimFormat::Json => {
+                    let text = std::fs::read_to_string(&file)?;
+                    let frames = decode_json_frames(&text).map_err(|e| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("{}: {e}", file.display()),
+                        )
+                    })?;
+                    (true, PLAYBACK_TICK, frames)
+                }
+                AnimFormat::Slices => {
+                    let frames = image::read_slices(&file, slice_order, threshold)?;
+                    let frames = frames.into_iter().map(|frame| (FRAME_TIME, frame));
+                    (false, FRAME_TIME, frames.collect())
+                }
+            };
+            // Recorded frames are each repeated for as many ticks as they were up, evenly timed
+            // ones just play faster or slower
+            let tick = if timed {
+                PLAYBACK_TICK
+            } else {
+                period.div_f64(speed)
+            };
+            let frames = anim::at_tick(&frames, tick, speed);
+            let skip = seek.map_or(0, |seek| {
+                seek.div_f64(speed).div_duration_f64(tick).round() as usize
+            });
+            if repeat {
+                Source::Frames(tick, Box::new(frames.into_iter().cycle().skip(skip)))
+            } else {
+                Source::Frames(tick, Box::new(frames.into_iter().skip(skip)))
+            }
+        }
+        Program::Alert { .. }
+        | Program::Snapshot { .. }
+        | Program::Info { .. }
+        | Program::Bake { .. }
+        | Program::Dump { .. }
+        | Program::LatencyTest { .. }
+        | Program::Diag { .. }
+        | Program::Playlist { .. } => {
+            unreachable!("not a source of frames")
+        }
+    })
+}
+
+/// Parse every playlist item, from the command line and then the file, exiting with a usage
+/// error if any of them isn't a program that can be played
+fn playlist_entries(items: &[String], file: Option<&Path>) -> Vec<Entry> {
+    let usage = |message: String| -> ! {
+        Cli::command()
+            .error(ErrorKind::ValueValidation, message)
+            .exit()
+    };
+
+    let mut specs = items.to_vec();
+    if let Some(file) = file {
+        match std::fs::read_to_string(file) {
+            Ok(text) => specs.extend(
+                text.lines()
+                    .map(|line| line.split('#').next().unwrap_or_default().trim())
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_owned),
+            ),
+            Err(e) => usage(format!("{}: {e}", file.display())),
+        }
+    }
+    if specs.is_empty() {
+        usage("a playlist needs at least one --item or a --file".to_owned());
+    }
+
+    specs
+        .iter()
+        .map(|spec| {
+            let (words, duration) = parse_item(spec).unwrap_or_else(|e| usage(e));
+            let name = words.join(" ");
+            let program =
+                Baked::try_parse_from(std::iter::once("playlist".to_owned()).chain(words))
+                    .unwrap_or_else(|e| e.exit())
+                    .program;
+            if !program.playable() {
+                usage(format!("{name} can't be played in a playlist"));
+            }
+            Entry {
+                name,
+                open: Box::new(move || {
+                    open_source(program.clone(), None, &Rejections::default(), None)
+                        .map(Source::into_frames)
+                }),
+                duration,
+            }
+        })
+        .collect()
+}
+
+/// The program named by `words`, if it is one a running instance can switch to
+fn switchable(words: &[String]) -> Result<Program, String> {
+    let program =
+        Baked::try_parse_from(std::iter::once("http").chain(words.iter().map(String::as_str)))
+            .map_err(|e| e.render().to_string())?
+            .program;
+    if !program.playable() {
+        return Err(format!("{} can't be switched to", words[0]));
+    }
+    Ok(program)
+}
+
+/// A unit running this same command line as a service
+fn systemd_unit() -> String {
+    let exe = std::env::current_exe().map_or_else(
+        |_| "rpi-led-cube".to_owned(),
+        |path| path.display().to_string(),
+    );
+    let mut words = vec![systemd::quote(&exe)];
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| arg != "--print-systemd-unit")
+        .collect();
+    if !args.iter().any(|arg| arg == "--daemon") {
+        words.push("--daemon".to_owned());
+    }
+    words.extend(args.iter().map(|arg| systemd::quote(arg)));
+    systemd::unit(&words.join(" "))
+}
+
+/// Print the display's stats every `interval` on a thread of their own, until stopped
+fn report_stats(stats: StatsHandle, interval: Duration, stop_token: Arc<AtomicBool>) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if stop_token.load(Ordering::Relaxed) {
+            return;
+        }
+        let stats = *stats.lock().expect("stats poisoned");
+        // Nothing to say until the display has measured itself once
+        if stats != RefreshStats::default() {
+            eprintln!("Display {stats}");
+        }
+    });
+}
+
+fn notify_systemd(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+        eprintln!("Could not tell systemd {state}: {e}");
+    }
+}
+
+fn send_alert(path: &Path, pattern: AlertPattern, hex: &[String], hold_ms: u64) -> ExitCode {
+    let frames = if hex.is_empty() {
+        pattern.frames()
+    } else {
+        match hex.iter().map(|h| read_base16_frame(h)).collect() {
+            Some(frames) => frames,
+            None => {
+                eprintln!("Frames must be 128 hex digits");
+                return ExitCode::FAILURE;
+            }
+        }
+    };
+
+    let mut command = format!("alert {hold_ms}").into_bytes();
+    for frame in &frames {
+        command.push(b' ');
+        write_base16_frame(&mut command, frame).expect("writing to a Vec cannot fail");
+        command.pop(); // Newline
+    }
+    let command = String::from_utf8(command).expect("hex is ASCII");
+
+    match control::request(path, &command) {
+        Ok(reply) if reply == "ok" => ExitCode::SUCCESS,
+        Ok(reply) => {
+            eprintln!("{reply}");
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("Could not reach {}: {e}", path.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn save_snapshot(path: &Path, output: Option<&Path>) -> ExitCode {
+    let hex = match control::request(path, "status --frame") {
+        Ok(reply) => match reply.strip_prefix("ok ") {
+            Some(hex) => hex.to_owned(),
+            None => {
+                eprintln!("{reply}");
+                return ExitCode::FAILURE;
+            }
+        },
+        Err(e) => {
+            eprintln!("Could not reach {}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let written = match output {
+        Some(file) => std::fs::write(file, format!("{hex}\n")),
+        None => writeln!(io::stdout(), "{hex}"),
+    };
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// --plugin-dir, found before parsing since the plugins' routines are among the subcommands
+fn plugin_dir() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--plugin-dir" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(dir) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix("--plugin-dir="))
+        {
+            return Some(dir.into());
+        }
+    }
+    None
+}
+
+fn main() -> ExitCode {
+    // First of all, before plugins or anything else can start a thread that would be left
+    // without SIGUSR1 blocked, see `toggle_on_sigusr1`
+    let pause = Arc::new(Pause::new());
+    if let Err(e) = pause::toggle_on_sigusr1(pause.clone()) {
+        eprintln!("Pausing with SIGUSR1 is unavailable: {e}");
+    }
+
+    if let Some(dir) = plugin_dir() {
+        if let Err(e) = plugin::load_dir(&dir) {
+            eprintln!("Could not load plugins from {}: {e}", dir.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    let matches = Cli::command().get_matches();
+    let args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    if args.print_systemd_unit {
+        print!("{}", systemd_unit());
+        return ExitCode::SUCCESS;
+    }
+    if args.daemon && args.backend == Backend::Sim {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--daemon has no terminal to draw the sim backend on",
+            )
+            .exit();
+    }
+
+    if let Program::Alert {
+        pattern,
+        hex,
+        hold_ms,
+    } = &args.program
+    {
+        return send_alert(&args.control_path(), *pattern, hex, *hold_ms);
+    }
+
+    if let Program::Snapshot { output } = &args.program {
+        return save_snapshot(&args.control_path(), output.as_deref());
+    }
+
+    if let Program::Info { file } = &args.program {
+        return match anim::read_info(file) {
+            Ok(header) => {
+                println!("{header}");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("{}: {e}", file.display());
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if args.step {
+        pause.start_stepping();
+    }
 
     let stop_token = Arc::new(AtomicBool::new(false));
     let stop_token_clone = stop_token.clone();
 
+    // Covers SIGINT, SIGTERM and SIGHUP so `systemctl stop` also blanks the cube
     ctrlc::set_handler(move || {
-        println!("Exiting...");
+        eprintln!("Exiting...");
         stop_token_clone.store(true, Ordering::Relaxed);
     })
     .expect("Error setting Ctrl-C handler");
+    // Scripts run on threads of their own and can be busy a while between frames
+    #[cfg(feature = "script")]
+    script::stop_with(stop_token.clone());
 
-    let ftime = Duration::from_millis(100);
+    let mut pipeline = Pipeline::new();
+    let orientation = Orientation::from(args.rotate).then(args.orient.unwrap_or_default());
+    pipeline.push(Orient(orientation));
+    if args.invert {
+        pipeline.push(Invert);
+    }
+    if let Some(frames) = args.persist {
+        pipeline.push(Persist::new(frames, args.persist_hard_clear));
+    }
 
-    match args.program {
-        Program::AllOn => run_routine(stop_token, ftime, AllOn::new(), args.invert, args.rotate),
-        Program::OneOn { row, col, layer } => run_routine(
-            stop_token,
-            ftime,
-            OneOn::new(row, col, layer),
-            args.invert,
-            args.rotate,
-        ),
-        Program::Cycle => run_routine(
-            stop_token,
-            ftime,
-            CycleLayers::new(),
-            args.invert,
-            args.rotate,
-        ),
-        Program::Rain => run_routine(stop_token, ftime, Rain::new(), args.invert, args.rotate),
-        Program::PlaneWave { reflect } => run_routine(
-            stop_token,
-            ftime,
-            DiagonalPlane::new(reflect.unwrap_or_default()),
-            args.invert,
-            args.rotate,
-        ),
-        Program::Wave => run_routine(stop_token, ftime, Wave::new(), args.invert, args.rotate),
-        Program::Chess => run_routine(stop_token, ftime, Chess::new(), args.invert, args.rotate),
-        Program::OneLayer { which: layer } => run_routine(
-            stop_token,
-            ftime,
-            OneLayer::new(layer),
-            args.invert,
-            args.rotate,
-        ),
-        Program::OneRow { which: row } => run_routine(
-            stop_token,
-            ftime,
-            OneRow::new(row),
-            args.invert,
-            args.rotate,
-        ),
-        Program::OneCol { which: col } => run_routine(
-            stop_token,
-            ftime,
-            OneCol::new(col),
-            args.invert,
-            args.rotate,
-        ),
-        Program::MiniCube => {
-            run_routine(stop_token, ftime, MiniCube::new(), args.invert, args.rotate)
-        }
-        Program::RandomFlip => run_routine(
-            stop_token,
-            ftime,
-            RandomFlip::new(),
-            args.invert,
-            args.rotate,
-        ),
-        Program::LittleBlips => run_routine(
-            stop_token,
-            Duration::from_millis(200),
-            LittleBlips::new(),
-            args.invert,
-            args.rotate,
-        ),
+    let remote = args.http.is_some() || args.mqtt.is_some();
+    if remote && !args.program.playable() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--http and --mqtt need a program that can be switched for another",
+            )
+            .exit();
+    }
+    let playlist = matches!(args.program, Program::Playlist { .. });
+    if args.keys && !args.program.playable() && !playlist {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--keys needs a program that can be switched for another, or a playlist",
+            )
+            .exit();
+    }
+    let reads_keys = matches!(
+        args.program,
+        Program::Pong { .. } | Program::Snake { .. } | Program::Diag { .. }
+    );
+    if args.step && reads_keys {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--step needs the keyboard, which this program reads itself",
+            )
+            .exit();
+    }
+    let control = (args.control.is_some() || remote || args.keys).then(|| {
+        let control = Arc::new(Control::new());
+        control.update_settings(|settings| {
+            settings.program = matches.subcommand_name().unwrap_or_default().to_owned();
+            settings.brightness = args.brightness;
+            settings.speed = args.speed;
+        });
+        control
+    });
+    if let (Some(path), Some(control)) = (&args.control, &control) {
+        if let Err(e) = control::serve(path.clone(), control.clone(), stop_token.clone()) {
+            eprintln!("Could not serve control socket {}: {e}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    if let (Some(address), Some(control)) = (args.http, &control) {
+        let check: control::ProgramCheck = Box::new(|words| switchable(words).map(|_| ()));
+        let served = TcpListener::bind(address)
+            .and_then(|listener| http::serve(listener, control.clone(), check, stop_token.clone()));
+        if let Err(e) = served {
+            eprintln!("Could not serve HTTP on {address}: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if let (Some(broker), Some(control)) = (&args.mqtt, &control) {
+        let check: control::ProgramCheck = Box::new(|words| switchable(words).map(|_| ()));
+        mqtt::spawn(
+            mqtt::with_default_port(broker),
+            args.mqtt_prefix.clone(),
+            control.clone(),
+            check,
+            stop_token.clone(),
+        );
+    }
+    if let (true, Some(control)) = (remote || args.keys, &control) {
+        pipeline.push(LiveOrientation(control.clone()));
+    }
+    // Dropped at the end of main, handing the terminal back
+    let _keys = if args.keys || args.step {
+        let control = control.clone().filter(|_| args.keys);
+        let help = if args.keys {
+            keys::HELP
+        } else {
+            keys::STEP_HELP
+        };
+        match KeyControl::spawn(control, pause.clone()) {
+            Ok(keys) => {
+                eprintln!("{help}");
+                Some(keys)
+            }
+            Err(e) => {
+                eprintln!("Could not read keys: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+
+    let pins = match &args.pins {
+        Some(path) => match PinConfig::load(path) {
+            Ok(pins) => pins,
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => PinConfig::default(),
+    };
+    let mut correction = Correction::new(args.gamma);
+    if let Some(path) = &args.calibration {
+        match gamma::load_calibration(path) {
+            Ok(calibration) => correction = correction.with_calibration(calibration),
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let mask = match &args.mask {
+        Some(path) => match DefectMask::load(path, args.cube_size) {
+            Ok(mask) => Some(mask),
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let remap = match (&args.remap, args.serpentine) {
+        (Some(path), _) => match Remap::load(path, args.cube_size) {
+            Ok(remap) => Some(remap),
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        (None, Some(serpentine)) => Some(Remap::serpentine(args.cube_size, serpentine)),
+        (None, None) => None,
+    };
+    let driver = DriverConfig {
+        pins,
+        geometry: args.cube_size,
+        rgb: args.rgb.then_some(args.palette),
+        mask,
+        remap,
+        pwm: args.pwm(),
+        brightness: args.brightness,
+        correction,
+        refresh_rate: args.refresh_rate,
+        timing: args.timing,
+        realtime: Realtime {
+            priority: args.rt_priority,
+            cpu: args.cpu,
+        },
+    };
+    let frame_time = args.frame_time();
+    let (program, destination, max_frames) = match args.program {
+        Program::Bake {
+            output,
+            frames,
+            compress,
+            program,
+        } => {
+            let baked = Baked::parse_from(std::iter::once("bake".to_owned()).chain(program));
+            if matches!(baked.program, Program::Bake { .. }) {
+                Baked::command()
+                    .error(ErrorKind::InvalidSubcommand, "bake can't record itself")
+                    .exit();
+            }
+            (
+                baked.program,
+                Destination::Bake {
+                    path: output,
+                    compress,
+                },
+                Some(frames),
+            )
+        }
+        Program::Dump {
+            frames,
+            format,
+            program,
+        } => {
+            let dumped = Baked::parse_from(std::iter::once("dump".to_owned()).chain(program));
+            if !dumped.program.playable() {
+                Baked::command()
+                    .error(
+                        ErrorKind::InvalidSubcommand,
+                        "dump needs a program that only shows frames",
+                    )
+                    .exit();
+            }
+            let destination = Destination::Dump {
+                format,
+                paced: false,
+            };
+            (dumped.program, destination, Some(frames))
+        }
+        program if args.check => {
+            let destination = Destination::Check {
+                rejected: Rejections::default(),
+                arrivals: Arrivals::new(stop_token.clone()),
+            };
+            (program, destination, args.frames)
+        }
+        program if args.dump => {
+            let destination = Destination::Dump {
+                format: DumpFormat::Hex,
+                paced: true,
+            };
+            (program, destination, args.frames)
+        }
+        program => (program, Destination::Display(args.backend), args.frames),
+    };
+
+    // Faster than the cube refreshes, frames would be dropped without anyone noticing
+    if let (Some(frame_time), Destination::Display(Backend::Gpio)) = (frame_time, &destination) {
+        let refresh = driver.min_refresh_time();
+        if frame_time < refresh {
+            Cli::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "frames every {frame_time:?} are faster than the cube can refresh, \
+                         which takes at least {refresh:?} with these settings (at most {:.0} \
+                         frames per second)",
+                        1.0 / refresh.as_secs_f64()
+                    ),
+                )
+                .exit();
+        }
+    }
+
+    let session = Session {
+        stop_token,
+        pipeline,
+        on_exit: args.on_exit,
+        control,
+        pause,
+        destination,
+        driver,
+        max_frames,
+        duration: args.duration,
+        frame_time,
+        record: args.record.clone(),
+        transition: args.transition,
+        transition_time: Duration::from_millis(args.transition_ms),
+        daemon: args.daemon,
+        stats: args.stats,
     };
+
+    let result = match program {
+        Program::LatencyTest { port } => latency::run(session.stop_token, port, session.driver),
+        Program::Diag { pause, step_ms } => {
+            let display = match args.backend {
+                Backend::Gpio => spawn_display(session.driver.clone(), None),
+                Backend::Spi => {
+                    let driver = session.driver.clone();
+                    spawn_refresh_on(move || SpiCubeDriver::open(&driver), None)
+                }
+                Backend::Sim => spawn_refresh_on(|| Ok(TerminalSink::new()), None),
+                Backend::Null => spawn_refresh_on(|| Ok(NullSink), None),
+            };
+            let step = Duration::from_millis(step_ms);
+            diag::run(
+                session.stop_token,
+                display,
+                &session.driver.pins,
+                pause,
+                step,
+            )
+        }
+        Program::Playlist { item, file } => {
+            let entries = playlist_entries(&item, file.as_deref());
+            let tick = session.frame_time.unwrap_or(PLAYLIST_TICK);
+            let playlist =
+                Playlist::new(entries, tick, session.transition, session.transition_time);
+            let playlist = match (args.keys, &session.control) {
+                (true, Some(control)) => playlist.with_control(control.clone()),
+                _ => playlist.with_speed(args.speed),
+            };
+            run_routine(session, tick, playlist)
+        }
+        program if remote || args.keys => match open_source(
+            program,
+            session.control.as_ref(),
+            &session.destination.rejections(),
+            session.destination.arrivals().as_ref(),
+        ) {
+            Ok(source) => {
+                let control = session
+                    .control
+                    .clone()
+                    .expect("--http, --mqtt and --keys use a control");
+                let tick = session.frame_time.unwrap_or(PLAYLIST_TICK);
+                let switched = control.clone();
+                let open = Box::new(move |words: &[String]| {
+                    let program = switchable(words).map_err(io::Error::other)?;
+                    open_source(program, Some(&switched), &Rejections::default(), None)
+                        .map(Source::into_frames)
+                });
+                let remote = Remote::new(control, source.into_frames(), open, tick);
+                run_routine(session, tick, remote)
+            }
+            Err(e) => Err(PipelineError::Io(e)),
+        },
+        program => match open_source(
+            program,
+            session.control.as_ref(),
+            &session.destination.rejections(),
+            session.destination.arrivals().as_ref(),
+        ) {
+            Ok(Source::Frames(period, frames)) => {
+                run_routine(session, period.div_f64(args.speed), frames)
+            }
+            Ok(Source::Gray(period, frames)) => {
+                run_routine(session, period.div_f64(args.speed), frames)
+            }
+            Err(e) => Err(PipelineError::Io(e)),
+        },
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            let priority = if args.daemon { systemd::ERROR } else { "" };
+            eprintln!("{priority}{e}");
+            e.exit_code()
+        }
+    }
 }
@@ -0,0 +1,131 @@
+//! Working around broken LEDs. A mask file lists voxels the driver forces off, such as dead
+//! LEDs or a column that ghosts, or forces on, such as one that's stuck lit, whatever the
+//! frame says, so animations can be watched without the faults standing out.
+
+use std::{fmt, fs, io, path::Path};
+
+use crate::geometry::{parse_voxels, CubeGeometry};
+
+/// Voxels to force off and on, with a bit for each LED of the cube as built, indexed `[z][x]`
+/// with Y as the bit like the layers the driver shifts in
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DefectMask {
+    off: [[u16; 16]; 16],
+    on: [[u16; 16]; 16],
+}
+
+#[derive(Debug)]
+pub enum MaskError {
+    Io(io::Error),
+    /// A line that isn't `x,y,z = off` or `x,y,z = on`, or names a voxel off the cube
+    Syntax {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for MaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaskError::Io(e) => write!(f, "{e}"),
+            MaskError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl DefectMask {
+    pub fn load(path: &Path, geometry: CubeGeometry) -> Result<Self, MaskError> {
+        Self::parse(&fs::read_to_string(path).map_err(MaskError::Io)?, geometry)
+    }
+
+    /// Read `x,y,z = off` and `x,y,z = on` lines as in TOML, coordinates going up to the
+    /// cube's own size, and any of them `*` for a whole row, column or layer. Later lines win
+    /// where they overlap.
+    pub fn parse(text: &str, geometry: CubeGeometry) -> Result<Self, MaskError> {
+        let mut mask = DefectMask::default();
+
+        for (line, content) in (1..).zip(text.lines()) {
+            let content = content.split('#').next().unwrap_or_default().trim();
+            // Blank, or a TOML table header such as [defects]
+            if content.is_empty() || content.starts_with('[') {
+                continue;
+            }
+
+            let syntax = |message: String| MaskError::Syntax { line, message };
+            let Some((voxels, state)) = content.split_once('=') else {
+                return Err(syntax(format!(
+                    "expected x,y,z = off or on, found {content:?}"
+                )));
+            };
+            let voxels = parse_voxels(voxels, geometry.size()).map_err(syntax)?;
+            let on = match state.trim().trim_matches('"') {
+                "off" => false,
+                "on" => true,
+                state => return Err(syntax(format!("expected off or on, found {state:?}"))),
+            };
+
+            for c in voxels {
+                let (z, x, bit) = (c.z as usize, c.x as usize, 1 << c.y);
+                let (set, clear) = if on {
+                    (&mut mask.on, &mut mask.off)
+                } else {
+                    (&mut mask.off, &mut mask.on)
+                };
+                set[z][x] |= bit;
+                clear[z][x] &= !bit;
+            }
+        }
+
+        Ok(mask)
+    }
+
+    /// Force the voxels of layer `z` off and on, `rows` being the layer as the driver shifts
+    /// it in
+    pub fn apply(&self, z: usize, rows: &mut [u16]) {
+        for (x, row) in rows.iter_mut().enumerate() {
+            *row = *row & !self.off[z][x] | self.on[z][x];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masked_voxels_are_forced_whatever_the_frame() {
+        let mask = DefectMask::parse(
+            "[defects]\n1,2,0 = off  # dead\n3,*,0 = \"off\"\n3,4,0 = on\n",
+            CubeGeometry::default(),
+        )
+        .unwrap();
+
+        let mut lit = [0xFF; 8];
+        mask.apply(0, &mut lit);
+        assert_eq!(lit[1], !(1 << 2) & 0xFF);
+        assert_eq!(lit[3], 1 << 4);
+        assert_eq!(lit[0], 0xFF);
+
+        let mut dark = [0; 8];
+        mask.apply(0, &mut dark);
+        assert_eq!(dark, [0, 0, 0, 1 << 4, 0, 0, 0, 0]);
+
+        let mut other_layer = [0xFF; 8];
+        mask.apply(1, &mut other_layer);
+        assert_eq!(other_layer, [0xFF; 8]);
+    }
+
+    #[test]
+    fn voxels_must_be_on_the_cube() {
+        let big = CubeGeometry::new(16).unwrap();
+        assert!(DefectMask::parse("15,15,15 = off", big).is_ok());
+        assert!(matches!(
+            DefectMask::parse("\n8,0,0 = off", CubeGeometry::default()),
+            Err(MaskError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            DefectMask::parse("1,2,3 = dim", big),
+            Err(MaskError::Syntax { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,119 @@
+//! The cube's health in Prometheus' text exposition format, served as `/metrics` by the HTTP
+//! server so a long running cube can be watched in Grafana like anything else in the house.
+
+use std::fmt::Write;
+
+use crate::control::Control;
+
+/// One metric with its help and type, named `cube_` something
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = write!(
+        out,
+        "# HELP cube_{name} {help}\n# TYPE cube_{name} {kind}\ncube_{name} {value}\n"
+    );
+}
+
+/// Every metric there is for now. Those the display thread measures are only there while
+/// frames go to a display.
+pub fn render(control: &Control) -> String {
+    let mut out = String::new();
+    metric(
+        &mut out,
+        "uptime_seconds",
+        "gauge",
+        "Seconds since the program started",
+        format_args!("{:.3}", control.uptime().as_secs_f64()),
+    );
+    metric(
+        &mut out,
+        "frames_sent_total",
+        "counter",
+        "Frames the program has sent to the output",
+        control.frames_counted(),
+    );
+
+    let Some(stats) = control.refresh_stats() else {
+        return out;
+    };
+    metric(
+        &mut out,
+        "frames_shown_total",
+        "counter",
+        "Frames the display has shown",
+        stats.shown,
+    );
+    metric(
+        &mut out,
+        "frames_dropped_total",
+        "counter",
+        "Frames replaced by a newer one before the display could show them",
+        stats.dropped,
+    );
+    metric(
+        &mut out,
+        "refresh_rate_hertz",
+        "gauge",
+        "Sweeps of the whole cube a second",
+        format_args!("{:.2}", stats.refresh_rate),
+    );
+    metric(
+        &mut out,
+        "frame_rate_hertz",
+        "gauge",
+        "Frames a second the display takes from the program",
+        format_args!("{:.2}", stats.frame_rate),
+    );
+    metric(
+        &mut out,
+        "refresh_slowest_seconds",
+        "gauge",
+        "The longest one refresh took over the last second",
+        format_args!("{:.6}", stats.worst_refresh.as_secs_f64()),
+    );
+    metric(
+        &mut out,
+        "queue_depth",
+        "gauge",
+        "The most frames found waiting for the display at once over the last second",
+        stats.queue_depth,
+    );
+    metric(
+        &mut out,
+        "gpio_errors_total",
+        "counter",
+        "Writes to the cube that failed",
+        stats.errors,
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::display::RefreshStats;
+
+    #[test]
+    fn display_metrics_follow_the_display() {
+        let control = Control::new();
+        control.count_frame();
+        let text = render(&control);
+        assert!(
+            text.contains("\n# TYPE cube_frames_sent_total counter\ncube_frames_sent_total 1\n")
+        );
+        assert!(!text.contains("cube_refresh_rate_hertz"));
+
+        control.watch_refresh(Arc::new(Mutex::new(RefreshStats {
+            refresh_rate: 120.0,
+            errors: 2,
+            ..RefreshStats::default()
+        })));
+        let text = render(&control);
+        assert!(text.contains("\ncube_refresh_rate_hertz 120.00\n"));
+        assert!(text.contains("\ncube_gpio_errors_total 2\n"));
+        // Every sample has its help and type
+        let samples = text.lines().filter(|line| !line.starts_with('#'));
+        assert_eq!(samples.count() * 3, text.lines().count());
+    }
+}
@@ -0,0 +1,416 @@
+//! MQTT (3.1.1) for home automation. The cube connects to a broker and subscribes to topics under
+//! a prefix, `cube` by default, taking each message as the value to change:
+//!
+//! - `cube/program`: a program and its arguments, as typed after `rpi-led-cube`
+//! - `cube/speed`: how fast the program runs, `2` or `0.5x`
+//! - `cube/brightness`: from 0 to 15
+//! - `cube/rotation`: `i`, `j`, `k` or orientation steps such as `x,z'`
+//! - `cube/frame`: 128 hex digits shown in place of the program, or nothing to blank the cube,
+//!   until the next program is chosen
+//!
+//! The cube's settings and frame rate go out as JSON on `cube/state` every few seconds and after
+//! each change, and `cube/availability` is `online` while connected and `offline` once not, as
+//! Home Assistant expects. Both are retained. The connection is remade whenever it drops.
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::invalid;
+
+use crate::control::{Control, ProgramCheck};
+
+/// Used when the broker is given without a port
+pub const DEFAULT_PORT: u16 = 1883;
+/// Topics taken as changes, each under the prefix
+const TOPICS: [&str; 5] = ["program", "speed", "brightness", "rotation", "frame"];
+/// Longest packet taken from the broker, far more than a frame needs
+const MAX_PACKET: usize = 4096;
+/// How long the broker waits without hearing from us before giving up, in seconds. State goes
+/// out far more often than this, so no pings are needed.
+const KEEP_ALIVE: u16 = 60;
+const STATE_INTERVAL: Duration = Duration::from_secs(5);
+const POLL: Duration = Duration::from_millis(50);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const DISCONNECT: u8 = 14;
+
+/// Add the default port to a broker's address if it has none
+pub fn with_default_port(broker: &str) -> String {
+    let has_port = match broker.rsplit_once(':') {
+        Some((host, port)) => {
+            port.parse::<u16>().is_ok() && (host.ends_with(']') || !host.contains(':'))
+        }
+        None => false,
+    };
+    if has_port {
+        broker.to_owned()
+    } else if broker.contains(':') && !broker.starts_with('[') {
+        format!("[{broker}]:{DEFAULT_PORT}")
+    } else {
+        format!("{broker}:{DEFAULT_PORT}")
+    }
+}
+
+/// A packet of `kind` with the low `flags`, prefixed with its remaining length
+fn packet(kind: u8, flags: u8, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![kind << 4 | flags];
+    let mut length = body.len();
+    loop {
+        let digit = (length % 128) as u8;
+        length /= 128;
+        out.push(if length > 0 { digit | 0x80 } else { digit });
+        if length == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(body);
+    out
+}
+
+/// Text prefixed with its length, as every string in MQTT is
+fn put_str(out: &mut Vec<u8>, text: &str) {
+    out.extend((text.len() as u16).to_be_bytes());
+    out.extend_from_slice(text.as_bytes());
+}
+
+/// Connect with a clean session, leaving `offline` retained on `will_topic` for when we vanish
+fn connect(client_id: &str, will_topic: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_str(&mut body, "MQTT");
+    // Protocol level 4 is 3.1.1; flags are clean session, a will, and retain the will
+    body.extend([4, 0b0010_0110]);
+    body.extend(KEEP_ALIVE.to_be_bytes());
+    put_str(&mut body, client_id);
+    put_str(&mut body, will_topic);
+    put_str(&mut body, "offline");
+    packet(CONNECT, 0, &body)
+}
+
+/// Subscribe to `topics` at QoS 0
+fn subscribe(id: u16, topics: &[String]) -> Vec<u8> {
+    let mut body = id.to_be_bytes().to_vec();
+    for topic in topics {
+        put_str(&mut body, topic);
+        body.push(0);
+    }
+    packet(SUBSCRIBE, 0b0010, &body)
+}
+
+/// Publish at QoS 0
+fn publish(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    put_str(&mut body, topic);
+    body.extend_from_slice(payload);
+    packet(PUBLISH, u8::from(retain), &body)
+}
+
+/// One packet's first byte and the rest of it after the length
+fn read_packet(input: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0; 1];
+    input.read_exact(&mut byte)?;
+    let header = byte[0];
+
+    let mut length = 0;
+    for shift in (0..4).map(|i| 7 * i) {
+        input.read_exact(&mut byte)?;
+        length |= usize::from(byte[0] & 0x7F) << shift;
+        if byte[0] & 0x80 == 0 {
+            if length > MAX_PACKET {
+                return Err(invalid(
+                    "MQTT packet",
+                    &format!("{length} bytes is too long"),
+                ));
+            }
+            let mut body = vec![0; length];
+            input.read_exact(&mut body)?;
+            return Ok((header, body));
+        }
+    }
+    Err(invalid(
+        "MQTT packet",
+        "remaining length runs past four bytes",
+    ))
+}
+
+/// The topic, payload and, for QoS 1 and 2, packet ID of a PUBLISH
+fn parse_publish(header: u8, body: &[u8]) -> io::Result<(String, &[u8], Option<u16>)> {
+    let short = || invalid("MQTT packet", "PUBLISH is cut short");
+    let length = usize::from(u16::from_be_bytes([
+        *body.first().ok_or_else(short)?,
+        *body.get(1).ok_or_else(short)?,
+    ]));
+    let topic = body.get(2..2 + length).ok_or_else(short)?;
+    let topic = String::from_utf8(topic.to_vec())
+        .map_err(|_| invalid("MQTT packet", "topic isn't UTF-8"))?;
+    let rest = &body[2 + length..];
+    if header >> 1 & 0b11 == 0 {
+        return Ok((topic, rest, None));
+    }
+    let id = rest.get(..2).ok_or_else(short)?;
+    Ok((topic, &rest[2..], Some(u16::from_be_bytes([id[0], id[1]]))))
+}
+
+/// Keep the cube connected to `broker` on a thread of its own until the stop token is set,
+/// reconnecting with a growing wait whenever the connection fails or drops. `check` decides
+/// which programs can be switched to.
+pub fn spawn(
+    broker: String,
+    prefix: String,
+    control: Arc<Control>,
+    check: ProgramCheck,
+    stop_token: Arc<AtomicBool>,
+) {
+    let check = Arc::new(Mutex::new(check));
+    thread::spawn(move || {
+        let mut backoff = Duration::from_secs(1);
+        while !stop_token.load(Ordering::Relaxed) {
+            let connected = Instant::now();
+            match session(&broker, &prefix, &control, &check, &stop_token) {
+                Ok(()) => break,
+                Err(e) => eprintln!("MQTT broker {broker}: {e}"),
+            }
+            // A connection that lasted a while was a drop rather than a broker turning us away
+            if connected.elapsed() > MAX_BACKOFF {
+                backoff = Duration::from_secs(1);
+            }
+            let retry = Instant::now() + backoff;
+            while Instant::now() < retry && !stop_token.load(Ordering::Relaxed) {
+                thread::sleep(POLL);
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+}
+
+/// One connection, from CONNECT until the stop token is set, which is `Ok`, or it fails
+fn session(
+    broker: &str,
+    prefix: &str,
+    control: &Arc<Control>,
+    check: &Arc<Mutex<ProgramCheck>>,
+    stop_token: &AtomicBool,
+) -> io::Result<()> {
+    let address = broker
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address found"))?;
+    let mut stream = TcpStream::connect_timeout(&address, Duration::from_secs(5))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let availability = format!("{prefix}/availability");
+    let client_id = format!("{prefix}-{}", std::process::id());
+    stream.write_all(&connect(&client_id, &availability))?;
+    match read_packet(&mut stream)? {
+        (header, body) if header >> 4 == CONNACK && body.len() == 2 => {
+            if body[1] != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!("connection refused with code {}", body[1]),
+                ));
+            }
+        }
+        _ => return Err(invalid("MQTT packet", "expected CONNACK")),
+    }
+    let topics: Vec<String> = TOPICS.iter().map(|t| format!("{prefix}/{t}")).collect();
+    stream.write_all(&subscribe(1, &topics))?;
+    stream.write_all(&publish(&availability, b"online", true))?;
+    stream.set_read_timeout(None)?;
+
+    let closed = Arc::new(AtomicBool::new(false));
+    let changed = Arc::new(AtomicBool::new(true));
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let reader = {
+        let (prefix, control, check) = (prefix.to_owned(), control.clone(), check.clone());
+        let (closed, changed, writer) = (closed.clone(), changed.clone(), writer.clone());
+        thread::spawn(move || {
+            let result = read_changes(&mut stream, &prefix, &control, &check, &changed, &writer);
+            closed.store(true, Ordering::Relaxed);
+            result
+        })
+    };
+
+    let state = format!("{prefix}/state");
+    let mut fps = 0.0;
+    let (mut counted, mut since) = (control.frames_counted(), Instant::now());
+    let mut sent_at = Instant::now();
+    while !closed.load(Ordering::Relaxed) {
+        if stop_token.load(Ordering::Relaxed) {
+            let mut out = writer.lock().expect("MQTT writer poisoned");
+            out.write_all(&publish(&availability, b"offline", true))?;
+            out.write_all(&packet(DISCONNECT, 0, &[]))?;
+            let _ = out.shutdown(Shutdown::Both);
+            return Ok(());
+        }
+        let due = sent_at.elapsed() >= STATE_INTERVAL;
+        if due || changed.swap(false, Ordering::Relaxed) {
+            if due {
+                let total = control.frames_counted();
+                fps = (total - counted) as f64 / since.elapsed().as_secs_f64();
+                (counted, since) = (total, Instant::now());
+            }
+            let payload = format!(
+                "{{{},\"fps\":{:.1}}}",
+                control.settings().json_members(),
+                fps
+            );
+            let mut out = writer.lock().expect("MQTT writer poisoned");
+            out.write_all(&publish(&state, payload.as_bytes(), true))?;
+            sent_at = Instant::now();
+        }
+        thread::sleep(POLL);
+    }
+    match reader.join() {
+        Ok(Err(e)) => Err(e),
+        _ => Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "connection closed",
+        )),
+    }
+}
+
+/// Take messages on the subscribed topics as changes until the connection ends
+fn read_changes(
+    stream: &mut TcpStream,
+    prefix: &str,
+    control: &Control,
+    check: &Mutex<ProgramCheck>,
+    changed: &AtomicBool,
+    writer: &Mutex<TcpStream>,
+) -> io::Result<()> {
+    loop {
+        let (header, body) = read_packet(stream)?;
+        match header >> 4 {
+            PUBLISH => {
+                let (topic, payload, id) = parse_publish(header, &body)?;
+                if let Some(id) = id {
+                    let mut out = writer.lock().expect("MQTT writer poisoned");
+                    out.write_all(&packet(PUBACK, 0, &id.to_be_bytes()))?;
+                }
+                let Some(name) = topic.strip_prefix(prefix).and_then(|t| t.strip_prefix('/'))
+                else {
+                    continue;
+                };
+                let value = String::from_utf8_lossy(payload);
+                let check = check.lock().expect("program check poisoned");
+                match control.change(name, value.trim(), &check) {
+                    Ok(()) => changed.store(true, Ordering::Relaxed),
+                    Err(e) => eprintln!("Ignoring MQTT {topic}: {e}"),
+                }
+            }
+            SUBACK if body.iter().skip(2).any(|&code| code == 0x80) => {
+                eprintln!("MQTT broker refused some of the cube's subscriptions");
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn lengths_take_as_many_bytes_as_they_need() {
+        assert_eq!(packet(DISCONNECT, 0, &[]), [0xE0, 0]);
+        let long = packet(PUBLISH, 0, &[0; 321]);
+        assert_eq!(long[..3], [0x30, 0xC1, 0x02]);
+        assert_eq!(read_packet(&mut &long[..]).unwrap(), (0x30, vec![0; 321]));
+
+        let retained = publish("cube/state", b"{}", true);
+        let (header, body) = read_packet(&mut &retained[..]).unwrap();
+        assert_eq!(header, 0x31);
+        assert_eq!(
+            parse_publish(header, &body).unwrap(),
+            ("cube/state".to_owned(), &b"{}"[..], None)
+        );
+    }
+
+    #[test]
+    fn brokers_get_the_default_port() {
+        assert_eq!(with_default_port("broker.local"), "broker.local:1883");
+        assert_eq!(with_default_port("10.0.0.2:8883"), "10.0.0.2:8883");
+        assert_eq!(with_default_port("::1"), "[::1]:1883");
+        assert_eq!(with_default_port("[::1]:1884"), "[::1]:1884");
+    }
+
+    /// The topic and payload of the next PUBLISH, skipping anything else
+    fn next_publish(stream: &mut TcpStream) -> (String, String) {
+        loop {
+            let (header, body) = read_packet(stream).unwrap();
+            if header >> 4 == PUBLISH {
+                let (topic, payload, _) = parse_publish(header, &body).unwrap();
+                return (topic, String::from_utf8(payload.to_vec()).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn changes_arrive_and_state_goes_out() {
+        let broker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = broker.local_addr().unwrap().to_string();
+        let control = Arc::new(Control::new());
+        let stop_token = Arc::new(AtomicBool::new(false));
+        let check: ProgramCheck = Box::new(|_| Ok(()));
+        spawn(
+            address,
+            "cube".to_owned(),
+            control.clone(),
+            check,
+            stop_token.clone(),
+        );
+
+        let (mut client, _) = broker.accept().unwrap();
+        let (header, body) = read_packet(&mut client).unwrap();
+        assert_eq!(header >> 4, CONNECT);
+        assert_eq!(body[..6], *b"\0\x04MQTT");
+        client.write_all(&packet(CONNACK, 0, &[0, 0])).unwrap();
+
+        let (header, body) = read_packet(&mut client).unwrap();
+        assert_eq!(header, SUBSCRIBE << 4 | 0b0010);
+        assert!(body.windows(12).any(|w| w == b"cube/program"));
+        client
+            .write_all(&packet(SUBACK, 0, &[0, 1, 0, 0, 0, 0, 0]))
+            .unwrap();
+        assert_eq!(
+            next_publish(&mut client),
+            ("cube/availability".to_owned(), "online".to_owned())
+        );
+        let (topic, _) = next_publish(&mut client);
+        assert_eq!(topic, "cube/state");
+
+        client
+            .write_all(&publish("cube/speed", b"2x", false))
+            .unwrap();
+        client
+            .write_all(&publish("cube/speed", b"fast", false))
+            .unwrap();
+        let (topic, state) = next_publish(&mut client);
+        assert_eq!(topic, "cube/state");
+        assert!(state.contains("\"speed\":2,"), "{state}");
+        assert!(state.contains("\"fps\":"), "{state}");
+        assert_eq!(control.settings().speed, 2.0);
+
+        stop_token.store(true, Ordering::Relaxed);
+        assert_eq!(
+            next_publish(&mut client),
+            ("cube/availability".to_owned(), "offline".to_owned())
+        );
+        assert_eq!(read_packet(&mut client).unwrap(), (DISCONNECT << 4, vec![]));
+    }
+}
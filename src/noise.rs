@@ -0,0 +1,93 @@
+/// Smoothly interpolated lattice noise in four dimensions, deterministic for a given seed
+pub struct ValueNoise {
+    seed: u64,
+}
+
+impl ValueNoise {
+    pub fn new(seed: u64) -> Self {
+        ValueNoise { seed }
+    }
+
+    /// Pseudo-random value in [0, 1] for a lattice point
+    fn lattice(&self, p: [i64; 4]) -> f32 {
+        // SplitMix64 finalizer over the seed and each coordinate in turn
+        let mut h = self.seed;
+        for c in p {
+            h = (h ^ c as u64).wrapping_add(0x9e37_79b9_7f4a_7c15);
+            h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            h ^= h >> 31;
+        }
+        (h >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Noise at an arbitrary point, in [0, 1] and continuous in every coordinate
+    pub fn sample(&self, p: [f32; 4]) -> f32 {
+        let base = p.map(|c| c.floor());
+        // Smoothstep so the gradient is also continuous across lattice cells
+        let weight = core::array::from_fn::<f32, 4, _>(|i| {
+            let f = p[i] - base[i];
+            f * f * (3.0 - 2.0 * f)
+        });
+        let base = base.map(|c| c as i64);
+
+        // Blend the 16 corners of the surrounding hypercube
+        (0..16usize)
+            .map(|corner| {
+                let mut point = base;
+                let mut w = 1.0;
+                for axis in 0..4 {
+                    if corner & (1 << axis) != 0 {
+                        // Wrapping, as saturated casts of huge coordinates sit at i64::MAX
+                        point[axis] = point[axis].wrapping_add(1);
+                        w *= weight[axis];
+                    } else {
+                        w *= 1.0 - weight[axis];
+                    }
+                }
+                w * self.lattice(point)
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn noise_is_deterministic_for_a_seed() {
+        let p = [1.3, -2.7, 0.4, 12.9];
+        assert_eq!(ValueNoise::new(5).sample(p), ValueNoise::new(5).sample(p));
+        assert_ne!(ValueNoise::new(5).sample(p), ValueNoise::new(6).sample(p));
+    }
+
+    #[test]
+    fn neighbouring_samples_stay_close() {
+        // Smoothstep's slope peaks at 1.5, so a step of `STEP` moves the value at most 1.5 * STEP
+        const STEP: f32 = 0.01;
+        let noise = ValueNoise::new(9);
+        let mut rng = SmallRng::seed_from_u64(9);
+        for _ in 0..2000 {
+            let p: [f32; 4] = core::array::from_fn(|_| rng.gen_range(-50.0..50.0));
+            let value = noise.sample(p);
+            assert!((0.0..=1.0).contains(&value), "{p:?}");
+            for axis in 0..4 {
+                let mut q = p;
+                q[axis] += STEP;
+                let change = (noise.sample(q) - value).abs();
+                assert!(change <= 1.5 * STEP + 1e-4, "{p:?} along {axis}: {change}");
+            }
+        }
+    }
+
+    #[test]
+    fn huge_coordinates_still_sample() {
+        let noise = ValueNoise::new(1);
+        for c in [1e30, -1e30, f32::MAX, f32::MIN] {
+            assert!((0.0..=1.0).contains(&noise.sample([c, 0.5, -c, c])), "{c}");
+        }
+    }
+}
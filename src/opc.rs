@@ -0,0 +1,246 @@
+//! Open Pixel Control, as FadeCandy's tools and Processing sketches speak it: messages over TCP
+//! of a channel, a command, a big-endian u16 length and that many bytes. "Set pixel colours"
+//! carries RGB for each pixel in turn, and the cube takes its first 512 pixels as voxels, each as
+//! bright as its brightest colour.
+
+use std::{
+    fs,
+    io::{self, ErrorKind, Read},
+    net::TcpListener,
+    path::Path,
+    sync::mpsc::{sync_channel, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::{
+    dmx,
+    geometry::Coord,
+    gray::{self, GrayFrame},
+};
+
+pub const PORT: u16 = 7890;
+
+/// Every voxel a pixel
+const PIXELS: usize = 512;
+const SET_PIXEL_COLOURS: u8 = 0;
+
+/// Which voxel each OPC pixel lights
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PixelOrder {
+    voxels: Vec<Option<Coord>>,
+}
+
+/// Pixels in the order of [`Coord::all`], layer by layer from the bottom
+impl Default for PixelOrder {
+    fn default() -> Self {
+        PixelOrder {
+            voxels: Coord::all().map(Some).collect(),
+        }
+    }
+}
+
+impl PixelOrder {
+    /// Read a lookup table of one line per pixel, from pixel 0, each the `x,y,z` of its voxel or
+    /// `-` for a pixel that lights nothing. `#` starts a comment. Pixels after the last line light
+    /// nothing either.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut voxels = Vec::new();
+        for (line, content) in (1..).zip(text.lines()) {
+            let content = content.split('#').next().unwrap_or_default().trim();
+            if content.is_empty() {
+                continue;
+            }
+            if voxels.len() == PIXELS {
+                return Err(invalid(line, format!("more than {PIXELS} pixels")));
+            }
+            if content == "-" {
+                voxels.push(None);
+                continue;
+            }
+            let position: Vec<Option<u8>> = content
+                .split(',')
+                .map(|n| n.trim().parse().ok().filter(|&n| n < 8))
+                .collect();
+            match position[..] {
+                [Some(x), Some(y), Some(z)] => voxels.push(Some(Coord::new(x, y, z))),
+                _ => {
+                    return Err(invalid(
+                        line,
+                        format!("expected x,y,z from 0 to 7 or -, found {content:?}"),
+                    ))
+                }
+            }
+        }
+        Ok(PixelOrder { voxels })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Set the voxels of the pixels a message gives colours for, leaving the rest as they were
+    fn apply(&self, rgb: &[u8], gray: &mut GrayFrame) {
+        for (voxel, colour) in self.voxels.iter().zip(rgb.chunks_exact(3)) {
+            if let Some(voxel) = voxel {
+                let brightest = colour.iter().copied().max().unwrap_or_default();
+                gray::set(gray, *voxel, dmx::level(brightest));
+            }
+        }
+    }
+}
+
+fn invalid(line: usize, message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("line {line}: {message}"))
+}
+
+/// One message's channel, command and data, or `None` when the client hung up between messages
+fn read_message(reader: &mut impl Read) -> io::Result<Option<(u8, u8, Vec<u8>)>> {
+    let mut header = [0u8; 4];
+    match reader.read_exact(&mut header[..1]) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    reader.read_exact(&mut header[1..])?;
+    let mut data = vec![0; usize::from(u16::from_be_bytes([header[2], header[3]]))];
+    reader.read_exact(&mut data)?;
+    Ok(Some((header[0], header[1], data)))
+}
+
+/// Gray frames from OPC clients, newest first like [`Listener`](crate::listener::Listener).
+/// One client is served at a time, and the next waiting one is accepted once it disconnects.
+/// Only channel 0, which OPC sends to every device, and channel 1 reach the cube.
+pub struct OpcServer {
+    rx: Receiver<GrayFrame>,
+    frame: GrayFrame,
+}
+
+impl OpcServer {
+    pub fn spawn(listener: TcpListener, order: PixelOrder) -> Self {
+        let (tx, rx) = sync_channel(64);
+        thread::spawn(move || {
+            let mut gray = [[[0; 8]; 8]; 8];
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("Could not accept an OPC client: {e}");
+                        continue;
+                    }
+                };
+                let peer = stream
+                    .peer_addr()
+                    .map_or_else(|_| "unknown peer".to_owned(), |addr| addr.to_string());
+                eprintln!("Showing pixels from {peer}");
+
+                loop {
+                    match read_message(&mut stream) {
+                        Ok(Some((0 | 1, SET_PIXEL_COLOURS, rgb))) => {
+                            order.apply(&rgb, &mut gray);
+                            if tx.send(gray).is_err() {
+                                return;
+                            }
+                        }
+                        // Other channels, and commands such as system exclusive, aren't ours
+                        Ok(Some(_)) => {}
+                        Ok(None) => {
+                            eprintln!("{peer} disconnected");
+                            break;
+                        }
+                        Err(e) => {
+                            eprintln!("Dropping {peer}: {e}");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        OpcServer {
+            rx,
+            frame: [[[0; 8]; 8]; 8],
+        }
+    }
+}
+
+impl Iterator for OpcServer {
+    type Item = GrayFrame;
+
+    fn next(&mut self) -> Option<GrayFrame> {
+        let mut fresh = false;
+        loop {
+            match self.rx.try_recv() {
+                Ok(frame) => {
+                    self.frame = frame;
+                    fresh = true;
+                }
+                Err(TryRecvError::Empty) => return Some(self.frame),
+                Err(TryRecvError::Disconnected) => return fresh.then_some(self.frame),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::TcpStream,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::gray::MAX_LEVEL;
+
+    #[test]
+    fn lookup_tables_place_pixels() {
+        let order = PixelOrder::parse("# a corner first\n7,7,7\n-\n0, 1, 0\n").unwrap();
+        let mut gray = [[[0; 8]; 8]; 8];
+        order.apply(
+            &[0, 255, 0, 255, 255, 255, 0, 0, 17, 255, 255, 255],
+            &mut gray,
+        );
+        assert_eq!(gray[7][7][7], MAX_LEVEL);
+        assert_eq!(gray[0][0][1], 1);
+        assert_eq!(
+            gray.iter().flatten().flatten().filter(|&&l| l > 0).count(),
+            2
+        );
+
+        for bad in ["8,0,0", "1,2", "x,y,z"] {
+            let error = PixelOrder::parse(bad).unwrap_err();
+            assert!(error.to_string().starts_with("line 1: "), "{bad}: {error}");
+        }
+        assert!(PixelOrder::parse(&"-\n".repeat(PIXELS + 1)).is_err());
+    }
+
+    #[test]
+    fn clients_set_pixels() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut opc = OpcServer::spawn(server, PixelOrder::default());
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        let message = |channel: u8, rgb: &[u8]| {
+            let mut message = vec![channel, SET_PIXEL_COLOURS];
+            message.extend((rgb.len() as u16).to_be_bytes());
+            message.extend(rgb);
+            message
+        };
+        // Another device's pixels, then ours, the second being the next voxel along Y
+        client.write_all(&message(2, &[255; 6])).unwrap();
+        client
+            .write_all(&message(0, &[0, 0, 0, 255, 0, 0]))
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let gray = opc.next().unwrap();
+            if gray[0][0][1] == MAX_LEVEL {
+                assert_eq!(gray[0][0][0], 0);
+                break;
+            }
+            assert!(Instant::now() < deadline, "pixels never arrived");
+            thread::yield_now();
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::{fmt, str::FromStr};
+
+use crate::{geometry::Coord, pipeline::Rotation, Frame};
+
+/// One of the 48 ways of laying the cube back onto itself: the 24 rotations, each with or
+/// without a mirror flip. Every axis of the result is one axis of the source, maybe reversed.
+/// Orientations compose with `then`, and parse from a comma separated list of steps such as
+/// `x90,z180,flip-y`, see `FromStr`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Orientation {
+    /// For each axis of the result, the axis of the source it is read from
+    from: [usize; 3],
+    /// For each axis of the result, whether it runs the other way to its source
+    reversed: [bool; 3],
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Orientation {
+    pub const IDENTITY: Orientation = Orientation {
+        from: [0, 1, 2],
+        reversed: [false; 3],
+    };
+
+    /// A quarter turn about `axis`, 0 to 2 for X to Z, anticlockwise as seen from +`axis`
+    /// looking back at the centre
+    pub fn quarter_turn(axis: usize) -> Self {
+        // The turn takes the next axis round onto the one after it, and that one back onto the
+        // reverse of the next, e.g. +Y onto +Z and +Z onto -Y about X
+        let next = (axis + 1) % 3;
+        let after = (axis + 2) % 3;
+        let mut turn = Self::IDENTITY;
+        turn.from[next] = after;
+        turn.reversed[next] = true;
+        turn.from[after] = next;
+        turn
+    }
+
+    /// Mirror along `axis`, 0 to 2 for X to Z
+    pub fn flip(axis: usize) -> Self {
+        let mut flip = Self::IDENTITY;
+        flip.reversed[axis] = true;
+        flip
+    }
+
+    /// This orientation followed by `next`
+    pub fn then(self, next: Orientation) -> Orientation {
+        Orientation {
+            from: next.from.map(|axis| self.from[axis]),
+            reversed: core::array::from_fn(|i| next.reversed[i] ^ self.reversed[next.from[i]]),
+        }
+    }
+
+    /// The orientation that undoes this one
+    pub fn inverse(self) -> Orientation {
+        let mut inverse = Self::IDENTITY;
+        for (axis, &source) in self.from.iter().enumerate() {
+            inverse.from[source] = axis;
+            inverse.reversed[source] = self.reversed[axis];
+        }
+        inverse
+    }
+
+    /// Whether this turns the cube inside out, so no rotation alone could do the same
+    pub fn is_mirror(self) -> bool {
+        // An odd permutation of the axes or an odd number of reversals, but not both
+        let swaps = (0..3)
+            .flat_map(|i| (i + 1..3).map(move |j| (i, j)))
+            .filter(|&(i, j)| self.from[i] > self.from[j])
+            .count();
+        let reversals = self.reversed.iter().filter(|&&r| r).count();
+        (swaps + reversals) % 2 == 1
+    }
+
+    /// Every distinct orientation, rotations and mirror images alike
+    pub fn all() -> impl Iterator<Item = Orientation> {
+        const PERMUTATIONS: [[usize; 3]; 6] = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        PERMUTATIONS.into_iter().flat_map(|from| {
+            (0..8).map(move |bits: u8| Orientation {
+                from,
+                reversed: core::array::from_fn(|i| bits & (1 << i) != 0),
+            })
+        })
+    }
+
+    /// Where a voxel ends up
+    pub fn apply_coord(&self, c: Coord) -> Coord {
+        let source = [c.x, c.y, c.z];
+        let [x, y, z]: [u8; 3] = core::array::from_fn(|i| {
+            let v = source[self.from[i]];
+            if self.reversed[i] {
+                7 - v
+            } else {
+                v
+            }
+        });
+        Coord::new(x, y, z)
+    }
+
+    pub fn apply(&self, data: &Frame) -> Frame {
+        if *self == Self::IDENTITY {
+            return *data;
+        }
+        let mut oriented = [[0u8; 8]; 8];
+        Coord::all()
+            .filter(|c| c.get(data))
+            .for_each(|c| self.apply_coord(c).set(&mut oriented));
+        oriented
+    }
+}
+
+impl From<Rotation> for Orientation {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::None => Orientation::IDENTITY,
+            Rotation::I => Orientation::quarter_turn(0),
+            Rotation::J => Orientation::quarter_turn(1),
+            // Always turned the other way round to the other two
+            Rotation::K => (0..3).fold(Orientation::IDENTITY, |o, _| {
+                o.then(Orientation::quarter_turn(2))
+            }),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseOrientationError(String);
+
+impl fmt::Display for ParseOrientationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for ParseOrientationError {}
+
+fn axis(name: char) -> Option<usize> {
+    match name.to_ascii_lowercase() {
+        'x' => Some(0),
+        'y' => Some(1),
+        'z' => Some(2),
+        _ => None,
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = ParseOrientationError;
+
+    /// Steps applied in order, separated by commas: a turn about an axis by a multiple of 90
+    /// degrees such as `x90` or `z-90`, a mirror such as `flip-y`, or `none`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut orientation = Orientation::IDENTITY;
+
+        for step in s.split(',').map(str::trim) {
+            let error = || {
+                ParseOrientationError(format!(
+                    "expected steps like x90, z-180 or flip-y, found {step:?}"
+                ))
+            };
+            let mut chars = step.chars();
+            let next = if step.eq_ignore_ascii_case("none") {
+                Orientation::IDENTITY
+            } else if let Some(name) = step.strip_prefix("flip-") {
+                let mut name = name.chars();
+                match (name.next().and_then(axis), name.next()) {
+                    (Some(a), None) => Orientation::flip(a),
+                    _ => return Err(error()),
+                }
+            } else if let (Some(a), Ok(degrees)) =
+                (chars.next().and_then(axis), chars.as_str().parse::<i32>())
+            {
+                if degrees % 90 != 0 {
+                    return Err(ParseOrientationError(format!(
+                        "{step}: only multiples of 90 degrees keep the cube's voxels on its grid"
+                    )));
+                }
+                let quarters = (degrees / 90).rem_euclid(4);
+                (0..quarters).fold(Orientation::IDENTITY, |o, _| {
+                    o.then(Orientation::quarter_turn(a))
+                })
+            } else {
+                return Err(error());
+            };
+            orientation = orientation.then(next);
+        }
+
+        Ok(orientation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn there_are_24_rotations_and_24_mirror_images() {
+        let all: HashSet<Orientation> = Orientation::all().collect();
+        assert_eq!(all.len(), 48);
+        assert_eq!(all.iter().filter(|o| o.is_mirror()).count(), 24);
+
+        // Quarter turns alone reach every rotation and nothing else
+        let turns = [0, 1, 2].map(Orientation::quarter_turn);
+        let mut reached = HashSet::from([Orientation::IDENTITY]);
+        let mut frontier = vec![Orientation::IDENTITY];
+        while let Some(o) = frontier.pop() {
+            for turn in turns {
+                if reached.insert(o.then(turn)) {
+                    frontier.push(o.then(turn));
+                }
+            }
+        }
+        assert_eq!(reached.len(), 24);
+        assert!(reached.iter().all(|o| !o.is_mirror()));
+    }
+
+    #[test]
+    fn composition_matches_applying_in_turn() {
+        let mut rng = SmallRng::seed_from_u64(6);
+        let all: Vec<Orientation> = Orientation::all().collect();
+        for _ in 0..200 {
+            let (a, b) = (all[rng.gen_range(0..48)], all[rng.gen_range(0..48)]);
+            let frame: Frame = rng.gen();
+            assert_eq!(a.then(b).apply(&frame), b.apply(&a.apply(&frame)));
+            assert_eq!(a.then(a.inverse()), Orientation::IDENTITY);
+        }
+    }
+
+    #[test]
+    fn quarter_turns_go_anticlockwise() {
+        // +Y towards +Z about X, +Z towards +X about Y and +X towards +Y about Z
+        let turned = |axis, c| Orientation::quarter_turn(axis).apply_coord(c);
+        assert_eq!(turned(0, Coord::new(0, 7, 0)), Coord::new(0, 7, 7));
+        assert_eq!(turned(1, Coord::new(0, 0, 7)), Coord::new(7, 0, 7));
+        assert_eq!(turned(2, Coord::new(7, 0, 0)), Coord::new(7, 7, 0));
+    }
+
+    #[test]
+    fn steps_parse_and_compose_in_order() {
+        let x90 = Orientation::quarter_turn(0);
+        let z90 = Orientation::quarter_turn(2);
+        assert_eq!("x90".parse(), Ok(x90));
+        assert_eq!("X90, z90".parse(), Ok(x90.then(z90)));
+        assert_eq!("x-90".parse(), Ok(x90.inverse()));
+        assert_eq!("y360,none".parse(), Ok(Orientation::IDENTITY));
+        assert_eq!("flip-y,flip-y".parse(), Ok(Orientation::IDENTITY));
+        assert!("x90,flip-z".parse::<Orientation>().unwrap().is_mirror());
+
+        for bad in ["", "x45", "w90", "flip-", "flip-xy", "x"] {
+            assert!(bad.parse::<Orientation>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn the_named_rotations_keep_their_turns() {
+        type Turn = fn(Coord) -> Coord;
+        let turns: [(Rotation, Turn); 4] = [
+            (Rotation::None, |c| c),
+            (Rotation::I, |c| Coord::new(c.x, 7 - c.z, c.y)),
+            (Rotation::J, |c| Coord::new(c.z, c.y, 7 - c.x)),
+            (Rotation::K, |c| Coord::new(c.y, 7 - c.x, c.z)),
+        ];
+        for (rotation, turn) in turns {
+            for c in Coord::all() {
+                assert_eq!(
+                    Orientation::from(rotation).apply_coord(c),
+                    turn(c),
+                    "{rotation}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Keeps a loop on a fixed cadence by only sleeping for whatever is left of each period after
+/// the work in it, such as producing a frame or writing one to a sink, is done
+pub struct Pacer {
+    period: Duration,
+    deadline: Instant,
+    warned: bool,
+}
+
+impl Pacer {
+    pub fn new(period: Duration) -> Self {
+        Pacer {
+            period,
+            deadline: Instant::now() + period,
+            warned: false,
+        }
+    }
+
+    /// A pacer that doesn't warn when overrun, for callers that report that themselves
+    pub fn quiet(period: Duration) -> Self {
+        Pacer {
+            warned: true,
+            ..Pacer::new(period)
+        }
+    }
+
+    /// What is left of the current period at `now`, moving on to the next one. Once the period
+    /// has passed this is zero, and the next period is measured from `now` rather than trying to
+    /// catch up.
+    pub fn remaining(&mut self, now: Instant) -> Duration {
+        let remaining = self.deadline.saturating_duration_since(now);
+
+        if remaining.is_zero() {
+            if !self.warned {
+                eprintln!(
+                    "Frames are taking longer than {:?}, running as fast as possible",
+                    self.period
+                );
+                self.warned = true;
+            }
+            self.deadline = now + self.period;
+        } else {
+            self.deadline += self.period;
+        }
+        remaining
+    }
+
+    /// Sleep until the end of the current period
+    pub fn wait(&mut self) {
+        thread::sleep(self.remaining(Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn quick_work_sleeps_off_the_rest_of_the_period() {
+        let start = Instant::now();
+        let mut pacer = Pacer::quiet(PERIOD);
+        pacer.deadline = start + PERIOD;
+
+        // Each period's work takes 3 ms, the sleeps make up the other 7
+        let mut now = start;
+        for period in 1..=5 {
+            now += Duration::from_millis(3);
+            let sleep = pacer.remaining(now);
+            assert_eq!(sleep, Duration::from_millis(7), "period {period}");
+            now += sleep;
+        }
+        assert_eq!(now, start + 5 * PERIOD);
+    }
+
+    #[test]
+    fn slow_work_sleeps_not_at_all_and_doesnt_catch_up() {
+        let start = Instant::now();
+        let mut pacer = Pacer::quiet(PERIOD);
+        pacer.deadline = start + PERIOD;
+
+        // Work taking 25 ms a period never sleeps
+        let mut now = start;
+        for _ in 0..3 {
+            now += Duration::from_millis(25);
+            assert_eq!(pacer.remaining(now), Duration::ZERO);
+        }
+
+        // Once it speeds up again the periods count from the last overrun, not from the start
+        now += Duration::from_millis(4);
+        assert_eq!(pacer.remaining(now), Duration::from_millis(6));
+    }
+}
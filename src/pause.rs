@@ -0,0 +1,144 @@
+use std::{
+    io,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Freezes the frame source while the display thread keeps refreshing whatever it last got.
+/// When stepping, the source is let through one frame at a time.
+#[derive(Default)]
+pub struct Pause {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    paused: bool,
+    /// Pause again after every frame
+    stepping: bool,
+}
+
+impl Pause {
+    pub fn new() -> Self {
+        Pause::default()
+    }
+
+    /// Start stepping, held on the next frame
+    pub fn start_stepping(&self) {
+        *self.state.lock().expect("pause state poisoned") = State {
+            paused: true,
+            stepping: true,
+        };
+        self.changed.notify_all();
+    }
+
+    /// Pause or resume, or when stepping, let the next frame through
+    pub fn toggle(&self) {
+        let mut state = self.state.lock().expect("pause state poisoned");
+        if state.stepping {
+            state.paused = false;
+        } else {
+            state.paused = !state.paused;
+            eprintln!("{}", if state.paused { "Paused" } else { "Resumed" });
+        }
+        self.changed.notify_all();
+    }
+
+    /// Switch between stepping and running freely
+    pub fn toggle_stepping(&self) {
+        let mut state = self.state.lock().expect("pause state poisoned");
+        state.stepping = !state.stepping;
+        state.paused = state.stepping;
+        eprintln!(
+            "{}",
+            if state.stepping {
+                "Stepping"
+            } else {
+                "Resumed"
+            }
+        );
+        self.changed.notify_all();
+    }
+
+    /// Block for as long as the source is paused, but never past the stop token being set
+    pub fn wait_while_paused(&self, stop_token: &AtomicBool) {
+        let mut state = self.state.lock().expect("pause state poisoned");
+        while state.paused && !stop_token.load(Ordering::Relaxed) {
+            // The Ctrl-C handler can't signal the condvar, so wake up to check the stop token
+            state = self
+                .changed
+                .wait_timeout(state, Duration::from_millis(100))
+                .expect("pause state poisoned")
+                .0;
+        }
+        // Hold the frame about to be pulled until the next step
+        if state.stepping {
+            state.paused = true;
+        }
+    }
+}
+
+fn sigusr1_set() -> libc::sigset_t {
+    let mut set = MaybeUninit::uninit();
+    // SAFETY: sigemptyset initialises the set before sigaddset reads it
+    unsafe {
+        libc::sigemptyset(set.as_mut_ptr());
+        libc::sigaddset(set.as_mut_ptr(), libc::SIGUSR1);
+        set.assume_init()
+    }
+}
+
+/// Toggle `pause` on every SIGUSR1. Must be called before any other thread is spawned, since
+/// threads inherit the blocked signal mask and an unblocked thread would be killed by it. That
+/// includes threads started by libraries and plugins, so `main` calls it before anything else.
+pub fn toggle_on_sigusr1(pause: Arc<Pause>) -> io::Result<()> {
+    let set = sigusr1_set();
+    // SAFETY: set is a valid, initialised signal set
+    let ret = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret));
+    }
+
+    thread::spawn(move || loop {
+        let mut signal = 0;
+        // SAFETY: set is a valid signal set blocked in every thread, signal is a live c_int
+        if unsafe { libc::sigwait(&set, &mut signal) } == 0 {
+            pause.toggle();
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paused(pause: &Pause) -> bool {
+        pause.state.lock().unwrap().paused
+    }
+
+    #[test]
+    fn stepping_lets_one_frame_through_at_a_time() {
+        let stop_token = AtomicBool::new(false);
+        let pause = Pause::new();
+        pause.start_stepping();
+        assert!(paused(&pause));
+
+        pause.toggle();
+        pause.wait_while_paused(&stop_token);
+        assert!(paused(&pause));
+
+        pause.toggle_stepping();
+        pause.wait_while_paused(&stop_token);
+        assert!(!paused(&pause));
+        pause.toggle();
+        assert!(paused(&pause));
+    }
+}
@@ -0,0 +1,265 @@
+use std::{fmt, fs, io, path::Path};
+
+/// Highest BCM GPIO on the 40-pin header
+const MAX_PIN: u8 = 27;
+
+/// Which BCM GPIO carries each signal to the cube, see `CubeDriver` for what the signals do
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PinConfig {
+    pub par_1: u8,
+    pub par_2: u8,
+    pub par_3: u8,
+    pub par_4: u8,
+    pub par_5: u8,
+    pub par_6: u8,
+    pub par_7: u8,
+    pub par_8: u8,
+    pub par_rclk: u8,
+    pub par_srclk: u8,
+    pub par_srclr: u8,
+    pub layer_sel_bit_0: u8,
+    pub layer_sel_bit_1: u8,
+    pub layer_sel_bit_2: u8,
+    /// Only claimed on 16x16x16 cubes, whose 16 layers need a fourth select line
+    pub layer_sel_bit_3: u8,
+    pub out_enable: u8,
+}
+
+/// The wiring the cube was built with
+impl Default for PinConfig {
+    fn default() -> Self {
+        PinConfig {
+            par_1: 12,
+            par_2: 5,
+            par_3: 10,
+            par_4: 18,
+            par_5: 17,
+            par_6: 4,
+            par_7: 2,
+            par_8: 3,
+            par_rclk: 8,
+            par_srclk: 11,
+            par_srclr: 7,
+            layer_sel_bit_0: 6,
+            layer_sel_bit_1: 13,
+            layer_sel_bit_2: 16,
+            layer_sel_bit_3: 19,
+            out_enable: 9,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PinConfigError {
+    Io(io::Error),
+    /// A line that isn't `signal = pin`, or names a signal or pin that doesn't exist
+    Syntax {
+        line: usize,
+        message: String,
+    },
+    /// Two signals on the same GPIO
+    Duplicate {
+        pin: u8,
+        first: &'static str,
+        second: &'static str,
+    },
+}
+
+impl fmt::Display for PinConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinConfigError::Io(e) => write!(f, "{e}"),
+            PinConfigError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            PinConfigError::Duplicate { pin, first, second } => {
+                write!(f, "GPIO {pin} is assigned to both {first} and {second}")
+            }
+        }
+    }
+}
+
+impl PinConfig {
+    /// Every signal by name, in the order the driver claims them
+    pub fn signals(&self) -> [(&'static str, u8); 16] {
+        [
+            ("layer_sel_bit_0", self.layer_sel_bit_0),
+            ("layer_sel_bit_1", self.layer_sel_bit_1),
+            ("layer_sel_bit_2", self.layer_sel_bit_2),
+            ("layer_sel_bit_3", self.layer_sel_bit_3),
+            ("out_enable", self.out_enable),
+            ("par_1", self.par_1),
+            ("par_2", self.par_2),
+            ("par_3", self.par_3),
+            ("par_4", self.par_4),
+            ("par_5", self.par_5),
+            ("par_6", self.par_6),
+            ("par_7", self.par_7),
+            ("par_8", self.par_8),
+            ("par_rclk", self.par_rclk),
+            ("par_srclk", self.par_srclk),
+            ("par_srclr", self.par_srclr),
+        ]
+    }
+
+    /// The par pins in order, par_1 first
+    pub fn par(&self) -> [u8; 8] {
+        [
+            self.par_1, self.par_2, self.par_3, self.par_4, self.par_5, self.par_6, self.par_7,
+            self.par_8,
+        ]
+    }
+
+    /// The layer select lines, least significant first
+    pub fn layer_select(&self) -> [u8; 4] {
+        [
+            self.layer_sel_bit_0,
+            self.layer_sel_bit_1,
+            self.layer_sel_bit_2,
+            self.layer_sel_bit_3,
+        ]
+    }
+
+    fn signal_mut(&mut self, name: &str) -> Option<&mut u8> {
+        Some(match name {
+            "par_1" => &mut self.par_1,
+            "par_2" => &mut self.par_2,
+            "par_3" => &mut self.par_3,
+            "par_4" => &mut self.par_4,
+            "par_5" => &mut self.par_5,
+            "par_6" => &mut self.par_6,
+            "par_7" => &mut self.par_7,
+            "par_8" => &mut self.par_8,
+            "par_rclk" => &mut self.par_rclk,
+            "par_srclk" => &mut self.par_srclk,
+            "par_srclr" => &mut self.par_srclr,
+            "layer_sel_bit_0" => &mut self.layer_sel_bit_0,
+            "layer_sel_bit_1" => &mut self.layer_sel_bit_1,
+            "layer_sel_bit_2" => &mut self.layer_sel_bit_2,
+            "layer_sel_bit_3" => &mut self.layer_sel_bit_3,
+            "out_enable" => &mut self.out_enable,
+            _ => return None,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, PinConfigError> {
+        Self::parse(&fs::read_to_string(path).map_err(PinConfigError::Io)?)
+    }
+
+    /// Read `signal = pin` lines as in TOML, or a flat JSON object of `"signal": pin`. Signals
+    /// that aren't mentioned keep their default pin.
+    pub fn parse(text: &str) -> Result<Self, PinConfigError> {
+        let mut pins = PinConfig::default();
+
+        for (line, content) in (1..).zip(text.lines()) {
+            let content = content.split('#').next().unwrap_or_default();
+            for entry in content.split(',') {
+                let entry = entry.trim().trim_matches(|c| c == '{' || c == '}').trim();
+                // Blank, or a TOML table header such as [pins]
+                if entry.is_empty() || entry.starts_with('[') {
+                    continue;
+                }
+
+                let syntax = |message: String| PinConfigError::Syntax { line, message };
+                let Some((name, pin)) = entry.split_once(['=', ':']) else {
+                    return Err(syntax(format!("expected signal = pin, found {entry:?}")));
+                };
+                let name = name.trim().trim_matches('"');
+                let pin: u8 = match pin.trim().parse() {
+                    Ok(pin) if pin <= MAX_PIN => pin,
+                    _ => {
+                        return Err(syntax(format!(
+                            "{name} must be a GPIO from 0 to {MAX_PIN}, found {}",
+                            pin.trim()
+                        )))
+                    }
+                };
+                match pins.signal_mut(name) {
+                    Some(signal) => *signal = pin,
+                    None => return Err(syntax(format!("unknown signal {name:?}"))),
+                }
+            }
+        }
+
+        pins.validate()?;
+        Ok(pins)
+    }
+
+    /// Every signal needs a GPIO of its own
+    pub fn validate(&self) -> Result<(), PinConfigError> {
+        let signals = self.signals();
+        for (i, &(first, pin)) in signals.iter().enumerate() {
+            if let Some(&(second, _)) = signals[i + 1..].iter().find(|&&(_, other)| other == pin) {
+                return Err(PinConfigError::Duplicate { pin, first, second });
+            }
+        }
+        Ok(())
+    }
+
+    /// Move out_enable to `pin`, whichever signal was there taking out_enable's old pin
+    pub fn with_out_enable_on(mut self, pin: u8) -> Self {
+        let old = self.out_enable;
+        if let Some((name, _)) = self.signals().into_iter().find(|&(_, p)| p == pin) {
+            *self.signal_mut(name).expect("listed signal") = old;
+        }
+        self.out_enable = pin;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_wiring_is_valid() {
+        assert!(PinConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn toml_overrides_only_what_it_names() {
+        let pins =
+            PinConfig::parse("# rewired\n[pins]\npar_1 = 20\nout_enable = 21 # moved\n").unwrap();
+        assert_eq!(pins.par_1, 20);
+        assert_eq!(pins.out_enable, 21);
+        assert_eq!(pins.par_2, PinConfig::default().par_2);
+    }
+
+    #[test]
+    fn json_is_accepted() {
+        let pins = PinConfig::parse("{\"par_1\": 20,\n \"par_2\": 21}").unwrap();
+        assert_eq!((pins.par_1, pins.par_2), (20, 21));
+    }
+
+    #[test]
+    fn duplicates_name_both_signals() {
+        match PinConfig::parse("par_1 = 5") {
+            Err(PinConfigError::Duplicate { pin, first, second }) => {
+                assert_eq!(pin, 5);
+                assert_eq!([first, second], ["par_1", "par_2"]);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_signals_and_bad_pins_are_rejected() {
+        assert!(matches!(
+            PinConfig::parse("\npar_9 = 20"),
+            Err(PinConfigError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            PinConfig::parse("par_1 = 40"),
+            Err(PinConfigError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            PinConfig::parse("par_1 20"),
+            Err(PinConfigError::Syntax { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn out_enable_swaps_with_the_signal_it_displaces() {
+        let pins = PinConfig::default().with_out_enable_on(18);
+        assert_eq!((pins.out_enable, pins.par_4), (18, 9));
+        assert!(pins.validate().is_ok());
+    }
+}
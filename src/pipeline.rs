@@ -0,0 +1,406 @@
+use clap::ValueEnum;
+
+use crate::{
+    color::{self, ColorFrame, Palette},
+    geometry::Coord,
+    gray::{self, GrayFrame, MAX_LEVEL},
+    orientation::Orientation,
+    Frame,
+};
+
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+/// Assume +X is "forward", +Y is "left", and +Z is "up", then
+pub enum Rotation {
+    /// No-op
+    #[default]
+    None,
+    /// Rotate about X
+    I,
+    /// Rotate about Y
+    J,
+    /// Rotate about Z
+    K,
+}
+
+impl std::fmt::Display for Rotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl Rotation {
+    /// Where a voxel ends up, each variant being a quarter turn about its axis
+    pub fn rotate(&self, c: Coord) -> Coord {
+        Orientation::from(*self).apply_coord(c)
+    }
+
+    pub fn apply(&self, data: &Frame) -> Frame {
+        Orientation::from(*self).apply(data)
+    }
+}
+
+/// A step applied to every frame between the routine and the display
+pub trait Transform {
+    fn apply(&mut self, frame: Frame) -> Frame;
+
+    /// The same step for a frame with intensity. Transforms that only know on/off see every
+    /// voxel that is lit at all and light their output at full intensity.
+    fn apply_gray(&mut self, gray: GrayFrame) -> GrayFrame {
+        gray::from_frame(&self.apply(gray::threshold(&gray, 1)))
+    }
+
+    /// The same step for a frame in colour. Transforms that only know on/off see every voxel
+    /// that is lit in any channel and light their output in white.
+    fn apply_color(&mut self, color: ColorFrame) -> ColorFrame {
+        color::from_frame(&self.apply(color::on_off(&color)), &Palette::default())
+    }
+}
+
+/// Turns or mirrors every frame, see `Orientation`
+pub struct Orient(pub Orientation);
+
+impl Transform for Orient {
+    fn apply(&mut self, frame: Frame) -> Frame {
+        self.0.apply(&frame)
+    }
+
+    fn apply_gray(&mut self, gray: GrayFrame) -> GrayFrame {
+        gray::orient(&gray, self.0)
+    }
+
+    fn apply_color(&mut self, color: ColorFrame) -> ColorFrame {
+        color::orient(&color, self.0)
+    }
+}
+
+pub struct Invert;
+
+impl Transform for Invert {
+    fn apply(&mut self, frame: Frame) -> Frame {
+        frame.map(|layer| layer.map(|row| row ^ 0xff))
+    }
+
+    fn apply_gray(&mut self, gray: GrayFrame) -> GrayFrame {
+        gray::invert(&gray)
+    }
+
+    fn apply_color(&mut self, color: ColorFrame) -> ColorFrame {
+        color::invert(&color)
+    }
+}
+
+/// Keeps each LED lit for a number of frames after the source last had it on, so sparse
+/// routines leave a short afterglow
+pub struct Persist {
+    frames: u32,
+    /// Drop every afterglow as soon as the source sends a blank frame
+    hard_clear: bool,
+    /// Frames each LED has left to glow, indexed [z][x][y]
+    remaining: [[[u32; 8]; 8]; 8],
+}
+
+impl Persist {
+    pub fn new(frames: u32, hard_clear: bool) -> Self {
+        Persist {
+            frames,
+            hard_clear,
+            remaining: [[[0; 8]; 8]; 8],
+        }
+    }
+}
+
+impl Transform for Persist {
+    fn apply(&mut self, frame: Frame) -> Frame {
+        if self.hard_clear && frame == [[0; 8]; 8] {
+            self.remaining = [[[0; 8]; 8]; 8];
+            return frame;
+        }
+
+        let mut out = frame;
+        for (z, layer) in frame.iter().enumerate() {
+            for (x, row) in layer.iter().enumerate() {
+                for (y, remaining) in self.remaining[z][x].iter_mut().enumerate() {
+                    if row & (1 << y) != 0 {
+                        *remaining = self.frames;
+                    } else if *remaining > 0 {
+                        *remaining -= 1;
+                        out[z][x] |= 1 << y;
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Afterglow fades out over the frames instead of staying at full intensity
+    fn apply_gray(&mut self, gray: GrayFrame) -> GrayFrame {
+        if self.hard_clear && gray == [[[0; 8]; 8]; 8] {
+            self.remaining = [[[0; 8]; 8]; 8];
+            return gray;
+        }
+
+        let mut out = gray;
+        for c in Coord::all() {
+            let remaining = &mut self.remaining[c.z as usize][c.x as usize][c.y as usize];
+            if gray::get(&gray, c) > 0 {
+                *remaining = self.frames;
+            } else if *remaining > 0 {
+                let level = u32::from(MAX_LEVEL) * *remaining / (self.frames + 1);
+                *remaining -= 1;
+                gray::brighten(&mut out, c, level as u8);
+            }
+        }
+        out
+    }
+}
+
+/// Transforms run in the order they were added, an empty pipeline passes frames through untouched
+#[derive(Default)]
+pub struct Pipeline {
+    transforms: Vec<Box<dyn Transform + Send>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    pub fn push(&mut self, transform: impl Transform + Send + 'static) {
+        self.transforms.push(Box::new(transform));
+    }
+
+    pub fn apply(&mut self, frame: Frame) -> Frame {
+        self.transforms
+            .iter_mut()
+            .fold(frame, |frame, transform| transform.apply(frame))
+    }
+
+    pub fn apply_gray(&mut self, gray: GrayFrame) -> GrayFrame {
+        self.transforms
+            .iter_mut()
+            .fold(gray, |gray, transform| transform.apply_gray(gray))
+    }
+
+    pub fn apply_color(&mut self, color: ColorFrame) -> ColorFrame {
+        self.transforms
+            .iter_mut()
+            .fold(color, |color, transform| transform.apply_color(color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::voxels::Voxels;
+
+    fn popcount(frame: &Frame) -> u32 {
+        Voxels(*frame).count()
+    }
+
+    /// A frame with only the voxel at (x, y, z) lit
+    fn voxel(x: u8, y: u8, z: u8) -> Voxels {
+        let mut voxels = Voxels::new();
+        voxels.set(x, y, z);
+        voxels
+    }
+
+    #[test]
+    fn four_quarter_turns_are_identity() {
+        let mut rng = SmallRng::seed_from_u64(4);
+        for rotation in [Rotation::I, Rotation::J, Rotation::K] {
+            for _ in 0..200 {
+                let frame: Frame = rng.gen();
+                let turned = (0..4).fold(frame, |f, _| rotation.apply(&f));
+                assert_eq!(turned, frame, "{rotation}");
+            }
+        }
+    }
+
+    #[test]
+    fn rotations_preserve_popcount() {
+        let mut rng = SmallRng::seed_from_u64(5);
+        for rotation in [Rotation::None, Rotation::I, Rotation::J, Rotation::K] {
+            for _ in 0..200 {
+                let frame: Frame = rng.gen();
+                assert_eq!(popcount(&rotation.apply(&frame)), popcount(&frame));
+            }
+        }
+    }
+
+    #[test]
+    fn quarter_turns_move_voxels() {
+        // I takes +Y onto +Z, J takes +Z onto +X and K takes +Y onto +X
+        let cases = [
+            (Rotation::None, (1, 2, 3), (1, 2, 3)),
+            (Rotation::I, (1, 2, 3), (1, 4, 2)),
+            (Rotation::I, (0, 0, 7), (0, 0, 0)),
+            (Rotation::J, (1, 2, 3), (3, 2, 6)),
+            (Rotation::J, (0, 0, 7), (7, 0, 7)),
+            (Rotation::K, (1, 2, 3), (2, 6, 3)),
+            (Rotation::K, (0, 0, 7), (0, 7, 7)),
+        ];
+        for (rotation, (x, y, z), (tx, ty, tz)) in cases {
+            let turned = Voxels(rotation.apply(&voxel(x, y, z).0));
+            assert_eq!(turned, voxel(tx, ty, tz), "{rotation} of ({x}, {y}, {z})");
+            assert_eq!(
+                rotation.rotate(Coord::new(x, y, z)),
+                Coord::new(tx, ty, tz),
+                "{rotation}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_corner_lands_on_its_corner_under_every_rotation() {
+        // (x, y, z) from the bottom, back, right corner, as `Coord` documents, stored as
+        // frame[z][x] bit y
+        let cases = [
+            (Rotation::None, (0, 0, 0), (0, 0, 0)),
+            (Rotation::None, (0, 7, 0), (0, 7, 0)),
+            (Rotation::None, (7, 0, 0), (7, 0, 0)),
+            (Rotation::None, (7, 7, 0), (7, 7, 0)),
+            (Rotation::None, (0, 0, 7), (0, 0, 7)),
+            (Rotation::None, (0, 7, 7), (0, 7, 7)),
+            (Rotation::None, (7, 0, 7), (7, 0, 7)),
+            (Rotation::None, (7, 7, 7), (7, 7, 7)),
+            (Rotation::I, (0, 0, 0), (0, 7, 0)),
+            (Rotation::I, (0, 7, 0), (0, 7, 7)),
+            (Rotation::I, (7, 0, 0), (7, 7, 0)),
+            (Rotation::I, (7, 7, 0), (7, 7, 7)),
+            (Rotation::I, (0, 0, 7), (0, 0, 0)),
+            (Rotation::I, (0, 7, 7), (0, 0, 7)),
+            (Rotation::I, (7, 0, 7), (7, 0, 0)),
+            (Rotation::I, (7, 7, 7), (7, 0, 7)),
+            (Rotation::J, (0, 0, 0), (0, 0, 7)),
+            (Rotation::J, (0, 7, 0), (0, 7, 7)),
+            (Rotation::J, (7, 0, 0), (0, 0, 0)),
+            (Rotation::J, (7, 7, 0), (0, 7, 0)),
+            (Rotation::J, (0, 0, 7), (7, 0, 7)),
+            (Rotation::J, (0, 7, 7), (7, 7, 7)),
+            (Rotation::J, (7, 0, 7), (7, 0, 0)),
+            (Rotation::J, (7, 7, 7), (7, 7, 0)),
+            (Rotation::K, (0, 0, 0), (0, 7, 0)),
+            (Rotation::K, (0, 7, 0), (7, 7, 0)),
+            (Rotation::K, (7, 0, 0), (0, 0, 0)),
+            (Rotation::K, (7, 7, 0), (7, 0, 0)),
+            (Rotation::K, (0, 0, 7), (0, 7, 7)),
+            (Rotation::K, (0, 7, 7), (7, 7, 7)),
+            (Rotation::K, (7, 0, 7), (0, 0, 7)),
+            (Rotation::K, (7, 7, 7), (7, 0, 7)),
+        ];
+        for (rotation, (x, y, z), (tx, ty, tz)) in cases {
+            let mut frame = [[0u8; 8]; 8];
+            frame[z][x] = 1 << y;
+            let mut expected = [[0u8; 8]; 8];
+            expected[tz][tx] = 1 << ty;
+            assert_eq!(
+                rotation.apply(&frame),
+                expected,
+                "{rotation} of ({x}, {y}, {z})"
+            );
+        }
+    }
+
+    #[test]
+    fn quarter_turns_are_neither_half_turns_nor_identity() {
+        // A voxel off every axis and diagonal comes back only after the fourth turn
+        let start = voxel(1, 2, 4);
+        for rotation in [Rotation::I, Rotation::J, Rotation::K] {
+            let mut turned = start;
+            for turn in 1..4 {
+                turned = Voxels(rotation.apply(&turned.0));
+                assert_ne!(turned, start, "{rotation} turn {turn}");
+            }
+        }
+    }
+
+    #[test]
+    fn frames_turn_voxel_by_voxel() {
+        let mut rng = SmallRng::seed_from_u64(6);
+        for rotation in [Rotation::None, Rotation::I, Rotation::J, Rotation::K] {
+            for _ in 0..50 {
+                let frame = Voxels(rng.gen());
+                let turned = Voxels(rotation.apply(&frame.0));
+                for c in frame.lit() {
+                    let t = rotation.rotate(c);
+                    assert!(turned.get(t.x, t.y, t.z), "{rotation} of {c:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_pipeline_is_identity() {
+        let mut rng = SmallRng::seed_from_u64(2);
+        let mut pipeline = Pipeline::new();
+        for _ in 0..100 {
+            let frame: Frame = rng.gen();
+            assert_eq!(pipeline.apply(frame), frame);
+            let mut gray: GrayFrame = [[[0; 8]; 8]; 8];
+            gray.as_flattened_mut()
+                .as_flattened_mut()
+                .iter_mut()
+                .for_each(|level| *level = rng.gen_range(0..=MAX_LEVEL));
+            assert_eq!(pipeline.apply_gray(gray), gray);
+        }
+    }
+
+    #[test]
+    fn invert_twice_is_identity() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        for _ in 0..1000 {
+            let frame: Frame = rng.gen();
+            assert_eq!(Invert.apply(Invert.apply(frame)), frame);
+        }
+    }
+
+    #[test]
+    fn persist_keeps_a_blip_for_exactly_n_frames() {
+        let mut blip = [[0; 8]; 8];
+        blip[2][5] = 1 << 3;
+
+        let mut persist = Persist::new(4, false);
+        assert_eq!(persist.apply(blip), blip);
+        for _ in 0..4 {
+            assert_eq!(persist.apply([[0; 8]; 8]), blip);
+        }
+        assert_eq!(persist.apply([[0; 8]; 8]), [[0; 8]; 8]);
+    }
+
+    #[test]
+    fn persist_hard_clear_drops_afterglow() {
+        let mut persist = Persist::new(4, true);
+        persist.apply([[255; 8]; 8]);
+        assert_eq!(persist.apply([[0; 8]; 8]), [[0; 8]; 8]);
+        assert_eq!(persist.apply([[0; 8]; 8]), [[0; 8]; 8]);
+    }
+
+    #[test]
+    fn gray_persist_fades_to_dark() {
+        let mut blip = [[[0; 8]; 8]; 8];
+        blip[2][5][3] = MAX_LEVEL;
+
+        let mut persist = Persist::new(4, false);
+        persist.apply_gray(blip);
+        let levels: Vec<u8> = (0..5)
+            .map(|_| persist.apply_gray([[[0; 8]; 8]; 8])[2][5][3])
+            .collect();
+        assert_eq!(levels, [12, 9, 6, 3, 0]);
+    }
+
+    #[test]
+    fn persist_after_invert_glows_with_the_inverted_frame() {
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Invert);
+        pipeline.push(Persist::new(1, false));
+
+        assert_eq!(pipeline.apply([[0; 8]; 8]), [[255; 8]; 8]);
+        assert_eq!(pipeline.apply([[255; 8]; 8]), [[255; 8]; 8]);
+        assert_eq!(pipeline.apply([[255; 8]; 8]), [[0; 8]; 8]);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{io, sync::Arc, time::Duration};
+
+use crate::{
+    control::{Command, Control},
+    transition::{Transition, TransitionStyle},
+    Frame,
+};
+
+/// Frames from one playlist item and the period they're meant to be shown at
+pub type Opened = (Duration, Box<dyn Iterator<Item = Frame>>);
+
+/// One item of a playlist: how to start it, afresh every time its turn comes round, and how
+/// long it runs for
+pub struct Entry {
+    pub name: String,
+    pub open: Box<dyn FnMut() -> io::Result<Opened>>,
+    pub duration: Duration,
+}
+
+/// Read `30s`, `5m`, `1500ms`, `1h` or a bare number of seconds
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.trim().parse().map_err(|e| format!("{e}"))?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        unit => return Err(format!("unknown unit {unit:?}, expected ms, s, m or h")),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| e.to_string())
+}
+
+/// Split `program and arguments:duration` into the words of the program and the duration
+pub fn parse_item(spec: &str) -> Result<(Vec<String>, Duration), String> {
+    let Some((program, duration)) = spec.rsplit_once(':') else {
+        return Err(format!("expected program:duration, found {spec:?}"));
+    };
+    let words: Vec<String> = program.split_whitespace().map(str::to_owned).collect();
+    if words.is_empty() {
+        return Err(format!("no program before the duration in {spec:?}"));
+    }
+    Ok((words, parse_duration(duration)?))
+}
+
+/// An item that is running, advanced one playlist tick at a time
+pub(crate) struct Playing {
+    frames: Box<dyn Iterator<Item = Frame>>,
+    period: Duration,
+    /// Time accumulated towards the item's next frame
+    due: Duration,
+    frame: Frame,
+    /// Ticks left before the next item takes over
+    ticks_left: u64,
+    ended: bool,
+}
+
+impl Playing {
+    /// Start on the first of `frames`, to run for `ticks`. `None` if there are no frames at all.
+    pub(crate) fn start(
+        period: Duration,
+        mut frames: Box<dyn Iterator<Item = Frame>>,
+        ticks: u64,
+    ) -> Option<Self> {
+        let frame = frames.next()?;
+        Some(Playing {
+            frames,
+            period,
+            due: Duration::ZERO,
+            frame,
+            ticks_left: ticks,
+            ended: false,
+        })
+    }
+
+    /// Let `tick` pass, returning the frame for it. Frames are pulled a tick ahead, as many as
+    /// the item's own period calls for, so that an item slower than the tick repeats its frame,
+    /// a faster one skips frames, and one that runs out is finished straight after its last.
+    pub(crate) fn advance(&mut self, tick: Duration) -> Frame {
+        let shown = self.frame;
+        self.due += tick;
+        while !self.ended && self.due >= self.period {
+            self.due -= self.period;
+            match self.frames.next() {
+                Some(frame) => self.frame = frame,
+                None => self.ended = true,
+            }
+        }
+        self.ticks_left = self.ticks_left.saturating_sub(1);
+        shown
+    }
+
+    fn finished(&self) -> bool {
+        self.ended || self.ticks_left == 0
+    }
+}
+
+/// Cycles through its entries forever as one stream of frames, one per `tick`, so whoever shows
+/// it never has to stop. Each item keeps its own frame rate, and hands over to the next through
+/// a transition during which both keep running. Items that fail to start are reported and
+/// skipped, and the playlist only ends if none of them can start.
+pub struct Playlist {
+    entries: Vec<Entry>,
+    tick: Duration,
+    transition: TransitionStyle,
+    transition_ticks: u32,
+    next: usize,
+    current: Option<Playing>,
+    outgoing: Option<(Playing, Transition)>,
+    /// How many times faster than their own rate items play, without a control
+    speed: f64,
+    /// Speed and skipping to the next item, when they can change while it plays
+    control: Option<Arc<Control>>,
+}
+
+impl Playlist {
+    pub fn new(
+        entries: Vec<Entry>,
+        tick: Duration,
+        transition: TransitionStyle,
+        transition_time: Duration,
+    ) -> Self {
+        Playlist {
+            entries,
+            tick,
+            transition,
+            transition_ticks: transition_time.div_duration_f32(tick).round() as u32,
+            next: 0,
+            current: None,
+            outgoing: None,
+            speed: 1.0,
+            control: None,
+        }
+    }
+
+    /// Play every item `speed` times faster than its own rate
+    pub fn with_speed(self, speed: f64) -> Self {
+        Playlist { speed, ..self }
+    }
+
+    /// Play at the speed in `control`'s settings and move on to the next item whenever it is
+    /// sent `Command::Next`
+    pub fn with_control(self, control: Arc<Control>) -> Self {
+        Playlist {
+            control: Some(control),
+            ..self
+        }
+    }
+
+    /// Start the next entry that opens
+    fn start_next(&mut self) -> Option<Playing> {
+        for _ in 0..self.entries.len() {
+            let index = self.next;
+            self.next = (index + 1) % self.entries.len();
+            let entry = &mut self.entries[index];
+
+            match (entry.open)() {
+                Ok((period, frames)) => {
+                    let ticks = (entry.duration.as_nanos() / self.tick.as_nanos().max(1)).max(1);
+                    match Playing::start(period, frames, ticks as u64) {
+                        Some(playing) => return Some(playing),
+                        None => eprintln!("{} produced no frames", entry.name),
+                    }
+                }
+                Err(e) => eprintln!("Skipping {}: {e}", entry.name),
+            }
+        }
+        None
+    }
+}
+
+impl Iterator for Playlist {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut current = match self.current.take() {
+            Some(current) => current,
+            None => self.start_next()?,
+        };
+        let (tick, skip) = match &self.control {
+            Some(control) => {
+                let skip = control.take_commands().contains(&Command::Next);
+                (self.tick.mul_f64(control.settings().speed), skip)
+            }
+            None => (self.tick.mul_f64(self.speed), false),
+        };
+        let frame = current.advance(tick);
+
+        let frame = match &mut self.outgoing {
+            Some((old, fade)) if !fade.finished() => fade.blend(&old.advance(tick), &frame),
+            _ => {
+                self.outgoing = None;
+                frame
+            }
+        };
+
+        if current.finished() || skip {
+            if self.transition == TransitionStyle::None {
+                self.current = None;
+            } else if let Some(next) = self.start_next() {
+                let fade = Transition::new(self.transition, self.transition_ticks);
+                self.outgoing = Some((current, fade));
+                self.current = Some(next);
+            }
+        } else {
+            self.current = Some(current);
+        }
+
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::{repeat, repeat_n};
+
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    fn constant(byte: u8, period: Duration, duration: Duration) -> Entry {
+        Entry {
+            name: format!("constant {byte}"),
+            open: Box::new(move || Ok((period, Box::new(repeat([[byte; 8]; 8])) as _))),
+            duration,
+        }
+    }
+
+    fn first_bytes(playlist: Playlist, count: usize) -> Vec<u8> {
+        playlist.take(count).map(|frame| frame[0][0]).collect()
+    }
+
+    #[test]
+    fn items_take_turns_for_their_duration() {
+        let playlist = Playlist::new(
+            vec![constant(1, TICK, 2 * TICK), constant(2, TICK, 3 * TICK)],
+            TICK,
+            TransitionStyle::None,
+            Duration::ZERO,
+        );
+        assert_eq!(first_bytes(playlist, 10), [1, 1, 2, 2, 2, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn slower_items_keep_their_own_rate() {
+        let counter = Entry {
+            name: "counter".to_owned(),
+            open: Box::new(|| Ok((3 * TICK, Box::new((0u8..).map(|n| [[n; 8]; 8])) as _))),
+            duration: Duration::from_secs(1),
+        };
+        let playlist = Playlist::new(vec![counter], TICK, TransitionStyle::None, Duration::ZERO);
+        assert_eq!(first_bytes(playlist, 7), [0, 0, 0, 1, 1, 1, 2]);
+    }
+
+    #[test]
+    fn items_that_fail_or_end_are_skipped() {
+        let failing = Entry {
+            name: "failing".to_owned(),
+            open: Box::new(|| Err(io::Error::other("no such file"))),
+            duration: Duration::from_secs(1),
+        };
+        let short = Entry {
+            name: "short".to_owned(),
+            open: Box::new(|| Ok((TICK, Box::new(repeat_n([[3u8; 8]; 8], 2)) as _))),
+            duration: Duration::from_secs(1),
+        };
+        let playlist = Playlist::new(
+            vec![failing, short, constant(4, TICK, TICK)],
+            TICK,
+            TransitionStyle::None,
+            Duration::ZERO,
+        );
+        assert_eq!(first_bytes(playlist, 6), [3, 3, 4, 3, 3, 4]);
+    }
+
+    #[test]
+    fn nothing_to_play_ends_the_playlist() {
+        let failing = Entry {
+            name: "failing".to_owned(),
+            open: Box::new(|| Err(io::Error::other("no such file"))),
+            duration: Duration::from_secs(1),
+        };
+        let mut playlist = Playlist::new(vec![failing], TICK, TransitionStyle::None, TICK);
+        assert_eq!(playlist.next(), None);
+    }
+
+    #[test]
+    fn a_control_skips_items_and_sets_the_speed() {
+        let counter = Entry {
+            name: "counter".to_owned(),
+            open: Box::new(|| Ok((TICK, Box::new((0u8..).map(|n| [[n; 8]; 8])) as _))),
+            duration: Duration::from_secs(1),
+        };
+        let control = Arc::new(Control::new());
+        let mut playlist = Playlist::new(
+            vec![counter, constant(9, TICK, Duration::from_secs(1))],
+            TICK,
+            TransitionStyle::None,
+            Duration::ZERO,
+        )
+        .with_control(control.clone());
+        assert_eq!(playlist.next().unwrap()[0][0], 0);
+
+        control.update_settings(|settings| settings.speed = 2.0);
+        assert_eq!(playlist.next().unwrap()[0][0], 1);
+        assert_eq!(playlist.next().unwrap()[0][0], 3);
+
+        control.send(Command::Next);
+        assert_eq!(playlist.next().unwrap()[0][0], 5);
+        assert_eq!(playlist.next().unwrap()[0][0], 9);
+    }
+
+    #[test]
+    fn speed_plays_items_faster() {
+        let counter = Entry {
+            name: "counter".to_owned(),
+            open: Box::new(|| Ok((TICK, Box::new((0u8..).map(|n| [[n; 8]; 8])) as _))),
+            duration: Duration::from_secs(1),
+        };
+        let playlist = Playlist::new(vec![counter], TICK, TransitionStyle::None, Duration::ZERO)
+            .with_speed(3.0);
+        let frames: Vec<u8> = playlist.take(3).map(|frame| frame[0][0]).collect();
+        assert_eq!(frames, [0, 3, 6]);
+    }
+
+    #[test]
+    fn transitions_run_both_items() {
+        let playlist = Playlist::new(
+            vec![
+                constant(0x00, TICK, 2 * TICK),
+                constant(0xff, TICK, 10 * TICK),
+            ],
+            TICK,
+            TransitionStyle::Wipe,
+            8 * TICK,
+        );
+        let frames: Vec<Frame> = playlist.take(11).collect();
+        // Half way through the wipe half the rows have switched
+        assert_eq!(frames[5][0], [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]);
+        assert_eq!(frames[10], [[0xff; 8]; 8]);
+    }
+
+    #[test]
+    fn items_parse_with_arguments() {
+        let (words, duration) = parse_item("rain --density 0.1:1.5m").unwrap();
+        assert_eq!(words, ["rain", "--density", "0.1"]);
+        assert_eq!(duration, Duration::from_secs(90));
+
+        assert!(parse_item("rain").is_err());
+        assert!(parse_item(":20s").is_err());
+        assert!(parse_item("rain:20x").is_err());
+    }
+}
@@ -0,0 +1,385 @@
+//! Routines loaded from shared libraries, so animations can be shipped without rebuilding this
+//! crate. Each library in the plugin directory exports one C function returning a static
+//! description of its routine, which becomes a subcommand taking any arguments after its name:
+//!
+//! ```c
+//! #include <stdint.h>
+//!
+//! typedef struct {
+//!     uint32_t abi_version;     /* CUBE_PLUGIN_ABI, 1 */
+//!     const char *name;         /* The subcommand, e.g. "spiral" */
+//!     const char *description;  /* One line for --help */
+//!     uint32_t frame_ms;        /* Milliseconds per frame, 0 for the usual 100 */
+//!     /* Start the frames over with the subcommand's arguments, NULL if they are wrong */
+//!     void *(*start)(int argc, const char *const *argv);
+//!     /* Write the next frame's 64 bytes, layers from the bottom then rows of X with a bit
+//!        per Y, returning 0 once there are no more */
+//!     int (*next)(void *state, uint8_t frame[64]);
+//!     /* Free what start returned */
+//!     void (*stop)(void *state);
+//! } CubePlugin;
+//!
+//! const CubePlugin *cube_plugin(void);
+//! ```
+//!
+//! None of the functions may be NULL, and a library offering one that is is refused. They may
+//! be called from any thread, though never for the same state at once. Libraries stay loaded
+//! until the process exits.
+
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString, OsStr},
+    fmt, fs, io,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    ptr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use clap::{Arg, ArgMatches, Command};
+
+use crate::{
+    registry::{self, Entry, Routine},
+    routines::FRAME_TIME,
+    Frame,
+};
+
+/// The version of [`CubePlugin`] this crate understands
+pub const ABI_VERSION: u32 = 1;
+
+/// The symbol each library exports
+const SYMBOL: &CStr = c"cube_plugin";
+
+/// What a plugin's `cube_plugin` returns, see the module documentation. The functions are
+/// options since C can leave them NULL, which loading refuses.
+#[repr(C)]
+pub struct CubePlugin {
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub description: *const c_char,
+    pub frame_ms: u32,
+    pub start: Option<extern "C" fn(argc: c_int, argv: *const *const c_char) -> *mut c_void>,
+    pub next: Option<extern "C" fn(state: *mut c_void, frame: *mut u8) -> c_int>,
+    pub stop: Option<extern "C" fn(state: *mut c_void)>,
+}
+
+// SAFETY: the description points only at static data that never changes, and its functions
+// may be called from any thread
+unsafe impl Sync for CubePlugin {}
+
+/// A loaded plugin's description, which lives as long as the process
+#[derive(Copy, Clone)]
+struct Plugin(&'static CubePlugin);
+
+#[derive(Debug)]
+pub enum PluginError {
+    Io(io::Error),
+    /// dlopen or dlsym failed, with what dlerror said
+    Load(String),
+    /// `cube_plugin` gave nothing usable
+    Invalid(&'static str),
+    /// Built for another version of the interface
+    Abi(u32),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Io(e) => e.fmt(f),
+            PluginError::Load(e) => f.write_str(e),
+            PluginError::Invalid(what) => write!(f, "{what}"),
+            PluginError::Abi(version) => write!(
+                f,
+                "built for plugin interface {version}, this is {ABI_VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+impl From<io::Error> for PluginError {
+    fn from(e: io::Error) -> Self {
+        PluginError::Io(e)
+    }
+}
+
+/// Plugins by the name of their routine
+static LOADED: Mutex<Vec<(&'static str, Plugin)>> = Mutex::new(Vec::new());
+
+/// Load every `.so` in `dir` and add its routine to the [`registry`], in name order. Libraries
+/// that fail to load, or whose routine is already taken, are reported and left out.
+pub fn load_dir(dir: &Path) -> Result<(), PluginError> {
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|path| path.extension() == Some(OsStr::new("so")));
+    paths.sort();
+
+    let mut entries = Vec::new();
+    for path in paths {
+        // SAFETY: loading a library runs its initialisers, which is what asking for it means
+        match unsafe { open(&path) }.and_then(add) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => eprintln!("Skipping plugin {}: {e}", path.display()),
+        }
+    }
+    for entry in registry::extend(entries) {
+        eprintln!("Skipping plugin {}: the name is taken", entry.name);
+    }
+    Ok(())
+}
+
+/// Load the library at `path` and check what it describes
+///
+/// # Safety
+/// The library's initialisers run, and its `cube_plugin` must follow the interface above.
+unsafe fn open(path: &Path) -> Result<Plugin, PluginError> {
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| PluginError::Invalid("path with a NUL byte"))?;
+    let library = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+    if library.is_null() {
+        return Err(PluginError::Load(dl_error()));
+    }
+    let symbol = libc::dlsym(library, SYMBOL.as_ptr());
+    if symbol.is_null() {
+        let e = dl_error();
+        libc::dlclose(library);
+        return Err(PluginError::Load(e));
+    }
+    let describe: extern "C" fn() -> *const CubePlugin = std::mem::transmute(symbol);
+    let description = describe()
+        .as_ref()
+        .ok_or(PluginError::Invalid("cube_plugin returned NULL"))?;
+    check(description).map(|()| Plugin(description))
+}
+
+fn dl_error() -> String {
+    // SAFETY: dlerror returns NULL or a string valid until the next dl call on this thread
+    unsafe {
+        let e = libc::dlerror();
+        if e.is_null() {
+            "unknown error".to_owned()
+        } else {
+            CStr::from_ptr(e).to_string_lossy().into_owned()
+        }
+    }
+}
+
+fn check(description: &CubePlugin) -> Result<(), PluginError> {
+    if description.abi_version != ABI_VERSION {
+        return Err(PluginError::Abi(description.abi_version));
+    }
+    if description.name.is_null() || description.description.is_null() {
+        return Err(PluginError::Invalid("no name or description"));
+    }
+    if description.start.is_none() || description.next.is_none() || description.stop.is_none() {
+        return Err(PluginError::Invalid("start, next or stop is NULL"));
+    }
+    Ok(())
+}
+
+/// Note down `plugin` and make the registry entry running it
+fn add(plugin: Plugin) -> Result<Entry, PluginError> {
+    let text = |s: *const c_char| {
+        // SAFETY: checked non-null, and the interface makes them static strings
+        unsafe { CStr::from_ptr(s) }
+            .to_str()
+            .map_err(|_| PluginError::Invalid("name or description not UTF-8"))
+    };
+    let name = text(plugin.0.name)?;
+    let description = text(plugin.0.description)?;
+    if name.is_empty() || name.starts_with('-') || name.contains(char::is_whitespace) {
+        return Err(PluginError::Invalid("name unusable as a subcommand"));
+    }
+
+    LOADED.lock().expect("plugins").push((name, plugin));
+    Ok(Entry::new(name, description, with_args, parse))
+}
+
+fn with_args(command: Command) -> Command {
+    command.arg(
+        Arg::new("args")
+            .help("Passed to the plugin as they are")
+            .num_args(0..)
+            .trailing_var_arg(true)
+            .allow_hyphen_values(true),
+    )
+}
+
+fn parse(entry: &Entry, matches: &ArgMatches) -> Result<Arc<dyn Routine>, clap::Error> {
+    let plugin = LOADED
+        .lock()
+        .expect("plugins")
+        .iter()
+        .find(|(name, _)| *name == entry.name)
+        .map(|&(_, plugin)| plugin)
+        .expect("entries are only made for loaded plugins");
+    let args = matches
+        .get_raw("args")
+        .into_iter()
+        .flatten()
+        .map(|arg| {
+            CString::new(arg.as_bytes()).map_err(|_| {
+                clap::Error::raw(
+                    clap::error::ErrorKind::InvalidValue,
+                    "plugin arguments can't contain NUL bytes",
+                )
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Arc::new(PluginRoutine {
+        name: entry.name,
+        plugin,
+        args,
+    }))
+}
+
+struct PluginRoutine {
+    name: &'static str,
+    plugin: Plugin,
+    args: Vec<CString>,
+}
+
+impl Routine for PluginRoutine {
+    fn preferred_frame_time(&self) -> Duration {
+        match self.plugin.0.frame_ms {
+            0 => FRAME_TIME,
+            ms => Duration::from_millis(ms.into()),
+        }
+    }
+
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        let mut argv: Vec<*const c_char> = self.args.iter().map(|arg| arg.as_ptr()).collect();
+        argv.push(ptr::null());
+        let start = self.plugin.0.start.expect("checked when loaded");
+        let state = start(self.args.len() as c_int, argv.as_ptr());
+        if state.is_null() {
+            eprintln!("{} could not start with those arguments", self.name);
+            return Box::new(std::iter::empty());
+        }
+        Box::new(PluginFrames {
+            plugin: self.plugin,
+            state,
+        })
+    }
+}
+
+/// A started plugin's frames, stopping it once dropped
+struct PluginFrames {
+    plugin: Plugin,
+    state: *mut c_void,
+}
+
+impl Iterator for PluginFrames {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = [[0u8; 8]; 8];
+        let next = self.plugin.0.next.expect("checked when loaded");
+        let more = next(self.state, frame.as_mut_ptr().cast());
+        (more != 0).then_some(frame)
+    }
+}
+
+impl Drop for PluginFrames {
+    fn drop(&mut self) {
+        let stop = self.plugin.0.stop.expect("checked when loaded");
+        stop(self.state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts up from its first argument, lighting the bottom row with the count, until 3
+    extern "C" fn start(argc: c_int, argv: *const *const c_char) -> *mut c_void {
+        let args = unsafe { std::slice::from_raw_parts(argv, argc as usize) };
+        let first = args
+            .first()
+            .map(|&arg| unsafe { CStr::from_ptr(arg) }.to_str().unwrap());
+        match first.unwrap_or("0").parse::<u8>() {
+            Ok(count) => Box::into_raw(Box::new(count)).cast(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+
+    extern "C" fn next(state: *mut c_void, frame: *mut u8) -> c_int {
+        let count = unsafe { &mut *state.cast::<u8>() };
+        if *count >= 3 {
+            return 0;
+        }
+        unsafe { *frame = *count };
+        *count += 1;
+        1
+    }
+
+    extern "C" fn stop(state: *mut c_void) {
+        drop(unsafe { Box::from_raw(state.cast::<u8>()) });
+    }
+
+    static COUNTER: CubePlugin = CubePlugin {
+        abi_version: ABI_VERSION,
+        name: c"counter".as_ptr(),
+        description: c"Counts to three".as_ptr(),
+        frame_ms: 50,
+        start: Some(start),
+        next: Some(next),
+        stop: Some(stop),
+    };
+
+    #[test]
+    fn plugins_take_their_arguments_and_give_frames() {
+        let entry = add(Plugin(&COUNTER)).unwrap();
+        assert_eq!(
+            (entry.name, entry.description),
+            ("counter", "Counts to three")
+        );
+        let matches = entry
+            .command()
+            .try_get_matches_from(["counter", "1", "--ignored"])
+            .unwrap();
+        let routine = parse(&entry, &matches).unwrap();
+        assert_eq!(routine.preferred_frame_time(), Duration::from_millis(50));
+        let rows: Vec<u8> = routine.frames().map(|frame| frame[0][0]).collect();
+        assert_eq!(rows, [1, 2]);
+
+        let matches = entry
+            .command()
+            .try_get_matches_from(["counter", "x"])
+            .unwrap();
+        assert_eq!(parse(&entry, &matches).unwrap().frames().count(), 0);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let future = CubePlugin {
+            abi_version: ABI_VERSION + 1,
+            ..COUNTER
+        };
+        assert!(matches!(check(&future), Err(PluginError::Abi(2))));
+    }
+
+    #[test]
+    fn missing_functions_are_refused() {
+        assert!(check(&COUNTER).is_ok());
+        let missing = [
+            CubePlugin {
+                start: None,
+                ..COUNTER
+            },
+            CubePlugin {
+                next: None,
+                ..COUNTER
+            },
+            CubePlugin {
+                stop: None,
+                ..COUNTER
+            },
+        ];
+        for plugin in &missing {
+            assert!(matches!(check(plugin), Err(PluginError::Invalid(_))));
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Drawing lines and surfaces into frames. Positions are fixed point, `ONE` to a voxel, and
+//! measured from the centre of the cube so that rotations keep shapes centred.
+
+use std::ops::{Add, Sub};
+
+use crate::{
+    geometry::{Coord, Point},
+    Frame,
+};
+
+/// Fractional bits of a fixed point coordinate
+const SHIFT: u32 = 12;
+/// One voxel in fixed point
+pub const ONE: i32 = 1 << SHIFT;
+/// The cube's centre, where `Fixed::default()` sits, lies between voxels 3 and 4 on every axis
+const CENTRE: i32 = 7 * ONE / 2;
+
+/// A position or offset relative to the centre of the cube, `ONE` to a voxel
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fixed {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Fixed {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Fixed { x, y, z }
+    }
+
+    /// From an offset from the centre in voxels
+    pub fn from_voxels(x: f32, y: f32, z: f32) -> Self {
+        let fixed = |v: f32| (v * ONE as f32).round() as i32;
+        Fixed::new(fixed(x), fixed(y), fixed(z))
+    }
+
+    /// The voxel this falls in, `None` outside the cube
+    pub fn coord(self) -> Option<Coord> {
+        // Round to nearest, then shift back to cube coordinates
+        let axis = |v: i32| {
+            u8::try_from((v + CENTRE + ONE / 2) >> SHIFT)
+                .ok()
+                .filter(|&v| v < 8)
+        };
+        Some(Coord::new(axis(self.x)?, axis(self.y)?, axis(self.z)?))
+    }
+
+    fn max_abs(self) -> i32 {
+        self.x.abs().max(self.y.abs()).max(self.z.abs())
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+/// A rotation matrix in fixed point, so turning a whole shape each frame costs only integer
+/// multiplies
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FixedRotation {
+    rows: [[i32; 3]; 3],
+}
+
+impl FixedRotation {
+    pub fn identity() -> Self {
+        FixedRotation {
+            rows: [[ONE, 0, 0], [0, ONE, 0], [0, 0, ONE]],
+        }
+    }
+
+    /// Turn `angle` radians anticlockwise about `axis` as seen from its tip. A zero axis gives
+    /// the identity.
+    pub fn about(axis: Point, angle: f32) -> Self {
+        let length = (axis.x * axis.x + axis.y * axis.y + axis.z * axis.z).sqrt();
+        if length == 0.0 {
+            return Self::identity();
+        }
+        let [x, y, z] = [axis.x / length, axis.y / length, axis.z / length];
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+
+        // Rodrigues' rotation formula
+        let rows = [
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos],
+        ];
+        FixedRotation {
+            rows: rows.map(|row| row.map(|v| (v * ONE as f32).round() as i32)),
+        }
+    }
+
+    pub fn apply(&self, v: Fixed) -> Fixed {
+        let row = |r: [i32; 3]| {
+            let sum = i64::from(r[0]) * i64::from(v.x)
+                + i64::from(r[1]) * i64::from(v.y)
+                + i64::from(r[2]) * i64::from(v.z);
+            (sum >> SHIFT) as i32
+        };
+        Fixed::new(row(self.rows[0]), row(self.rows[1]), row(self.rows[2]))
+    }
+}
+
+/// Light every voxel the straight line from `a` to `b` passes through, clipped to the cube
+pub fn line(frame: &mut Frame, a: Fixed, b: Fixed) {
+    let delta = b - a;
+    // At least one sample per voxel along the longest axis
+    let steps = (delta.max_abs() + ONE - 1) / ONE;
+    for i in 0..=steps {
+        let at = if steps == 0 {
+            a
+        } else {
+            a + Fixed::new(
+                delta.x * i / steps,
+                delta.y * i / steps,
+                delta.z * i / steps,
+            )
+        };
+        if let Some(c) = at.coord() {
+            c.set(frame);
+        }
+    }
+}
+
+/// Light the voxels whose centres lie within half a voxel of the sphere of `radius` voxels
+/// around `centre`
+pub fn sphere_shell(frame: &mut Frame, centre: Fixed, radius: f32) {
+    let centre = Point::new(
+        (centre.x + CENTRE) as f32 / ONE as f32,
+        (centre.y + CENTRE) as f32 / ONE as f32,
+        (centre.z + CENTRE) as f32 / ONE as f32,
+    );
+    for c in Coord::all() {
+        if (Point::from(c).distance(&centre) - radius).abs() <= 0.5 {
+            c.set(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn lit(frame: &Frame) -> u32 {
+        frame.iter().flatten().map(|row| row.count_ones()).sum()
+    }
+
+    #[test]
+    fn the_centre_rounds_into_the_upper_voxels() {
+        assert_eq!(Fixed::default().coord(), Some(Coord::new(4, 4, 4)));
+        let corner = Fixed::from_voxels(-3.5, -3.5, 3.5);
+        assert_eq!(corner.coord(), Some(Coord::new(0, 0, 7)));
+        assert_eq!(Fixed::from_voxels(4.0, 0.0, 0.0).coord(), None);
+        assert_eq!(Fixed::from_voxels(0.0, -4.1, 0.0).coord(), None);
+    }
+
+    #[test]
+    fn rotations_permute_axes() {
+        let v = Fixed::from_voxels(2.0, 0.0, 0.0);
+        let turned = FixedRotation::about(Point::new(0.0, 0.0, 1.0), FRAC_PI_2).apply(v);
+        // A voxel's worth of error would be ONE, rounding leaves far less
+        assert!((turned - Fixed::from_voxels(0.0, 2.0, 0.0)).max_abs() <= 2);
+
+        let around = FixedRotation::about(Point::new(1.0, 1.0, 1.0), FRAC_PI_2 * 4.0 / 3.0);
+        let turned = around.apply(v);
+        assert!((turned - Fixed::from_voxels(0.0, 2.0, 0.0)).max_abs() <= 4);
+
+        assert_eq!(
+            FixedRotation::about(Point::default(), 1.0),
+            FixedRotation::identity()
+        );
+    }
+
+    #[test]
+    fn lines_are_unbroken_and_clipped() {
+        let mut frame = [[0u8; 8]; 8];
+        line(
+            &mut frame,
+            Fixed::from_voxels(-3.5, -3.5, -3.5),
+            Fixed::from_voxels(3.5, 3.5, 3.5),
+        );
+        assert_eq!(lit(&frame), 8);
+        assert!((0..8).all(|i| Coord::new(i, i, i).get(&frame)));
+
+        // Running off the side only draws the part inside
+        let mut frame = [[0u8; 8]; 8];
+        line(
+            &mut frame,
+            Fixed::from_voxels(-10.0, 0.5, 0.5),
+            Fixed::from_voxels(10.0, 0.5, 0.5),
+        );
+        assert_eq!(frame[4], [0x10; 8]);
+        assert_eq!(lit(&frame), 8);
+    }
+
+    #[test]
+    fn sphere_shells_are_hollow() {
+        let mut frame = [[0u8; 8]; 8];
+        sphere_shell(&mut frame, Fixed::default(), 3.0);
+        assert!(lit(&frame) > 50);
+        assert!(!Coord::new(3, 3, 3).get(&frame));
+        assert!(!Coord::new(0, 0, 0).get(&frame));
+    }
+}
@@ -0,0 +1,104 @@
+//! Keeping the display thread's refresh steady when the rest of the system is busy. Any delay
+//! the scheduler adds while a layer is lit shows as that layer flashing brighter, so the thread
+//! can ask for real-time priority and a core of its own, ideally one kept clear of everything
+//! else with `isolcpus=`.
+
+use std::{io, mem};
+
+/// How the thread refreshing the cube is scheduled
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Realtime {
+    /// SCHED_FIFO priority from 1 to 99, ahead of every normal thread
+    pub priority: Option<u8>,
+    /// The only CPU to run on
+    pub cpu: Option<usize>,
+}
+
+impl Realtime {
+    /// Apply to the calling thread. Each part that can't be had, usually for want of the
+    /// permission, is only warned about and the thread carries on as it was.
+    pub fn apply(&self) {
+        if let Some(cpu) = self.cpu {
+            if let Err(e) = pin_to(cpu) {
+                eprintln!("Could not pin the display thread to CPU {cpu} ({e}), running anywhere");
+            }
+        }
+        if let Some(priority) = self.priority {
+            if let Err(e) = set_fifo(priority) {
+                eprintln!(
+                    "Could not give the display thread real-time priority {priority} ({e}), \
+                     scheduling it normally. It needs root or CAP_SYS_NICE."
+                );
+            }
+        }
+    }
+}
+
+fn pin_to(cpu: usize) -> io::Result<()> {
+    // CPU_SET would index past the end of the set
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    // SAFETY: an all zero cpu_set_t is the empty set, and it outlives the call reading it
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn set_fifo(priority: u8) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: i32::from(priority),
+    };
+    // SAFETY: the current thread's handle is always valid, and param outlives the call
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) } {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// The lowest numbered CPU the tests may run on
+    fn allowed_cpu() -> usize {
+        // SAFETY: as in pin_to, with the set written by the kernel
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            assert_eq!(
+                libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set),
+                0
+            );
+            (0..libc::CPU_SETSIZE as usize)
+                .find(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .unwrap()
+        }
+    }
+
+    #[test]
+    fn pinned_threads_stay_on_their_cpu() {
+        let cpu = allowed_cpu();
+        let ran_on = thread::spawn(move || {
+            Realtime {
+                priority: None,
+                cpu: Some(cpu),
+            }
+            .apply();
+            thread::yield_now();
+            // SAFETY: no arguments, only reads which CPU this is
+            unsafe { libc::sched_getcpu() }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(ran_on, cpu as i32);
+
+        assert!(pin_to(libc::CPU_SETSIZE as usize).is_err());
+    }
+}
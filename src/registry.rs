@@ -0,0 +1,1096 @@
+//! Every routine that can be picked by name, with its options, so the command line, playlists
+//! and remotes all find them in one place. A new routine needs its options here and an entry in
+//! [`ROUTINES`], and nothing in the binary. Routines from elsewhere, such as
+//! [`plugin`](crate::plugin)s, join them through [`extend`].
+
+use std::{
+    ops::RangeInclusive,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use clap::{builder::RangedU64ValueParser, ArgMatches, Args, Command, FromArgMatches, Subcommand};
+
+#[cfg(feature = "script")]
+use crate::script::Script;
+use crate::{
+    decoders::decode_base16_frame, geometry::Point, gray::GrayFrame, routines::*, trail::Decay,
+    Frame, Index,
+};
+
+/// A routine with its options set, able to start its frames over any number of times
+pub trait Routine: Send + Sync {
+    /// How long each frame is shown at normal speed
+    fn preferred_frame_time(&self) -> Duration {
+        FRAME_TIME
+    }
+
+    /// Frames from the start, lighting every voxel that is lit at all
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>>;
+
+    /// Frames from the start with their grays, for routines asked to show them
+    fn gray_frames(&self) -> Option<Box<dyn Iterator<Item = GrayFrame>>> {
+        None
+    }
+}
+
+/// A routine that can be picked by name
+pub struct Entry {
+    /// The subcommand running it
+    pub name: &'static str,
+    /// What it shows, for --help
+    pub description: &'static str,
+    augment: fn(Command) -> Command,
+    parse: Parse,
+}
+
+/// Makes a routine from its entry and the options given to its subcommand
+pub type Parse = fn(&Entry, &ArgMatches) -> Result<Arc<dyn Routine>, clap::Error>;
+
+impl Entry {
+    /// An entry whose options `augment` adds to its subcommand and `parse` reads back
+    pub const fn new(
+        name: &'static str,
+        description: &'static str,
+        augment: fn(Command) -> Command,
+        parse: Parse,
+    ) -> Self {
+        Entry {
+            name,
+            description,
+            augment,
+            parse,
+        }
+    }
+
+    const fn of<R>(name: &'static str, description: &'static str) -> Self
+    where
+        R: Routine + Args + FromArgMatches + 'static,
+    {
+        Entry::new(name, description, R::augment_args, parse::<R>)
+    }
+
+    /// The subcommand with all of the routine's options
+    pub fn command(&self) -> Command {
+        (self.augment)(Command::new(self.name).about(self.description))
+    }
+
+    /// The routine's options, in the order --help lists them
+    pub fn params(&self) -> Vec<Param> {
+        self.command()
+            .get_arguments()
+            .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+            .map(|arg| Param {
+                name: arg.get_id().to_string(),
+                description: arg.get_help().map(ToString::to_string).unwrap_or_default(),
+                default: arg
+                    .get_default_values()
+                    .iter()
+                    .map(|value| value.to_string_lossy().into_owned())
+                    .collect(),
+                choices: arg
+                    .get_possible_values()
+                    .iter()
+                    .map(|value| value.get_name().to_owned())
+                    .collect(),
+                positional: arg.is_positional(),
+            })
+            .collect()
+    }
+}
+
+fn parse<R>(_: &Entry, matches: &ArgMatches) -> Result<Arc<dyn Routine>, clap::Error>
+where
+    R: Routine + FromArgMatches + 'static,
+{
+    Ok(Arc::new(R::from_arg_matches(matches)?))
+}
+
+/// One option a routine takes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub description: String,
+    /// Values it takes when not given, empty if it has none
+    pub default: Vec<String>,
+    /// Values it is limited to, empty for any
+    pub choices: Vec<String>,
+    /// Given in place rather than as --name
+    pub positional: bool,
+}
+
+/// Routines added at run time, after those built in
+static EXTRA: OnceLock<Vec<Entry>> = OnceLock::new();
+
+/// Add routines from outside the crate, once and before any command line is parsed. Those named
+/// the same as one already there are left out and returned.
+pub fn extend(entries: Vec<Entry>) -> Vec<Entry> {
+    let (mut added, mut clashing) = (Vec::new(), Vec::new());
+    for entry in entries {
+        let taken =
+            find(entry.name).is_some() || added.iter().any(|a: &Entry| a.name == entry.name);
+        if taken {
+            clashing.push(entry);
+        } else {
+            added.push(entry);
+        }
+    }
+    if let Err(added) = EXTRA.set(added) {
+        clashing.extend(added);
+    }
+    clashing
+}
+
+/// Every routine, built in first
+pub fn all() -> impl Iterator<Item = &'static Entry> {
+    ROUTINES.iter().chain(EXTRA.get().into_iter().flatten())
+}
+
+/// The routine called `name`
+pub fn find(name: &str) -> Option<&'static Entry> {
+    all().find(|entry| entry.name == name)
+}
+
+/// A routine picked as a subcommand, with the options it was given. Flattened into a
+/// subcommand enum it adds one subcommand per routine.
+#[derive(Clone)]
+pub struct Chosen {
+    pub entry: &'static Entry,
+    pub routine: Arc<dyn Routine>,
+}
+
+impl FromArgMatches for Chosen {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        Self::from_arg_matches_mut(&mut matches.clone())
+    }
+
+    fn from_arg_matches_mut(matches: &mut ArgMatches) -> Result<Self, clap::Error> {
+        let Some((name, options)) = matches.remove_subcommand() else {
+            return Err(clap::Error::new(clap::error::ErrorKind::MissingSubcommand));
+        };
+        let entry = find(&name).ok_or_else(|| {
+            clap::Error::raw(
+                clap::error::ErrorKind::InvalidSubcommand,
+                format!("no routine called {name}"),
+            )
+        })?;
+        Ok(Chosen {
+            entry,
+            routine: (entry.parse)(entry, &options)?,
+        })
+    }
+
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+        Ok(())
+    }
+}
+
+impl Subcommand for Chosen {
+    fn augment_subcommands(command: Command) -> Command {
+        command.subcommands(all().map(Entry::command))
+    }
+
+    fn augment_subcommands_for_update(command: Command) -> Command {
+        Self::augment_subcommands(command)
+    }
+
+    fn has_subcommand(name: &str) -> bool {
+        find(name).is_some()
+    }
+}
+
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if f > 0.0 && f.is_finite() => Ok(f),
+        Ok(_) => Err("must be a positive number".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// A fraction from 0 to 1 such as a density, which NaN is not
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(f) if (0.0..=1.0).contains(&f) => Ok(f),
+        Ok(_) => Err("must be between 0 and 1".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The most a routine starts in one frame, so a huge `--rate` can't stall it
+pub const MAX_SPAWN_RATE: f64 = 512.0;
+
+/// A positive rate of things started per frame, up to [`MAX_SPAWN_RATE`]
+pub fn parse_spawn_rate(s: &str) -> Result<f64, String> {
+    match parse_rate(s)? {
+        rate if rate <= MAX_SPAWN_RATE => Ok(rate),
+        _ => Err(format!("must be at most {MAX_SPAWN_RATE}")),
+    }
+}
+
+/// How far `--speed` can slow playing down or speed it up, keeping frame periods within what a
+/// `Duration` holds and away from zero
+pub const SPEEDS: RangeInclusive<f64> = 0.01..=100.0;
+
+/// A speed such as `2` or `2x`, within [`SPEEDS`]
+pub fn parse_speed(s: &str) -> Result<f64, String> {
+    rate_within(s.strip_suffix(['x', 'X']).unwrap_or(s), SPEEDS)
+}
+
+/// Columns `text` scrolls a second, within [`SPEEDS`] too so its frame period stays valid
+/// however `--speed` scales it
+fn parse_scroll_speed(s: &str) -> Result<f64, String> {
+    rate_within(s, SPEEDS)
+}
+
+fn rate_within(s: &str, range: RangeInclusive<f64>) -> Result<f64, String> {
+    match parse_rate(s)? {
+        rate if range.contains(&rate) => Ok(rate),
+        _ => Err(format!(
+            "must be between {} and {}",
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+pub fn parse_frame(s: &str) -> Result<Frame, String> {
+    decode_base16_frame(s).map_err(|e| e.to_string())
+}
+
+/// Every routine, in the order --help lists them
+pub static ROUTINES: &[Entry] = &[
+    Entry::of::<AllOnArgs>("all-on", "Turn on all of the LEDs"),
+    Entry::of::<OneOnArgs>("one-on", "Turn on a single LED"),
+    Entry::of::<CycleArgs>("cycle", "Cycle one layer at a time"),
+    Entry::of::<RainArgs>("rain", "Like rainfall"),
+    Entry::of::<RainFillArgs>(
+        "rain-fill",
+        "Rain that collects at the bottom until the cube is full",
+    ),
+    Entry::of::<PlaneWaveArgs>("plane-wave", "Plane waves moving diagonally"),
+    Entry::of::<WaveArgs>("wave", "Flat wave"),
+    Entry::of::<ChessArgs>("chess", "Turn on alternate LEDs like a chessboard"),
+    Entry::of::<OneLayerArgs>("one-layer", "Turn on one full layer of LEDs"),
+    Entry::of::<OneRowArgs>("one-row", "Turn on one full row of LEDs"),
+    Entry::of::<OneColArgs>("one-col", "Turn on one full column of LEDs"),
+    Entry::of::<MiniCubeArgs>("mini-cube", "Tiny cube in a cube"),
+    Entry::of::<RandomFlipArgs>("random-flip", "Flip a random bit at a time"),
+    Entry::of::<LittleBlipsArgs>("little-blips", "A fistful of lights"),
+    Entry::of::<SparkleArgs>("sparkle", "A fixed number of twinkling lights"),
+    Entry::of::<SandArgs>(
+        "sand",
+        "Falling grains that pile up until the cube tips over",
+    ),
+    Entry::of::<BinaryClockArgs>(
+        "binary-clock",
+        "The time as binary coded decimal columns on the front face",
+    ),
+    Entry::of::<TextArgs>("text", "A message scrolling across the side of the cube"),
+    Entry::of::<LifeArgs>(
+        "life",
+        "Conway's Game of Life in 3D, starting over once it dies out or settles down",
+    ),
+    Entry::of::<RippleArgs>(
+        "ripple",
+        "Spherical ripples spreading from one or more points",
+    ),
+    Entry::of::<ShapesArgs>(
+        "shapes",
+        "A wireframe cube, a sphere or a pyramid tumbling about an axis",
+    ),
+    Entry::of::<SineArgs>("sine", "A sine surface rippling out from the centre"),
+    Entry::of::<CometArgs>("comet", "A point on a looping path with a fading tail"),
+    Entry::of::<FireworksArgs>("fireworks", "Rockets bursting into falling sparks"),
+    Entry::of::<PlasmaArgs>("plasma", "Organic blobs that grow, merge and shrink"),
+    Entry::of::<RubikArgs>(
+        "rubik",
+        "Slices turning a quarter at a time, scrambling a pattern like a Rubik's cube",
+    ),
+    Entry::of::<StarfieldArgs>(
+        "starfield",
+        "Stars streaking past faster and faster, as if at warp speed",
+    ),
+    Entry::of::<HelixArgs>(
+        "helix",
+        "Helices twisting round the vertical axis, like a DNA strand",
+    ),
+    Entry::of::<PulseArgs>(
+        "pulse",
+        "A spherical shell growing from the centre out to the corners",
+    ),
+    Entry::of::<SnowArgs>(
+        "snow",
+        "Snow wobbling down and piling up on the bottom, then thawing",
+    ),
+    Entry::of::<WipeArgs>(
+        "wipe",
+        "Filling and emptying the cube in sweeps, a low effort screensaver",
+    ),
+    Entry::of::<AntArgs>(
+        "ant",
+        "Langton's ant in three dimensions, wandering and flipping voxels",
+    ),
+    Entry::of::<BlocksArgs>(
+        "blocks",
+        "Falling blocks stacking up, full layers flashing and clearing",
+    ),
+    Entry::of::<Sweep3DArgs>(
+        "sweep3d",
+        "Trace a path through every voxel, filling behind the head and clearing in the same order",
+    ),
+    #[cfg(feature = "script")]
+    Entry::of::<ScriptArgs>("script", "Run an animation script"),
+];
+
+#[derive(Args, Clone)]
+struct AllOnArgs {}
+
+impl Routine for AllOnArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(AllOn::new().into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct OneOnArgs {
+    #[arg(long)]
+    x: Index,
+    #[arg(long)]
+    y: Index,
+    #[arg(long)]
+    z: Index,
+}
+
+impl Routine for OneOnArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(OneOn::new(self.x, self.y, self.z).into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct CycleArgs {}
+
+impl Routine for CycleArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(CycleLayers::new())
+    }
+}
+
+#[derive(Args, Clone)]
+struct RainArgs {
+    /// Fraction of voxels lit in each new layer of drops
+    #[arg(long, visible_alias = "intensity", default_value_t = DEFAULT_DENSITY, value_parser = parse_fraction)]
+    density: f64,
+    /// Light a ring on the bottom layer where each drop lands
+    #[arg(long)]
+    splashes: bool,
+    /// Voxels the drops drift along X per frame, negative for the other way
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    wind_x: f32,
+    /// Voxels the drops drift along Y per frame, negative for the other way
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    wind_y: f32,
+    /// Extra drift of the occasional gust, along the wind or any way if there is none
+    #[arg(long, default_value_t = 0.0)]
+    gusts: f32,
+}
+
+impl Routine for RainArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        let rain = Rain::new(self.density)
+            .with_wind(self.wind_x, self.wind_y)
+            .with_gusts(self.gusts);
+        Box::new(if self.splashes {
+            rain.with_splashes()
+        } else {
+            rain
+        })
+    }
+}
+
+#[derive(Args, Clone)]
+struct RainFillArgs {
+    /// Average drops per frame
+    #[arg(long, default_value_t = 1.0, value_parser = parse_spawn_rate)]
+    rate: f64,
+    /// How the full cube empties before it fills again
+    #[arg(long, default_value_t = Drain::Gradual)]
+    drain: Drain,
+    /// Reproduce the same animation
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl Routine for RainFillArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(RainFill::new(self.rate, self.drain, self.seed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct PlaneWaveArgs {
+    reflect: Option<bool>,
+}
+
+impl Routine for PlaneWaveArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(DiagonalPlane::new(self.reflect.unwrap_or_default()).into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct WaveArgs {}
+
+impl Routine for WaveArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Wave::new())
+    }
+}
+
+#[derive(Args, Clone)]
+struct ChessArgs {}
+
+impl Routine for ChessArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Chess::new().into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct OneLayerArgs {
+    #[arg(long)]
+    z: Index,
+}
+
+impl Routine for OneLayerArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(OneLayer::new(self.z).into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct OneRowArgs {
+    #[arg(long)]
+    x: Index,
+}
+
+impl Routine for OneRowArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(OneRow::new(self.x).into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct OneColArgs {
+    #[arg(long)]
+    y: Index,
+}
+
+impl Routine for OneColArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(OneCol::new(self.y).into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct MiniCubeArgs {}
+
+impl Routine for MiniCubeArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(MiniCube::new().into_iter())
+    }
+}
+
+#[derive(Args, Clone)]
+struct RandomFlipArgs {}
+
+impl Routine for RandomFlipArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(RandomFlip::new())
+    }
+}
+
+#[derive(Args, Clone)]
+struct LittleBlipsArgs {
+    /// Fraction of voxels lit in each frame
+    #[arg(long, default_value_t = DEFAULT_DENSITY, value_parser = parse_fraction)]
+    density: f64,
+}
+
+impl Routine for LittleBlipsArgs {
+    /// Slower, so each blip is seen before the next
+    fn preferred_frame_time(&self) -> Duration {
+        Duration::from_millis(200)
+    }
+
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(LittleBlips::new(self.density))
+    }
+}
+
+#[derive(Args, Clone)]
+struct SparkleArgs {
+    /// LEDs lit in every frame
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u16).range(0..=512))]
+    count: u16,
+    /// Frames each LED stays lit
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    hold: u32,
+}
+
+impl Routine for SparkleArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Sparkle::new(self.count.into(), self.hold))
+    }
+}
+
+#[derive(Args, Clone)]
+struct SandArgs {
+    /// Average grains spawned per frame
+    #[arg(long, default_value_t = 0.5, value_parser = parse_spawn_rate)]
+    rate: f64,
+    /// Let resting grains flow sideways
+    #[arg(long)]
+    liquid: bool,
+}
+
+impl Routine for SandArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Sand::new(self.rate, self.liquid))
+    }
+}
+
+#[derive(Args, Clone)]
+struct BinaryClockArgs {
+    /// Show UTC instead of local time
+    #[arg(long)]
+    utc: bool,
+    /// Sparse rain with an afterglow behind the digits
+    #[arg(long)]
+    background: bool,
+}
+
+impl Routine for BinaryClockArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(BinaryClock::new(self.utc, self.background))
+    }
+}
+
+#[derive(Args, Clone)]
+struct TextArgs {
+    /// Printable ASCII, anything else shows as ?
+    message: String,
+    #[arg(long, default_value_t = TextFace::Front)]
+    face: TextFace,
+    /// Columns scrolled per second
+    #[arg(long, default_value_t = 10.0, value_parser = parse_scroll_speed)]
+    speed: f64,
+}
+
+impl Routine for TextArgs {
+    /// A column at a time
+    fn preferred_frame_time(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.speed)
+    }
+
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Text::new(&self.message, self.face))
+    }
+}
+
+#[derive(Args, Clone)]
+struct LifeArgs {
+    /// Neighbour counts, out of 26, for a cell to be born and to survive
+    #[arg(long, default_value_t = LifeRule::default())]
+    rule: LifeRule,
+    /// Fraction of cells alive in a random start
+    #[arg(long, default_value_t = 0.2, value_parser = parse_fraction)]
+    density: f64,
+    /// Start from this frame, as 128 hex digits, instead of at random
+    #[arg(long, value_parser = parse_frame)]
+    pattern: Option<Frame>,
+    /// Reproduce the same animation
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl Routine for LifeArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Life::new(self.rule, self.density, self.pattern, self.seed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct RippleArgs {
+    /// Where a ripple starts as x,y,z, repeat for interfering ripples
+    #[arg(long, default_value = "0,0,0")]
+    origin: Vec<Point>,
+    /// Sum the ripples into intensities instead of lighting where they cross half
+    #[arg(long)]
+    gray: bool,
+}
+
+impl Routine for RippleArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Ripple::new(self.origin.clone()))
+    }
+
+    fn gray_frames(&self) -> Option<Box<dyn Iterator<Item = GrayFrame>>> {
+        self.gray
+            .then(|| Box::new(Ripple::new(self.origin.clone()).gray()) as _)
+    }
+}
+
+#[derive(Args, Clone)]
+struct ShapesArgs {
+    #[arg(long, default_value_t = Shape::Cube)]
+    shape: Shape,
+    /// Direction of the axis through the centre the shape turns about, as x,y,z
+    #[arg(long, default_value = "1,2,3")]
+    spin_axis: Point,
+    /// Radians turned per frame
+    #[arg(long, default_value_t = 0.1, allow_negative_numbers = true)]
+    speed: f32,
+}
+
+impl Routine for ShapesArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Shapes::new(self.shape, self.spin_axis, self.speed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct SineArgs {
+    /// Distance between crests in voxels
+    #[arg(long, default_value_t = 4.0, value_parser = parse_rate)]
+    wavelength: f64,
+    /// Distance the crests travel per frame
+    #[arg(long, default_value_t = 0.25)]
+    speed: f32,
+}
+
+impl Routine for SineArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Sine::new(self.wavelength as f32, self.speed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct CometArgs {
+    /// Number of positions in the tail, including the head
+    #[arg(long, default_value_t = 6)]
+    length: usize,
+    #[arg(long, default_value_t = Decay::Linear)]
+    decay: Decay,
+    /// Fade the tail out instead of lighting all of it fully
+    #[arg(long)]
+    gray: bool,
+}
+
+impl Routine for CometArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Comet::new(self.length, self.decay))
+    }
+
+    fn gray_frames(&self) -> Option<Box<dyn Iterator<Item = GrayFrame>>> {
+        self.gray
+            .then(|| Box::new(Comet::new(self.length, self.decay).gray()) as _)
+    }
+}
+
+#[derive(Args, Clone)]
+struct FireworksArgs {
+    /// Average rockets launched per frame
+    #[arg(long, default_value_t = 0.1, value_parser = parse_spawn_rate)]
+    rate: f64,
+    /// Sparks in each burst
+    #[arg(long, default_value_t = 24, value_parser = RangedU64ValueParser::<usize>::new().range(..=512))]
+    particles: usize,
+    /// Reproduce the same animation
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Fade the sparks out as they fall
+    #[arg(long)]
+    gray: bool,
+}
+
+impl FireworksArgs {
+    fn fireworks(&self) -> Fireworks {
+        Fireworks::new(self.rate, self.particles, self.seed)
+    }
+}
+
+impl Routine for FireworksArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(self.fireworks())
+    }
+
+    fn gray_frames(&self) -> Option<Box<dyn Iterator<Item = GrayFrame>>> {
+        self.gray.then(|| Box::new(self.fireworks().gray()) as _)
+    }
+}
+
+#[derive(Args, Clone)]
+struct PlasmaArgs {
+    /// Noise features per voxel, smaller values give bigger blobs
+    #[arg(long, default_value_t = 0.25)]
+    scale: f32,
+    /// How far the field evolves each frame
+    #[arg(long, default_value_t = 0.05)]
+    speed: f32,
+    /// Raise to light fewer voxels, lower to light more
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    bias: f32,
+    /// Reproduce the same animation
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Soften the edges of the blobs
+    #[arg(long)]
+    gray: bool,
+}
+
+impl PlasmaArgs {
+    fn plasma(&self) -> Plasma {
+        Plasma::new(self.scale, self.speed, self.bias, self.seed)
+    }
+}
+
+impl Routine for PlasmaArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(self.plasma())
+    }
+
+    fn gray_frames(&self) -> Option<Box<dyn Iterator<Item = GrayFrame>>> {
+        self.gray.then(|| Box::new(self.plasma().gray()) as _)
+    }
+}
+
+#[derive(Args, Clone)]
+struct RubikArgs {
+    /// Frames each quarter turn takes
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    steps: u32,
+    /// Reproduce the same scramble
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl Routine for RubikArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Rubik::new(self.steps, self.seed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct StarfieldArgs {
+    /// Average stars appearing per frame
+    #[arg(long, default_value_t = 1.0, value_parser = parse_spawn_rate)]
+    density: f64,
+    /// Which way the stars fly, before any --rotate or --orient
+    #[arg(long, default_value_t = Direction::Forward)]
+    direction: Direction,
+}
+
+impl Routine for StarfieldArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Starfield::new(self.density, self.direction))
+    }
+}
+
+#[derive(Args, Clone)]
+struct HelixArgs {
+    /// Number of strands, the second half a turn round from the first
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=2))]
+    strands: u8,
+    /// Layers for one full twist of a strand
+    #[arg(long, default_value_t = 8.0, value_parser = parse_rate)]
+    pitch: f64,
+    /// Radians the helix turns per frame
+    #[arg(long, default_value_t = 0.2)]
+    speed: f32,
+    /// Join the two strands every other layer
+    #[arg(long)]
+    rungs: bool,
+}
+
+impl Routine for HelixArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Helix::new(
+            self.strands,
+            self.pitch as f32,
+            self.speed,
+            self.rungs,
+        ))
+    }
+}
+
+#[derive(Args, Clone)]
+struct PulseArgs {
+    /// What the shell does once it reaches the corners
+    #[arg(long, default_value_t = PulseEnd::Wrap)]
+    end: PulseEnd,
+}
+
+impl Routine for PulseArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Pulse::new(self.end))
+    }
+}
+
+#[derive(Args, Clone)]
+struct SnowArgs {
+    /// Average flakes per frame
+    #[arg(long, default_value_t = 0.5, value_parser = parse_spawn_rate)]
+    rate: f64,
+    /// Layers the deepest pile reaches before the snow thaws
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=8))]
+    depth: u8,
+    /// How the snow clears before it starts again
+    #[arg(long, default_value_t = Thaw::Melt)]
+    thaw: Thaw,
+    /// Reproduce the same animation
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl Routine for SnowArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Snow::new(self.rate, self.depth, self.thaw, self.seed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct WipeArgs {
+    /// Only this kind of wipe, instead of taking turns at each
+    #[arg(long)]
+    style: Option<WipeStyle>,
+    /// Frames to stay full or empty between wipes
+    #[arg(long, default_value_t = 10)]
+    hold: u32,
+    /// Reproduce the same animation
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl Routine for WipeArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Wipe::new(self.style, self.hold, self.seed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct AntArgs {
+    /// A turn for each state a voxel cycles through: Left, Right, Up, Down, No turn or Back
+    #[arg(long, default_value_t = AntRule::default())]
+    rule: AntRule,
+    /// Number of ants, the first starting in the middle
+    #[arg(long, default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..=512))]
+    ants: usize,
+    /// What ants do at the sides of the cube
+    #[arg(long, default_value_t = AntEdge::Wrap)]
+    edge: AntEdge,
+    /// Moves each ant makes per frame
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=512))]
+    speed: u32,
+    /// Reproduce the same animation
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl Routine for AntArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Ants::new(
+            self.rule.clone(),
+            self.ants,
+            self.edge,
+            self.speed,
+            self.seed,
+        ))
+    }
+}
+
+#[derive(Args, Clone)]
+struct BlocksArgs {
+    /// Reproduce the same game
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl Routine for BlocksArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Blocks::new(self.seed))
+    }
+}
+
+#[derive(Args, Clone)]
+struct Sweep3DArgs {
+    #[arg(long, default_value_t = SweepOrder::Hilbert)]
+    order: SweepOrder,
+    /// How many voxels stay lit behind the head, 512 fills the whole cube before clearing
+    #[arg(long, default_value_t = 16, value_parser = RangedU64ValueParser::<usize>::new().range(1..=512))]
+    tail: usize,
+    /// Voxels advanced per frame
+    #[arg(long, default_value_t = 4, value_parser = RangedU64ValueParser::<usize>::new().range(1..=512))]
+    speed: usize,
+}
+
+impl Routine for Sweep3DArgs {
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        Box::new(Sweep3D::new(self.order, self.tail, self.speed))
+    }
+}
+
+#[cfg(feature = "script")]
+#[derive(Args, Clone)]
+struct ScriptArgs {
+    /// The script, drawing with set(x, y, z), unset, clear() and fill() and showing each
+    /// frame with frame()
+    #[arg(value_parser = parse_script)]
+    file: Script,
+    /// Milliseconds per frame, which time() counts in
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    frame_ms: u64,
+    /// Repeat the same random() numbers
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Start over each time the file is saved, showing the last frame once the script ends
+    #[arg(long)]
+    watch: bool,
+}
+
+#[cfg(feature = "script")]
+fn parse_script(s: &str) -> Result<Script, String> {
+    Script::load(std::path::Path::new(s)).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "script")]
+impl Routine for ScriptArgs {
+    fn preferred_frame_time(&self) -> Duration {
+        Duration::from_millis(self.frame_ms)
+    }
+
+    fn frames(&self) -> Box<dyn Iterator<Item = Frame>> {
+        let frame_time = self.preferred_frame_time();
+        if self.watch {
+            match self.file.run_watched(frame_time, self.seed) {
+                Ok(frames) => return Box::new(frames),
+                Err(e) => eprintln!("Not watching the script: {e}"),
+            }
+        }
+        Box::new(self.file.run(frame_time, self.seed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_routine_has_its_own_name_and_sound_options() {
+        for (i, entry) in ROUTINES.iter().enumerate() {
+            assert!(
+                ROUTINES[..i].iter().all(|other| other.name != entry.name),
+                "{} twice",
+                entry.name
+            );
+            entry.command().debug_assert();
+        }
+    }
+
+    #[test]
+    fn chosen_routines_parse_from_their_subcommands() {
+        let command = Chosen::augment_subcommands(Command::new("cube"));
+        let matches = command
+            .try_get_matches_from(["cube", "text", "Hi", "--speed", "4"])
+            .unwrap();
+        let chosen = Chosen::from_arg_matches(&matches).unwrap();
+        assert_eq!(chosen.entry.name, "text");
+        assert_eq!(
+            chosen.routine.preferred_frame_time(),
+            Duration::from_millis(250)
+        );
+        assert!(chosen.routine.gray_frames().is_none());
+
+        let matches = Chosen::augment_subcommands(Command::new("cube"))
+            .try_get_matches_from(["cube", "ripple", "--gray"])
+            .unwrap();
+        let chosen = Chosen::from_arg_matches(&matches).unwrap();
+        assert!(chosen.routine.gray_frames().is_some());
+
+        let params = find("snow").unwrap().params();
+        let depth = params.iter().find(|param| param.name == "depth").unwrap();
+        assert_eq!(depth.default, ["4"]);
+        let thaw = params.iter().find(|param| param.name == "thaw").unwrap();
+        assert_eq!(thaw.choices, ["melt", "reset"]);
+    }
+
+    #[test]
+    fn densities_are_fractions() {
+        for name in ["rain", "little-blips", "life"] {
+            let command = || find(name).unwrap().command();
+            assert!(command()
+                .try_get_matches_from([name, "--density", "0.5"])
+                .is_ok());
+            for density in ["NaN", "1.5", "-0.1"] {
+                assert!(
+                    command()
+                        .try_get_matches_from([name, "--density", density])
+                        .is_err(),
+                    "{name} {density}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn speeds_are_bounded() {
+        assert_eq!(parse_speed("2x"), Ok(2.0));
+        assert_eq!(parse_speed("0.5"), Ok(0.5));
+        for speed in ["1e-30x", "1e30", "0.001", "101", "NaN", "0", "-2x"] {
+            assert!(parse_speed(speed).is_err(), "{speed}");
+        }
+        let text = || find("text").unwrap().command();
+        assert!(text()
+            .try_get_matches_from(["text", "hi", "--speed", "20"])
+            .is_ok());
+        assert!(text()
+            .try_get_matches_from(["text", "hi", "--speed", "1e-30"])
+            .is_err());
+    }
+
+    #[test]
+    fn spawn_rates_are_bounded() {
+        assert_eq!(parse_spawn_rate("2.5"), Ok(2.5));
+        assert_eq!(parse_spawn_rate("512"), Ok(MAX_SPAWN_RATE));
+        for rate in ["513", "1e30", "inf", "NaN", "0", "-1"] {
+            assert!(parse_spawn_rate(rate).is_err(), "{rate}");
+        }
+        for name in ["sand", "rain-fill", "fireworks", "snow"] {
+            assert!(find(name)
+                .unwrap()
+                .command()
+                .try_get_matches_from([name, "--rate", "1e30"])
+                .is_err());
+        }
+        assert!(find("starfield")
+            .unwrap()
+            .command()
+            .try_get_matches_from(["starfield", "--density", "1e30"])
+            .is_err());
+    }
+
+    #[test]
+    fn ant_counts_and_speeds_are_bounded() {
+        let ant = || find("ant").unwrap().command();
+        for option in ["--ants", "--speed"] {
+            assert!(ant().try_get_matches_from(["ant", option, "512"]).is_ok());
+            for value in ["0", "513", "4000000000"] {
+                assert!(
+                    ant().try_get_matches_from(["ant", option, value]).is_err(),
+                    "{option} {value}"
+                );
+            }
+        }
+    }
+}
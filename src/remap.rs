@@ -0,0 +1,238 @@
+//! Cubes soldered in some other order than the one the driver assumes. Frames are drawn in a
+//! clean logical coordinate system, and the driver moves each voxel to where its LED really
+//! sits before shifting a layer in: alternate rows running backwards, alternate layers
+//! mirrored, or any permutation read from a file.
+
+use std::{fmt, fs, io, path::Path};
+
+use clap::ValueEnum;
+
+use crate::geometry::{parse_voxels, Coord, CubeGeometry};
+
+/// Zig-zag wiring common to hand-built cubes
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Serpentine {
+    /// Every odd row runs from high Y to low
+    Rows,
+    /// Every odd layer has its rows in the opposite order, from high X to low
+    Layers,
+    /// Both of the above
+    Both,
+}
+
+impl std::fmt::Display for Serpentine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Where each logical voxel's LED is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remap {
+    size: u8,
+    /// Indexed by the logical voxel, layer by layer, then row by row
+    physical: Vec<Coord>,
+}
+
+#[derive(Debug)]
+pub enum RemapError {
+    Io(io::Error),
+    /// A line that isn't `x,y,z = x,y,z` with both voxels on the cube, or that sends a voxel
+    /// to an LED another already goes to
+    Syntax {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for RemapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemapError::Io(e) => write!(f, "{e}"),
+            RemapError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl Remap {
+    /// Every voxel where it is
+    pub fn identity(geometry: CubeGeometry) -> Self {
+        let size = geometry.size();
+        Remap {
+            size,
+            physical: voxels(size).collect(),
+        }
+    }
+
+    pub fn serpentine(geometry: CubeGeometry, serpentine: Serpentine) -> Self {
+        let size = geometry.size();
+        let last = size - 1;
+        let (rows, layers) = match serpentine {
+            Serpentine::Rows => (true, false),
+            Serpentine::Layers => (false, true),
+            Serpentine::Both => (true, true),
+        };
+        let physical = voxels(size)
+            .map(|c| {
+                let x = if layers && c.z % 2 == 1 {
+                    last - c.x
+                } else {
+                    c.x
+                };
+                let y = if rows && x % 2 == 1 { last - c.y } else { c.y };
+                Coord::new(x, y, c.z)
+            })
+            .collect();
+        Remap { size, physical }
+    }
+
+    pub fn load(path: &Path, geometry: CubeGeometry) -> Result<Self, RemapError> {
+        Self::parse(&fs::read_to_string(path).map_err(RemapError::Io)?, geometry)
+    }
+
+    /// Read `x,y,z = x,y,z` lines as in TOML, the logical voxel and then the LED it is wired
+    /// to. Voxels that aren't mentioned stay where they are, as long as no other voxel was sent
+    /// to their LED.
+    pub fn parse(text: &str, geometry: CubeGeometry) -> Result<Self, RemapError> {
+        let mut remap = Remap::identity(geometry);
+        let size = geometry.size();
+        // Which line moved a voxel onto each LED
+        let mut claimed = vec![None; remap.physical.len()];
+
+        for (line, content) in (1..).zip(text.lines()) {
+            let content = content.split('#').next().unwrap_or_default().trim();
+            // Blank, or a TOML table header such as [remap]
+            if content.is_empty() || content.starts_with('[') {
+                continue;
+            }
+
+            let syntax = |message: String| RemapError::Syntax { line, message };
+            let Some((logical, physical)) = content.split_once('=') else {
+                return Err(syntax(format!("expected x,y,z = x,y,z, found {content:?}")));
+            };
+            let voxel = |spec: &str| match parse_voxels(spec, size)?[..] {
+                [c] => Ok(c),
+                _ => Err(format!("{:?} isn't a single voxel", spec.trim())),
+            };
+            let logical = voxel(logical).map_err(syntax)?;
+            let physical = voxel(physical).map_err(syntax)?;
+            if let Some(other) = claimed[remap.index(physical)] {
+                return Err(syntax(format!(
+                    "LED {},{},{} is already wired to on line {other}",
+                    physical.x, physical.y, physical.z
+                )));
+            }
+            claimed[remap.index(physical)] = Some(line);
+            let i = remap.index(logical);
+            remap.physical[i] = physical;
+        }
+
+        // Any LED nothing was sent to must be one an unmentioned voxel still claims
+        let mut lit = vec![false; remap.physical.len()];
+        for &c in &remap.physical {
+            let i = remap.index(c);
+            if lit[i] {
+                return Err(RemapError::Syntax {
+                    line: claimed[i].unwrap_or_default(),
+                    message: format!(
+                        "LED {},{},{} is wired to twice, move the voxel that was there too",
+                        c.x, c.y, c.z
+                    ),
+                });
+            }
+            lit[i] = true;
+        }
+        Ok(remap)
+    }
+
+    fn index(&self, c: Coord) -> usize {
+        let size = usize::from(self.size);
+        (usize::from(c.z) * size + usize::from(c.x)) * size + usize::from(c.y)
+    }
+
+    /// Move the voxels of one bank's layers to their LEDs, `layers` being indexed `[z][x]`
+    /// with Y as the bit
+    pub fn apply(&self, layers: &[Vec<u16>]) -> Vec<Vec<u16>> {
+        let size = usize::from(self.size);
+        let mut wired = vec![vec![0; size]; size];
+        for (c, physical) in voxels(self.size).zip(&self.physical) {
+            if layers[c.z as usize][c.x as usize] & (1 << c.y) != 0 {
+                wired[physical.z as usize][physical.x as usize] |= 1 << physical.y;
+            }
+        }
+        wired
+    }
+}
+
+/// Every voxel of a cube `size` across, in the order `Remap::index` numbers them
+fn voxels(size: u8) -> impl Iterator<Item = Coord> {
+    (0..size)
+        .flat_map(move |z| (0..size).flat_map(move |x| (0..size).map(move |y| Coord::new(x, y, z))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(layers: &[Vec<u16>]) -> Vec<(usize, usize, usize)> {
+        (0..layers.len())
+            .flat_map(|z| (0..layers.len()).flat_map(move |x| (0..16).map(move |y| (x, y, z))))
+            .filter(|&(x, y, z)| layers[z][x] & (1 << y) != 0)
+            .collect()
+    }
+
+    fn single(x: usize, y: usize, z: usize) -> Vec<Vec<u16>> {
+        let mut layers = vec![vec![0; 8]; 8];
+        layers[z][x] = 1 << y;
+        layers
+    }
+
+    #[test]
+    fn serpentine_rows_and_layers_run_backwards_on_odd_ones() {
+        let eight = CubeGeometry::default();
+        let rows = Remap::serpentine(eight, Serpentine::Rows);
+        assert_eq!(lit(&rows.apply(&single(1, 0, 0))), [(1, 7, 0)]);
+        assert_eq!(lit(&rows.apply(&single(2, 0, 0))), [(2, 0, 0)]);
+
+        let layers = Remap::serpentine(eight, Serpentine::Layers);
+        assert_eq!(lit(&layers.apply(&single(0, 3, 1))), [(7, 3, 1)]);
+        assert_eq!(lit(&layers.apply(&single(0, 3, 2))), [(0, 3, 2)]);
+
+        // The row a voxel lands on decides whether it runs backwards
+        let both = Remap::serpentine(eight, Serpentine::Both);
+        assert_eq!(lit(&both.apply(&single(0, 3, 1))), [(7, 4, 1)]);
+    }
+
+    #[test]
+    fn tables_swap_voxels_and_leave_the_rest() {
+        let remap = Remap::parse(
+            "[remap]\n0,0,0 = 1,0,0 # swapped\n1,0,0 = 0,0,0\n",
+            CubeGeometry::default(),
+        )
+        .unwrap();
+        assert_eq!(lit(&remap.apply(&single(0, 0, 0))), [(1, 0, 0)]);
+        assert_eq!(lit(&remap.apply(&single(1, 0, 0))), [(0, 0, 0)]);
+        assert_eq!(lit(&remap.apply(&single(5, 6, 7))), [(5, 6, 7)]);
+    }
+
+    #[test]
+    fn tables_must_be_permutations() {
+        let eight = CubeGeometry::default();
+        assert!(matches!(
+            Remap::parse("0,0,0 = 1,0,0\n2,0,0 = 1,0,0", eight),
+            Err(RemapError::Syntax { line: 2, .. })
+        ));
+        // 1,0,0 still goes where it was, on top of 0,0,0
+        assert!(matches!(
+            Remap::parse("0,0,0 = 1,0,0", eight),
+            Err(RemapError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            Remap::parse("0,0,* = 1,0,0", eight),
+            Err(RemapError::Syntax { line: 1, .. })
+        ));
+    }
+}
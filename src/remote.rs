@@ -0,0 +1,137 @@
+use std::{io, sync::Arc, time::Duration};
+
+use crate::{
+    control::{Command, Control},
+    playlist::{Opened, Playing},
+    Frame,
+};
+
+/// Starts a program from its name and arguments
+pub type Opener = Box<dyn FnMut(&[String]) -> io::Result<Opened>>;
+
+/// A program that can be switched for another, sped up or slowed down, or covered by a pushed
+/// frame while it runs, as `Command`s and `Settings` on a `Control` ask. Like a playlist it
+/// yields one frame per `tick` whatever the program's own rate, and it never ends: a program
+/// that runs out keeps showing its last frame until another takes over.
+///
+/// [`Settings`]: crate::control::Settings
+pub struct Remote {
+    control: Arc<Control>,
+    open: Opener,
+    tick: Duration,
+    current: Option<Playing>,
+    pushed: Option<Frame>,
+    /// Shown once the program has ended, or when none could be started
+    last: Frame,
+}
+
+impl Remote {
+    pub fn new(control: Arc<Control>, first: Opened, open: Opener, tick: Duration) -> Self {
+        let (period, frames) = first;
+        Remote {
+            control,
+            open,
+            tick,
+            current: Playing::start(period, frames, u64::MAX),
+            pushed: None,
+            last: [[0; 8]; 8],
+        }
+    }
+
+    fn switch(&mut self, words: &[String]) {
+        let name = words.join(" ");
+        match (self.open)(words) {
+            Ok((period, frames)) => match Playing::start(period, frames, u64::MAX) {
+                Some(playing) => self.current = Some(playing),
+                None => eprintln!("{name} produced no frames"),
+            },
+            Err(e) => eprintln!("Could not start {name}: {e}"),
+        }
+    }
+}
+
+impl Iterator for Remote {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        for command in self.control.take_commands() {
+            match command {
+                Command::Program(words) => {
+                    self.pushed = None;
+                    self.switch(&words);
+                }
+                Command::Frame(frame) => self.pushed = Some(frame),
+                // Only a playlist has items to move on to
+                Command::Next => {}
+            }
+        }
+        self.control
+            .update_settings(|settings| settings.frame_pushed = self.pushed.is_some());
+        if let Some(frame) = self.pushed {
+            return Some(frame);
+        }
+
+        let speed = self.control.settings().speed;
+        if let Some(current) = &mut self.current {
+            self.last = current.advance(self.tick.mul_f64(speed));
+        }
+        Some(self.last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::repeat;
+
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    /// Frames numbered from 0 in their first byte, one per tick
+    fn counter() -> Opened {
+        (TICK, Box::new((0u8..).map(|n| [[n; 8]; 8])))
+    }
+
+    fn remote(control: &Arc<Control>) -> Remote {
+        let open: Opener = Box::new(|words| match words {
+            [name] if name == "fives" => Ok((TICK, Box::new(repeat([[5u8; 8]; 8])) as _)),
+            _ => Err(io::Error::other("no such program")),
+        });
+        Remote::new(control.clone(), counter(), open, TICK)
+    }
+
+    fn first_bytes(remote: &mut Remote, count: usize) -> Vec<u8> {
+        remote.take(count).map(|frame| frame[0][0]).collect()
+    }
+
+    #[test]
+    fn speed_changes_while_running() {
+        let control = Arc::new(Control::new());
+        let mut remote = remote(&control);
+        assert_eq!(first_bytes(&mut remote, 3), [0, 1, 2]);
+
+        control.update_settings(|settings| settings.speed = 2.0);
+        assert_eq!(first_bytes(&mut remote, 3), [3, 5, 7]);
+        control.update_settings(|settings| settings.speed = 0.5);
+        assert_eq!(first_bytes(&mut remote, 4), [9, 9, 10, 10]);
+    }
+
+    #[test]
+    fn programs_switch_and_frames_cover_them() {
+        let control = Arc::new(Control::new());
+        let mut remote = remote(&control);
+        assert_eq!(first_bytes(&mut remote, 2), [0, 1]);
+
+        control.send(Command::Frame([[9; 8]; 8]));
+        assert_eq!(first_bytes(&mut remote, 2), [9, 9]);
+        assert!(control.settings().frame_pushed);
+
+        // A program that can't start leaves the old one running
+        control.send(Command::Program(vec!["nothing".to_owned()]));
+        assert_eq!(first_bytes(&mut remote, 1), [2]);
+        assert!(!control.settings().frame_pushed);
+
+        control.send(Command::Program(vec!["fives".to_owned()]));
+        assert_eq!(first_bytes(&mut remote, 2), [5, 5]);
+    }
+}
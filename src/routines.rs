@@ -1,10 +1,51 @@
-type Frame = [[u8; 8]; 8];
-
-use std::iter::{once, repeat};
-
-use crate::Index;
-
-use rand::{RngCore, SeedableRng};
+use std::iter::{from_fn, once, repeat, repeat_n};
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+use crate::geometry::{hilbert_coord, scan_coord, Coord, Point};
+use crate::gray::{self, GrayFrame, MAX_LEVEL};
+use crate::noise::ValueNoise;
+use crate::trail::{Decay, Trail};
+use crate::voxels::{Axis, Voxels};
+use crate::{Frame, Index};
+
+use rand::{Rng, RngCore, SeedableRng};
+
+mod ant;
+mod binary_clock;
+mod blocks;
+mod fireworks;
+mod helix;
+mod life;
+mod rain_fill;
+mod rubik;
+mod sand;
+mod shapes;
+#[cfg(test)]
+mod snapshots;
+mod snow;
+mod starfield;
+mod text;
+mod wipe;
+
+pub use ant::{AntEdge, AntRule, Ants};
+pub use binary_clock::BinaryClock;
+pub use blocks::Blocks;
+pub use fireworks::Fireworks;
+pub use helix::Helix;
+pub use life::{Life, LifeRule};
+pub use rain_fill::{Drain, RainFill};
+pub use rubik::Rubik;
+pub use sand::Sand;
+pub use shapes::{Shape, Shapes};
+pub use snow::{Snow, Thaw};
+pub use starfield::{Direction, Starfield};
+pub use text::{Text, TextFace};
+pub use wipe::{Wipe, WipeStyle};
+
+/// Period of most routines' frames
+pub const FRAME_TIME: Duration = Duration::from_millis(100);
 
 pub struct AllOn {}
 
@@ -14,6 +55,12 @@ impl AllOn {
     }
 }
 
+impl Default for AllOn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IntoIterator for AllOn {
     type Item = Frame;
     type IntoIter = std::iter::Repeat<Frame>;
@@ -24,17 +71,13 @@ impl IntoIterator for AllOn {
 }
 
 pub struct OneOn {
-    row: u8,
-    col: u8,
-    layer: u8,
+    voxel: Coord,
 }
 
 impl OneOn {
-    pub fn new(row_: Index, col_: Index, layer_: Index) -> Self {
+    pub fn new(x: Index, y: Index, z: Index) -> Self {
         OneOn {
-            row: row_.into(),
-            col: col_.into(),
-            layer: layer_.into(),
+            voxel: Coord::new(x.into(), y.into(), z.into()),
         }
     }
 }
@@ -44,23 +87,21 @@ impl IntoIterator for OneOn {
     type IntoIter = std::iter::Repeat<Frame>;
 
     fn into_iter(self) -> Self::IntoIter {
-        use std::ops::Shl;
-
-        let mut frame = [[0u8; 8]; 8];
-
-        frame[self.layer as usize][self.row as usize] = 1u8.shl(self.col);
+        let mut voxels = Voxels::new();
+        voxels.set(self.voxel.x, self.voxel.y, self.voxel.z);
 
-        repeat(frame)
+        repeat(voxels.into())
     }
 }
 
+/// Every voxel with the given X
 pub struct OneRow {
-    row: u8,
+    x: u8,
 }
 
 impl OneRow {
-    pub fn new(row_: Index) -> Self {
-        OneRow { row: row_.into() }
+    pub fn new(x: Index) -> Self {
+        OneRow { x: x.into() }
     }
 }
 
@@ -69,25 +110,21 @@ impl IntoIterator for OneRow {
     type IntoIter = std::iter::Repeat<Frame>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let layer_pattern: [u8; 8] = core::array::from_fn(|i| {
-            if i == u8::from(self.row).into() {
-                255
-            } else {
-                0
-            }
-        });
-        let frame = [layer_pattern; 8];
-        repeat(frame)
+        let mut voxels = Voxels::new();
+        voxels.fill_plane(Axis::X, self.x);
+
+        repeat(voxels.into())
     }
 }
 
+/// Every voxel with the given Y
 pub struct OneCol {
-    col: u8,
+    y: u8,
 }
 
 impl OneCol {
-    pub fn new(col_: Index) -> Self {
-        OneCol { col: col_.into() }
+    pub fn new(y: Index) -> Self {
+        OneCol { y: y.into() }
     }
 }
 
@@ -96,21 +133,21 @@ impl IntoIterator for OneCol {
     type IntoIter = std::iter::Repeat<Frame>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let frame = [[1 << u8::from(self.col); 8]; 8];
+        let mut voxels = Voxels::new();
+        voxels.fill_plane(Axis::Y, self.y);
 
-        repeat(frame)
+        repeat(voxels.into())
     }
 }
 
+/// Every voxel with the given Z
 pub struct OneLayer {
-    layer: u8,
+    z: u8,
 }
 
 impl OneLayer {
-    pub fn new(layer_: Index) -> Self {
-        OneLayer {
-            layer: layer_.into(),
-        }
+    pub fn new(z: Index) -> Self {
+        OneLayer { z: z.into() }
     }
 }
 
@@ -119,15 +156,10 @@ impl IntoIterator for OneLayer {
     type IntoIter = std::iter::Repeat<Frame>;
 
     fn into_iter(self) -> Self::IntoIter {
-        let frame: Frame = core::array::from_fn(|i| {
-            if i == u8::from(self.layer).into() {
-                [255; 8]
-            } else {
-                [0; 8]
-            }
-        });
+        let mut voxels = Voxels::new();
+        voxels.fill_plane(Axis::Z, self.z);
 
-        repeat(frame)
+        repeat(voxels.into())
     }
 }
 
@@ -139,6 +171,12 @@ impl Chess {
     }
 }
 
+impl Default for Chess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IntoIterator for Chess {
     type Item = Frame;
     type IntoIter = std::iter::Repeat<Frame>;
@@ -155,20 +193,27 @@ impl IntoIterator for Chess {
     }
 }
 
+type LayerCycle =
+    std::iter::Cycle<std::iter::Chain<std::iter::Once<[u8; 8]>, std::iter::RepeatN<[u8; 8]>>>;
+
 pub struct CycleLayers {
-    layer_cycle: std::iter::Cycle<
-        std::iter::Chain<std::iter::Once<[u8; 8]>, std::iter::Take<std::iter::Repeat<[u8; 8]>>>,
-    >,
+    layer_cycle: LayerCycle,
 }
 
 impl CycleLayers {
     pub fn new() -> Self {
         CycleLayers {
-            layer_cycle: once([255; 8]).chain(repeat([0; 8]).take(8)).cycle(),
+            layer_cycle: once([255; 8]).chain(repeat_n([0; 8], 8)).cycle(),
         }
     }
 }
 
+impl Default for CycleLayers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Iterator for CycleLayers {
     type Item = Frame;
 
@@ -216,35 +261,149 @@ impl IntoIterator for DiagonalPlane {
     }
 }
 
+/// Density the sparse routines have always used, one voxel in sixteen
+pub const DEFAULT_DENSITY: f64 = 1.0 / 16.0;
+
+/// A layer where each voxel is independently lit with probability `density`, NaN lighting none
+pub fn sparse_layer(rng: &mut impl Rng, density: f64) -> [u8; 8] {
+    let density = if density.is_nan() {
+        0.0
+    } else {
+        density.clamp(0.0, 1.0)
+    };
+    core::array::from_fn(|_| {
+        (0..8).fold(0u8, |row, bit| {
+            row | (u8::from(rng.gen_bool(density)) << bit)
+        })
+    })
+}
+
+/// How many of something to start this frame for an average of `rate` a frame, the fraction
+/// being the odds of one more than the whole part
+pub fn spawn_count(rng: &mut impl Rng, rate: f64) -> usize {
+    let rate = rate.max(0.0);
+    rate.trunc() as usize + usize::from(rng.gen_bool(rate.fract()))
+}
+
+/// Drops falling from the top layer, optionally blown sideways by wind and gusts and splashing
+/// as they land
 pub struct Rain {
     rng: rand::rngs::SmallRng,
-    memory: Frame,
-    head: usize,
+    drops: Voxels,
+    density: f64,
+    splashes: bool,
+    /// Steady drift in voxels per frame along X and Y
+    wind: (f32, f32),
+    /// Strength of a new gust in voxels per frame, 0 for none
+    gusts: f32,
+    /// The gust blowing now, dying away each frame
+    gust: (f32, f32),
+    /// Drift not yet moved, always less than a voxel
+    drift: (f32, f32),
 }
 
 impl Rain {
-    pub fn new() -> Self {
-        let rng = rand::rngs::SmallRng::from_entropy();
+    /// Chance of a gust starting each frame
+    const GUST_CHANCE: f64 = 1.0 / 40.0;
+    /// What is left of a gust after each frame
+    const GUST_DECAY: f32 = 0.8;
+
+    pub fn new(density: f64) -> Self {
+        Rain {
+            rng: rand::rngs::SmallRng::from_entropy(),
+            drops: Voxels::new(),
+            density,
+            splashes: false,
+            wind: (0.0, 0.0),
+            gusts: 0.0,
+            gust: (0.0, 0.0),
+            drift: (0.0, 0.0),
+        }
+    }
+
+    /// Light a ring on the bottom layer round each drop for the frame after it lands
+    pub fn with_splashes(self) -> Self {
+        Rain {
+            splashes: true,
+            ..self
+        }
+    }
+
+    /// Blow the drops along X and Y, wrapping round so the rain stays even
+    pub fn with_wind(self, x: f32, y: f32) -> Self {
+        Rain {
+            wind: (x, y),
+            ..self
+        }
+    }
 
-        let memory = [[0u8; 8]; 8];
-        let head = 0usize;
+    /// Add gusts of `strength` now and then, along the wind or any way if there is none
+    pub fn with_gusts(self, strength: f32) -> Self {
+        Rain {
+            gusts: strength.max(0.0),
+            ..self
+        }
+    }
+
+    fn blow(&mut self) {
+        if self.gusts > 0.0 && self.rng.gen_bool(Self::GUST_CHANCE) {
+            let (x, y) = self.wind;
+            let angle = if (x, y) == (0.0, 0.0) {
+                self.rng.gen_range(0.0..std::f32::consts::TAU)
+            } else {
+                y.atan2(x)
+            };
+            self.gust = (self.gusts * angle.cos(), self.gusts * angle.sin());
+        }
 
-        Rain { rng, memory, head }
+        self.drift.0 += self.wind.0 + self.gust.0;
+        self.drift.1 += self.wind.1 + self.gust.1;
+        self.gust.0 *= Self::GUST_DECAY;
+        self.gust.1 *= Self::GUST_DECAY;
+
+        // Whole voxels move now, the rest waits until it adds up
+        let (x, y) = (self.drift.0.trunc(), self.drift.1.trunc());
+        self.drift = (self.drift.0 - x, self.drift.1 - y);
+        let x = (x as i32).rem_euclid(8) as usize;
+        let y = (y as i32).rem_euclid(8) as u32;
+        for layer in &mut self.drops.0 {
+            layer.rotate_right(x);
+            for row in layer.iter_mut() {
+                *row = row.rotate_left(y);
+            }
+        }
     }
 }
 
+/// The voxels beside each one lit in `layer`, but not those lit themselves
+fn ring(layer: &[u8; 8]) -> [u8; 8] {
+    core::array::from_fn(|x| {
+        let row = layer[x];
+        let beside = (row << 1) | (row >> 1);
+        let above = if x > 0 { layer[x - 1] } else { 0 };
+        let below = layer.get(x + 1).copied().unwrap_or_default();
+        (beside | above | below) & !row
+    })
+}
+
 impl Iterator for Rain {
     type Item = Frame;
 
     fn next(&mut self) -> Option<Frame> {
-        self.memory[self.head] =
-            (self.rng.next_u64() & self.rng.next_u64() & self.rng.next_u64() & self.rng.next_u64())
-                .to_be_bytes();
-        self.head = (self.head + 1) % 8;
+        let landed = self.drops.0[0];
 
-        Some(core::array::from_fn(|i| {
-            self.memory[(self.head + i) % self.memory.len()]
-        }))
+        // Everything falls a layer and new drops appear at the top
+        self.drops.shift(Axis::Z, -1);
+        self.blow();
+        self.drops.0[7] = sparse_layer(&mut self.rng, self.density);
+
+        let mut frame: Frame = self.drops.into();
+        if self.splashes {
+            for (row, splash) in frame[0].iter_mut().zip(ring(&landed)) {
+                *row |= splash;
+            }
+        }
+        Some(frame)
     }
 }
 
@@ -258,6 +417,12 @@ impl Wave {
     }
 }
 
+impl Default for Wave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Iterator for Wave {
     type Item = Frame;
 
@@ -291,6 +456,12 @@ impl MiniCube {
     }
 }
 
+impl Default for MiniCube {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IntoIterator for MiniCube {
     type Item = Frame;
     type IntoIter = std::iter::Repeat<Frame>;
@@ -329,6 +500,12 @@ impl RandomFlip {
     }
 }
 
+impl Default for RandomFlip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Iterator for RandomFlip {
     type Item = Frame;
 
@@ -346,18 +523,19 @@ impl Iterator for RandomFlip {
 
 pub struct LittleBlips {
     rng: rand::rngs::SmallRng,
+    density: f64,
 }
 
 impl LittleBlips {
-    pub fn new() -> Self {
+    pub fn new(density: f64) -> Self {
         LittleBlips {
             rng: rand::rngs::SmallRng::from_entropy(),
+            density,
         }
     }
 
     fn gen_layer(&mut self) -> [u8; 8] {
-        (self.rng.next_u64() & self.rng.next_u64() & self.rng.next_u64() & self.rng.next_u64())
-            .to_be_bytes()
+        sparse_layer(&mut self.rng, self.density)
     }
 }
 
@@ -377,3 +555,618 @@ impl Iterator for LittleBlips {
         ])
     }
 }
+
+/// Exactly `count` LEDs lit in every frame, each staying on for `hold` frames before it goes out
+/// and another takes its place
+pub struct Sparkle {
+    rng: rand::rngs::SmallRng,
+    count: usize,
+    hold: u32,
+    /// Lit cells and the frames each has left
+    lit: Vec<(Coord, u32)>,
+}
+
+impl Sparkle {
+    pub fn new(count: usize, hold: u32) -> Self {
+        let mut rng = rand::rngs::SmallRng::from_entropy();
+        let hold = hold.max(1);
+        let count = count.min(512);
+
+        // Stagger the first batch so cells don't all expire on the same frame
+        let lit = rand::seq::index::sample(&mut rng, 512, count)
+            .into_iter()
+            .map(|i| (Self::cell(i), rng.gen_range(1..=hold)))
+            .collect();
+
+        Sparkle {
+            rng,
+            count,
+            hold,
+            lit,
+        }
+    }
+
+    fn cell(i: usize) -> Coord {
+        Coord::new((i % 8) as u8, (i / 8 % 8) as u8, (i / 64) as u8)
+    }
+
+    fn index(c: &Coord) -> usize {
+        usize::from(c.z) * 64 + usize::from(c.y) * 8 + usize::from(c.x)
+    }
+}
+
+impl Iterator for Sparkle {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = [[0; 8]; 8];
+        for (c, _) in &self.lit {
+            c.set(&mut frame);
+        }
+
+        for (_, left) in &mut self.lit {
+            *left -= 1;
+        }
+        let (expired, lit): (Vec<_>, Vec<_>) = self.lit.drain(..).partition(|&(_, left)| left == 0);
+        self.lit = lit;
+
+        // Cells that just went out sit a frame out, unless the count needs more than half the cube
+        let mut taken = [false; 512];
+        for (c, _) in self.lit.iter().chain(&expired) {
+            taken[Self::index(c)] = true;
+        }
+        let mut candidates: Vec<Coord> = (0..512).filter(|&i| !taken[i]).map(Self::cell).collect();
+        let wanted = self.count - self.lit.len();
+        if candidates.len() < wanted {
+            candidates.extend(expired.iter().map(|&(c, _)| c));
+        }
+
+        // A partial shuffle picks the replacements
+        for i in rand::seq::index::sample(&mut self.rng, candidates.len(), wanted) {
+            self.lit.push((candidates[i], self.hold));
+        }
+
+        Some(frame)
+    }
+}
+
+pub struct Ripple {
+    origins: Vec<Point>,
+    radius: f32,
+    period: f32,
+}
+
+impl Ripple {
+    /// Width of the lit shell around the current radius
+    const BAND: f32 = 1.0;
+    /// Growth of the radius per frame
+    const STEP: f32 = 0.5;
+
+    pub fn new(origins: Vec<Point>) -> Self {
+        let origins = if origins.is_empty() {
+            vec![Point::default()]
+        } else {
+            origins
+        };
+        let period = origins
+            .iter()
+            .map(Point::furthest_corner)
+            .fold(0.0, f32::max)
+            + Self::BAND;
+
+        Ripple {
+            origins,
+            radius: 0.0,
+            period,
+        }
+    }
+}
+
+impl Ripple {
+    /// The same ripples with the origins' waves summed into intensities, so their
+    /// interference shows instead of only where it crosses half
+    pub fn gray(mut self) -> impl Iterator<Item = GrayFrame> {
+        from_fn(move || {
+            let mut gray = [[[0u8; 8]; 8]; 8];
+            self.step(|c, intensity| {
+                gray::set(
+                    &mut gray,
+                    c,
+                    (intensity.min(1.0) * f32::from(MAX_LEVEL)).round() as u8,
+                );
+            });
+            Some(gray)
+        })
+    }
+
+    /// Hand every voxel's summed intensity to `voxel`, then grow the ripples
+    fn step(&mut self, mut voxel: impl FnMut(Coord, f32)) {
+        for c in Coord::all() {
+            let point = Point::from(c);
+
+            // Each wavefront contributes most at its crest, so overlapping shells reinforce
+            let intensity: f32 = self
+                .origins
+                .iter()
+                .map(|o| (1.0 - (o.distance(&point) - self.radius).abs() / Self::BAND).max(0.0))
+                .sum();
+            voxel(c, intensity);
+        }
+
+        self.radius += Self::STEP;
+        if self.radius > self.period {
+            self.radius = 0.0;
+        }
+    }
+}
+
+impl Iterator for Ripple {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = [[0u8; 8]; 8];
+        self.step(|c, intensity| {
+            if intensity >= 0.5 {
+                c.set(&mut frame);
+            }
+        });
+        Some(frame)
+    }
+}
+
+/// A surface rippling out from the vertical axis through the centre, one voxel lit per column
+/// at the height of a travelling sine
+pub struct Sine {
+    /// Horizontal distance from one crest to the next
+    wavelength: f32,
+    /// Distance the crests travel outwards per frame
+    speed: f32,
+    /// How far the crests have travelled so far
+    offset: f32,
+}
+
+impl Sine {
+    pub fn new(wavelength: f32, speed: f32) -> Self {
+        Sine {
+            wavelength,
+            speed,
+            offset: 0.0,
+        }
+    }
+
+    /// Layer of the surface above column (x, y)
+    fn height(&self, x: u8, y: u8) -> u8 {
+        let centre = Point::new(3.5, 3.5, 0.0);
+        let distance = centre.distance(&Point::new(f32::from(x), f32::from(y), 0.0));
+        let phase = std::f32::consts::TAU * (distance - self.offset) / self.wavelength;
+        (3.5 + 3.5 * phase.sin()).round() as u8
+    }
+}
+
+impl Iterator for Sine {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = [[0u8; 8]; 8];
+        for x in 0..8 {
+            for y in 0..8 {
+                Coord::new(x, y, self.height(x, y)).set(&mut frame);
+            }
+        }
+
+        // Wrapped so the phase stays precise however long it runs
+        self.offset = (self.offset + self.speed).rem_euclid(self.wavelength);
+        Some(frame)
+    }
+}
+
+/// A point tracing a Lissajous knot through the cube with a fading tail
+pub struct Comet {
+    trail: Trail,
+    t: f32,
+}
+
+impl Comet {
+    pub fn new(length: usize, decay: Decay) -> Self {
+        Comet {
+            trail: Trail::new(length, decay),
+            t: 0.0,
+        }
+    }
+
+    /// The same comet with its tail fading out instead of lit at full intensity
+    pub fn gray(mut self) -> impl Iterator<Item = GrayFrame> {
+        from_fn(move || Some(self.next_gray()))
+    }
+
+    fn next_gray(&mut self) -> GrayFrame {
+        // Coprime frequencies so the path only repeats after visiting most of the cube
+        let axis =
+            |freq: f32, phase: f32| (3.5 + 3.5 * (freq * self.t + phase).sin()).round() as u8;
+        let head = Coord::new(axis(3.0, 0.5), axis(2.0, 0.0), axis(5.0, 1.0));
+
+        self.trail.push(head);
+        self.t += 0.05;
+
+        let mut gray = [[[0u8; 8]; 8]; 8];
+        self.trail.render(&mut gray);
+        gray
+    }
+}
+
+impl Iterator for Comet {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        // Anything lit at all
+        Some(gray::threshold(&self.next_gray(), 1))
+    }
+}
+
+/// Voxels where a drifting noise field rises above a slowly breathing threshold
+pub struct Plasma {
+    noise: ValueNoise,
+    scale: f32,
+    speed: f32,
+    bias: f32,
+    t: f32,
+}
+
+impl Plasma {
+    pub fn new(scale: f32, speed: f32, bias: f32, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| rand::rngs::SmallRng::from_entropy().next_u64());
+
+        Plasma {
+            noise: ValueNoise::new(seed),
+            scale,
+            speed,
+            bias,
+            t: 0.0,
+        }
+    }
+
+    /// The same blobs with soft edges, fading in over `PLASMA_EDGE` around the threshold
+    pub fn gray(mut self) -> impl Iterator<Item = GrayFrame> {
+        from_fn(move || {
+            let mut gray = [[[0u8; 8]; 8]; 8];
+            self.step(|c, value, threshold| {
+                let level = (value - threshold) / PLASMA_EDGE + 0.5;
+                gray::set(
+                    &mut gray,
+                    c,
+                    (level.clamp(0.0, 1.0) * f32::from(MAX_LEVEL)).round() as u8,
+                );
+            });
+            Some(gray)
+        })
+    }
+
+    /// Hand every voxel's noise value and the current threshold to `voxel`, then move on in time
+    fn step(&mut self, mut voxel: impl FnMut(Coord, f32, f32)) {
+        let threshold = 0.6 + self.bias + 0.08 * (self.t * 0.7).sin();
+
+        for c in Coord::all() {
+            let p = Point::from(c);
+            let value =
+                self.noise
+                    .sample([p.x * self.scale, p.y * self.scale, p.z * self.scale, self.t]);
+            voxel(c, value, threshold);
+        }
+
+        self.t += self.speed;
+    }
+}
+
+/// Width of the noise values a gray `Plasma` fades across, centred on the threshold
+const PLASMA_EDGE: f32 = 0.2;
+
+impl Iterator for Plasma {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = [[0u8; 8]; 8];
+        self.step(|c, value, threshold| {
+            if value > threshold {
+                c.set(&mut frame);
+            }
+        });
+        Some(frame)
+    }
+}
+
+/// Path a `Sweep3D` takes through every voxel
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum SweepOrder {
+    /// Order-3 Hilbert curve
+    #[default]
+    Hilbert,
+    /// Back and forth along rows, layer by layer
+    Scan,
+}
+
+impl std::fmt::Display for SweepOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Walk a path through all 512 voxels, lighting each in turn and clearing them in the same
+/// order once they fall `tail` steps behind the head
+pub struct Sweep3D {
+    path: Vec<Coord>,
+    tail: usize,
+    speed: usize,
+    head: usize,
+}
+
+impl Sweep3D {
+    pub fn new(order: SweepOrder, tail: usize, speed: usize) -> Self {
+        let step = match order {
+            SweepOrder::Hilbert => hilbert_coord,
+            SweepOrder::Scan => scan_coord,
+        };
+
+        let path: Vec<Coord> = (0..512).map(step).collect();
+        // Any further and the head would skip the path and the tail altogether
+        Sweep3D {
+            tail: tail.clamp(1, path.len()),
+            speed: speed.clamp(1, path.len()),
+            head: 0,
+            path,
+        }
+    }
+}
+
+impl Iterator for Sweep3D {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        // The head runs off the end of the path until the tail has followed it
+        self.head += self.speed;
+        if self.head >= self.path.len() + self.tail {
+            self.head = self.speed;
+        }
+
+        let lit = self.head.saturating_sub(self.tail)..self.head.min(self.path.len());
+
+        let mut frame = [[0u8; 8]; 8];
+        self.path[lit].iter().for_each(|c| c.set(&mut frame));
+
+        Some(frame)
+    }
+}
+
+/// What a `Pulse` does once its shell reaches the corners
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum PulseEnd {
+    /// Start again from the centre
+    #[default]
+    Wrap,
+    /// Shrink back to the centre, then grow again
+    Reflect,
+}
+
+impl std::fmt::Display for PulseEnd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// A spherical shell a voxel thick growing from the centre out to the corners. Distances are
+/// compared squared, in half voxels, so every frame is exactly symmetric.
+pub struct Pulse {
+    end: PulseEnd,
+    /// Outer edge of the shell in half voxels
+    radius: u16,
+    shrinking: bool,
+}
+
+impl Pulse {
+    /// Just the middle eight voxels
+    const SMALLEST: u16 = 2;
+    /// Just the corners, the first radius whose square reaches their 147
+    const LARGEST: u16 = 13;
+
+    pub fn new(end: PulseEnd) -> Self {
+        Pulse {
+            end,
+            radius: Self::SMALLEST,
+            shrinking: false,
+        }
+    }
+}
+
+impl Iterator for Pulse {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        // Two half voxels thick
+        let inner = (self.radius - 2).pow(2);
+        let outer = self.radius.pow(2);
+        let mut frame = [[0u8; 8]; 8];
+        for c in Coord::all() {
+            if (inner + 1..=outer).contains(&c.centre_distance_squared()) {
+                c.set(&mut frame);
+            }
+        }
+
+        self.radius = match (self.end, self.shrinking) {
+            (_, false) if self.radius < Self::LARGEST => self.radius + 1,
+            (PulseEnd::Wrap, _) => Self::SMALLEST,
+            (PulseEnd::Reflect, false) => {
+                self.shrinking = true;
+                self.radius - 1
+            }
+            (PulseEnd::Reflect, true) if self.radius > Self::SMALLEST => self.radius - 1,
+            (PulseEnd::Reflect, true) => {
+                self.shrinking = false;
+                self.radius + 1
+            }
+        };
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparkle_lights_exactly_count() {
+        for (count, hold) in [(0, 1), (20, 3), (300, 2), (512, 4)] {
+            for frame in Sparkle::new(count, hold).take(100) {
+                let lit: u32 = frame.iter().flatten().map(|row| row.count_ones()).sum();
+                assert_eq!(lit as usize, count);
+            }
+        }
+    }
+
+    #[test]
+    fn sparkle_holds_every_cell_for_its_full_time() {
+        let hold = 3;
+        let frames: Vec<Frame> = Sparkle::new(20, hold).take(200).collect();
+        for c in Coord::all() {
+            let lit: Vec<bool> = frames.iter().map(|f| c.get(f)).collect();
+            // Runs cut off by either end of the recording are shorter, skip those
+            let runs: Vec<&[bool]> = lit.split(|&on| !on).collect();
+            for run in runs[1..runs.len() - 1].iter().filter(|run| !run.is_empty()) {
+                assert_eq!(run.len(), hold as usize, "{c:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_layers_light_their_density() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(8);
+        for density in [0.0, DEFAULT_DENSITY, 0.3, 0.75, 1.0] {
+            let layers = 2000;
+            let lit: u32 = (0..layers)
+                .flat_map(|_| sparse_layer(&mut rng, density))
+                .map(u8::count_ones)
+                .sum();
+            let expected = density * f64::from(64 * layers);
+            // Well over four standard deviations of the binomial count
+            let slack = 4.5 * (expected * (1.0 - density)).sqrt();
+            assert!(
+                (f64::from(lit) - expected).abs() <= slack,
+                "{density}: {lit} lit, expected {expected}"
+            );
+        }
+        assert_eq!(sparse_layer(&mut rng, f64::NAN), [0; 8]);
+    }
+
+    #[test]
+    fn sweeps_of_any_speed_and_tail_keep_to_the_path() {
+        for order in [SweepOrder::Hilbert, SweepOrder::Scan] {
+            for (tail, speed) in [(1, 1), (16, 4), (512, 512), (0, 600), (9000, 100_000)] {
+                for frame in Sweep3D::new(order, tail, speed).take(20) {
+                    assert!(Voxels(frame).count() <= 512);
+                }
+            }
+        }
+
+        // A tail of 3 at 2 a frame, restarting once the tail has left the end
+        let lit: Vec<u32> = Sweep3D::new(SweepOrder::Scan, 3, 2)
+            .take(258)
+            .map(|frame| Voxels(frame).count())
+            .collect();
+        assert_eq!(lit[..3], [2, 3, 3]);
+        assert_eq!(lit[255..], [3, 1, 2]);
+    }
+
+    #[test]
+    fn gray_ripples_sum_where_they_cross() {
+        // Half a band from both crests, so each origin gives half
+        let crest = |origins| {
+            let mut ripple = Ripple::new(origins).gray();
+            let gray = ripple.nth(7).unwrap();
+            gray::get(&gray, Coord::new(3, 0, 0))
+        };
+        let (a, b) = (Point::new(0.0, 0.0, 0.0), Point::new(7.0, 0.0, 0.0));
+        assert_eq!(crest(vec![a]), 8);
+        assert_eq!(crest(vec![b]), 8);
+        assert_eq!(crest(vec![a, b]), MAX_LEVEL);
+    }
+
+    #[test]
+    fn gray_ripples_light_where_the_on_off_ones_do_from_half() {
+        let origins = vec![Point::new(0.0, 0.0, 0.0), Point::new(7.0, 7.0, 3.0)];
+        let on_off = Ripple::new(origins.clone());
+        let gray = Ripple::new(origins).gray();
+        for (frame, gray) in on_off.zip(gray).take(40) {
+            assert_eq!(gray::threshold(&gray, MAX_LEVEL.div_ceil(2)), frame);
+        }
+    }
+
+    #[test]
+    fn sine_lights_one_voxel_per_column() {
+        for frame in Sine::new(4.0, 0.25).take(50) {
+            for x in 0..8 {
+                for y in 0..8 {
+                    let lit = (0..8).filter(|&z| Coord::new(x, y, z).get(&frame)).count();
+                    assert_eq!(lit, 1, "({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sine_crests_travel_outwards() {
+        // Exactly the step out from (3, 3) to (2, 2) per frame
+        let mut sine = Sine::new(4.0, std::f32::consts::SQRT_2);
+        let inner = sine.height(3, 3);
+        sine.next();
+        assert_eq!(sine.height(2, 2), inner);
+        // Symmetric about the centre
+        assert_eq!(sine.height(0, 0), sine.height(7, 7));
+        assert_eq!(sine.height(0, 7), sine.height(7, 0));
+    }
+
+    #[test]
+    fn pulses_grow_to_the_corners_and_wrap_or_reflect() {
+        let wrapping: Vec<Frame> = Pulse::new(PulseEnd::Wrap).take(13).collect();
+        assert_eq!(Voxels(wrapping[0]).count(), 8);
+        // The last shell holds the corners and nothing near the middle
+        assert!(Coord::new(7, 0, 7).get(&wrapping[11]));
+        assert!(Voxels(wrapping[11])
+            .lit()
+            .all(|c| c.centre_distance_squared() > 121));
+        assert_eq!(wrapping[12], wrapping[0]);
+        // Every voxel is lit on the way out
+        let swept = wrapping[..12]
+            .iter()
+            .fold(Voxels::new(), |all, &f| all | Voxels(f));
+        assert_eq!(swept, Voxels::full());
+
+        let reflecting: Vec<Frame> = Pulse::new(PulseEnd::Reflect).take(23).collect();
+        assert_eq!(reflecting[12], reflecting[10]);
+        assert_eq!(reflecting[22], reflecting[0]);
+    }
+
+    #[test]
+    fn rain_splashes_where_it_lands_and_drifts_with_the_wind() {
+        let mut rain = Rain::new(0.0).with_splashes();
+        rain.drops.put(3, 4, 0, true);
+        let splash = Voxels(rain.next().unwrap());
+        let mut ring: Vec<_> = splash.lit().map(|c| (c.x, c.y, c.z)).collect();
+        ring.sort();
+        assert_eq!(ring, [(2, 4, 0), (3, 3, 0), (3, 5, 0), (4, 4, 0)]);
+        // Gone the frame after
+        assert_eq!(Voxels(rain.next().unwrap()).count(), 0);
+
+        // Half a voxel a frame moves every other frame, wrapping round the side
+        let mut rain = Rain::new(0.0).with_wind(0.0, -0.5);
+        rain.drops.put(1, 0, 7, true);
+        let path: Vec<_> = rain
+            .take(3)
+            .map(|frame| Voxels(frame).lit().map(|c| (c.x, c.y, c.z)).next())
+            .collect();
+        assert_eq!(path, [Some((1, 0, 6)), Some((1, 7, 5)), Some((1, 7, 4))]);
+    }
+}
@@ -0,0 +1,271 @@
+use std::{fmt, str::FromStr};
+
+use clap::ValueEnum;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::Frame;
+use crate::geometry::Coord;
+
+/// A unit step along one axis
+type Heading = [i8; 3];
+
+/// Which way an ant turns on leaving a voxel
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Turn {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Carry straight on
+    None,
+    /// Back the way it came
+    Back,
+}
+
+impl Turn {
+    fn letter(self) -> char {
+        match self {
+            Turn::Left => 'L',
+            Turn::Right => 'R',
+            Turn::Up => 'U',
+            Turn::Down => 'D',
+            Turn::None => 'N',
+            Turn::Back => 'B',
+        }
+    }
+}
+
+/// How an ant turns on each state of voxel, written as one letter per state such as `RLUD`:
+/// `L`eft, `R`ight, `U`p, `D`own, `N`o turn or `B`ack. Each visit moves a voxel on to the next
+/// state, and any state but the first is lit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AntRule(Vec<Turn>);
+
+/// Four states so the ant leaves its starting plane, unlike the flat `RL` of the original
+impl Default for AntRule {
+    fn default() -> Self {
+        "RLUD".parse().expect("valid rule")
+    }
+}
+
+impl FromStr for AntRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let turns = s
+            .trim()
+            .chars()
+            .map(|c| match c.to_ascii_uppercase() {
+                'L' => Ok(Turn::Left),
+                'R' => Ok(Turn::Right),
+                'U' => Ok(Turn::Up),
+                'D' => Ok(Turn::Down),
+                'N' => Ok(Turn::None),
+                'B' => Ok(Turn::Back),
+                c => Err(format!("{c:?} is not one of L, R, U, D, N or B")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !(2..=u8::MAX as usize).contains(&turns.len()) {
+            return Err(format!(
+                "expected a turn for each of 2 to 255 states, found {s:?}"
+            ));
+        }
+        Ok(AntRule(turns))
+    }
+}
+
+impl fmt::Display for AntRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|turn| write!(f, "{}", turn.letter()))
+    }
+}
+
+/// What an ant does on reaching the side of the cube
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum AntEdge {
+    /// Come back in on the opposite face
+    #[default]
+    Wrap,
+    /// Turn round and head back in
+    Reflect,
+}
+
+impl std::fmt::Display for AntEdge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+struct Ant {
+    at: Coord,
+    heading: Heading,
+    /// Which way is up for the ant, always square to its heading
+    up: Heading,
+}
+
+fn cross(a: Heading, b: Heading) -> Heading {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn reverse(a: Heading) -> Heading {
+    a.map(|v| -v)
+}
+
+impl Ant {
+    fn turn(&mut self, turn: Turn) {
+        let (heading, up) = (self.heading, self.up);
+        (self.heading, self.up) = match turn {
+            Turn::Left => (cross(up, heading), up),
+            Turn::Right => (cross(heading, up), up),
+            Turn::Up => (up, reverse(heading)),
+            Turn::Down => (reverse(up), heading),
+            Turn::None => (heading, up),
+            Turn::Back => (reverse(heading), up),
+        };
+    }
+
+    /// The voxel ahead, `None` past the side of the cube
+    fn ahead(&self, edge: AntEdge) -> Option<Coord> {
+        let step = |v: u8, d: i8| {
+            let v = i16::from(v) + i16::from(d);
+            match edge {
+                AntEdge::Wrap => Some(v.rem_euclid(8) as u8),
+                AntEdge::Reflect => u8::try_from(v).ok().filter(|&v| v < 8),
+            }
+        };
+        Some(Coord::new(
+            step(self.at.x, self.heading[0])?,
+            step(self.at.y, self.heading[1])?,
+            step(self.at.z, self.heading[2])?,
+        ))
+    }
+
+    fn step(&mut self, edge: AntEdge) {
+        self.at = match self.ahead(edge) {
+            Some(at) => at,
+            None => {
+                self.heading = reverse(self.heading);
+                self.ahead(edge).expect("the way back is on the cube")
+            }
+        };
+    }
+}
+
+/// Langton's ant in three dimensions: each ant turns by the rule for the state of the voxel it
+/// is on, moves that voxel on to its next state and steps forward, leaving trails that look
+/// random for a long while before any structure appears
+pub struct Ants {
+    rule: AntRule,
+    edge: AntEdge,
+    /// Moves each ant makes per frame
+    speed: u32,
+    ants: Vec<Ant>,
+    /// Indexed `[z][x][y]`
+    states: [[[u8; 8]; 8]; 8],
+}
+
+impl Ants {
+    pub fn new(rule: AntRule, count: usize, edge: AntEdge, speed: u32, seed: Option<u64>) -> Self {
+        let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+        let ants = (0..count)
+            .map(|i| Ant {
+                // The first from the middle, any others from anywhere
+                at: if i == 0 {
+                    Coord::new(4, 4, 4)
+                } else {
+                    Coord::new(
+                        rng.gen_range(0..8),
+                        rng.gen_range(0..8),
+                        rng.gen_range(0..8),
+                    )
+                },
+                heading: [1, 0, 0],
+                up: [0, 0, 1],
+            })
+            .collect();
+        Ants {
+            rule,
+            edge,
+            speed,
+            ants,
+            states: [[[0; 8]; 8]; 8],
+        }
+    }
+
+    fn step(&mut self) {
+        let states = self.rule.0.len() as u8;
+        for ant in &mut self.ants {
+            let state = &mut self.states[ant.at.z as usize][ant.at.x as usize][ant.at.y as usize];
+            ant.turn(self.rule.0[usize::from(*state)]);
+            *state = (*state + 1) % states;
+            ant.step(self.edge);
+        }
+    }
+}
+
+impl Iterator for Ants {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        for _ in 0..self.speed {
+            self.step();
+        }
+
+        let mut frame = [[0u8; 8]; 8];
+        for c in Coord::all() {
+            if self.states[c.z as usize][c.x as usize][c.y as usize] != 0 {
+                c.set(&mut frame);
+            }
+        }
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::Voxels;
+
+    #[test]
+    fn rules_parse_and_print_back() {
+        let rule: AntRule = "rlud".parse().unwrap();
+        assert_eq!(rule.to_string(), "RLUD");
+        assert_eq!(rule, AntRule::default());
+        assert!("R".parse::<AntRule>().is_err());
+        assert!("RX".parse::<AntRule>().is_err());
+    }
+
+    #[test]
+    fn a_flat_rule_stays_in_its_layer_and_makes_a_square() {
+        // The classic ant's first four moves turn right off dark voxels round a square
+        let mut ants = Ants::new("RL".parse().unwrap(), 1, AntEdge::Wrap, 4, None);
+        let square = Voxels(ants.next().unwrap());
+        assert_eq!(square.count(), 4);
+        assert!(square.lit().all(|c| c.z == 4));
+        assert_eq!(ants.ants[0].at, Coord::new(4, 4, 4));
+
+        let later = Voxels(ants.nth(100).unwrap());
+        assert!(later.lit().all(|c| c.z == 4));
+    }
+
+    #[test]
+    fn reflecting_ants_turn_round_at_the_sides() {
+        let mut ants = Ants::new("NN".parse().unwrap(), 1, AntEdge::Reflect, 1, None);
+        let path: Vec<u8> = (0..6)
+            .map(|_| {
+                ants.step();
+                ants.ants[0].at.x
+            })
+            .collect();
+        assert_eq!(path, [5, 6, 7, 6, 5, 4]);
+    }
+}
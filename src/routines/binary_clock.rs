@@ -0,0 +1,132 @@
+use std::{mem::MaybeUninit, time::SystemTime};
+
+use super::{Frame, Rain};
+use crate::geometry::Coord;
+use crate::pipeline::{Persist, Transform};
+
+/// X of each digit column on the Y = 0 face, reading left to right as hours, minutes and seconds,
+/// tens before units. Columns 2 and 5 stay dark to separate the three pairs.
+const COLUMNS: [u8; 6] = [0, 1, 3, 4, 6, 7];
+
+/// Background rain is kept this far behind the face so it never touches the digits
+const BACKGROUND_DEPTH: u8 = 2;
+const BACKGROUND_DENSITY: f64 = 1.0 / 48.0;
+const BACKGROUND_PERSIST: u32 = 3;
+
+/// Draw hours, minutes and seconds as six BCD columns on the Y = 0 face, see `COLUMNS`. Bit N
+/// of a digit lights the LED N layers up from the bottom.
+pub fn render_time(frame: &mut Frame, hours: u8, minutes: u8, seconds: u8) {
+    let digits = [
+        hours / 10,
+        hours % 10,
+        minutes / 10,
+        minutes % 10,
+        seconds / 10,
+        seconds % 10,
+    ];
+    for (x, digit) in COLUMNS.into_iter().zip(digits) {
+        for z in 0..4 {
+            if digit & (1 << z) != 0 {
+                Coord::new(x, 0, z).set(frame);
+            }
+        }
+    }
+}
+
+/// Wall-clock time of day as hours, minutes and seconds
+fn time_of_day(utc: bool) -> (u8, u8, u8) {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs()) as libc::time_t;
+    let mut tm = MaybeUninit::<libc::tm>::uninit();
+    // SAFETY: both pointers are valid for the call, the _r variants don't touch shared state
+    let tm = unsafe {
+        let filled = if utc {
+            libc::gmtime_r(&now, tm.as_mut_ptr())
+        } else {
+            libc::localtime_r(&now, tm.as_mut_ptr())
+        };
+        if filled.is_null() {
+            return (0, 0, 0);
+        }
+        tm.assume_init()
+    };
+    (tm.tm_hour as u8, tm.tm_min as u8, tm.tm_sec as u8)
+}
+
+/// The time in binary on the front face, read afresh from the wall clock every frame so it never
+/// drifts and rolls over at midnight along with the system
+pub struct BinaryClock {
+    utc: bool,
+    /// Sparse rain with an afterglow behind the digits
+    background: Option<(Rain, Persist)>,
+}
+
+impl BinaryClock {
+    pub fn new(utc: bool, background: bool) -> Self {
+        BinaryClock {
+            utc,
+            background: background.then(|| {
+                (
+                    Rain::new(BACKGROUND_DENSITY),
+                    Persist::new(BACKGROUND_PERSIST, false),
+                )
+            }),
+        }
+    }
+}
+
+impl Iterator for BinaryClock {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = match &mut self.background {
+            Some((rain, persist)) => {
+                let behind = u8::MAX << BACKGROUND_DEPTH;
+                persist
+                    .apply(rain.next()?)
+                    .map(|layer| layer.map(|row| row & behind))
+            }
+            None => [[0; 8]; 8],
+        };
+
+        let (hours, minutes, seconds) = time_of_day(self.utc);
+        render_time(&mut frame, hours, minutes, seconds);
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(frame: &Frame, x: u8) -> u8 {
+        (0..8).fold(0, |bits, z| {
+            bits | u8::from(Coord::new(x, 0, z).get(frame)) << z
+        })
+    }
+
+    #[test]
+    fn digits_land_in_their_columns() {
+        let mut frame = [[0; 8]; 8];
+        render_time(&mut frame, 23, 59, 48);
+        let columns: Vec<u8> = (0..8).map(|x| column(&frame, x)).collect();
+        assert_eq!(columns, [2, 3, 0, 5, 9, 0, 4, 8]);
+    }
+
+    #[test]
+    fn only_the_front_face_is_used() {
+        let mut frame = [[0; 8]; 8];
+        render_time(&mut frame, 18, 38, 58);
+        assert!(Coord::all()
+            .filter(|c| c.get(&frame))
+            .all(|c| c.y == 0 && c.z < 4));
+    }
+
+    #[test]
+    fn midnight_is_dark() {
+        let mut frame = [[0; 8]; 8];
+        render_time(&mut frame, 0, 0, 0);
+        assert_eq!(frame, [[0; 8]; 8]);
+    }
+}
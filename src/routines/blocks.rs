@@ -0,0 +1,211 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::Frame;
+use crate::voxels::Voxels;
+
+/// Frames a completed layer flashes for before it clears, on and off in turn
+const FLASH_FRAMES: u32 = 6;
+
+/// The flat tetrominoes, as (x, y) squares
+const PIECES: [[(u8, u8); 4]; 5] = [
+    // I
+    [(0, 0), (1, 0), (2, 0), (3, 0)],
+    // O
+    [(0, 0), (1, 0), (0, 1), (1, 1)],
+    // T
+    [(0, 0), (1, 0), (2, 0), (1, 1)],
+    // S
+    [(0, 0), (1, 0), (1, 1), (2, 1)],
+    // L
+    [(0, 0), (1, 0), (2, 0), (2, 1)],
+];
+
+/// A piece's squares a quarter turn round, moved back so none is below 0
+fn turned(squares: &[(u8, u8)]) -> Vec<(u8, u8)> {
+    let width = squares.iter().map(|&(x, _)| x).max().unwrap_or_default();
+    squares.iter().map(|&(x, y)| (y, width - x)).collect()
+}
+
+struct Piece {
+    /// (x, y) of each voxel
+    squares: Vec<(u8, u8)>,
+    /// Layer the piece is falling through
+    z: u8,
+}
+
+/// Falling blocks that play themselves: flat tetrominoes drop from the top into the lowest
+/// spot they fit, stack up, and full layers flash then clear. Once nothing fits at the top the
+/// whole stack flashes and clears.
+pub struct Blocks {
+    rng: StdRng,
+    stack: Voxels,
+    falling: Option<Piece>,
+    /// Layers about to clear, and for how many more frames they flash
+    clearing: Option<(Vec<u8>, u32)>,
+}
+
+impl Blocks {
+    pub fn new(seed: Option<u64>) -> Self {
+        Blocks {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            stack: Voxels::new(),
+            falling: None,
+            clearing: None,
+        }
+    }
+
+    fn fits(&self, squares: &[(u8, u8)], z: u8) -> bool {
+        squares.iter().all(|&(x, y)| !self.stack.get(x, y, z))
+    }
+
+    /// Where a piece dropped from the top would come to rest, `None` if it doesn't fit there
+    fn landing(&self, squares: &[(u8, u8)]) -> Option<u8> {
+        if !self.fits(squares, 7) {
+            return None;
+        }
+        let blocked = (0..7).rev().find(|&z| !self.fits(squares, z));
+        Some(blocked.map_or(0, |z| z + 1))
+    }
+
+    /// A random piece placed where it lands lowest, `None` once the stack reaches the top
+    fn spawn(&mut self) -> Option<Piece> {
+        let mut squares = PIECES.choose(&mut self.rng).expect("pieces").to_vec();
+        let mut placements = Vec::new();
+        for _ in 0..4 {
+            squares = turned(&squares);
+            let width = squares.iter().map(|&(x, _)| x).max().unwrap_or_default();
+            let depth = squares.iter().map(|&(_, y)| y).max().unwrap_or_default();
+            for dx in 0..8 - width {
+                for dy in 0..8 - depth {
+                    let placed: Vec<(u8, u8)> =
+                        squares.iter().map(|&(x, y)| (x + dx, y + dy)).collect();
+                    if let Some(z) = self.landing(&placed) {
+                        placements.push((z, placed));
+                    }
+                }
+            }
+        }
+
+        let lowest = placements.iter().map(|&(z, _)| z).min()?;
+        placements.retain(|&(z, _)| z == lowest);
+        let (_, squares) = placements.swap_remove(self.rng.gen_range(0..placements.len()));
+        Some(Piece { squares, z: 7 })
+    }
+
+    fn full_layers(&self) -> Vec<u8> {
+        (0..8)
+            .filter(|&z| self.stack.0[z as usize] == [0xFF; 8])
+            .collect()
+    }
+
+    fn step(&mut self) {
+        if let Some((layers, frames)) = &mut self.clearing {
+            *frames -= 1;
+            if *frames == 0 {
+                // From the top down, so the indices below stay put
+                for &z in layers.iter().rev() {
+                    let z = usize::from(z);
+                    self.stack.0.copy_within(z + 1.., z);
+                    self.stack.0[7] = [0; 8];
+                }
+                self.clearing = None;
+            }
+            return;
+        }
+
+        match self.falling.take() {
+            Some(piece) if piece.z > 0 && self.fits(&piece.squares, piece.z - 1) => {
+                self.falling = Some(Piece {
+                    z: piece.z - 1,
+                    ..piece
+                });
+            }
+            Some(piece) => {
+                for &(x, y) in &piece.squares {
+                    self.stack.put(x, y, piece.z, true);
+                }
+                let full = self.full_layers();
+                if !full.is_empty() {
+                    self.clearing = Some((full, FLASH_FRAMES));
+                }
+            }
+            None => {
+                self.falling = self.spawn();
+                if self.falling.is_none() {
+                    // Topped out, so everything goes
+                    let layers = (0..8).filter(|&z| self.stack.0[z as usize] != [0; 8]);
+                    self.clearing = Some((layers.collect(), FLASH_FRAMES));
+                }
+            }
+        }
+    }
+
+    fn render(&self) -> Frame {
+        let mut frame = self.stack;
+        if let Some(piece) = &self.falling {
+            for &(x, y) in &piece.squares {
+                frame.put(x, y, piece.z, true);
+            }
+        }
+        if let Some((layers, frames)) = &self.clearing {
+            if frames % 2 == 1 {
+                for &z in layers {
+                    frame.0[usize::from(z)] = [0; 8];
+                }
+            }
+        }
+        frame.into()
+    }
+}
+
+impl Iterator for Blocks {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.step();
+        Some(self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pieces_stack_without_overlapping() {
+        let mut blocks = Blocks::new(Some(5));
+        let mut landed = 0;
+        for _ in 0..200 {
+            let before = blocks.stack.count();
+            blocks.step();
+            if blocks.clearing.is_some() {
+                break;
+            }
+            let after = blocks.stack.count();
+            assert!(after == before || after == before + 4);
+            landed += (after - before) / 4;
+        }
+        assert!(landed > 5);
+    }
+
+    #[test]
+    fn full_layers_flash_then_clear_with_the_rest_dropping() {
+        let mut blocks = Blocks::new(Some(1));
+        blocks.stack.0[0] = [0xFF; 8];
+        blocks.stack.0[1] = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F];
+        blocks.stack.0[2] = [1, 0, 0, 0, 0, 0, 0, 0];
+        blocks.falling = Some(Piece {
+            squares: vec![(7, 4), (7, 5), (7, 6), (7, 7)],
+            z: 1,
+        });
+
+        blocks.step();
+        assert_eq!(blocks.clearing, Some((vec![0, 1], FLASH_FRAMES)));
+        let flashes: Vec<bool> = (0..FLASH_FRAMES)
+            .map(|_| Voxels(blocks.next().unwrap()).get(0, 0, 0))
+            .collect();
+        assert_eq!(flashes, [false, true, false, true, false, true]);
+        assert_eq!(blocks.stack.count(), 1);
+        assert!(blocks.stack.get(0, 0, 0));
+    }
+}
@@ -0,0 +1,204 @@
+use std::iter::from_fn;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{spawn_count, Frame};
+use crate::geometry::{Coord, Point};
+use crate::gray::{self, GrayFrame, MAX_LEVEL};
+
+/// Pull on a particle's vertical speed each frame
+const GRAVITY: f32 = 0.06;
+/// Frames a particle stays lit after its burst, fading all the way
+const PARTICLE_LIFE: u32 = 14;
+/// Layers a rocket may burst at
+const BURST_HEIGHTS: std::ops::RangeInclusive<u8> = 4..=6;
+/// Range of a particle's starting speed in voxels per frame
+const BURST_SPEED: std::ops::Range<f32> = 0.3..0.7;
+
+struct Rocket {
+    x: u8,
+    y: u8,
+    z: u8,
+    burst_at: u8,
+}
+
+struct Particle {
+    position: Point,
+    /// Voxels moved per frame along each axis
+    velocity: Point,
+    /// Frames left before it goes dark
+    life: u32,
+}
+
+impl Particle {
+    /// The voxel it lights, if it is inside the cube
+    fn voxel(&self) -> Option<Coord> {
+        let axis = |v: f32| {
+            let v = v.round();
+            (0.0..=7.0).contains(&v).then_some(v as u8)
+        };
+        let p = &self.position;
+        Some(Coord::new(axis(p.x)?, axis(p.y)?, axis(p.z)?))
+    }
+}
+
+/// Rockets rising from the bottom layer and bursting into particles that fall and fade
+pub struct Fireworks {
+    rng: StdRng,
+    /// Average rockets launched per frame
+    rate: f64,
+    /// Particles in each burst
+    particles: usize,
+    rockets: Vec<Rocket>,
+    sparks: Vec<Particle>,
+}
+
+impl Fireworks {
+    pub fn new(rate: f64, particles: usize, seed: Option<u64>) -> Self {
+        Fireworks {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            rate: rate.max(0.0),
+            particles,
+            rockets: Vec::new(),
+            sparks: Vec::new(),
+        }
+    }
+
+    /// The same fireworks with the particles fading out as they fall
+    pub fn gray(mut self) -> impl Iterator<Item = GrayFrame> {
+        from_fn(move || Some(self.next_gray()))
+    }
+
+    fn launch(&mut self) {
+        for _ in 0..spawn_count(&mut self.rng, self.rate) {
+            self.rockets.push(Rocket {
+                x: self.rng.gen_range(1..7),
+                y: self.rng.gen_range(1..7),
+                z: 0,
+                burst_at: self.rng.gen_range(BURST_HEIGHTS),
+            });
+        }
+    }
+
+    fn burst(&mut self, rocket: &Rocket) {
+        let centre = Point::from(Coord::new(rocket.x, rocket.y, rocket.z));
+        for _ in 0..self.particles {
+            // Uniform over the sphere: a uniform height and an angle around it
+            let vz: f32 = self.rng.gen_range(-1.0..1.0);
+            let angle: f32 = self.rng.gen_range(0.0..std::f32::consts::TAU);
+            let across = (1.0 - vz * vz).sqrt();
+            let speed = self.rng.gen_range(BURST_SPEED);
+
+            self.sparks.push(Particle {
+                position: centre,
+                velocity: Point::new(
+                    speed * across * angle.cos(),
+                    speed * across * angle.sin(),
+                    speed * vz,
+                ),
+                life: PARTICLE_LIFE,
+            });
+        }
+    }
+
+    fn next_gray(&mut self) -> GrayFrame {
+        for spark in &mut self.sparks {
+            spark.position.x += spark.velocity.x;
+            spark.position.y += spark.velocity.y;
+            spark.position.z += spark.velocity.z;
+            spark.velocity.z -= GRAVITY;
+            spark.life -= 1;
+        }
+        // Gone once dark or on the floor, sparks off the sides may still fall back in view
+        self.sparks
+            .retain(|spark| spark.life > 0 && spark.position.z > -0.5);
+
+        let mut rockets = std::mem::take(&mut self.rockets);
+        for rocket in &mut rockets {
+            rocket.z += 1;
+        }
+        for rocket in rockets.iter().filter(|r| r.z >= r.burst_at) {
+            self.burst(rocket);
+        }
+        rockets.retain(|r| r.z < r.burst_at);
+        self.rockets = rockets;
+        self.launch();
+
+        let mut gray = [[[0u8; 8]; 8]; 8];
+        for rocket in &self.rockets {
+            gray::set(
+                &mut gray,
+                Coord::new(rocket.x, rocket.y, rocket.z),
+                MAX_LEVEL,
+            );
+        }
+        for spark in &self.sparks {
+            if let Some(c) = spark.voxel() {
+                let level = spark.life * u32::from(MAX_LEVEL) / PARTICLE_LIFE;
+                gray::brighten(&mut gray, c, level.max(1) as u8);
+            }
+        }
+        gray
+    }
+}
+
+impl Iterator for Fireworks {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        // Anything lit at all
+        Some(gray::threshold(&self.next_gray(), 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(gray: &GrayFrame) -> usize {
+        gray.iter().flatten().flatten().filter(|&&l| l > 0).count()
+    }
+
+    #[test]
+    fn a_rocket_rises_from_the_floor_then_bursts() {
+        let mut fireworks = Fireworks::new(0.0, 30, Some(1));
+        fireworks.rate = 1.0;
+        let first = fireworks.next_gray();
+        fireworks.rate = 0.0;
+
+        let rocket = &fireworks.rockets[0];
+        let (x, y, burst_at) = (rocket.x, rocket.y, rocket.burst_at);
+        assert_eq!(gray::get(&first, Coord::new(x, y, 0)), MAX_LEVEL);
+        assert_eq!(lit(&first), 1);
+
+        for z in 1..burst_at {
+            let gray = fireworks.next_gray();
+            assert_eq!(gray::get(&gray, Coord::new(x, y, z)), MAX_LEVEL);
+            assert_eq!(lit(&gray), 1);
+        }
+
+        // Every particle starts at the burst, then they spread out
+        let burst = fireworks.next_gray();
+        assert!(fireworks.rockets.is_empty());
+        assert_eq!(fireworks.sparks.len(), 30);
+        assert_eq!(gray::get(&burst, Coord::new(x, y, burst_at)), MAX_LEVEL);
+        assert!(lit(&fireworks.next_gray()) > 1);
+    }
+
+    #[test]
+    fn particles_fade_and_fall_away() {
+        let mut fireworks = Fireworks::new(0.0, 50, Some(2));
+        fireworks.burst(&Rocket {
+            x: 3,
+            y: 3,
+            z: 6,
+            burst_at: 6,
+        });
+
+        let frames: Vec<GrayFrame> = (0..PARTICLE_LIFE).map(|_| fireworks.next_gray()).collect();
+        let brightest = |gray: &GrayFrame| gray.iter().flatten().flatten().copied().max();
+        assert!(brightest(&frames[0]) > brightest(&frames[frames.len() - 2]));
+        assert_eq!(lit(frames.last().unwrap()), 0);
+        assert!(fireworks.sparks.is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+use std::f32::consts::{PI, TAU};
+
+use super::Frame;
+use crate::raster::{self, Fixed};
+
+/// Distance of the strands from the vertical axis, in voxels
+const RADIUS: f32 = 3.0;
+
+/// Helices twisting up the vertical axis through the centre and turning about it, like a DNA
+/// strand. With two strands, rungs can join them every other layer.
+pub struct Helix {
+    /// 1 or 2, the second half a turn round from the first
+    strands: u8,
+    /// Layers for one full twist of a strand
+    pitch: f32,
+    /// Radians turned per frame
+    speed: f32,
+    rungs: bool,
+    angle: f32,
+}
+
+impl Helix {
+    pub fn new(strands: u8, pitch: f32, speed: f32, rungs: bool) -> Self {
+        Helix {
+            strands: strands.clamp(1, 2),
+            pitch,
+            speed,
+            rungs,
+            angle: 0.0,
+        }
+    }
+
+    /// Where a strand half a turn round from the start crosses layer `z`
+    fn strand(&self, z: u8, half_turns: u8) -> Fixed {
+        let angle = self.angle + TAU * f32::from(z) / self.pitch + PI * f32::from(half_turns);
+        Fixed::from_voxels(
+            RADIUS * angle.cos(),
+            RADIUS * angle.sin(),
+            f32::from(z) - 3.5,
+        )
+    }
+}
+
+impl Iterator for Helix {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = [[0u8; 8]; 8];
+        for z in 0..8 {
+            for half_turns in 0..self.strands {
+                if let Some(c) = self.strand(z, half_turns).coord() {
+                    c.set(&mut frame);
+                }
+            }
+            if self.rungs && self.strands == 2 && z % 2 == 0 {
+                raster::line(&mut frame, self.strand(z, 0), self.strand(z, 1));
+            }
+        }
+
+        // Wrapped so the angle stays precise however long it runs
+        self.angle = (self.angle + self.speed).rem_euclid(TAU);
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::Voxels;
+
+    #[test]
+    fn strands_light_a_voxel_a_layer_and_rungs_cross_the_middle() {
+        let single = Voxels(Helix::new(1, 8.0, 0.3, true).next().unwrap());
+        assert_eq!(single.count(), 8);
+        assert!((0..8).all(|z| single.lit().filter(|c| c.z == z).count() == 1));
+
+        let double = Voxels(Helix::new(2, 8.0, 0.3, false).next().unwrap());
+        assert_eq!(double.count(), 16);
+
+        // Rungs on layers 0, 2, 4 and 6 run through the axis
+        let laddered = Voxels(Helix::new(2, 8.0, 0.3, true).next().unwrap());
+        for z in 0..8 {
+            let layer = laddered.lit().filter(|c| c.z == z).count();
+            if z % 2 == 0 {
+                assert!(layer >= 6, "layer {z} has {layer}");
+            } else {
+                assert_eq!(layer, 2);
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::{collections::VecDeque, fmt, str::FromStr};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::Frame;
+use crate::geometry::Coord;
+
+/// Generations remembered to notice that the cube has settled into a still life or a short cycle
+const HISTORY: usize = 12;
+/// Generations a settled cube keeps being shown before it is reseeded
+const SETTLED_GENERATIONS: u32 = 20;
+
+/// Which counts of the 26 surrounding cells bring a dead cell to life and keep a live one alive,
+/// written `B6/S567`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LifeRule {
+    /// Bit N set for a birth with N live neighbours
+    birth: u32,
+    survival: u32,
+}
+
+impl LifeRule {
+    fn next(&self, alive: bool, neighbours: u32) -> bool {
+        let counts = if alive { self.survival } else { self.birth };
+        counts & (1 << neighbours) != 0
+    }
+}
+
+/// A rule known to keep 3D Life going rather than exploding or dying straight away
+impl Default for LifeRule {
+    fn default() -> Self {
+        "B6/S567".parse().expect("valid rule")
+    }
+}
+
+impl FromStr for LifeRule {
+    type Err = String;
+
+    /// Parses `B` and `S` followed by neighbour counts from 0 to 26, separated by `/`. Counts
+    /// above 9 are separated by commas, as in `B56/S5,6,7,10`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let counts = |part: &str, prefix: char| -> Result<u32, String> {
+            let digits = part
+                .strip_prefix(prefix)
+                .or_else(|| part.strip_prefix(prefix.to_ascii_lowercase()))
+                .ok_or_else(|| format!("expected {prefix} in {part:?}"))?;
+            let numbers: Vec<&str> = if digits.contains(',') {
+                digits.split(',').collect()
+            } else {
+                digits.split("").filter(|d| !d.is_empty()).collect()
+            };
+            numbers
+                .into_iter()
+                .try_fold(0, |mask, n| match n.trim().parse::<u32>() {
+                    Ok(n) if n <= 26 => Ok(mask | 1 << n),
+                    _ => Err(format!("{n:?} is not a neighbour count from 0 to 26")),
+                })
+        };
+
+        let (birth, survival) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected B.../S..., found {s:?}"))?;
+        Ok(LifeRule {
+            birth: counts(birth.trim(), 'B')?,
+            survival: counts(survival.trim(), 'S')?,
+        })
+    }
+}
+
+impl fmt::Display for LifeRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = |mask: u32| {
+            let counts: Vec<String> = (0..=26)
+                .filter(|n| mask & (1 << n) != 0)
+                .map(|n: u32| n.to_string())
+                .collect();
+            let separator = if counts.iter().any(|n| n.len() > 1) {
+                ","
+            } else {
+                ""
+            };
+            counts.join(separator)
+        };
+        write!(f, "B{}/S{}", counts(self.birth), counts(self.survival))
+    }
+}
+
+/// Conway's Game of Life in three dimensions, on a cube whose faces wrap around. Starts from a
+/// random soup or a given frame, and starts over once everything has died or settled down.
+pub struct Life {
+    rng: StdRng,
+    rule: LifeRule,
+    density: f64,
+    pattern: Option<Frame>,
+    cells: Frame,
+    history: VecDeque<Frame>,
+    settled_for: u32,
+}
+
+impl Life {
+    /// `density` is the fraction of cells alive in a random start, used whenever `pattern`
+    /// isn't given
+    pub fn new(rule: LifeRule, density: f64, pattern: Option<Frame>, seed: Option<u64>) -> Self {
+        let mut life = Life {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            rule,
+            density: if density.is_nan() {
+                0.0
+            } else {
+                density.clamp(0.0, 1.0)
+            },
+            pattern,
+            cells: [[0; 8]; 8],
+            history: VecDeque::with_capacity(HISTORY),
+            settled_for: 0,
+        };
+        life.reseed();
+        life
+    }
+
+    fn reseed(&mut self) {
+        self.cells = match self.pattern {
+            Some(pattern) => pattern,
+            None => {
+                let mut cells = [[0; 8]; 8];
+                for c in Coord::all() {
+                    if self.rng.gen_bool(self.density) {
+                        c.set(&mut cells);
+                    }
+                }
+                cells
+            }
+        };
+        self.history.clear();
+        self.settled_for = 0;
+    }
+
+    fn neighbours(&self, c: Coord) -> u32 {
+        let mut count = 0;
+        for dz in [7, 0, 1] {
+            for dx in [7, 0, 1] {
+                for dy in [7, 0, 1] {
+                    if (dx, dy, dz) == (0, 0, 0) {
+                        continue;
+                    }
+                    let n = Coord::new((c.x + dx) % 8, (c.y + dy) % 8, (c.z + dz) % 8);
+                    count += u32::from(n.get(&self.cells));
+                }
+            }
+        }
+        count
+    }
+
+    fn step(&mut self) {
+        let mut next = [[0; 8]; 8];
+        for c in Coord::all() {
+            if self.rule.next(c.get(&self.cells), self.neighbours(c)) {
+                c.set(&mut next);
+            }
+        }
+
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(self.cells);
+        self.cells = next;
+    }
+}
+
+impl Iterator for Life {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let frame = self.cells;
+
+        self.step();
+        if self.cells == [[0; 8]; 8] {
+            self.reseed();
+        } else if self.history.contains(&self.cells) {
+            self.settled_for += 1;
+            if self.settled_for >= SETTLED_GENERATIONS {
+                self.reseed();
+            }
+        } else {
+            self.settled_for = 0;
+        }
+
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_round_trip() {
+        for rule in ["B6/S567", "B45/S5", "B56/S5,6,7,10", "B/S"] {
+            assert_eq!(rule.parse::<LifeRule>().unwrap().to_string(), rule);
+        }
+        assert_eq!("b6/s567".parse::<LifeRule>(), "B6/S567".parse());
+        assert!("B6".parse::<LifeRule>().is_err());
+        assert!("B6/S5,27".parse::<LifeRule>().is_err());
+        assert!("S5/B6".parse::<LifeRule>().is_err());
+    }
+
+    #[test]
+    fn neighbours_wrap_around() {
+        let mut cells = [[0; 8]; 8];
+        Coord::new(7, 7, 7).set(&mut cells);
+        let life = Life::new(LifeRule::default(), 0.0, Some(cells), Some(1));
+        assert_eq!(life.neighbours(Coord::new(0, 0, 0)), 1);
+        assert_eq!(life.neighbours(Coord::new(7, 7, 7)), 0);
+    }
+
+    #[test]
+    fn densities_out_of_range_start_empty_or_full() {
+        let start = |density| Life::new(LifeRule::default(), density, None, Some(1)).cells;
+        assert_eq!(start(f64::NAN), [[0; 8]; 8]);
+        assert_eq!(start(-1.0), [[0; 8]; 8]);
+        assert_eq!(start(2.0), [[0xff; 8]; 8]);
+    }
+
+    #[test]
+    fn a_still_life_is_reseeded() {
+        // Nothing is born and everything survives, so any start is a still life
+        let frozen = LifeRule {
+            birth: 0,
+            survival: u32::MAX,
+        };
+        let frames: Vec<Frame> = Life::new(frozen, 0.3, None, Some(1))
+            .take(SETTLED_GENERATIONS as usize + 1)
+            .collect();
+        let (settled, reseeded) = frames.split_at(SETTLED_GENERATIONS as usize);
+        assert!(settled.iter().all(|&frame| frame == settled[0]));
+        assert_ne!(reseeded[0], settled[0]);
+    }
+
+    #[test]
+    fn a_dead_cube_is_reseeded() {
+        let mut lonely = [[0; 8]; 8];
+        Coord::new(1, 1, 1).set(&mut lonely);
+        let frames: Vec<Frame> = Life::new(LifeRule::default(), 0.0, Some(lonely), Some(1))
+            .take(3)
+            .collect();
+        assert_eq!(frames, [lonely; 3]);
+    }
+}
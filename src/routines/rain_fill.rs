@@ -0,0 +1,111 @@
+use clap::ValueEnum;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{spawn_count, Frame};
+use crate::geometry::Coord;
+
+/// How a full `RainFill` cube empties before it starts again
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum Drain {
+    /// Every column drops a layer per frame
+    #[default]
+    Gradual,
+    /// Everything disappears at once
+    Instant,
+}
+
+impl std::fmt::Display for Drain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Rain that collects at the bottom, each column filling at its own pace until the cube is full
+pub struct RainFill {
+    rng: StdRng,
+    /// Settled voxels per (x, y) column
+    heights: [[u8; 8]; 8],
+    /// Drops still falling
+    drops: Vec<Coord>,
+    /// Average drops spawned per frame
+    rate: f64,
+    drain: Drain,
+    draining: bool,
+}
+
+impl RainFill {
+    pub fn new(rate: f64, drain: Drain, seed: Option<u64>) -> Self {
+        RainFill {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            heights: [[0; 8]; 8],
+            drops: Vec::new(),
+            rate: rate.max(0.0),
+            drain,
+            draining: false,
+        }
+    }
+
+    fn fall(&mut self) {
+        let heights = &mut self.heights;
+        self.drops.retain_mut(|drop| {
+            let surface = &mut heights[drop.x as usize][drop.y as usize];
+            if drop.z <= *surface {
+                // Landed, the drop becomes part of its column
+                *surface = (*surface + 1).min(8);
+                false
+            } else {
+                drop.z -= 1;
+                true
+            }
+        });
+    }
+
+    fn spawn(&mut self) {
+        for _ in 0..spawn_count(&mut self.rng, self.rate) {
+            let (x, y) = (self.rng.gen_range(0..8), self.rng.gen_range(0..8));
+            if self.heights[x as usize][y as usize] < 8 {
+                self.drops.push(Coord::new(x, y, 7));
+            }
+        }
+    }
+
+    fn render(&self) -> Frame {
+        let mut frame = [[0u8; 8]; 8];
+        Coord::all()
+            .filter(|c| c.z < self.heights[c.x as usize][c.y as usize])
+            .chain(self.drops.iter().copied())
+            .for_each(|c| c.set(&mut frame));
+        frame
+    }
+}
+
+impl Iterator for RainFill {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.draining {
+            match self.drain {
+                Drain::Gradual => self
+                    .heights
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|h| *h = h.saturating_sub(1)),
+                Drain::Instant => self.heights = [[0; 8]; 8],
+            }
+            self.draining = self.heights.iter().flatten().any(|&h| h > 0);
+        } else {
+            self.fall();
+            if self.heights.iter().flatten().all(|&h| h == 8) {
+                self.drops.clear();
+                self.draining = true;
+            } else {
+                self.spawn();
+            }
+        }
+
+        Some(self.render())
+    }
+}
@@ -0,0 +1,100 @@
+use std::f32::consts::FRAC_PI_2;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::Frame;
+use crate::voxels::{Axis, Voxels};
+
+/// A slice part way through a quarter turn
+struct Turn {
+    axis: Axis,
+    index: u8,
+    /// 1 anticlockwise, -1 clockwise
+    direction: f32,
+    /// Frames of the turn shown so far
+    step: u32,
+}
+
+/// Scrambles the cube like a Rubik's cube: one slice at a time, along a random axis, turns a
+/// quarter in `steps` frames. The pattern starts as a checkerboard of 2x2x2 blocks and keeps
+/// every turn, so it gets more scrambled as it goes.
+pub struct Rubik {
+    rng: StdRng,
+    cube: Voxels,
+    steps: u32,
+    turn: Option<Turn>,
+}
+
+impl Rubik {
+    pub fn new(steps: u32, seed: Option<u64>) -> Self {
+        let mut cube = Voxels::new();
+        for c in Voxels::full().lit() {
+            cube.put(c.x, c.y, c.z, (c.x / 2 + c.y / 2 + c.z / 2) % 2 == 0);
+        }
+        Rubik {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            cube,
+            steps: steps.max(1),
+            turn: None,
+        }
+    }
+
+    fn start_turn(&mut self) -> Turn {
+        Turn {
+            axis: [Axis::X, Axis::Y, Axis::Z][self.rng.gen_range(0..3)],
+            index: self.rng.gen_range(0..8),
+            direction: if self.rng.gen() { 1.0 } else { -1.0 },
+            step: 0,
+        }
+    }
+}
+
+impl Iterator for Rubik {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut turn = match self.turn.take() {
+            Some(turn) => turn,
+            None => self.start_turn(),
+        };
+        turn.step += 1;
+        let angle = turn.direction * FRAC_PI_2 * turn.step as f32 / self.steps as f32;
+
+        if turn.step == self.steps {
+            // Settled, so the next turn starts from it
+            self.cube.turn_slice(turn.axis, turn.index, angle);
+            return Some(self.cube.into());
+        }
+        let mut frame = self.cube;
+        frame.turn_slice(turn.axis, turn.index, angle);
+        self.turn = Some(turn);
+        Some(frame.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Coord;
+
+    #[test]
+    fn each_turn_moves_one_slice_and_keeps_it() {
+        let mut rubik = Rubik::new(3, Some(12));
+        let start = rubik.cube;
+        let frames: Vec<Frame> = rubik.by_ref().take(3).collect();
+
+        // A quarter turn keeps every voxel of the slice, only moving them round
+        let settled = Voxels(frames[2]);
+        assert_eq!(settled, rubik.cube);
+        assert_eq!(settled.count(), start.count());
+        assert_ne!(settled, start);
+        let changed = (settled ^ start).lit().collect::<Vec<_>>();
+        let positions = |c: &Coord| [c.x, c.y, c.z];
+        let one_slice = (0..3).any(|axis| {
+            changed
+                .iter()
+                .all(|c| positions(c)[axis] == positions(&changed[0])[axis])
+        });
+        assert!(one_slice);
+    }
+}
@@ -0,0 +1,172 @@
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{spawn_count, Frame};
+use crate::geometry::Coord;
+
+/// Occupancy per cell, indexed `[z][x][y]` with z = 7 as the top layer
+type Grains = [[[bool; 8]; 8]; 8];
+
+/// Horizontal unit steps, used for both diagonal falls and liquid spreading
+const SIDEWAYS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+pub struct Sand {
+    rng: SmallRng,
+    grains: Grains,
+    /// Average number of grains spawned on the top layer per frame
+    rate: f64,
+    /// Resting grains also flow sideways
+    liquid: bool,
+    /// Slice of the clearing sweep once the pile has reached the top
+    tipping: Option<usize>,
+}
+
+impl Sand {
+    pub fn new(rate: f64, liquid: bool) -> Self {
+        Sand {
+            rng: SmallRng::from_entropy(),
+            grains: [[[false; 8]; 8]; 8],
+            rate: rate.max(0.0),
+            liquid,
+            tipping: None,
+        }
+    }
+
+    fn spawn(&mut self) {
+        for _ in 0..spawn_count(&mut self.rng, self.rate) {
+            let x = self.rng.gen_range(0..8);
+            let y = self.rng.gen_range(0..8);
+            self.grains[7][x][y] = true;
+        }
+    }
+
+    /// Empty in-bounds cells one step sideways from (x, y) on layer z
+    fn free_neighbors(&self, z: usize, x: usize, y: usize) -> Vec<(usize, usize)> {
+        SIDEWAYS
+            .iter()
+            .filter_map(|&(dx, dy)| {
+                let nx = x.checked_add_signed(dx).filter(|&n| n < 8)?;
+                let ny = y.checked_add_signed(dy).filter(|&n| n < 8)?;
+                (!self.grains[z][nx][ny]).then_some((nx, ny))
+            })
+            .collect()
+    }
+
+    /// Move every grain at most one cell, returning whether a grain came to rest on the top layer
+    fn settle(&mut self) -> bool {
+        let mut moved = [[[false; 8]; 8]; 8];
+        let mut cells: Vec<(usize, usize)> =
+            (0..8).flat_map(|x| (0..8).map(move |y| (x, y))).collect();
+        let mut topped_out = false;
+
+        // Bottom-up so a grain that just fell isn't visited again this frame
+        for z in 0..8 {
+            cells.shuffle(&mut self.rng);
+
+            for &(x, y) in &cells {
+                if !self.grains[z][x][y] || moved[z][x][y] {
+                    continue;
+                }
+
+                let target = if z == 0 {
+                    None
+                } else if !self.grains[z - 1][x][y] {
+                    Some((z - 1, x, y))
+                } else {
+                    self.free_neighbors(z - 1, x, y)
+                        .choose(&mut self.rng)
+                        .map(|&(nx, ny)| (z - 1, nx, ny))
+                };
+
+                let target = target.or_else(|| {
+                    if self.liquid {
+                        self.free_neighbors(z, x, y)
+                            .choose(&mut self.rng)
+                            .map(|&(nx, ny)| (z, nx, ny))
+                    } else {
+                        None
+                    }
+                });
+
+                match target {
+                    Some((tz, tx, ty)) => {
+                        self.grains[z][x][y] = false;
+                        self.grains[tz][tx][ty] = true;
+                        moved[tz][tx][ty] = true;
+                    }
+                    None => topped_out |= z == 7,
+                }
+            }
+        }
+
+        topped_out
+    }
+
+    fn render(&self) -> Frame {
+        let mut frame = [[0u8; 8]; 8];
+        Coord::all()
+            .filter(|c| self.grains[c.z as usize][c.x as usize][c.y as usize])
+            .for_each(|c| c.set(&mut frame));
+        frame
+    }
+}
+
+impl Iterator for Sand {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        match self.tipping {
+            Some(slice) if slice < 8 => {
+                // Sweep the pile out one X slice at a time
+                for layer in self.grains.iter_mut() {
+                    layer[slice] = [false; 8];
+                }
+                self.tipping = Some(slice + 1);
+            }
+            Some(_) => self.tipping = None,
+            None => {
+                if self.settle() {
+                    self.tipping = Some(0);
+                } else {
+                    self.spawn();
+                }
+            }
+        }
+
+        Some(self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(cells: &[bool]) -> usize {
+        cells.iter().filter(|&&grain| grain).count()
+    }
+
+    #[test]
+    fn settling_never_makes_or_loses_grains() {
+        for liquid in [false, true] {
+            let mut sand = Sand::new(0.5, liquid);
+            sand.rng = SmallRng::seed_from_u64(3);
+            for cell in sand.grains.as_flattened_mut().as_flattened_mut() {
+                *cell = sand.rng.gen_bool(0.3);
+            }
+
+            let grains = count(sand.grains.as_flattened().as_flattened());
+            for _ in 0..32 {
+                sand.settle();
+                assert_eq!(count(sand.grains.as_flattened().as_flattened()), grains);
+            }
+        }
+    }
+
+    #[test]
+    fn grains_spawn_on_the_top_layer() {
+        let mut sand = Sand::new(3.0, false);
+        sand.spawn();
+        let top = count(sand.grains[7].as_flattened());
+        assert!((1..=3).contains(&top));
+        assert_eq!(count(sand.grains.as_flattened().as_flattened()), top);
+    }
+}
@@ -0,0 +1,160 @@
+use clap::ValueEnum;
+
+use super::Frame;
+use crate::geometry::Point;
+use crate::raster::{self, Fixed, FixedRotation};
+
+/// What `Shapes` draws
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Shape {
+    /// The twelve edges of a cube
+    #[default]
+    Cube,
+    /// A hollow ball
+    Sphere,
+    /// The eight edges of a square pyramid
+    Pyramid,
+}
+
+impl std::fmt::Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Half the side of the wireframe cube, small enough that its corners mostly stay inside
+const CUBE_HALF: f32 = 2.5;
+const SPHERE_RADIUS: f32 = 3.0;
+/// Half the side of the pyramid's base and how far its base and apex are from the centre
+const PYRAMID_HALF: f32 = 3.0;
+const PYRAMID_BASE: f32 = -2.5;
+const PYRAMID_APEX: f32 = 3.5;
+
+impl Shape {
+    /// Corners and the pairs of them joined by edges
+    fn wireframe(self) -> (Vec<Fixed>, Vec<(usize, usize)>) {
+        match self {
+            Shape::Cube => {
+                let corners = (0..8)
+                    .map(|i| {
+                        let side = |bit: usize| if i & bit == 0 { -CUBE_HALF } else { CUBE_HALF };
+                        Fixed::from_voxels(side(1), side(2), side(4))
+                    })
+                    .collect();
+                // Corners one bit apart share an edge
+                let edges = (0..8)
+                    .flat_map(|i| [1, 2, 4].map(|bit| (i, i | bit)))
+                    .filter(|&(i, j)| i != j)
+                    .collect();
+                (corners, edges)
+            }
+            Shape::Sphere => (Vec::new(), Vec::new()),
+            Shape::Pyramid => {
+                let h = PYRAMID_HALF;
+                let corners = vec![
+                    Fixed::from_voxels(-h, -h, PYRAMID_BASE),
+                    Fixed::from_voxels(h, -h, PYRAMID_BASE),
+                    Fixed::from_voxels(h, h, PYRAMID_BASE),
+                    Fixed::from_voxels(-h, h, PYRAMID_BASE),
+                    Fixed::from_voxels(0.0, 0.0, PYRAMID_APEX),
+                ];
+                let edges = vec![
+                    (0, 1),
+                    (1, 2),
+                    (2, 3),
+                    (3, 0),
+                    (0, 4),
+                    (1, 4),
+                    (2, 4),
+                    (3, 4),
+                ];
+                (corners, edges)
+            }
+        }
+    }
+}
+
+/// A shape spinning about an axis through the centre of the cube
+pub struct Shapes {
+    shape: Shape,
+    corners: Vec<Fixed>,
+    edges: Vec<(usize, usize)>,
+    axis: Point,
+    /// Radians turned per frame
+    speed: f32,
+    angle: f32,
+}
+
+impl Shapes {
+    pub fn new(shape: Shape, axis: Point, speed: f32) -> Self {
+        let (corners, edges) = shape.wireframe();
+        Shapes {
+            shape,
+            corners,
+            edges,
+            axis,
+            speed,
+            angle: 0.0,
+        }
+    }
+}
+
+impl Iterator for Shapes {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = [[0u8; 8]; 8];
+        let rotation = FixedRotation::about(self.axis, self.angle);
+        let corners: Vec<Fixed> = self.corners.iter().map(|&c| rotation.apply(c)).collect();
+
+        for &(a, b) in &self.edges {
+            raster::line(&mut frame, corners[a], corners[b]);
+        }
+        if self.shape == Shape::Sphere {
+            raster::sphere_shell(&mut frame, Fixed::default(), SPHERE_RADIUS);
+        }
+
+        self.angle = (self.angle + self.speed).rem_euclid(std::f32::consts::TAU);
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Coord;
+
+    #[test]
+    fn wireframes_have_their_edges() {
+        let (corners, edges) = Shape::Cube.wireframe();
+        assert_eq!((corners.len(), edges.len()), (8, 12));
+        let (corners, edges) = Shape::Pyramid.wireframe();
+        assert_eq!((corners.len(), edges.len()), (5, 8));
+    }
+
+    #[test]
+    fn a_cube_turned_a_quarter_looks_the_same() {
+        let mut shapes = Shapes::new(
+            Shape::Cube,
+            Point::new(0.0, 0.0, 1.0),
+            std::f32::consts::FRAC_PI_2,
+        );
+        let first = shapes.next().unwrap();
+        assert_eq!(shapes.next().unwrap(), first);
+        // Two voxels in from each face, corner to corner along the bottom edge
+        assert!((1..7).all(|x| Coord::new(x, 1, 1).get(&first)));
+        assert!(!Coord::new(3, 3, 3).get(&first));
+    }
+
+    #[test]
+    fn a_pyramid_has_an_apex() {
+        let frame = Shapes::new(Shape::Pyramid, Point::new(0.0, 0.0, 1.0), 0.1)
+            .nth(5)
+            .unwrap();
+        assert!(Coord::new(4, 4, 7).get(&frame));
+        assert_eq!(frame[0], [0; 8]);
+    }
+}
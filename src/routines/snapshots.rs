@@ -0,0 +1,154 @@
+//! The first frames of every routine that plays the same way each time, checked against golden
+//! files in `snapshots/`, one `--dump` line per frame. After a change meant to alter an
+//! animation, run the tests with `UPDATE_SNAPSHOTS` set to write them again and look over the
+//! diff.
+//!
+//! Routines taking a `--seed` draw from `StdRng`, whose numbers are the same on every platform,
+//! so these hold on a 32 bit Pi as well as where they were written. `SmallRng` differs between
+//! 32 and 64 bit targets.
+
+use std::{env, fs, path::PathBuf};
+
+use super::*;
+use crate::decoders::write_base16_frame;
+
+/// Frames compared for each routine
+const FRAMES: usize = 16;
+
+fn check(name: &str, frames: impl IntoIterator<Item = Frame>) {
+    let mut dumped = Vec::new();
+    for frame in frames.into_iter().take(FRAMES) {
+        write_base16_frame(&mut dumped, &frame).unwrap();
+    }
+    let dumped = String::from_utf8(dumped).unwrap();
+
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/routines/snapshots")
+        .join(format!("{name}.hex"));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, &dumped).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {e}, set UPDATE_SNAPSHOTS to write it", path.display()));
+    for (i, (got, want)) in dumped.lines().zip(golden.lines()).enumerate() {
+        assert_eq!(got, want, "{name} frame {i}");
+    }
+    assert_eq!(dumped.lines().count(), golden.lines().count(), "{name}");
+}
+
+#[test]
+fn ant() {
+    check(
+        "ant",
+        Ants::new(AntRule::default(), 3, AntEdge::Wrap, 4, Some(1)),
+    );
+}
+
+#[test]
+fn blocks() {
+    check("blocks", Blocks::new(Some(1)));
+}
+
+#[test]
+fn chess() {
+    check("chess", Chess::new());
+}
+
+#[test]
+fn comet() {
+    check("comet", Comet::new(6, Decay::Linear));
+}
+
+#[test]
+fn cycle_layers() {
+    check("cycle_layers", CycleLayers::new());
+}
+
+#[test]
+fn fireworks() {
+    check("fireworks", Fireworks::new(0.3, 24, Some(1)));
+}
+
+#[test]
+fn helix() {
+    check("helix", Helix::new(2, 8.0, 0.2, true));
+}
+
+#[test]
+fn life() {
+    check("life", Life::new(LifeRule::default(), 0.2, None, Some(1)));
+}
+
+#[test]
+fn mini_cube() {
+    check("mini_cube", MiniCube::new());
+}
+
+#[test]
+fn plane_wave() {
+    check("plane_wave", DiagonalPlane::new(true));
+}
+
+#[test]
+fn plasma() {
+    check("plasma", Plasma::new(0.25, 0.05, 0.0, Some(1)));
+}
+
+#[test]
+fn pulse() {
+    check("pulse", Pulse::new(PulseEnd::Reflect));
+}
+
+#[test]
+fn rain_fill() {
+    check("rain_fill", RainFill::new(1.0, Drain::Gradual, Some(1)));
+}
+
+#[test]
+fn ripple() {
+    check("ripple", Ripple::new(vec![Point::default()]));
+}
+
+#[test]
+fn rubik() {
+    check("rubik", Rubik::new(3, Some(1)));
+}
+
+#[test]
+fn shapes() {
+    check(
+        "shapes",
+        Shapes::new(Shape::Cube, Point::new(1.0, 2.0, 3.0), 0.1),
+    );
+}
+
+#[test]
+fn sine() {
+    check("sine", Sine::new(4.0, 0.25));
+}
+
+#[test]
+fn snow() {
+    check("snow", Snow::new(2.0, 4, Thaw::Melt, Some(1)));
+}
+
+#[test]
+fn sweep() {
+    check("sweep", Sweep3D::new(SweepOrder::Hilbert, 4, 2));
+}
+
+#[test]
+fn text() {
+    check("text", Text::new("Hi", TextFace::Front));
+}
+
+#[test]
+fn wave() {
+    check("wave", Wave::new());
+}
+
+#[test]
+fn wipe() {
+    check("wipe", Wipe::new(None, 2, Some(1)));
+}
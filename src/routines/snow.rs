@@ -0,0 +1,167 @@
+use clap::ValueEnum;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{spawn_count, Frame};
+use crate::geometry::Coord;
+
+/// Chance of a flake falling a layer each frame, so snow drifts down slower than rain
+const FALL_CHANCE: f64 = 0.5;
+/// Chance of a flake being blown to a neighbouring column each frame
+const WOBBLE_CHANCE: f64 = 0.3;
+/// Chance of each pile losing its top voxel each frame while melting
+const MELT_CHANCE: f64 = 0.15;
+
+/// How `Snow` clears once the deepest pile is deep enough
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum Thaw {
+    /// Piles sink a voxel at a time, each at its own pace
+    #[default]
+    Melt,
+    /// Everything disappears at once
+    Reset,
+}
+
+impl std::fmt::Display for Thaw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Flakes wobbling down and settling where they land, building uneven piles on the bottom
+/// until one is `depth` deep, then thawing and starting again
+pub struct Snow {
+    rng: StdRng,
+    /// Settled voxels per (x, y) column
+    heights: [[u8; 8]; 8],
+    /// Flakes still falling
+    flakes: Vec<Coord>,
+    /// Average flakes spawned per frame
+    rate: f64,
+    depth: u8,
+    thaw: Thaw,
+    melting: bool,
+}
+
+impl Snow {
+    pub fn new(rate: f64, depth: u8, thaw: Thaw, seed: Option<u64>) -> Self {
+        Snow {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            heights: [[0; 8]; 8],
+            flakes: Vec::new(),
+            rate: rate.max(0.0),
+            depth: depth.clamp(1, 8),
+            thaw,
+            melting: false,
+        }
+    }
+
+    fn fall(&mut self) {
+        let Snow {
+            rng,
+            heights,
+            flakes,
+            ..
+        } = self;
+        flakes.retain_mut(|flake| {
+            if rng.gen_bool(WOBBLE_CHANCE) {
+                let step =
+                    |v: u8, rng: &mut StdRng| v.saturating_add_signed(rng.gen_range(-1..=1)).min(7);
+                let (x, y) = (step(flake.x, rng), step(flake.y, rng));
+                // Only into air, never into the side of a pile
+                if heights[x as usize][y as usize] < flake.z {
+                    (flake.x, flake.y) = (x, y);
+                }
+            }
+
+            let surface = &mut heights[flake.x as usize][flake.y as usize];
+            if flake.z <= *surface {
+                // Landed, the flake becomes part of its pile
+                *surface = (*surface + 1).min(8);
+                false
+            } else {
+                if rng.gen_bool(FALL_CHANCE) {
+                    flake.z -= 1;
+                }
+                true
+            }
+        });
+    }
+
+    fn spawn(&mut self) {
+        for _ in 0..spawn_count(&mut self.rng, self.rate) {
+            let (x, y) = (self.rng.gen_range(0..8), self.rng.gen_range(0..8));
+            self.flakes.push(Coord::new(x, y, 7));
+        }
+    }
+
+    fn render(&self) -> Frame {
+        let mut frame = [[0u8; 8]; 8];
+        Coord::all()
+            .filter(|c| c.z < self.heights[c.x as usize][c.y as usize])
+            .chain(self.flakes.iter().copied())
+            .for_each(|c| c.set(&mut frame));
+        frame
+    }
+}
+
+impl Iterator for Snow {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.melting {
+            match self.thaw {
+                Thaw::Melt => {
+                    for h in self.heights.iter_mut().flatten() {
+                        if self.rng.gen_bool(MELT_CHANCE) {
+                            *h = h.saturating_sub(1);
+                        }
+                    }
+                }
+                Thaw::Reset => self.heights = [[0; 8]; 8],
+            }
+            self.melting = self.heights.iter().flatten().any(|&h| h > 0);
+        } else {
+            self.fall();
+            if self.heights.iter().flatten().any(|&h| h >= self.depth) {
+                self.flakes.clear();
+                self.melting = true;
+            } else {
+                self.spawn();
+            }
+        }
+
+        Some(self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::Voxels;
+
+    #[test]
+    fn snow_piles_up_then_melts_away() {
+        let mut snow = Snow::new(4.0, 3, Thaw::Melt, Some(7));
+        let mut deepest = 0;
+        while !snow.melting {
+            let frame = snow.next().unwrap();
+            // Piles never grow past the depth, so anything higher is still falling
+            for c in Voxels(frame).lit() {
+                assert!(c.z < 3 || snow.flakes.contains(&c));
+            }
+            deepest = deepest.max(*snow.heights.iter().flatten().max().unwrap());
+        }
+        assert_eq!(deepest, 3);
+        assert!(snow.flakes.is_empty());
+
+        let melt = snow
+            .by_ref()
+            .take_while(|frame| *frame != [[0; 8]; 8])
+            .count();
+        assert!(melt > 1, "melted in {melt} frames");
+        assert!(!snow.melting);
+    }
+}
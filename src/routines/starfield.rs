@@ -0,0 +1,147 @@
+use clap::ValueEnum;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use super::{spawn_count, Frame};
+use crate::geometry::Coord;
+
+/// Speed stars start at, in voxels a frame
+const START_SPEED: f32 = 0.25;
+/// How much faster stars get every frame
+const ACCELERATION: f32 = 1.35;
+
+/// Which way a `Starfield`'s stars fly, see [`Coord`] for the axes
+#[derive(Copy, Clone, Debug, Default, ValueEnum)]
+pub enum Direction {
+    /// Along +X, towards the viewer
+    #[default]
+    Forward,
+    /// Along -X
+    Backward,
+    /// Along +Y
+    Left,
+    /// Along -Y
+    Right,
+    /// Along +Z
+    Up,
+    /// Along -Z
+    Down,
+}
+
+impl std::fmt::Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl Direction {
+    /// The voxel `along` voxels into the flight from the face stars start on, at `a` and `b`
+    /// across it
+    fn coord(self, along: u8, a: u8, b: u8) -> Coord {
+        match self {
+            Direction::Forward => Coord::new(along, a, b),
+            Direction::Backward => Coord::new(7 - along, a, b),
+            Direction::Left => Coord::new(a, along, b),
+            Direction::Right => Coord::new(a, 7 - along, b),
+            Direction::Up => Coord::new(a, b, along),
+            Direction::Down => Coord::new(a, b, 7 - along),
+        }
+    }
+}
+
+struct Star {
+    a: u8,
+    b: u8,
+    /// How far it has flown
+    along: f32,
+    speed: f32,
+}
+
+/// The warp speed effect: stars appear on the far face and speed up as they fly past, drawn as
+/// streaks as long as they moved each frame
+pub struct Starfield {
+    rng: SmallRng,
+    stars: Vec<Star>,
+    /// Average number of stars appearing per frame
+    density: f64,
+    direction: Direction,
+}
+
+impl Starfield {
+    pub fn new(density: f64, direction: Direction) -> Self {
+        Starfield {
+            rng: SmallRng::from_entropy(),
+            stars: Vec::new(),
+            density: density.max(0.0),
+            direction,
+        }
+    }
+
+    fn spawn(&mut self) {
+        for _ in 0..spawn_count(&mut self.rng, self.density) {
+            let star = Star {
+                a: self.rng.gen_range(0..8),
+                b: self.rng.gen_range(0..8),
+                along: 0.0,
+                speed: START_SPEED,
+            };
+            self.stars.push(star);
+        }
+    }
+}
+
+impl Iterator for Starfield {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.spawn();
+
+        let mut frame = [[0u8; 8]; 8];
+        for star in &self.stars {
+            // From where it was a frame ago to where it is now
+            let tail = (star.along - star.speed / ACCELERATION).max(0.0);
+            for along in tail.round() as u8..=star.along.round().min(7.0) as u8 {
+                self.direction.coord(along, star.a, star.b).set(&mut frame);
+            }
+        }
+
+        for star in &mut self.stars {
+            star.along += star.speed;
+            star.speed *= ACCELERATION;
+        }
+        self.stars.retain(|star| star.along < 7.5);
+
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::Voxels;
+
+    #[test]
+    fn stars_start_on_the_far_face_and_fly_off() {
+        let mut starfield = Starfield::new(0.0, Direction::Down);
+        starfield.stars.push(Star {
+            a: 2,
+            b: 5,
+            along: 0.0,
+            speed: START_SPEED,
+        });
+
+        let first = Voxels(starfield.next().unwrap());
+        assert_eq!(first.lit().collect::<Vec<_>>(), [Coord::new(2, 5, 7)]);
+
+        // Streaks lengthen as they speed up, always in the one column
+        let mut longest = 0;
+        while !starfield.stars.is_empty() {
+            let streak = Voxels(starfield.next().unwrap());
+            assert!(streak.lit().all(|c| (c.x, c.y) == (2, 5)));
+            longest = longest.max(streak.count());
+        }
+        assert!(longest > 2);
+    }
+}
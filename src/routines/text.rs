@@ -0,0 +1,180 @@
+use clap::ValueEnum;
+
+use super::Frame;
+use crate::font::{self, GLYPH_HEIGHT};
+use crate::geometry::Coord;
+
+/// Where `Text` scrolls, each face read from outside the cube with Z up
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TextFace {
+    /// The Y = 0 face
+    #[default]
+    Front,
+    /// The X = 7 face
+    Right,
+    /// The Y = 7 face
+    Back,
+    /// The X = 0 face
+    Left,
+    /// All four vertical faces, the text running around the cube from front to right to back
+    /// to left
+    Wrap,
+}
+
+impl std::fmt::Display for TextFace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl TextFace {
+    /// Columns of LEDs the text passes through, in reading order
+    fn columns(self) -> Vec<(u8, u8)> {
+        match self {
+            TextFace::Front => (0..8).map(|x| (x, 0)).collect(),
+            TextFace::Right => (0..8).map(|y| (7, y)).collect(),
+            TextFace::Back => (0..8).rev().map(|x| (x, 7)).collect(),
+            TextFace::Left => (0..8).rev().map(|y| (0, y)).collect(),
+            // Each corner column belongs to the face before it
+            TextFace::Wrap => (0..8)
+                .map(|x| (x, 0))
+                .chain((1..8).map(|y| (7, y)))
+                .chain((0..7).rev().map(|x| (x, 7)))
+                .chain((1..7).rev().map(|y| (0, y)))
+                .collect(),
+        }
+    }
+}
+
+/// Render a message as font columns left to right with a blank column between characters,
+/// bit 0 being the top row. Anything outside printable ASCII shows as `?`.
+fn rasterize(message: &str) -> Vec<u8> {
+    let mut strip = Vec::new();
+    for (i, c) in message.chars().enumerate() {
+        if i > 0 {
+            strip.push(0);
+        }
+        strip.extend(
+            font::columns(c)
+                .or_else(|| font::columns('?'))
+                .unwrap_or_default(),
+        );
+    }
+    strip
+}
+
+/// A message scrolling past from right to left one column per frame, over and over. Each pass
+/// starts and ends with the face blank.
+pub struct Text {
+    /// Where each visible column of the strip is drawn, in reading order
+    columns: Vec<(u8, u8)>,
+    /// Blank columns filling the face, then the message
+    strip: Vec<u8>,
+    step: usize,
+}
+
+impl Text {
+    pub fn new(message: &str, face: TextFace) -> Self {
+        let columns = face.columns();
+        let mut strip = vec![0; columns.len()];
+        strip.extend(rasterize(message));
+        Text {
+            columns,
+            strip,
+            step: 0,
+        }
+    }
+}
+
+impl Iterator for Text {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut frame = [[0u8; 8]; 8];
+        for (i, &(x, y)) in self.columns.iter().enumerate() {
+            let bits = self.strip[(self.step + i) % self.strip.len()];
+            for row in 0..GLYPH_HEIGHT {
+                if bits & (1 << row) != 0 {
+                    Coord::new(x, y, 7 - row).set(&mut frame);
+                }
+            }
+        }
+        self.step = (self.step + 1) % self.strip.len();
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The strip column shown at each position of a face, read back from a frame
+    fn read_face(frame: &Frame, face: TextFace) -> Vec<u8> {
+        face.columns()
+            .into_iter()
+            .map(|(x, y)| {
+                (0..GLYPH_HEIGHT)
+                    .filter(|&row| Coord::new(x, y, 7 - row).get(frame))
+                    .map(|row| 1 << row)
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn characters_are_spaced_and_unknowns_replaced() {
+        let strip = rasterize("Aé");
+        assert_eq!(strip.len(), 11);
+        assert_eq!(strip[..5], font::columns('A').unwrap());
+        assert_eq!(strip[5], 0);
+        assert_eq!(strip[6..], font::columns('?').unwrap());
+        assert!(rasterize("").is_empty());
+    }
+
+    #[test]
+    fn text_enters_from_the_right_and_loops() {
+        let strip = rasterize("Hi");
+        let mut text = Text::new("Hi", TextFace::Front);
+
+        assert_eq!(read_face(&text.next().unwrap(), TextFace::Front), [0; 8]);
+        let entering = read_face(&text.next().unwrap(), TextFace::Front);
+        assert_eq!(entering[..7], [0; 7]);
+        assert_eq!(entering[7], strip[0]);
+
+        // The whole pass is the blank lead-in plus the message, then it starts over
+        let pass = 8 + strip.len();
+        let frames: Vec<Frame> = Text::new("Hi", TextFace::Front).take(2 * pass).collect();
+        let leaving = read_face(&frames[pass - 1], TextFace::Front);
+        assert_eq!(leaving[0], strip[strip.len() - 1]);
+        assert_eq!(leaving[1..], [0; 7]);
+        assert_eq!(frames[..pass], frames[pass..]);
+    }
+
+    #[test]
+    fn wrap_covers_every_side_column_once() {
+        let mut columns = TextFace::Wrap.columns();
+        assert_eq!(columns.len(), 28);
+        columns.sort();
+        columns.dedup();
+        assert_eq!(columns.len(), 28);
+        assert!(columns.iter().all(|&(x, y)| x % 7 == 0 || y % 7 == 0));
+    }
+
+    #[test]
+    fn faces_read_from_outside() {
+        // The first letter reaches the far end of each face first, the leftmost as seen by
+        // someone standing in front of it
+        for (face, leftmost) in [
+            (TextFace::Front, Coord::new(0, 0, 7)),
+            (TextFace::Right, Coord::new(7, 0, 7)),
+            (TextFace::Back, Coord::new(7, 7, 7)),
+            (TextFace::Left, Coord::new(0, 7, 7)),
+        ] {
+            let frame = Text::new("|", face).nth(8 + 2).unwrap();
+            assert!(leftmost.get(&frame), "{face}");
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use clap::ValueEnum;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::Frame;
+use crate::geometry::Coord;
+
+/// Voxels a `Dissolve` wipe changes per frame
+const DISSOLVE_RATE: usize = 16;
+
+/// How a `Wipe` fills or empties the cube
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WipeStyle {
+    /// A plane sweeping along a random axis
+    Plane,
+    /// Spreading out from a random corner, voxels the same number of steps away together
+    Corner,
+    /// Random voxels at a time
+    Dissolve,
+}
+
+impl std::fmt::Display for WipeStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Fills the cube and empties it again, over and over, with a new direction every time and
+/// taking turns at each style unless given one
+pub struct Wipe {
+    rng: StdRng,
+    style: Option<WipeStyle>,
+    /// Frames shown full or empty between wipes
+    hold: u32,
+    /// Wipes started so far, to take turns at the styles
+    wipes: usize,
+    /// The frame each voxel changes on, in the order of `Coord::all`
+    ranks: Vec<u8>,
+    steps: u8,
+    step: u8,
+    filling: bool,
+    held: u32,
+}
+
+impl Wipe {
+    pub fn new(style: Option<WipeStyle>, hold: u32, seed: Option<u64>) -> Self {
+        let mut wipe = Wipe {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            style,
+            hold,
+            wipes: 0,
+            ranks: Vec::new(),
+            steps: 0,
+            step: 0,
+            filling: true,
+            held: 0,
+        };
+        wipe.start();
+        wipe
+    }
+
+    fn start(&mut self) {
+        let style = self.style.unwrap_or_else(|| {
+            let styles = WipeStyle::value_variants();
+            styles[self.wipes % styles.len()]
+        });
+        self.wipes += 1;
+        self.step = 0;
+
+        match style {
+            WipeStyle::Plane => {
+                let axis = self.rng.gen_range(0..3);
+                let reverse = self.rng.gen_bool(0.5);
+                self.ranks = Coord::all()
+                    .map(|c| {
+                        let along = [c.x, c.y, c.z][axis];
+                        if reverse {
+                            7 - along
+                        } else {
+                            along
+                        }
+                    })
+                    .collect();
+                self.steps = 8;
+            }
+            WipeStyle::Corner => {
+                let corner: [u8; 3] = core::array::from_fn(|_| if self.rng.gen() { 7 } else { 0 });
+                self.ranks = Coord::all()
+                    .map(|c| {
+                        c.x.abs_diff(corner[0]) + c.y.abs_diff(corner[1]) + c.z.abs_diff(corner[2])
+                    })
+                    .collect();
+                self.steps = 22;
+            }
+            WipeStyle::Dissolve => {
+                let mut order: Vec<usize> = (0..512).collect();
+                order.shuffle(&mut self.rng);
+                self.ranks = vec![0; 512];
+                for (position, &voxel) in order.iter().enumerate() {
+                    self.ranks[voxel] = (position / DISSOLVE_RATE) as u8;
+                }
+                self.steps = (512 / DISSOLVE_RATE) as u8;
+            }
+        }
+    }
+
+    fn render(&self) -> Frame {
+        let mut frame = [[0u8; 8]; 8];
+        for (c, &rank) in Coord::all().zip(&self.ranks) {
+            // Filling lights what has been reached, emptying what hasn't
+            if (rank < self.step) == self.filling {
+                c.set(&mut frame);
+            }
+        }
+        frame
+    }
+}
+
+impl Iterator for Wipe {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.held > 0 {
+            self.held -= 1;
+            // Between wipes, so everything is as the last one left it
+            let level = if self.filling { 0 } else { 0xFF };
+            return Some([[level; 8]; 8]);
+        }
+
+        self.step += 1;
+        let frame = self.render();
+        if self.step == self.steps {
+            self.filling = !self.filling;
+            self.held = self.hold;
+            self.start();
+        }
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxels::Voxels;
+
+    #[test]
+    fn wipes_fill_then_empty_in_turn() {
+        let mut wipe = Wipe::new(None, 2, Some(3));
+        let counts: Vec<u32> = wipe.by_ref().map(|f| Voxels(f).count()).take(8).collect();
+        // A plane at a time, then held full
+        assert_eq!(counts, [64, 128, 192, 256, 320, 384, 448, 512]);
+        assert_eq!(Voxels(wipe.next().unwrap()).count(), 512);
+        assert_eq!(Voxels(wipe.next().unwrap()).count(), 512);
+
+        // Emptying from a corner takes a frame per step across the cube
+        let corner: Vec<u32> = wipe.by_ref().map(|f| Voxels(f).count()).take(22).collect();
+        assert_eq!(corner[0], 511);
+        assert!(corner.windows(2).all(|w| w[0] > w[1]));
+        assert_eq!(corner[21], 0);
+
+        wipe.nth(1);
+        let dissolve: Vec<u32> = wipe.take(32).map(|f| Voxels(f).count()).collect();
+        assert!(dissolve
+            .iter()
+            .zip(1..)
+            .all(|(&n, i)| n as usize == i * DISSOLVE_RATE));
+    }
+}
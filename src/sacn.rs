@@ -0,0 +1,194 @@
+//! E1.31, better known as sACN or Streaming ACN: DMX over UDP as xLights, Jinx! and most other
+//! lighting software send it. Each universe is multicast to its own group, and can also be sent
+//! straight to the cube.
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use crate::dmx::{Dmx, DmxProtocol, UNIVERSE_SIZE};
+
+pub const PORT: u16 = 5568;
+
+/// Starts the root layer of every ACN packet
+const PACKET_IDENTIFIER: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_DATA: u32 = 0x0000_0004;
+const VECTOR_FRAMING_DATA: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+/// Set in the framing options when the data is for a visualiser rather than the lights
+const PREVIEW_DATA: u8 = 0x80;
+/// Set in the framing options when the sender is done with the universe
+const STREAM_TERMINATED: u8 = 0x40;
+/// Where the DMX start code is, the values follow
+const START_CODE: usize = 125;
+
+/// The multicast group a universe is sent to
+pub fn multicast_group(universe: u16) -> Ipv4Addr {
+    let [high, low] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, high, low)
+}
+
+/// Join the multicast group of each of `universes` on `socket`, which only warns on failure
+/// since unicast still works without
+pub fn join(socket: &UdpSocket, universes: &[u16]) {
+    for &universe in universes {
+        let group = multicast_group(universe);
+        if let Err(e) = socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED) {
+            eprintln!(
+                "Could not join {group} for universe {universe}, only unicast will reach it: {e}"
+            );
+        }
+    }
+}
+
+/// E1.31 data packets. Anything else, and preview data, is ignored. A terminated stream blanks
+/// its universe.
+pub struct Sacn;
+
+impl DmxProtocol for Sacn {
+    fn decode<'a>(&mut self, datagram: &'a [u8], _: SocketAddr, _: &UdpSocket) -> Option<Dmx<'a>> {
+        let vector = |at: usize| {
+            datagram
+                .get(at..at + 4)
+                .map(|bytes| u32::from_be_bytes(bytes.try_into().expect("4 bytes")))
+        };
+        if datagram.get(4..16)? != PACKET_IDENTIFIER
+            || vector(18)? != VECTOR_ROOT_DATA
+            || vector(40)? != VECTOR_FRAMING_DATA
+            || *datagram.get(117)? != VECTOR_DMP_SET_PROPERTY
+        {
+            return None;
+        }
+
+        let options = datagram[112];
+        if options & PREVIEW_DATA != 0 {
+            return None;
+        }
+        let universe = u16::from_be_bytes([datagram[113], datagram[114]]);
+        let sequence = Some(datagram[111]);
+        if options & STREAM_TERMINATED != 0 {
+            return Some(Dmx {
+                universe,
+                sequence,
+                channels: &[],
+            });
+        }
+        // Anything but a start code of 0 is something other than levels, such as RDM
+        if *datagram.get(START_CODE)? != 0 {
+            return None;
+        }
+        let count = usize::from(u16::from_be_bytes([datagram[123], datagram[124]]));
+        let end = (START_CODE + count).min(datagram.len());
+        let channels = &datagram[START_CODE + 1..end];
+        Some(Dmx {
+            universe,
+            sequence,
+            channels: &channels[..channels.len().min(UNIVERSE_SIZE)],
+        })
+    }
+}
+
+/// A data packet as E1.31 lays it out, for tests
+#[cfg(test)]
+pub(crate) fn data_packet(universe: u16, sequence: u8, options: u8, channels: &[u8]) -> Vec<u8> {
+    let flags_length = |length: usize| (0x7000 | length as u16).to_be_bytes();
+    let total = START_CODE + 1 + channels.len();
+
+    let mut packet = vec![0x00, 0x10, 0x00, 0x00];
+    packet.extend(PACKET_IDENTIFIER);
+    packet.extend(flags_length(total - 16));
+    packet.extend(VECTOR_ROOT_DATA.to_be_bytes());
+    packet.extend([0xCC; 16]);
+    packet.extend(flags_length(total - 38));
+    packet.extend(VECTOR_FRAMING_DATA.to_be_bytes());
+    let mut name = [0u8; 64];
+    name[..4].copy_from_slice(b"test");
+    packet.extend(name);
+    packet.extend([100, 0, 0, sequence, options]);
+    packet.extend(universe.to_be_bytes());
+    packet.extend(flags_length(total - 115));
+    packet.extend([VECTOR_DMP_SET_PROPERTY, 0xA1, 0, 0, 0, 1]);
+    packet.extend((channels.len() as u16 + 1).to_be_bytes());
+    packet.push(0);
+    packet.extend(channels);
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::{
+        dmx::{DmxMap, DmxReceiver},
+        gray::MAX_LEVEL,
+    };
+
+    #[test]
+    fn universes_have_their_own_groups() {
+        assert_eq!(multicast_group(1), Ipv4Addr::new(239, 255, 0, 1));
+        assert_eq!(multicast_group(300), Ipv4Addr::new(239, 255, 1, 44));
+    }
+
+    #[test]
+    fn data_packets_decode() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let peer = socket.local_addr().unwrap();
+        let packet = data_packet(7, 3, 0, &[1, 2, 3]);
+        assert_eq!(
+            Sacn.decode(&packet, peer, &socket),
+            Some(Dmx {
+                universe: 7,
+                sequence: Some(3),
+                channels: &[1, 2, 3]
+            })
+        );
+
+        let terminated = data_packet(7, 4, STREAM_TERMINATED, &[1, 2, 3]);
+        assert_eq!(
+            Sacn.decode(&terminated, peer, &socket).unwrap().channels,
+            []
+        );
+        assert_eq!(
+            Sacn.decode(&data_packet(7, 5, PREVIEW_DATA, &[1]), peer, &socket),
+            None
+        );
+        assert_eq!(Sacn.decode(&packet[..100], peer, &socket), None);
+    }
+
+    #[test]
+    fn stale_packets_are_dropped() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let mut receiver = DmxReceiver::spawn(socket, DmxMap::per_layer(1), Sacn);
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let wait_for = |receiver: &mut DmxReceiver, z: usize, level: u8| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                let gray = receiver.next().unwrap();
+                if gray[z][0][0] == level {
+                    return gray;
+                }
+                assert!(Instant::now() < deadline, "level {level} never arrived");
+                thread::yield_now();
+            }
+        };
+        sender
+            .send_to(&data_packet(1, 10, 0, &[255]), addr)
+            .unwrap();
+        wait_for(&mut receiver, 0, MAX_LEVEL);
+
+        // Out of order for the bottom layer's universe, which the next layer's doesn't share
+        sender.send_to(&data_packet(1, 9, 0, &[0]), addr).unwrap();
+        sender.send_to(&data_packet(2, 0, 0, &[255]), addr).unwrap();
+        assert_eq!(wait_for(&mut receiver, 1, MAX_LEVEL)[0][0][0], MAX_LEVEL);
+
+        // A sender that starts again from far behind is taken to have restarted
+        sender
+            .send_to(&data_packet(1, 200, 0, &[17]), addr)
+            .unwrap();
+        wait_for(&mut receiver, 0, 1);
+    }
+}
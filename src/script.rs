@@ -0,0 +1,976 @@
+//! A small scripting language for animations, so they can be written and tried without
+//! building anything. A script runs once from the top, drawing into a frame and showing it
+//! with `frame()`, and its animation ends when the script does:
+//!
+//! ```text
+//! # A plane climbing the cube, over and over
+//! while 1 {
+//!     for z in 0..8 {
+//!         clear()
+//!         for x in 0..8 { for y in 0..8 { set(x, y, z) } }
+//!         frame()
+//!     }
+//! }
+//! ```
+//!
+//! Every value is a number, with 0 false and anything else true. Variables are made by
+//! assigning to them, `for` counts up to but not including its end, and `#` starts a comment.
+//! Statements go on lines of their own or are separated by `;`. Blocks are in braces, with
+//! `if` and `else`, `while`, `for`, `break` and `continue`. Operators are `+ - * / %`,
+//! `== != < <= > >=`, `&& || !` and parentheses, and the functions:
+//!
+//! - `set(x, y, z)` and `unset(x, y, z)` light or darken a voxel, ignoring any off the cube,
+//!   and `get(x, y, z)` is 1 if it is lit. Coordinates are rounded down.
+//! - `clear()` darkens every voxel and `fill()` lights them all
+//! - `frame()` shows the frame drawn so far, which stays drawn for the next
+//! - `time()` is the seconds of animation shown so far, and `frames()` the frames
+//! - `random()` is between 0 and 1, and `sin`, `cos`, `sqrt`, `abs`, `floor`, `ceil`,
+//!   `round`, `min` and `max` are as usual, with `pi` a variable like any other
+//!
+//! Run with `--watch`, a script starts over from the top each time its file is saved, so it
+//! can be edited while the cube shows it.
+//!
+//! A script that runs [`STEP_LIMIT`] statements, loop tests and `for` steps without showing a
+//! frame is stopped as stuck, and one nested more than [`MAX_DEPTH`] deep doesn't parse.
+
+use std::{
+    collections::HashMap,
+    ffi::{CString, OsString},
+    fmt,
+    fs::{self, File},
+    io::{self, Read},
+    mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Path, PathBuf},
+    ptr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{voxels::Voxels, Frame};
+
+/// Steps a script may take without showing a frame before it is taken to be stuck, each
+/// statement, loop test and `for` step being one
+pub const STEP_LIMIT: u64 = 10_000_000;
+
+/// Nesting of blocks, parentheses and operators allowed, so a script can't overflow the stack
+/// being parsed or run
+pub const MAX_DEPTH: usize = 200;
+
+/// Set once the program is stopping, so scripts busy between frames end too
+static STOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// End every script at its next step once `token` is set, as the program's stop token is by
+/// SIGINT and SIGTERM. Only the first token given counts.
+pub fn stop_with(token: Arc<AtomicBool>) {
+    let _ = STOP.set(token);
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+    /// Not a script, at a line
+    Syntax(usize, String),
+    /// Went wrong while running, at a line
+    Run(usize, String),
+    /// Ran on for [`STEP_LIMIT`] steps without showing a frame
+    Stuck,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(e) => e.fmt(f),
+            ScriptError::Syntax(line, e) | ScriptError::Run(line, e) => {
+                write!(f, "line {line}: {e}")
+            }
+            ScriptError::Stuck => write!(f, "{STEP_LIMIT} steps without a frame"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<io::Error> for ScriptError {
+    fn from(e: io::Error) -> Self {
+        ScriptError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    /// Punctuation and operators
+    Symbol(&'static str),
+    /// The end of a line, which ends a statement like `;`
+    Newline,
+}
+
+const SYMBOLS: [&str; 22] = [
+    "==", "!=", "<=", ">=", "&&", "||", "..", "+", "-", "*", "/", "%", "<", ">", "!", "=", "(",
+    ")", "{", "}", ",", ";",
+];
+
+/// Tokens with the line each is on
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ScriptError> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    let mut line = 1;
+    while let Some(c) = rest.chars().next() {
+        if c == '#' {
+            rest = rest.find('\n').map_or("", |end| &rest[end..]);
+        } else if c == '\n' {
+            tokens.push((Token::Newline, line));
+            line += 1;
+            rest = &rest[1..];
+        } else if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() {
+            let mut end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            // A range such as 0..8 isn't a number with two points
+            if let Some(range) = rest[..end].find("..") {
+                end = range;
+            }
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| ScriptError::Syntax(line, format!("bad number {}", &rest[..end])))?;
+            tokens.push((Token::Number(number), line));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push((Token::Name(rest[..end].to_owned()), line));
+            rest = &rest[end..];
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or_else(|| ScriptError::Syntax(line, format!("unexpected {c:?}")))?;
+            tokens.push((Token::Symbol(symbol), line));
+            rest = &rest[symbol.len()..];
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f64),
+    Variable(String, usize),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>, usize),
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Assign(String, Expr),
+    If(Expr, Vec<Statement>, Vec<Statement>),
+    While(Expr, Vec<Statement>),
+    For(String, Expr, Expr, Vec<Statement>),
+    Break,
+    Continue,
+    Expr(Expr),
+}
+
+/// Binary operators from loosest to tightest binding
+const PRECEDENCE: [&[&str]; 5] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    at: usize,
+    /// How deeply nested what is being parsed is
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at).map(|(token, _)| token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.at)
+            .or(self.tokens.last())
+            .map_or(1, |&(_, line)| line)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, ScriptError> {
+        Err(ScriptError::Syntax(self.line(), message.into()))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.at += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ScriptError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            self.error(format!("expected {symbol}"))
+        }
+    }
+
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ScriptError>,
+    ) -> Result<T, ScriptError> {
+        if self.depth == MAX_DEPTH {
+            return self.error("nested too deeply");
+        }
+        self.depth += 1;
+        let parsed = parse(self);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn skip_newlines(&mut self) {
+        while matches!(self.peek(), Some(Token::Newline | Token::Symbol(";"))) {
+            self.at += 1;
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ScriptError> {
+        match self.next() {
+            Some(Token::Name(name)) => Ok(name),
+            _ => {
+                self.at -= 1;
+                self.error("expected a name")
+            }
+        }
+    }
+
+    /// Statements up to the end of the script or a closing brace
+    fn statements(&mut self) -> Result<Vec<Statement>, ScriptError> {
+        let mut statements = Vec::new();
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                None | Some(Token::Symbol("}")) => return Ok(statements),
+                _ => statements.push(self.nested(Self::statement)?),
+            }
+            if !matches!(
+                self.peek(),
+                None | Some(Token::Newline | Token::Symbol(";" | "}"))
+            ) {
+                return self.error("expected the end of the statement");
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, ScriptError> {
+        self.expect("{")?;
+        let statements = self.statements()?;
+        self.expect("}")?;
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Statement, ScriptError> {
+        let keyword = match self.peek() {
+            Some(Token::Name(name)) => name.clone(),
+            _ => return Ok(Statement::Expr(self.expr()?)),
+        };
+        match keyword.as_str() {
+            "if" => {
+                self.at += 1;
+                let condition = self.expr()?;
+                let then = self.block()?;
+                let otherwise = if self.eat_keyword("else") {
+                    if matches!(self.peek(), Some(Token::Name(name)) if name == "if") {
+                        vec![self.nested(Self::statement)?]
+                    } else {
+                        self.block()?
+                    }
+                } else {
+                    Vec::new()
+                };
+                Ok(Statement::If(condition, then, otherwise))
+            }
+            "while" => {
+                self.at += 1;
+                let condition = self.expr()?;
+                Ok(Statement::While(condition, self.block()?))
+            }
+            "for" => {
+                self.at += 1;
+                let variable = self.name()?;
+                if !self.eat_keyword("in") {
+                    return self.error("expected in");
+                }
+                let start = self.expr()?;
+                self.expect("..")?;
+                let end = self.expr()?;
+                Ok(Statement::For(variable, start, end, self.block()?))
+            }
+            "break" => {
+                self.at += 1;
+                Ok(Statement::Break)
+            }
+            "continue" => {
+                self.at += 1;
+                Ok(Statement::Continue)
+            }
+            _ if matches!(self.tokens.get(self.at + 1), Some((Token::Symbol("="), _))) => {
+                self.at += 2;
+                Ok(Statement::Assign(keyword, self.expr()?))
+            }
+            _ => Ok(Statement::Expr(self.expr()?)),
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Name(name)) if name == keyword);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expr(&mut self) -> Result<Expr, ScriptError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, ScriptError> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        // Each operator nests what came before it a level deeper
+        let depth = self.depth;
+        while let Some(&operator) = operators.iter().find(|op| self.eat(op)) {
+            let right = self.nested(|parser| parser.binary(level + 1))?;
+            left = Expr::Binary(operator, Box::new(left), Box::new(right));
+            self.depth += 1;
+            if self.depth > MAX_DEPTH {
+                return self.error("nested too deeply");
+            }
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ScriptError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.nested(Self::unary)?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.nested(Self::unary)?)));
+        }
+        let line = self.line();
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Name(name)) if self.eat("(") => {
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.nested(Self::expr)?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args, line))
+            }
+            Some(Token::Name(name)) => Ok(Expr::Variable(name, line)),
+            Some(Token::Symbol("(")) => self.nested(|parser| {
+                let inner = parser.expr()?;
+                parser.expect(")")?;
+                Ok(inner)
+            }),
+            _ => {
+                self.at -= 1;
+                self.error("expected a number, name or (")
+            }
+        }
+    }
+}
+
+/// A parsed script, ready to run any number of times
+#[derive(Clone, Debug)]
+pub struct Script {
+    statements: Arc<Vec<Statement>>,
+    /// The file it was loaded from
+    path: Option<PathBuf>,
+}
+
+impl FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            at: 0,
+            depth: 0,
+        };
+        let statements = parser.statements()?;
+        if parser.peek().is_some() {
+            return parser.error("unexpected }");
+        }
+        Ok(Script {
+            statements: Arc::new(statements),
+            path: None,
+        })
+    }
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, ScriptError> {
+        let script: Script = fs::read_to_string(path)?.parse()?;
+        Ok(Script {
+            path: Some(path.to_owned()),
+            ..script
+        })
+    }
+
+    /// Run the script from the top on a thread of its own, a frame at a time as they are
+    /// taken. `frame_time` is what `time()` counts in, and `seed` makes `random()` repeat.
+    /// It ends early once the program is stopping, see [`stop_with`].
+    pub fn run(&self, frame_time: Duration, seed: Option<u64>) -> ScriptFrames {
+        let stop = STOP.get().cloned().unwrap_or_default();
+        self.run_until(frame_time, seed, stop)
+    }
+
+    /// Run the script like [`run`](Self::run), ending it early once `stop` is set
+    pub fn run_until(
+        &self,
+        frame_time: Duration,
+        seed: Option<u64>,
+        stop: Arc<AtomicBool>,
+    ) -> ScriptFrames {
+        let (sender, frames) = mpsc::sync_channel(0);
+        let statements = self.statements.clone();
+        thread::spawn(move || {
+            let mut run = Run {
+                variables: HashMap::from([("pi".to_owned(), std::f64::consts::PI)]),
+                voxels: Voxels::new(),
+                shown: 0,
+                frame_time,
+                rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+                steps: 0,
+                stop,
+                sender,
+            };
+            // A break outside any loop ends the script like reaching the end
+            if let Err(Stop::Error(e)) = run.block(&statements) {
+                eprintln!("Script stopped, {e}");
+            }
+        });
+        ScriptFrames { frames }
+    }
+
+    /// Run the script like [`run`](Self::run), loading it again and starting over whenever
+    /// the file it came from is saved. Once it ends its last frame stays until then, and a
+    /// save that doesn't parse is reported and leaves the running script be.
+    pub fn run_watched(
+        &self,
+        frame_time: Duration,
+        seed: Option<u64>,
+    ) -> io::Result<WatchedFrames> {
+        let path = self
+            .path
+            .clone()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not loaded from a file"))?;
+        Ok(WatchedFrames {
+            watch: Watch::new(&path)?,
+            path,
+            frame_time,
+            seed,
+            frames: self.run(frame_time, seed),
+            last: [[0; 8]; 8],
+        })
+    }
+}
+
+/// The frames of a running script, ending when it does
+pub struct ScriptFrames {
+    frames: mpsc::Receiver<Frame>,
+}
+
+impl Iterator for ScriptFrames {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.frames.recv().ok()
+    }
+}
+
+/// The frames of a script started over each time its file is saved, going on until dropped
+pub struct WatchedFrames {
+    watch: Watch,
+    path: PathBuf,
+    frame_time: Duration,
+    seed: Option<u64>,
+    frames: ScriptFrames,
+    last: Frame,
+}
+
+impl Iterator for WatchedFrames {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        match self.watch.changed() {
+            Ok(false) => {}
+            Ok(true) => match Script::load(&self.path) {
+                // Dropping the old frames stops its thread at its next frame()
+                Ok(script) => {
+                    eprintln!("Reloaded {}", self.path.display());
+                    self.frames = script.run(self.frame_time, self.seed);
+                }
+                Err(e) => eprintln!("Keeping the running script, {}: {e}", self.path.display()),
+            },
+            Err(e) => eprintln!("Can't tell if {} changed: {e}", self.path.display()),
+        }
+        if let Some(frame) = self.frames.next() {
+            self.last = frame;
+        }
+        Some(self.last)
+    }
+}
+
+/// Notices a file being saved, through inotify on its directory so editors that write a new
+/// file and rename it over the old one are seen too
+pub struct Watch {
+    inotify: File,
+    name: OsString,
+}
+
+impl Watch {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?
+            .to_owned();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = CString::new(dir.as_os_str().as_bytes())?;
+
+        // SAFETY: takes no pointers, and a descriptor it returns is ours alone
+        let inotify = unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            OwnedFd::from_raw_fd(fd)
+        };
+        // SAFETY: the descriptor is open and dir is a NUL-terminated string
+        let watched = unsafe {
+            libc::inotify_add_watch(
+                inotify.as_raw_fd(),
+                dir.as_ptr(),
+                libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO,
+            )
+        };
+        if watched < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Watch {
+            inotify: inotify.into(),
+            name,
+        })
+    }
+
+    /// Whether the file was saved since this was last asked, without waiting
+    pub fn changed(&self) -> io::Result<bool> {
+        const HEADER: usize = mem::size_of::<libc::inotify_event>();
+        // Enough for any one event, whose name is at most NAME_MAX bytes
+        let mut buffer = [0u8; 4096];
+        let mut changed = false;
+        loop {
+            let read = match (&self.inotify).read(&mut buffer) {
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(changed),
+                Err(e) => return Err(e),
+            };
+            let mut events = &buffer[..read];
+            while events.len() >= HEADER {
+                // SAFETY: the kernel writes whole events, each a header and then its name
+                let event: libc::inotify_event =
+                    unsafe { ptr::read_unaligned(events.as_ptr().cast()) };
+                let (name, rest) = events[HEADER..].split_at(event.len as usize);
+                // Names are padded with NUL bytes
+                let name = name.split(|&b| b == 0).next().unwrap_or_default();
+                changed |= name == self.name.as_bytes();
+                events = rest;
+            }
+        }
+    }
+}
+
+/// Why a script stopped short of the end of a block
+enum Stop {
+    Error(ScriptError),
+    /// Nobody is taking frames any more, or the program is stopping
+    Taken,
+}
+
+/// What a loop does after its body
+enum Flow {
+    Next,
+    Break,
+    Continue,
+}
+
+fn run_error<T>(line: usize, message: impl Into<String>) -> Result<T, Stop> {
+    Err(Stop::Error(ScriptError::Run(line, message.into())))
+}
+
+struct Run {
+    variables: HashMap<String, f64>,
+    voxels: Voxels,
+    shown: u64,
+    frame_time: Duration,
+    rng: StdRng,
+    /// Steps since the last frame
+    steps: u64,
+    stop: Arc<AtomicBool>,
+    sender: mpsc::SyncSender<Frame>,
+}
+
+impl Run {
+    fn block(&mut self, statements: &[Statement]) -> Result<Flow, Stop> {
+        for statement in statements {
+            match self.statement(statement)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Run a loop body, true if it breaks out of the loop
+    fn body(&mut self, statements: &[Statement]) -> Result<bool, Stop> {
+        Ok(matches!(self.block(statements)?, Flow::Break))
+    }
+
+    /// Count a step towards [`STEP_LIMIT`], and end the script if the program is stopping
+    fn step(&mut self) -> Result<(), Stop> {
+        self.steps += 1;
+        if self.steps > STEP_LIMIT {
+            return Err(Stop::Error(ScriptError::Stuck));
+        }
+        if self.stop.load(Ordering::Relaxed) {
+            return Err(Stop::Taken);
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<Flow, Stop> {
+        self.step()?;
+        match statement {
+            Statement::Assign(name, value) => {
+                let value = self.eval(value)?;
+                self.variables.insert(name.clone(), value);
+            }
+            Statement::If(condition, then, otherwise) => {
+                let branch = if self.eval(condition)? != 0.0 {
+                    then
+                } else {
+                    otherwise
+                };
+                return self.block(branch);
+            }
+            Statement::While(condition, body) => loop {
+                self.step()?;
+                if self.eval(condition)? == 0.0 || self.body(body)? {
+                    break;
+                }
+            },
+            Statement::For(name, start, end, body) => {
+                let (start, end) = (self.eval(start)?, self.eval(end)?);
+                let mut i = start;
+                while i < end {
+                    self.step()?;
+                    self.variables.insert(name.clone(), i);
+                    if self.body(body)? {
+                        break;
+                    }
+                    i += 1.0;
+                }
+            }
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Expr(expr) => {
+                self.eval(expr)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<f64, Stop> {
+        let truth = |b: bool| if b { 1.0 } else { 0.0 };
+        Ok(match expr {
+            Expr::Number(n) => *n,
+            Expr::Variable(name, line) => match self.variables.get(name) {
+                Some(&value) => value,
+                None => return run_error(*line, format!("{name} has no value")),
+            },
+            Expr::Not(inner) => truth(self.eval(inner)? == 0.0),
+            Expr::Negate(inner) => -self.eval(inner)?,
+            Expr::Binary("&&", left, right) => {
+                truth(self.eval(left)? != 0.0 && self.eval(right)? != 0.0)
+            }
+            Expr::Binary("||", left, right) => {
+                truth(self.eval(left)? != 0.0 || self.eval(right)? != 0.0)
+            }
+            Expr::Binary(operator, left, right) => {
+                let (a, b) = (self.eval(left)?, self.eval(right)?);
+                match *operator {
+                    "+" => a + b,
+                    "-" => a - b,
+                    "*" => a * b,
+                    "/" => a / b,
+                    "%" => a.rem_euclid(b),
+                    "==" => truth(a == b),
+                    "!=" => truth(a != b),
+                    "<" => truth(a < b),
+                    "<=" => truth(a <= b),
+                    ">" => truth(a > b),
+                    ">=" => truth(a >= b),
+                    _ => unreachable!("only parsed operators"),
+                }
+            }
+            Expr::Call(name, args, line) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(name, &args, *line)?
+            }
+        })
+    }
+
+    fn call(&mut self, name: &str, args: &[f64], line: usize) -> Result<f64, Stop> {
+        let wanted = match name {
+            "clear" | "fill" | "frame" | "time" | "frames" | "random" => 0,
+            "sin" | "cos" | "sqrt" | "abs" | "floor" | "ceil" | "round" => 1,
+            "min" | "max" => 2,
+            "set" | "unset" | "get" => 3,
+            _ => return run_error(line, format!("no function called {name}")),
+        };
+        if args.len() != wanted {
+            return run_error(
+                line,
+                format!("{name} takes {wanted} arguments, not {}", args.len()),
+            );
+        }
+
+        let voxel = || {
+            let [x, y, z] = [args[0], args[1], args[2]].map(f64::floor);
+            let inside = |v: f64| (0.0..8.0).contains(&v);
+            (inside(x) && inside(y) && inside(z)).then_some((x as u8, y as u8, z as u8))
+        };
+        Ok(match name {
+            "set" | "unset" => {
+                if let Some((x, y, z)) = voxel() {
+                    self.voxels.put(x, y, z, name == "set");
+                }
+                0.0
+            }
+            "get" => voxel().map_or(0.0, |(x, y, z)| self.voxels.get(x, y, z).into()),
+            "clear" => {
+                self.voxels = Voxels::new();
+                0.0
+            }
+            "fill" => {
+                self.voxels = Voxels::full();
+                0.0
+            }
+            "frame" => {
+                self.sender.send(self.voxels.0).map_err(|_| Stop::Taken)?;
+                self.shown += 1;
+                self.steps = 0;
+                0.0
+            }
+            "time" => self.frame_time.as_secs_f64() * self.shown as f64,
+            "frames" => self.shown as f64,
+            "random" => self.rng.gen(),
+            "sin" => args[0].sin(),
+            "cos" => args[0].cos(),
+            "sqrt" => args[0].sqrt(),
+            "abs" => args[0].abs(),
+            "floor" => args[0].floor(),
+            "ceil" => args[0].ceil(),
+            "round" => args[0].round(),
+            "min" => args[0].min(args[1]),
+            "max" => args[0].max(args[1]),
+            _ => unreachable!("checked above"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(source: &str) -> Vec<Voxels> {
+        let script: Script = source.parse().unwrap();
+        script
+            .run(Duration::from_millis(100), Some(1))
+            .take(20)
+            .map(Voxels)
+            .collect()
+    }
+
+    #[test]
+    fn scripts_draw_frames_until_they_end() {
+        let frames = frames(
+            "# A plane climbing the cube
+            for z in 0..8 {
+                clear(); for x in 0..8 { for y in 0..8 { set(x, y, z) } }
+                frame()
+            }",
+        );
+        assert_eq!(frames.len(), 8);
+        assert!(frames
+            .iter()
+            .zip(0..)
+            .all(|(frame, z)| frame.count() == 64 && frame.lit().all(|c| c.z == z)));
+    }
+
+    #[test]
+    fn control_flow_and_arithmetic() {
+        let frames = frames(
+            "n = 0
+            while 1 {
+                n = n + 1
+                if n % 2 == 0 { continue } else if n > 7 { break }
+                set(n, -1 + 1, min(2 * 3, time() * 10))
+                frame()
+            }
+            fill()
+            unset(0, 0, 0); unset(9, 9, 9)
+            if !get(0, 0, 0) && get(1, 1, 1) { frame() }",
+        );
+        let lit: Vec<Vec<_>> = frames
+            .iter()
+            .map(|frame| frame.lit().map(|c| (c.x, c.y, c.z)).collect())
+            .collect();
+        assert_eq!(lit[0], [(1, 0, 0)]);
+        assert_eq!(lit[3], [(1, 0, 0), (3, 0, 1), (5, 0, 2), (7, 0, 3)]);
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[4].count(), 511);
+    }
+
+    #[test]
+    fn mistakes_give_their_line() {
+        let syntax = "set(1, 2, 3)\nfor x 0..8 {}".parse::<Script>().unwrap_err();
+        assert_eq!(syntax.to_string(), "line 2: expected in");
+        assert!("x = (1".parse::<Script>().is_err());
+        assert!("x = 1 2".parse::<Script>().is_err());
+
+        // Errors while running end the frames there
+        assert_eq!(frames("frame()\nframe(nope)\nframe()").len(), 1);
+        assert_eq!(frames("frame()\nx = y").len(), 1);
+        assert_eq!(frames("while 1 { x = 1 }").len(), 0);
+    }
+
+    #[test]
+    fn empty_loops_are_stuck_too() {
+        assert_eq!(frames("while 1 {}").len(), 0);
+        assert_eq!(frames("frame(); for i in 0..1000000000000 {}").len(), 1);
+    }
+
+    #[test]
+    fn scripts_end_once_the_program_is_stopping() {
+        let script: Script = "while 1 { frame() }".parse().unwrap();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut frames = script.run_until(Duration::from_millis(100), None, stop.clone());
+        assert!(frames.next().is_some());
+        stop.store(true, Ordering::Relaxed);
+        // At most the frame already waiting to be taken
+        assert!(frames.count() <= 1);
+
+        let script: Script = "frame(); while 1 {}".parse().unwrap();
+        let stopped = Arc::new(AtomicBool::new(true));
+        assert_eq!(
+            script
+                .run_until(Duration::from_millis(100), None, stopped)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        let deep = 100_000;
+        let sources = [
+            format!("x = {}1{}", "(".repeat(deep), ")".repeat(deep)),
+            format!("x = {}1", "-".repeat(deep)),
+            format!("x = 1{}", " + 1".repeat(deep)),
+            format!("x = {}1{}", "abs(".repeat(deep), ")".repeat(deep)),
+            format!("{}{}", "if 1 {".repeat(deep), "}".repeat(deep)),
+            format!("if 0 {{}}{}", " else if 0 {}".repeat(deep)),
+        ];
+        for source in sources {
+            let e = source.parse::<Script>().unwrap_err();
+            assert!(e.to_string().ends_with("nested too deeply"), "{e}");
+        }
+
+        let fine = MAX_DEPTH / 2 - 5;
+        let x = format!("x = {}1{}", "(".repeat(fine), " + 1)".repeat(fine));
+        assert_eq!(
+            frames(&format!("{x}\nset(x - {fine}, 0, 0)\nframe()")).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn watched_scripts_start_over_when_saved() {
+        let dir = std::env::temp_dir().join(format!("cube-script-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plane.cube");
+        fs::write(&path, "set(0, 0, 0); frame()").unwrap();
+
+        let script = Script::load(&path).unwrap();
+        let mut frames = script
+            .run_watched(Duration::from_millis(100), None)
+            .unwrap();
+        let one = Voxels(frames.next().unwrap());
+        assert_eq!(one.count(), 1);
+        // The last frame stays once the script ends
+        assert_eq!(Voxels(frames.next().unwrap()).count(), 1);
+
+        // Other files and saves that don't parse change nothing
+        fs::write(dir.join("other.cube"), "fill(); frame()").unwrap();
+        fs::write(&path, "fill(").unwrap();
+        assert_eq!(Voxels(frames.next().unwrap()).count(), 1);
+
+        fs::write(&path, "fill(); frame()").unwrap();
+        assert_eq!(Voxels(frames.next().unwrap()).count(), 512);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    fs::OpenOptions,
+    io,
+    mem::size_of,
+    os::fd::AsRawFd,
+    path::Path,
+    ptr::{self, NonNull},
+    sync::atomic::{fence, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::Frame;
+
+/// Layout of the shared file. `sequence` works as a seqlock: odd while the writer is part way
+/// through a frame, bumped to the next even number once the frame is complete.
+#[repr(C)]
+struct Shared {
+    sequence: AtomicU64,
+    /// Non-zero once the writer has published its first frame
+    ready: AtomicU64,
+    /// One Z layer per word, rows in X order as native-endian bytes
+    layers: [AtomicU64; 8],
+}
+
+/// A read-write `MAP_SHARED` mapping of a file holding one `Shared`
+struct Mapping(NonNull<Shared>);
+
+// SAFETY: everything in `Shared` is atomic, so the mapping can be used from any thread
+unsafe impl Send for Mapping {}
+
+impl Mapping {
+    /// Map the file at `path`, creating it or growing it to size first. Either side may start
+    /// first, a fresh file reads as a writer that hasn't published yet.
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if file.metadata()?.len() < size_of::<Shared>() as u64 {
+            file.set_len(size_of::<Shared>() as u64)?;
+        }
+
+        // SAFETY: a fresh shared mapping of an fd we own, checked for failure below
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size_of::<Shared>(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // The mapping outlives the fd, which can be closed when `file` drops
+        Ok(Mapping(
+            NonNull::new(addr as *mut Shared).expect("mmap succeeded"),
+        ))
+    }
+
+    fn shared(&self) -> &Shared {
+        // SAFETY: page aligned, sized for `Shared` and all-zero or written through `Shared`
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly what `open` mapped, nothing borrows it past `self`
+        unsafe {
+            libc::munmap(self.0.as_ptr() as *mut libc::c_void, size_of::<Shared>());
+        }
+    }
+}
+
+/// The producer's side of a shared-memory frame file
+pub struct ShmWriter {
+    mapping: Mapping,
+}
+
+/// Open or create the frame file at `path` for publishing. Meant for other local processes, the
+/// frames reach the cube through `rpi-led-cube shm <path>` without any serialization.
+///
+/// ```no_run
+/// let mut writer = rpi_led_cube::shm::shm_writer("/dev/shm/cube").unwrap();
+/// writer.publish(&[[0xff; 8]; 8]);
+/// ```
+pub fn shm_writer(path: impl AsRef<Path>) -> io::Result<ShmWriter> {
+    Ok(ShmWriter {
+        mapping: Mapping::open(path.as_ref())?,
+    })
+}
+
+impl ShmWriter {
+    /// Make `frame` the newest frame. Only one writer may publish to a file at a time.
+    pub fn publish(&mut self, frame: &Frame) {
+        let shared = self.mapping.shared();
+        shared.sequence.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        for (word, layer) in shared.layers.iter().zip(frame) {
+            word.store(u64::from_ne_bytes(*layer), Ordering::Relaxed);
+        }
+        shared.sequence.fetch_add(1, Ordering::Release);
+        shared.ready.store(1, Ordering::Release);
+    }
+}
+
+/// Shows whatever a local process last published to a shared-memory file, blanking when it
+/// hasn't published anything new for `idle_timeout`
+pub struct ShmSource {
+    mapping: Mapping,
+    idle_timeout: Duration,
+    last_sequence: u64,
+    last_change: Instant,
+    frame: Frame,
+    idle: bool,
+}
+
+impl ShmSource {
+    pub fn open(path: &Path, idle_timeout: Duration) -> io::Result<Self> {
+        Ok(ShmSource {
+            mapping: Mapping::open(path)?,
+            idle_timeout,
+            last_sequence: 0,
+            last_change: Instant::now(),
+            frame: [[0; 8]; 8],
+            idle: false,
+        })
+    }
+
+    /// A consistent copy of the newest frame and its sequence number, `None` if the writer is
+    /// in the middle of publishing
+    fn read(&self) -> Option<(u64, Frame)> {
+        let shared = self.mapping.shared();
+        let before = shared.sequence.load(Ordering::Acquire);
+        if before % 2 == 1 {
+            return None;
+        }
+        let frame = shared
+            .layers
+            .each_ref()
+            .map(|word| word.load(Ordering::Relaxed).to_ne_bytes());
+        fence(Ordering::Acquire);
+        (shared.sequence.load(Ordering::Relaxed) == before).then_some((before, frame))
+    }
+}
+
+impl Iterator for ShmSource {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if self.mapping.shared().ready.load(Ordering::Acquire) != 0 {
+            match self.read() {
+                Some((sequence, frame)) if sequence != self.last_sequence => {
+                    if self.idle {
+                        eprintln!("Shared memory writer is back");
+                        self.idle = false;
+                    }
+                    self.last_sequence = sequence;
+                    self.last_change = Instant::now();
+                    self.frame = frame;
+                }
+                // Torn reads keep the previous frame, the next poll catches up
+                _ => {}
+            }
+        }
+
+        if !self.idle && self.last_change.elapsed() > self.idle_timeout {
+            eprintln!(
+                "Nothing published for {:?}, blanking until the writer is back",
+                self.idle_timeout
+            );
+            self.idle = true;
+            self.frame = [[0; 8]; 8];
+        }
+
+        Some(self.frame)
+    }
+}
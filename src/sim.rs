@@ -0,0 +1,137 @@
+use std::{
+    io::{self, Stdout, Write},
+    thread,
+};
+
+use crate::{
+    display::{FrameSink, SOFT_REFRESH},
+    geometry::Coord,
+    gray::{self, GrayFrame, MAX_LEVEL},
+    Frame,
+};
+
+/// Columns and rows the projection takes up, see `position`
+const WIDTH: usize = 2 * 7 + 7 + 1;
+const HEIGHT: usize = 7 + 7 + 1;
+
+/// Characters for a lit voxel, dimmest first, an unlit one being `UNLIT`
+const SHADES: [char; 4] = ['░', '▒', '▓', '█'];
+const UNLIT: char = '·';
+
+/// Draws the cube in the terminal instead of on the GPIO, redrawing in place whenever the frame
+/// changes
+pub struct TerminalSink {
+    out: Stdout,
+    shown: Option<GrayFrame>,
+}
+
+impl TerminalSink {
+    pub fn new() -> Self {
+        TerminalSink {
+            out: io::stdout(),
+            shown: None,
+        }
+    }
+}
+
+impl Default for TerminalSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TerminalSink {
+    fn drop(&mut self) {
+        // Show the cursor again, below the drawing
+        let _ = write!(self.out, "\x1b[?25h");
+        let _ = self.out.flush();
+    }
+}
+
+impl FrameSink for TerminalSink {
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        self.write_gray_frame(&gray::from_frame(&frame))
+    }
+
+    fn write_gray_frame(&mut self, gray: &GrayFrame) -> io::Result<()> {
+        if self.shown.as_ref() != Some(gray) {
+            let mut out = self.out.lock();
+            if self.shown.is_none() {
+                // Clear once and hide the cursor, after that every drawing overwrites the last
+                write!(out, "\x1b[2J\x1b[?25l")?;
+            }
+            write!(out, "\x1b[H{}", render(gray))?;
+            out.flush()?;
+            self.shown = Some(*gray);
+        }
+        thread::sleep(SOFT_REFRESH);
+        Ok(())
+    }
+}
+
+/// Where a voxel lands in an oblique view of the Y = 0 face, X running left to right and Z
+/// upwards, with layers further back along Y shifted up and to the right
+fn position(c: Coord) -> (usize, usize) {
+    let (x, y, z) = (usize::from(c.x), usize::from(c.y), usize::from(c.z));
+    (2 * x + y, (7 - z) + (7 - y))
+}
+
+/// The projection as lines of text. Lit voxels show through unlit ones in front of them,
+/// otherwise nearer voxels cover those behind.
+pub fn render(gray: &GrayFrame) -> String {
+    let mut canvas = [[' '; WIDTH]; HEIGHT];
+
+    // Back to front, so nearer voxels are drawn last
+    for c in (0..8)
+        .rev()
+        .flat_map(|y| (0..8).flat_map(move |z| (0..8).map(move |x| Coord::new(x, y, z))))
+    {
+        let (col, row) = position(c);
+        let cell = &mut canvas[row][col];
+        match gray::get(gray, c) {
+            0 if SHADES.contains(cell) => {}
+            0 => *cell = UNLIT,
+            level => {
+                let shade = usize::from(level - 1) * SHADES.len() / usize::from(MAX_LEVEL);
+                *cell = SHADES[shade];
+            }
+        }
+    }
+
+    canvas
+        .iter()
+        .map(|row| row.iter().collect::<String>().trim_end().to_owned() + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_cube_is_all_unlit() {
+        let drawing = render(&[[[0; 8]; 8]; 8]);
+        assert_eq!(drawing.lines().count(), HEIGHT);
+        assert!(drawing.contains(UNLIT));
+        assert!(!drawing.contains(SHADES));
+    }
+
+    #[test]
+    fn front_bottom_left_voxel_is_bottom_left() {
+        let mut gray = [[[0; 8]; 8]; 8];
+        gray::set(&mut gray, Coord::new(0, 0, 0), MAX_LEVEL);
+        let drawing = render(&gray);
+        assert!(drawing.lines().last().unwrap().starts_with('█'));
+        assert_eq!(drawing.matches('█').count(), 1);
+    }
+
+    #[test]
+    fn lit_voxels_show_through_unlit_ones() {
+        let mut gray = [[[0; 8]; 8]; 8];
+        // Lands on the same cell as (1, 0, 2), behind it
+        let back = Coord::new(0, 2, 0);
+        assert_eq!(position(back), position(Coord::new(1, 0, 2)));
+        gray::set(&mut gray, back, 1);
+        assert_eq!(render(&gray).matches('░').count(), 1);
+    }
+}
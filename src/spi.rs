@@ -0,0 +1,111 @@
+//! Shifting rows in over the Pi's hardware SPI rather than bit-banging the par lines. The eight
+//! 74HC595s are chained instead of fed in parallel: MOSI (GPIO 10) into the serial input of the
+//! register par_1 fed, its QH' into the next register's input and so on up to par_8's, with
+//! SCLK (GPIO 11) on every SRCLK. A layer is then one 8 byte write at MHz rates, and the rest
+//! of the signals stay on the GPIO they were. Cubes of other sizes chain every par line's
+//! registers the same way, par_1's first.
+
+use std::io;
+
+use rppal::{
+    gpio::OutputPin,
+    spi::{self, Bus, Mode, SlaveSelect, Spi},
+};
+
+use crate::{
+    cube::{CubeDriver, DriverConfig, RowShifter},
+    display::PipelineError,
+    geometry::CubeGeometry,
+    timing::Delay,
+};
+
+/// Well within what a 74HC595 on 3.3 V shifts at
+const CLOCK_SPEED: u32 = 4_000_000;
+
+/// The cube driver with rows going out over SPI
+pub type SpiCubeDriver = CubeDriver<OutputPin, SpiRows>;
+
+impl SpiCubeDriver {
+    /// Claims SPI0, whose chip select the registers don't use, so par_rclk can stay on CE0
+    /// (GPIO 8) as wired: claiming it as an output takes it back from SPI.
+    pub fn open(config: &DriverConfig) -> Result<Self, PipelineError> {
+        let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, CLOCK_SPEED, Mode::Mode0)
+            .map_err(PipelineError::SpiInit)?;
+        CubeDriver::try_with_rows(config, |_, geometry, _| Ok(SpiRows { spi, geometry }))
+            .map_err(PipelineError::GpioInit)
+    }
+}
+
+/// Rows sent down the register chain over SPI
+pub struct SpiRows {
+    spi: Spi,
+    geometry: CubeGeometry,
+}
+
+impl RowShifter for SpiRows {
+    fn shift(&mut self, rows: &[u16], _: &Delay) -> io::Result<()> {
+        self.spi
+            .write(&chain_bytes(&self.geometry, rows))
+            .map_err(spi_error)?;
+        Ok(())
+    }
+}
+
+/// What to send for a layer so every register ends up as it would fed in parallel: on an 8³
+/// cube the register on par_k holding bit k-1 of every row, the first row furthest along. The
+/// first bit sent travels furthest down the chain, so par_8's registers come first, and each
+/// line's share is padded out to whole registers ahead of the bits it would have shifted in.
+/// SPI sends each byte from its most significant bit.
+fn chain_bytes(geometry: &CubeGeometry, rows: &[u16]) -> Vec<u8> {
+    let clocks = geometry.clocks_per_layer();
+    let padding = geometry.chain_length() * 8 - clocks;
+    let bits: Vec<bool> = (0..geometry.par_lines())
+        .rev()
+        .flat_map(|line| {
+            let share = (0..clocks).map(move |clock| geometry.par_bit(rows, line, clock));
+            std::iter::repeat_n(false, padding).chain(share)
+        })
+        .collect();
+    bits.chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+        .collect()
+}
+
+fn spi_error(e: spi::Error) -> io::Error {
+    match e {
+        spi::Error::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_land_where_the_par_lines_put_them() {
+        let eight = CubeGeometry::default();
+        let mut rows = [0; 8];
+        // The first row's Y=0, so the most significant bit of par_1's byte, sent last
+        rows[0] = 1;
+        // The last row's Y=7, so the least significant bit of par_8's byte, sent first
+        rows[7] = 1 << 7;
+        rows[2] = 0b10;
+        assert_eq!(
+            chain_bytes(&eight, &rows),
+            [1, 0, 0, 0, 0, 0, 1 << 5, 1 << 7]
+        );
+        assert_eq!(chain_bytes(&eight, &[0xFF; 8]), [0xFF; 8]);
+    }
+
+    #[test]
+    fn small_cubes_pad_each_register() {
+        let four = CubeGeometry::new(4).unwrap();
+        // par_1 carries Y=0 of every row, its four bits landing at the register's near end
+        assert_eq!(chain_bytes(&four, &[1; 4]), [0, 0, 0, 0b1111]);
+        assert_eq!(
+            chain_bytes(&CubeGeometry::new(16).unwrap(), &[0xFFFF; 16]),
+            [0xFF; 32]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Running as a systemd service with `Type=notify`: telling systemd once the cube is up, and
+//! petting its watchdog from the display thread so a wedged refresh loop gets the service
+//! restarted. Everything here does nothing when systemd didn't ask for it.
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{display::FrameSink, gray::GrayFrame, Frame};
+
+/// Starts a line on stderr that journald should file as an error rather than at the unit's
+/// default priority
+pub const ERROR: &str = "<3>";
+
+/// Send `state`, such as `READY=1`, to the socket systemd gave us. False when there is none,
+/// because the process isn't a notify service.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_to(&socket, state).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// Socket paths starting with `@` are in the abstract namespace
+fn notify_to(socket: &OsStr, state: &str) -> io::Result<()> {
+    let address = match socket.as_bytes() {
+        [b'@', name @ ..] => SocketAddr::from_abstract_name(name)?,
+        path => SocketAddr::from_pathname(OsStr::from_bytes(path))?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// How often to pet the watchdog, half the timeout systemd set so a slow refresh or two can't
+/// trip it. `None` unless the watchdog is on and meant for this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = env::var_os("WATCHDOG_PID") {
+        if pid.to_str()?.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// A sink that pets the watchdog every `interval` while it keeps writing, so the pets stop as
+/// soon as the display thread does
+pub struct Watchdog<S> {
+    sink: S,
+    /// Where to send pets and how often, when there's a watchdog at all
+    pet: Option<(OsString, Duration)>,
+    last: Instant,
+}
+
+impl<S: FrameSink> Watchdog<S> {
+    /// Pet systemd's watchdog, if it has one for us, every `interval`
+    pub fn new(sink: S, interval: Option<Duration>) -> Self {
+        let pet = interval.zip(env::var_os("NOTIFY_SOCKET"));
+        Self::with(sink, pet.map(|(interval, socket)| (socket, interval)))
+    }
+
+    fn with(sink: S, pet: Option<(OsString, Duration)>) -> Self {
+        Watchdog {
+            sink,
+            pet,
+            last: Instant::now(),
+        }
+    }
+
+    fn pet(&mut self) {
+        let Some((socket, interval)) = &self.pet else {
+            return;
+        };
+        if self.last.elapsed() >= *interval {
+            if let Err(e) = notify_to(socket, "WATCHDOG=1") {
+                eprintln!("Could not pet the watchdog: {e}");
+            }
+            self.last = Instant::now();
+        }
+    }
+}
+
+impl<S: FrameSink> FrameSink for Watchdog<S> {
+    fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
+        self.sink.write_frame(frame)?;
+        self.pet();
+        Ok(())
+    }
+
+    fn write_gray_frame(&mut self, gray: &GrayFrame) -> io::Result<()> {
+        self.sink.write_gray_frame(gray)?;
+        self.pet();
+        Ok(())
+    }
+
+    fn set_brightness(&mut self, level: u8) {
+        self.sink.set_brightness(level)
+    }
+
+    fn refresh_period(&self) -> Option<Duration> {
+        self.sink.refresh_period()
+    }
+
+    fn at_frame_start(&self) -> bool {
+        self.sink.at_frame_start()
+    }
+
+    fn rest(&mut self) -> io::Result<()> {
+        self.sink.rest()
+    }
+}
+
+/// A unit file running `exec_start` as a notify service with the watchdog on
+pub fn unit(exec_start: &str) -> String {
+    format!(
+        "[Unit]
+Description=8x8x8 LED cube
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+Type=notify
+ExecStart={exec_start}
+# The cube blanks itself before exiting on SIGTERM, give it time to
+TimeoutStopSec=5
+WatchdogSec=10
+Restart=on-failure
+RestartSec=2
+
+[Install]
+WantedBy=multi-user.target
+"
+    )
+}
+
+/// Quote a word for `ExecStart=` if it needs it
+pub fn quote(word: &str) -> String {
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || "\"'\\$%;".contains(c)) {
+        return word.to_owned();
+    }
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        match c {
+            '"' | '\\' => quoted.extend(['\\', c]),
+            '$' => quoted.push_str("$$"),
+            '%' => quoted.push_str("%%"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::*;
+    use crate::display::NullSink;
+
+    #[test]
+    fn notifications_reach_path_and_abstract_sockets() {
+        let dir = env::temp_dir().join(format!("cube-notify-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify");
+        let _ = fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        notify_to(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0; 64];
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let name = format!("cube-notify-{}", process::id());
+        let address = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let systemd = UnixDatagram::bind_addr(&address).unwrap();
+        notify_to(OsStr::new(&format!("@{name}")), "STOPPING=1").unwrap();
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STOPPING=1");
+    }
+
+    #[test]
+    fn writes_pet_the_watchdog_at_most_once_an_interval() {
+        let name = format!("cube-watchdog-{}", process::id());
+        let address = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let systemd = UnixDatagram::bind_addr(&address).unwrap();
+        systemd.set_nonblocking(true).unwrap();
+
+        let pet = Some((OsString::from(format!("@{name}")), Duration::ZERO));
+        let mut sink = Watchdog::with(NullSink, pet);
+        sink.write_frame([[0; 8]; 8]).unwrap();
+        let mut buf = [0; 64];
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+
+        sink.pet = Some((
+            OsString::from(format!("@{name}")),
+            Duration::from_secs(3600),
+        ));
+        sink.write_frame([[0; 8]; 8]).unwrap();
+        assert!(systemd.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn units_quote_what_systemd_would_split() {
+        assert_eq!(quote("rain"), "rain");
+        assert_eq!(quote("my file"), "\"my file\"");
+        assert_eq!(quote("100%"), "\"100%%\"");
+        let unit = unit("/usr/bin/rpi-led-cube --daemon rain");
+        assert!(unit.contains("\nType=notify\n"));
+        assert!(unit.contains("\nExecStart=/usr/bin/rpi-led-cube --daemon rain\n"));
+    }
+}
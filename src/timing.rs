@@ -0,0 +1,103 @@
+//! The short waits bit-banging needs between GPIO writes. Linux wakes a thread from even a 5 µs
+//! `thread::sleep` something like 60-100 µs later on a Pi, which made every wait the driver
+//! meant to be a few microseconds ten times longer and capped the refresh rate. Precise timing
+//! spins instead, sleeping first only when a wait is long enough that the wake-up lateness
+//! measured at startup still leaves time to spin out the rest.
+
+use std::{
+    hint, thread,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+
+/// Waits shorter than this always spin, whatever calibration found
+const SPIN_BELOW: Duration = Duration::from_micros(50);
+/// Short sleeps taken to measure how late the scheduler wakes us
+const CALIBRATION_SLEEPS: usize = 50;
+
+/// How the driver waits between GPIO writes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Timing {
+    /// Sleep, leaving the CPU to other work at the cost of every wait overrunning
+    Relaxed,
+    /// Spin for waits too short for sleeping to get right, keeping a core busy while refreshing
+    #[default]
+    Precise,
+}
+
+impl std::fmt::Display for Timing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Waits as a [`Timing`] says
+#[derive(Copy, Clone, Debug)]
+pub struct Delay {
+    /// How late a sleep may wake, beyond which it has to spin. `None` only ever sleeps.
+    lateness: Option<Duration>,
+}
+
+impl Delay {
+    /// Precise timing calibrates, which takes a few milliseconds of sleeping
+    pub fn new(timing: Timing) -> Self {
+        Delay {
+            lateness: match timing {
+                Timing::Relaxed => None,
+                Timing::Precise => Some(calibrate().max(SPIN_BELOW)),
+            },
+        }
+    }
+
+    pub fn wait(&self, duration: Duration) {
+        let Some(lateness) = self.lateness else {
+            thread::sleep(duration);
+            return;
+        };
+        let deadline = Instant::now() + duration;
+        if duration > lateness {
+            thread::sleep(duration - lateness);
+        }
+        while Instant::now() < deadline {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// How late a short sleep wakes, going by the slowest of all but the worst tenth of a batch so
+/// that a stray preemption doesn't make every wait spin
+fn calibrate() -> Duration {
+    let mut late: Vec<Duration> = (0..CALIBRATION_SLEEPS)
+        .map(|_| {
+            let start = Instant::now();
+            thread::sleep(Duration::from_micros(1));
+            start.elapsed()
+        })
+        .collect();
+    late.sort_unstable();
+    late[CALIBRATION_SLEEPS * 9 / 10]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precise_waits_end_on_time() {
+        let delay = Delay::new(Timing::Precise);
+        for wait in [Duration::from_micros(5), Duration::from_millis(2)] {
+            let start = Instant::now();
+            delay.wait(wait);
+            let took = start.elapsed();
+            assert!(took >= wait, "{wait:?} took only {took:?}");
+        }
+
+        let lateness = delay.lateness.unwrap();
+        assert!(lateness >= SPIN_BELOW);
+        assert!(Delay::new(Timing::Relaxed).lateness.is_none());
+    }
+}
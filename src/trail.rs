@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use clap::ValueEnum;
+
+use crate::{
+    geometry::Coord,
+    gray::{self, GrayFrame, MAX_LEVEL},
+};
+
+/// How quickly a trail dims from its head to its tip
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Decay {
+    /// Equal steps down to the last position
+    #[default]
+    Linear,
+    /// Dim by the same ratio at each step, down to the faintest level at the last position
+    Exponential,
+}
+
+impl std::fmt::Display for Decay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// The last few positions of a moving point, rendered dimmer the older they are
+pub struct Trail {
+    positions: VecDeque<Coord>,
+    length: usize,
+    decay: Decay,
+}
+
+impl Trail {
+    pub fn new(length: usize, decay: Decay) -> Self {
+        let length = length.max(1);
+        Trail {
+            positions: VecDeque::with_capacity(length),
+            length,
+            decay,
+        }
+    }
+
+    /// Move the head, forgetting the oldest position once the trail is full
+    pub fn push(&mut self, head: Coord) {
+        if self.positions.len() == self.length {
+            self.positions.pop_back();
+        }
+        self.positions.push_front(head);
+    }
+
+    /// Brightness of the position `age` steps behind the head
+    fn level(&self, age: usize) -> u8 {
+        match self.decay {
+            Decay::Linear => {
+                (usize::from(MAX_LEVEL) * (self.length - age)).div_ceil(self.length) as u8
+            }
+            Decay::Exponential if self.length == 1 => MAX_LEVEL,
+            Decay::Exponential => {
+                let tip = age as f32 / (self.length - 1) as f32;
+                (f32::from(MAX_LEVEL).powf(1.0 - tip).round() as u8).max(1)
+            }
+        }
+    }
+
+    /// Draw the trail on top of `gray`
+    pub fn render(&self, gray: &mut GrayFrame) {
+        for (age, &c) in self.positions.iter().enumerate() {
+            gray::brighten(gray, c, self.level(age));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(length: usize, decay: Decay) -> Vec<u8> {
+        let mut trail = Trail::new(length, decay);
+        let path: Vec<Coord> = Coord::all().take(length + 3).collect();
+        path.iter().for_each(|&c| trail.push(c));
+        let mut gray = [[[0; 8]; 8]; 8];
+        trail.render(&mut gray);
+        path.iter().rev().map(|&c| gray::get(&gray, c)).collect()
+    }
+
+    #[test]
+    fn a_trail_of_one_lights_only_the_head() {
+        for decay in [Decay::Linear, Decay::Exponential] {
+            assert_eq!(levels(1, decay), [MAX_LEVEL, 0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn trails_dim_to_their_tip_and_no_further() {
+        for decay in [Decay::Linear, Decay::Exponential] {
+            for length in 2..=40 {
+                let levels = levels(length, decay);
+                let (trail, forgotten) = levels.split_at(length);
+                assert_eq!(trail[0], MAX_LEVEL, "{decay} {length}");
+                assert!(trail.iter().all(|&level| level > 0), "{decay} {length}");
+                assert!(trail.windows(2).all(|w| w[0] >= w[1]), "{decay} {length}");
+                assert_eq!(forgotten, [0, 0, 0], "{decay} {length}");
+            }
+        }
+    }
+
+    #[test]
+    fn exponential_trails_fade_faster_and_reach_the_faintest_level() {
+        for length in 2..=40 {
+            let linear = levels(length, Decay::Linear);
+            let exponential = levels(length, Decay::Exponential);
+            assert_eq!(exponential[length - 1], 1, "{length}");
+            assert!(
+                exponential.iter().zip(&linear).all(|(e, l)| e <= l),
+                "{length}: {exponential:?} {linear:?}"
+            );
+        }
+        assert_eq!(levels(5, Decay::Exponential)[..5], [15, 8, 4, 2, 1]);
+    }
+}
@@ -0,0 +1,130 @@
+use clap::ValueEnum;
+use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
+
+use crate::{
+    geometry::Coord,
+    gray::{self, GrayFrame},
+    Frame,
+};
+
+/// How one source of frames gives way to another
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TransitionStyle {
+    /// Cut straight to the new frames
+    #[default]
+    None,
+    /// A plane sweeps along +X, leaving the new frames behind it
+    Wipe,
+    /// Random LEDs switch over until all of them show the new frames
+    Dissolve,
+    /// The old frames scroll out along -X while the new ones scroll in behind them
+    Push,
+}
+
+impl std::fmt::Display for TransitionStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("all values possible")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+/// Composes an outgoing and an incoming frame for a fixed number of frames. Whoever switches
+/// sources keeps pulling from both and hands each pair to `blend` until `finished`, then drops
+/// the outgoing one.
+pub struct Transition {
+    style: TransitionStyle,
+    frames: u32,
+    step: u32,
+    /// Order cells switch over in when dissolving
+    order: Vec<Coord>,
+}
+
+impl Transition {
+    /// Lasts `frames` frames, at least one
+    pub fn new(style: TransitionStyle, frames: u32) -> Self {
+        let mut order = Vec::new();
+        if style == TransitionStyle::Dissolve {
+            order.extend(Coord::all());
+            order.shuffle(&mut SmallRng::from_entropy());
+        }
+        Transition {
+            style,
+            frames: frames.max(1),
+            step: 0,
+            order,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.style == TransitionStyle::None || self.step >= self.frames
+    }
+
+    /// The next frame of the transition, moving one step further from `old` to `new`
+    pub fn blend(&mut self, old: &Frame, new: &Frame) -> Frame {
+        let switched = self.step();
+        self.blend_rows(old, new).unwrap_or_else(|| {
+            let mut frame = *old;
+            for c in &self.order[..switched] {
+                if c.get(new) {
+                    c.set(&mut frame);
+                } else {
+                    c.clear(&mut frame);
+                }
+            }
+            frame
+        })
+    }
+
+    /// `blend` for frames with intensity
+    pub fn blend_gray(&mut self, old: &GrayFrame, new: &GrayFrame) -> GrayFrame {
+        let switched = self.step();
+        self.blend_rows(old, new).unwrap_or_else(|| {
+            let mut gray = *old;
+            for &c in &self.order[..switched] {
+                gray::set(&mut gray, c, gray::get(new, c));
+            }
+            gray
+        })
+    }
+
+    /// Advance one step, returning how many cells a dissolve has switched over by now
+    fn step(&mut self) -> usize {
+        self.step = (self.step + 1).min(self.frames);
+        (self.progress() * self.order.len() as f32).round() as usize
+    }
+
+    fn progress(&self) -> f32 {
+        self.step as f32 / self.frames as f32
+    }
+
+    /// The styles that move whole rows, which work the same for either kind of frame. `None`
+    /// for a dissolve, which has to pick out single voxels.
+    fn blend_rows<R: Copy>(&self, old: &[[R; 8]; 8], new: &[[R; 8]; 8]) -> Option<[[R; 8]; 8]> {
+        let progress = self.progress();
+
+        match self.style {
+            TransitionStyle::None => Some(*new),
+            TransitionStyle::Wipe => {
+                let edge = (progress * 8.0).round() as usize;
+                Some(core::array::from_fn(|z| {
+                    core::array::from_fn(|x| if x < edge { new[z][x] } else { old[z][x] })
+                }))
+            }
+            TransitionStyle::Dissolve => None,
+            TransitionStyle::Push => {
+                let shift = (progress * 8.0).round() as usize;
+                Some(core::array::from_fn(|z| {
+                    core::array::from_fn(|x| {
+                        if x + shift < 8 {
+                            old[z][x + shift]
+                        } else {
+                            new[z][x + shift - 8]
+                        }
+                    })
+                }))
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! A [`Frame`] worked on voxel by voxel rather than bit by bit. [`Voxels`] wraps the same storage,
+//! so converting either way is free, and routines can build a frame with whole planes, shifts and
+//! set operations instead of masks.
+
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+use crate::geometry::Coord;
+use crate::Frame;
+
+/// One of the cube's axes, see [`Coord`] for which way each points
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// A frame with methods for reading and drawing voxels by position
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Voxels(pub Frame);
+
+impl Voxels {
+    /// Every voxel off
+    pub fn new() -> Self {
+        Voxels::default()
+    }
+
+    /// Every voxel on
+    pub fn full() -> Self {
+        Voxels([[0xFF; 8]; 8])
+    }
+
+    pub fn get(&self, x: u8, y: u8, z: u8) -> bool {
+        Self::coord(x, y, z).get(&self.0)
+    }
+
+    pub fn set(&mut self, x: u8, y: u8, z: u8) {
+        Self::coord(x, y, z).set(&mut self.0)
+    }
+
+    pub fn clear(&mut self, x: u8, y: u8, z: u8) {
+        Self::coord(x, y, z).clear(&mut self.0)
+    }
+
+    /// Set a voxel on or off
+    pub fn put(&mut self, x: u8, y: u8, z: u8, on: bool) {
+        if on {
+            self.set(x, y, z)
+        } else {
+            self.clear(x, y, z)
+        }
+    }
+
+    fn coord(x: u8, y: u8, z: u8) -> Coord {
+        assert!(
+            x < 8 && y < 8 && z < 8,
+            "({x}, {y}, {z}) is outside the cube"
+        );
+        Coord::new(x, y, z)
+    }
+
+    /// How many voxels are on
+    pub fn count(&self) -> u32 {
+        self.0.iter().flatten().map(|row| row.count_ones()).sum()
+    }
+
+    /// The voxels that are on, in the order of [`Coord::all`]
+    pub fn lit(&self) -> impl Iterator<Item = Coord> + '_ {
+        Coord::all().filter(|c| c.get(&self.0))
+    }
+
+    /// Turn on every voxel whose position along `axis` is `index`
+    pub fn fill_plane(&mut self, axis: Axis, index: u8) {
+        assert!(index < 8, "plane {index} is outside the cube");
+        match axis {
+            Axis::X => self
+                .0
+                .iter_mut()
+                .for_each(|layer| layer[usize::from(index)] = 0xFF),
+            Axis::Y => self
+                .0
+                .iter_mut()
+                .flatten()
+                .for_each(|row| *row |= 1 << index),
+            Axis::Z => self.0[usize::from(index)] = [0xFF; 8],
+        }
+    }
+
+    /// Only the voxels whose position along `axis` is `index`
+    pub fn slice(&self, axis: Axis, index: u8) -> Voxels {
+        let mut plane = Voxels::new();
+        plane.fill_plane(axis, index);
+        *self & plane
+    }
+
+    /// Turn the slice whose position along `axis` is `index` by `angle` radians about the line
+    /// through its centre, anticlockwise as seen from the far end of `axis` as with
+    /// [`Orientation::quarter_turn`](crate::Orientation::quarter_turn), leaving every other
+    /// voxel where it is. Each voxel lands on the nearest one, so quarter turns are exact, and
+    /// in between the corners swing out past the edge and are lost.
+    pub fn turn_slice(&mut self, axis: Axis, index: u8, angle: f32) {
+        let slice = self.slice(axis, index);
+        *self ^= slice;
+
+        let (sin, cos) = angle.sin_cos();
+        // The two axes in the slice, the turn taking the first towards the second
+        let plane = |c: Coord| match axis {
+            Axis::X => (c.y, c.z),
+            Axis::Y => (c.z, c.x),
+            Axis::Z => (c.x, c.y),
+        };
+        for c in slice.lit() {
+            let (u, v) = plane(c);
+            let (u, v) = (f32::from(u) - 3.5, f32::from(v) - 3.5);
+            let voxel = |w: f32| {
+                let w = (w + 3.5).round();
+                (0.0..8.0).contains(&w).then_some(w as u8)
+            };
+            let (Some(u), Some(v)) = (voxel(u * cos - v * sin), voxel(u * sin + v * cos)) else {
+                continue;
+            };
+            match axis {
+                Axis::X => self.set(index, u, v),
+                Axis::Y => self.set(v, index, u),
+                Axis::Z => self.set(u, v, index),
+            }
+        }
+    }
+
+    /// Move everything `n` voxels along `axis`, towards the far end when positive. Voxels pushed
+    /// past the edge are lost and the space left behind is off.
+    pub fn shift(&mut self, axis: Axis, n: i8) {
+        let moved = |i: usize| {
+            let from = i as i32 - i32::from(n);
+            (0..8).contains(&from).then_some(from as usize)
+        };
+        let frame = self.0;
+        match axis {
+            Axis::X => {
+                for (layer, old) in self.0.iter_mut().zip(frame) {
+                    *layer = core::array::from_fn(|x| moved(x).map_or(0, |from| old[from]));
+                }
+            }
+            Axis::Y => {
+                let distance = u32::from(n.unsigned_abs());
+                for row in self.0.iter_mut().flatten() {
+                    *row = if n >= 0 {
+                        row.checked_shl(distance).unwrap_or(0)
+                    } else {
+                        row.checked_shr(distance).unwrap_or(0)
+                    };
+                }
+            }
+            Axis::Z => {
+                self.0 = core::array::from_fn(|z| moved(z).map_or([0; 8], |from| frame[from]))
+            }
+        }
+    }
+}
+
+impl From<Frame> for Voxels {
+    fn from(frame: Frame) -> Self {
+        Voxels(frame)
+    }
+}
+
+impl From<Voxels> for Frame {
+    fn from(voxels: Voxels) -> Self {
+        voxels.0
+    }
+}
+
+/// Combine two frames row by row with `op`
+fn zip_rows(a: Frame, b: Frame, op: impl Fn(u8, u8) -> u8) -> Frame {
+    core::array::from_fn(|z| core::array::from_fn(|x| op(a[z][x], b[z][x])))
+}
+
+/// Voxels on in either frame
+impl BitOr for Voxels {
+    type Output = Voxels;
+
+    fn bitor(self, other: Voxels) -> Voxels {
+        Voxels(zip_rows(self.0, other.0, |a, b| a | b))
+    }
+}
+
+/// Voxels on in both frames
+impl BitAnd for Voxels {
+    type Output = Voxels;
+
+    fn bitand(self, other: Voxels) -> Voxels {
+        Voxels(zip_rows(self.0, other.0, |a, b| a & b))
+    }
+}
+
+/// Voxels on in one frame but not the other
+impl BitXor for Voxels {
+    type Output = Voxels;
+
+    fn bitxor(self, other: Voxels) -> Voxels {
+        Voxels(zip_rows(self.0, other.0, |a, b| a ^ b))
+    }
+}
+
+impl Not for Voxels {
+    type Output = Voxels;
+
+    fn not(self) -> Voxels {
+        Voxels(self.0.map(|layer| layer.map(|row| !row)))
+    }
+}
+
+impl BitOrAssign for Voxels {
+    fn bitor_assign(&mut self, other: Voxels) {
+        *self = *self | other;
+    }
+}
+
+impl BitAndAssign for Voxels {
+    fn bitand_assign(&mut self, other: Voxels) {
+        *self = *self & other;
+    }
+}
+
+impl BitXorAssign for Voxels {
+    fn bitxor_assign(&mut self, other: Voxels) {
+        *self = *self ^ other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::Orientation;
+
+    #[test]
+    fn voxels_land_where_coord_puts_them() {
+        let mut voxels = Voxels::new();
+        voxels.set(1, 2, 3);
+        assert_eq!(voxels.0[3][1], 1 << 2);
+        assert!(voxels.get(1, 2, 3));
+        assert_eq!(voxels.lit().collect::<Vec<_>>(), [Coord::new(1, 2, 3)]);
+
+        voxels.put(1, 2, 3, false);
+        assert_eq!(voxels, Voxels::new());
+    }
+
+    #[test]
+    fn planes_and_shifts() {
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let mut plane = Voxels::new();
+            plane.fill_plane(axis, 2);
+            assert_eq!(plane.count(), 64);
+
+            let mut shifted = plane;
+            shifted.shift(axis, 3);
+            let mut expected = Voxels::new();
+            expected.fill_plane(axis, 5);
+            assert_eq!(shifted, expected, "{axis:?}");
+
+            shifted.shift(axis, -6);
+            assert_eq!(shifted, Voxels::new(), "{axis:?}");
+        }
+
+        let mut corner = Voxels::new();
+        corner.set(7, 7, 7);
+        corner.shift(Axis::Y, -7);
+        assert_eq!(corner.lit().collect::<Vec<_>>(), [Coord::new(7, 0, 7)]);
+    }
+
+    #[test]
+    fn set_operations() {
+        let (mut a, mut b) = (Voxels::new(), Voxels::new());
+        a.fill_plane(Axis::X, 0);
+        b.fill_plane(Axis::Z, 0);
+
+        assert_eq!((a | b).count(), 64 + 64 - 8);
+        assert_eq!((a & b).count(), 8);
+        assert_eq!((a ^ b).count(), 64 + 64 - 16);
+        assert_eq!((!a).count(), 512 - 64);
+
+        let mut c = a;
+        c ^= a;
+        assert_eq!(c, Voxels::new());
+        c |= Voxels::full();
+        c &= b;
+        assert_eq!(c, b);
+    }
+
+    #[test]
+    fn slices_turn_like_the_whole_cube() {
+        let mut rng = SmallRng::seed_from_u64(11);
+        for (axis, i) in [(Axis::X, 0), (Axis::Y, 1), (Axis::Z, 2)] {
+            let voxels = Voxels(rng.gen());
+            let turned = Voxels(Orientation::quarter_turn(i).apply(&voxels.0));
+            for index in 0..8 {
+                let mut slice_turned = voxels;
+                slice_turned.turn_slice(axis, index, FRAC_PI_2);
+                assert_eq!(
+                    slice_turned.slice(axis, index),
+                    turned.slice(axis, index),
+                    "{axis:?} {index}"
+                );
+                assert_eq!(
+                    slice_turned ^ slice_turned.slice(axis, index),
+                    voxels ^ voxels.slice(axis, index)
+                );
+            }
+        }
+
+        // Part way round, the corners are lost past the edge
+        let mut full = Voxels::full();
+        full.turn_slice(Axis::Z, 0, FRAC_PI_4);
+        assert!(full.slice(Axis::Z, 0).count() < 64);
+        assert_eq!(full.slice(Axis::Z, 1).count(), 64);
+    }
+}
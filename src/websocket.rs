@@ -0,0 +1,350 @@
+//! WebSockets (RFC 6455) for the HTTP server's `/ws`. Every frame the cube shows is sent to each
+//! connection as a 64 byte binary message, one byte per row with layers from the bottom, so a
+//! browser can mirror the cube. Messages the other way are shown in place of the program, like
+//! `POST /frame`: 64 byte binary messages, or text messages of 128 hex digits.
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    control::{Command, Control},
+    decoders::{encode_base64, read_base16_frame, read_binary_frame, write_binary_frame},
+    invalid,
+};
+
+/// Appended to a client's key to prove the server speaks WebSocket
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Longest message taken from a client, far more than a frame needs
+const MAX_MESSAGE: u64 = 4096;
+/// How often the frame shown is checked for a change to send on
+const MIRROR_POLL: Duration = Duration::from_millis(20);
+
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// SHA-1 (FIPS 180-4), only ever used here for the handshake
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let next = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, next);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+/// The `Sec-WebSocket-Accept` answering a client's `Sec-WebSocket-Key`
+fn accept_key(key: &str) -> String {
+    encode_base64(&sha1(format!("{}{GUID}", key.trim()).as_bytes()))
+}
+
+/// Read one whole message, unmasked, as its opcode and payload. Fragmented messages aren't
+/// supported, no client sending frames has a reason to split them.
+fn read_message(reader: &mut impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header)?;
+    let (fin, opcode) = (header[0] & 0x80 != 0, header[0] & 0x0F);
+    if !fin || opcode == 0 {
+        return Err(invalid(
+            "WebSocket message",
+            "fragmented messages are not supported",
+        ));
+    }
+
+    let len = match header[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u64::from(u16::from_be_bytes(len))
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE {
+        return Err(invalid("WebSocket message", "too long"));
+    }
+
+    let mut mask = [0u8; 4];
+    if header[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((opcode, payload))
+}
+
+/// Write one unmasked message, as servers send them
+fn write_message(out: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut message = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => message.push(len as u8),
+        len @ 126..=0xFFFF => {
+            message.push(126);
+            message.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            message.push(127);
+            message.extend((len as u64).to_be_bytes());
+        }
+    }
+    message.extend_from_slice(payload);
+    out.write_all(&message)
+}
+
+/// Finish the handshake for a client that asked to upgrade with `key`, then mirror the cube to
+/// it and show what it sends, each on a detached thread of its own so a quiet client never holds
+/// up the exit. Both stop once the connection closes, and the mirror also once the stop token is
+/// set.
+pub fn start(
+    mut stream: TcpStream,
+    key: &str,
+    control: Arc<Control>,
+    stop_token: Arc<AtomicBool>,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    stream.set_read_timeout(None)?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let closed = Arc::new(AtomicBool::new(false));
+
+    let mirror = writer.clone();
+    let mirror_closed = closed.clone();
+    let mirror_control = control.clone();
+    thread::spawn(move || {
+        let mut sent = None;
+        while !mirror_closed.load(Ordering::Relaxed) {
+            if stop_token.load(Ordering::Relaxed) {
+                let mut out = mirror.lock().expect("WebSocket writer poisoned");
+                let _ = write_message(&mut *out, CLOSE, &1001u16.to_be_bytes());
+                let _ = out.shutdown(Shutdown::Both);
+                break;
+            }
+            let frame = mirror_control.shown();
+            if sent != Some(frame) {
+                let mut out = mirror.lock().expect("WebSocket writer poisoned");
+                if write_message(&mut *out, BINARY, &write_binary_frame(&frame)).is_err() {
+                    break;
+                }
+                sent = Some(frame);
+            }
+            thread::sleep(MIRROR_POLL);
+        }
+    });
+
+    thread::spawn(move || {
+        loop {
+            let (opcode, payload) = match read_message(&mut stream) {
+                Ok(message) => message,
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::InvalidData {
+                        eprintln!("Closing WebSocket: {e}");
+                    }
+                    break;
+                }
+            };
+            let frame = match opcode {
+                BINARY => read_binary_frame(&payload),
+                TEXT => std::str::from_utf8(&payload)
+                    .ok()
+                    .and_then(|text| read_base16_frame(text.trim())),
+                PING => {
+                    let mut out = writer.lock().expect("WebSocket writer poisoned");
+                    if write_message(&mut *out, PONG, &payload).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                CLOSE => {
+                    let mut out = writer.lock().expect("WebSocket writer poisoned");
+                    let _ = write_message(&mut *out, CLOSE, &payload);
+                    break;
+                }
+                _ => continue,
+            };
+            match frame {
+                Some(frame) => control.send(Command::Frame(frame)),
+                None => eprintln!("Ignored a WebSocket message that isn't a frame"),
+            }
+        }
+        closed.store(true, Ordering::Relaxed);
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::{Ipv4Addr, TcpListener},
+        time::Instant,
+    };
+
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// A message as a client sends it, masked
+    fn client_message(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut message = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        message.extend(mask);
+        message.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        message
+    }
+
+    #[test]
+    fn handshake_keys() {
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(&sha1(&[b'a'; 1000])),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+        // The example in RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let mut masked = client_message(BINARY, &[1, 2, 3, 4, 5]);
+        assert_eq!(
+            read_message(&mut masked.as_slice()).unwrap(),
+            (BINARY, vec![1, 2, 3, 4, 5])
+        );
+
+        for len in [0, 125, 126, 300] {
+            let mut out = Vec::new();
+            write_message(&mut out, BINARY, &vec![7; len]).unwrap();
+            assert_eq!(
+                read_message(&mut out.as_slice()).unwrap(),
+                (BINARY, vec![7; len])
+            );
+        }
+
+        masked[0] &= 0x7F;
+        assert!(read_message(&mut masked.as_slice()).is_err());
+        let mut long = Vec::new();
+        write_message(&mut long, BINARY, &[0; MAX_MESSAGE as usize + 1]).unwrap();
+        assert!(read_message(&mut long.as_slice()).is_err());
+    }
+
+    #[test]
+    fn frames_flow_both_ways() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let control = Arc::new(Control::new());
+        let stop_token = Arc::new(AtomicBool::new(false));
+        let check = Box::new(|_: &[String]| Ok(()));
+        let server = crate::http::serve(listener, control.clone(), check, stop_token.clone());
+        let server = server.unwrap();
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with("HTTP/1.1 101"), "{line}");
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+
+        // What the cube shows first, then each change
+        assert_eq!(read_message(&mut reader).unwrap(), (BINARY, vec![0; 64]));
+        control.set_shown([[3; 8]; 8]);
+        assert_eq!(read_message(&mut reader).unwrap(), (BINARY, vec![3; 64]));
+
+        let mut frame = [0u8; 64];
+        frame[63] = 0x80;
+        stream.write_all(&client_message(BINARY, &frame)).unwrap();
+        stream.write_all(&client_message(PING, b"hi")).unwrap();
+        assert_eq!(read_message(&mut reader).unwrap(), (PONG, b"hi".to_vec()));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let commands = loop {
+            let commands = control.take_commands();
+            if !commands.is_empty() || Instant::now() > deadline {
+                break commands;
+            }
+            thread::yield_now();
+        };
+        let mut expected = [[0; 8]; 8];
+        expected[7][7] = 0x80;
+        assert_eq!(commands, [Command::Frame(expected)]);
+
+        stop_token.store(true, Ordering::Relaxed);
+        server.join().unwrap();
+        assert_eq!(read_message(&mut reader).unwrap().0, CLOSE);
+    }
+}